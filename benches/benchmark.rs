@@ -74,12 +74,61 @@ fn parse_int_success(c: &mut Criterion) {
     });
 }
 
+fn parse_identifier_or(c: &mut Criterion) {
+    let ident = pany_range('a'..='z')
+        .or(pany_range('A'..='Z'))
+        .or(pchar('_'));
+    let alpha_numeric = pany_range('a'..='z')
+        .or(pany_range('A'..='Z'))
+        .or(pany_range('0'..='9'))
+        .or(pchar('_'))
+        .many();
+    let ident_parser = ident.then(alpha_numeric);
+
+    c.bench_function("Parse identifier Or", |b| {
+        b.iter(|| {
+            for _ in 0..100 {
+                let _ = black_box(ident_parser.parse("long_identifier_123".into()));
+            }
+        })
+    });
+}
+
+fn parse_identifier_class(c: &mut Criterion) {
+    let ident = pclass(CharClass::alpha() | CharClass::char('_'));
+    let alpha_numeric = ptake_while(CharClass::alnum() | CharClass::char('_'));
+    let ident_parser = ident.then(alpha_numeric);
+
+    c.bench_function("Parse identifier Class", |b| {
+        b.iter(|| {
+            for _ in 0..100 {
+                let _ = black_box(ident_parser.parse("long_identifier_123".into()));
+            }
+        })
+    });
+}
+
+fn parse_identifier_regex(c: &mut Criterion) {
+    let ident_parser = pregex("[A-Za-z_][A-Za-z0-9_]*");
+
+    c.bench_function("Parse identifier Regex", |b| {
+        b.iter(|| {
+            for _ in 0..100 {
+                let _ = black_box(ident_parser.parse("long_identifier_123".into()));
+            }
+        })
+    });
+}
+
 criterion_group!(
     benches,
     parse_char_success,
     parse_string_success,
     parse_char_fail,
     parse_string_fail,
-    parse_int_success
+    parse_int_success,
+    parse_identifier_or,
+    parse_identifier_class,
+    parse_identifier_regex
 );
 criterion_main!(benches);
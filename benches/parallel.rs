@@ -0,0 +1,41 @@
+extern crate ngl;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use ngl::untyped_language::parallel::*;
+
+const FUN: &str = "fun name(param: type, param_x: type_x) -> unit {
+    let x = 1;
+    let str = \"hello\";
+    call(x, param, function(param_x,4));
+    if true {
+        call(1);
+    } else {
+        call(2);
+    };
+}
+";
+
+//100k lines of functions
+fn source() -> String {
+    FUN.repeat(100_000 / FUN.lines().count())
+}
+
+fn parse_100k_lines(c: &mut Criterion) {
+    let source = source();
+    //timing a unit full of errors would measure error recovery instead
+    let unit = parse_unit(&source);
+    assert!(unit.errors.is_empty(), "{:?}", unit.errors);
+    assert_eq!(unit.items.len(), 100_000 / FUN.lines().count());
+    let mut group = c.benchmark_group("Parse 100k lines");
+    group.sample_size(10);
+    group.bench_function("Sequential", |b| b.iter(|| black_box(parse_unit(&source))));
+    let threads = std::thread::available_parallelism().map_or(4, |n| n.get());
+    group.bench_function("Parallel", |b| {
+        b.iter(|| black_box(parse_unit_parallel(&source, threads)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, parse_100k_lines);
+criterion_main!(benches);
@@ -0,0 +1,63 @@
+extern crate ngl;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use ngl::parser_combinator::*;
+use ngl::untyped_language::{pprogram, Interpreter, RuntimeValue};
+use ngl::vm::{Function, Instruction::*, Module, Value, Vm};
+
+const FIB: &str = "fun fib(n: int) -> int {
+    if n < 2 {
+        return n;
+    };
+    fib(n - 1) + fib(n - 2);
+}";
+
+//the same function assembled by hand
+fn fib_module() -> Module {
+    let code = vec![
+        LoadConst(1, 0),
+        Lt(2, 0, 1),
+        JumpIfNot(2, 4),
+        Return(0),
+        LoadConst(1, 2),
+        LoadConst(2, 1),
+        Sub(4, 0, 2),
+        Call(3, 1, 4, 1),
+        Sub(4, 0, 2),
+        Sub(4, 4, 2),
+        Call(4, 1, 4, 1),
+        Add(3, 3, 4),
+        Return(3),
+    ];
+    Module {
+        constants: vec![Value::Int(2), Value::Int(1), Value::Function(0)],
+        functions: vec![Function {
+            name: "fib".to_string(),
+            arity: 1,
+            registers: 5,
+            slots: 0,
+            code,
+            spans: Vec::new(),
+        }],
+    }
+}
+
+fn fib_20(c: &mut Criterion) {
+    let (program, _) = pprogram().parse(FIB.into()).unwrap();
+    let module = fib_module();
+    let mut group = c.benchmark_group("fib(20)");
+    group.bench_function("Tree walking", |b| {
+        b.iter(|| {
+            let mut interpreter = Interpreter::with_output(&program.value, std::io::sink());
+            black_box(interpreter.call("fib", vec![RuntimeValue::Int(20)]))
+        })
+    });
+    group.bench_function("Register VM", |b| {
+        b.iter(|| black_box(Vm::new(&module).call(0, vec![Value::Int(20)])))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, fib_20);
+criterion_main!(benches);
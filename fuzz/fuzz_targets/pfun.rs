@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ngl::parser_combinator::*;
+use ngl::untyped_language::*;
+
+fuzz_target!(|input: &str| {
+    let _ = pfun().parse(input.into());
+});
@@ -0,0 +1,36 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ngl::parser_combinator::*;
+use ngl::pchoice;
+
+fuzz_target!(|input: &str| {
+    let _ = pchar('é').parse(input.into());
+    let _ = pstring("héllo").parse(input.into());
+    let _ = pany(&['a', 'ß', '\n']).parse(input.into());
+    let _ = pany_range('a'..='z').parse(input.into());
+    let _ = pchar('x').ws().parse(input.into());
+    let _ = pclass(!CharClass::alpha()).parse(input.into());
+    let _ = palpha().parse(input.into());
+    let _ = pdigit().parse(input.into());
+    let _ = palnum().parse(input.into());
+    let _ = pxid_start().then(pxid_continue().many()).parse(input.into());
+    let _ = psatisfy(|c| c.is_uppercase()).parse(input.into());
+    let _ = ptake_while(CharClass::alnum()).parse(input.into());
+    let _ = pregex("[^x]*x|(ab)+").parse(input.into());
+    let _ = pkeyword("if").parse(input.into());
+    let _ = pstring_ci("straße").parse(input.into());
+    let _ = pone_of_strings(&["<", "<=", "é"]).parse(input.into());
+    let _ = pchar('"').take_until().parse(input.into());
+    let _ = pchar('a').optional().many().parse(input.into());
+    let _ = pchar('a').sep_by(pchar(',')).parse(input.into());
+    let _ = pdigit().many1().between(pchar('('), pchar(')')).parse(input.into());
+    let _ = pchoice!(pchar('a'), pchar('b'), pdigit()).parse(input.into());
+
+    //the first line is used as a regex pattern for the rest of the input
+    if let Some((pattern, rest)) = input.split_once('\n') {
+        if let Ok(regex) = Regex::new(pattern) {
+            let _ = regex.match_len(rest);
+        }
+    }
+});
@@ -0,0 +1,699 @@
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fmt::{self, Display, Formatter},
+    ops::Range,
+};
+
+use crate::{
+    parser_combinator::Token,
+    untyped_language::{
+        lowering::{globals, literal_sign, InlineConsts},
+        BinaryOp, Builtin, Expr, ExprOrStatement, Fun, Program, Statement, Value as Literal,
+    },
+};
+
+use super::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildError {
+    Undefined(String, Range<usize>),
+    Unsupported(&'static str, Range<usize>), //what, where
+    RecursiveConst(String, Range<usize>),
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            BuildError::Undefined(name, span) => {
+                write!(f, "{} is not defined at {:?}", name, span)
+            }
+            BuildError::Unsupported(what, span) => {
+                write!(f, "{} cannot be built yet at {:?}", what, span)
+            }
+            BuildError::RecursiveConst(name, span) => {
+                write!(f, "constant {} refers to itself at {:?}", name, span)
+            }
+        }
+    }
+}
+
+//a local, or a value such as the result of an if that is assigned on several paths
+type Variable = usize;
+
+struct Loop<'p> {
+    label: Option<&'p str>,
+    exit: BlockId,
+    next: BlockId,            //where continue goes
+    result: Option<Variable>, //for loops that break with a value
+}
+
+//builds SSA form directly from the AST, as in "Simple and Efficient Construction of Static
+//Single Assignment Form" by Braun et al: a variable read in a block without a definition of
+//its own is looked up in the predecessors, through a phi where they meet. Blocks are sealed
+//once all their predecessors are known, and phis placed before then are completed on sealing
+struct Builder<'p, 'c> {
+    funs: &'c HashSet<&'p str>,
+    consts: &'c HashMap<&'p str, &'p Token<Expr>>,
+    blocks: Vec<(Vec<Instruction>, Option<Terminator>)>,
+    predecessors: Vec<Vec<BlockId>>,
+    sealed: Vec<bool>,
+    incomplete: HashMap<BlockId, Vec<(Variable, ValueId)>>, //phis placed before sealing
+    definitions: HashMap<(Variable, BlockId), ValueId>,
+    current: BlockId,
+    values: ValueId,
+    variables: Variable,
+    bindings: Vec<(&'p str, Variable)>, //innermost last
+    loops: Vec<Loop<'p>>,
+    evaluating: Vec<&'p str>, //constants being inlined, to catch cycles
+}
+
+impl<'p, 'c> InlineConsts<'p> for Builder<'p, 'c> {
+    type Binding = Variable;
+    type Output = ValueId;
+    type Error = BuildError;
+
+    fn definition(&self, name: &str) -> Option<(&'p str, &'p Token<Expr>)> {
+        self.consts
+            .get_key_value(name)
+            .map(|(&name, &value)| (name, value))
+    }
+
+    fn evaluating(&mut self) -> &mut Vec<&'p str> {
+        &mut self.evaluating
+    }
+
+    fn bindings(&mut self) -> &mut Vec<(&'p str, Variable)> {
+        &mut self.bindings
+    }
+
+    fn lower(&mut self, expr: &'p Token<Expr>) -> Result<ValueId, BuildError> {
+        self.expr(expr)
+    }
+
+    fn recursive(name: &str, span: &Range<usize>) -> BuildError {
+        BuildError::RecursiveConst(name.to_string(), span.clone())
+    }
+}
+
+impl<'p, 'c> Builder<'p, 'c> {
+    fn block(&mut self) -> BlockId {
+        self.blocks.push((Vec::new(), None));
+        self.predecessors.push(Vec::new());
+        self.sealed.push(false);
+        self.blocks.len() as BlockId - 1
+    }
+
+    fn variable(&mut self) -> Variable {
+        self.variables += 1;
+        self.variables - 1
+    }
+
+    fn emit_in(&mut self, block: BlockId, op: Op, span: &Range<usize>) -> ValueId {
+        let value = self.values;
+        self.values += 1;
+        let instructions = &mut self.blocks[block as usize].0;
+        let instruction = Instruction {
+            value,
+            op,
+            span: span.clone(),
+        };
+        match instruction.op {
+            //phis go before everything else
+            Op::Phi(_) => {
+                let position = instructions
+                    .iter()
+                    .position(|instruction| !matches!(instruction.op, Op::Phi(_)));
+                let position = position.unwrap_or(instructions.len());
+                instructions.insert(position, instruction);
+            }
+            _ => instructions.push(instruction),
+        }
+        value
+    }
+
+    fn emit(&mut self, op: Op, span: &Range<usize>) -> ValueId {
+        self.emit_in(self.current, op, span)
+    }
+
+    fn constant(&mut self, constant: Constant, span: &Range<usize>) -> ValueId {
+        self.emit(Op::Const(constant), span)
+    }
+
+    //ends the current block, carrying on in a new one that nothing jumps to yet
+    fn terminate(&mut self, terminator: Terminator) {
+        for successor in terminator.successors() {
+            self.predecessors[successor as usize].push(self.current);
+        }
+        self.blocks[self.current as usize].1 = Some(terminator);
+        self.current = self.block();
+        self.sealed[self.current as usize] = true;
+    }
+
+    fn switch(&mut self, block: BlockId) {
+        //drops the block started by the last terminator if nothing was built in it
+        let last = self.blocks.len() as BlockId - 1;
+        if self.current == last && self.blocks[last as usize] == (Vec::new(), None) {
+            self.blocks.pop();
+            self.predecessors.pop();
+            self.sealed.pop();
+        }
+        self.current = block;
+    }
+
+    fn seal(&mut self, block: BlockId) {
+        for (variable, phi) in self.incomplete.remove(&block).unwrap_or_default() {
+            self.complete(variable, phi, block);
+        }
+        self.sealed[block as usize] = true;
+    }
+
+    fn write(&mut self, variable: Variable, value: ValueId) {
+        self.definitions.insert((variable, self.current), value);
+    }
+
+    fn read(&mut self, variable: Variable, span: &Range<usize>) -> ValueId {
+        self.read_in(variable, self.current, span)
+    }
+
+    fn read_in(&mut self, variable: Variable, block: BlockId, span: &Range<usize>) -> ValueId {
+        if let Some(value) = self.definitions.get(&(variable, block)) {
+            return *value;
+        }
+        let value = match self.predecessors[block as usize].as_slice() {
+            _ if !self.sealed[block as usize] => {
+                let phi = self.emit_in(block, Op::Phi(Vec::new()), span);
+                self.incomplete
+                    .entry(block)
+                    .or_default()
+                    .push((variable, phi));
+                phi
+            }
+            //only code that cannot run reads a variable before it is defined
+            [] => self.emit_in(block, Op::Const(Constant::Unit), span),
+            [predecessor] => self.read_in(variable, *predecessor, span),
+            _ => {
+                //defined before the operands are read, to end cycles through loops
+                let phi = self.emit_in(block, Op::Phi(Vec::new()), span);
+                self.definitions.insert((variable, block), phi);
+                self.complete(variable, phi, block);
+                phi
+            }
+        };
+        self.definitions.insert((variable, block), value);
+        value
+    }
+
+    fn complete(&mut self, variable: Variable, phi: ValueId, block: BlockId) {
+        let span = self.blocks[block as usize]
+            .0
+            .iter()
+            .find(|instruction| instruction.value == phi)
+            .expect("a phi in the block")
+            .span
+            .clone();
+        let mut incoming = Vec::new();
+        for predecessor in self.predecessors[block as usize].clone() {
+            incoming.push((predecessor, self.read_in(variable, predecessor, &span)));
+        }
+        let instruction = self.blocks[block as usize]
+            .0
+            .iter_mut()
+            .find(|instruction| instruction.value == phi)
+            .expect("a phi in the block");
+        instruction.op = Op::Phi(incoming);
+    }
+
+    fn body(
+        &mut self,
+        body: &'p [Token<ExprOrStatement>],
+        span: &Range<usize>,
+    ) -> Result<ValueId, BuildError> {
+        let scope = self.bindings.len();
+        let mut value = None;
+        for item in body {
+            value = match &item.value {
+                ExprOrStatement::Expr(expr) => Some(self.expr_at(expr, &item.span())?),
+                ExprOrStatement::Statement(statement) => {
+                    self.statement(statement, &item.span())?;
+                    None
+                }
+            };
+        }
+        self.bindings.truncate(scope);
+        match value {
+            Some(value) => Ok(value),
+            None => Ok(self.constant(Constant::Unit, span)),
+        }
+    }
+
+    fn local(&self, name: &str) -> Option<Variable> {
+        let binding = self.bindings.iter().rev().find(|(bound, _)| *bound == name);
+        binding.map(|(_, variable)| *variable)
+    }
+
+    fn bind(&mut self, name: &'p str, value: ValueId) {
+        let variable = self.variable();
+        self.write(variable, value);
+        self.bindings.push((name, variable));
+    }
+
+    fn ident(&mut self, name: &'p str, span: &Range<usize>) -> Result<ValueId, BuildError> {
+        if let Some(variable) = self.local(name) {
+            return Ok(self.read(variable, span));
+        }
+        if let Some(result) = self.inline_const(name, span) {
+            return result;
+        }
+        let builtin = Builtin::ALL.iter().any(|builtin| builtin.name() == name);
+        match self.funs.contains(name) || builtin {
+            true => Ok(self.constant(Constant::Function(name.to_string()), span)),
+            false => Err(BuildError::Undefined(name.to_string(), span.clone())),
+        }
+    }
+
+    fn binary(
+        &mut self,
+        lhs: &'p Token<Expr>,
+        op: &Token<BinaryOp>,
+        rhs: &'p Token<Expr>,
+        span: &Range<usize>,
+    ) -> Result<ValueId, BuildError> {
+        let lhs = self.expr(lhs)?;
+        if let BinaryOp::And | BinaryOp::Or = op.value {
+            let result = self.variable();
+            self.write(result, lhs);
+            let (other, end) = (self.block(), self.block());
+            let branch = match op.value {
+                BinaryOp::And => Terminator::Branch(lhs, other, end),
+                _ => Terminator::Branch(lhs, end, other),
+            };
+            self.terminate(branch);
+            self.seal(other);
+            self.switch(other);
+            let value = self.expr(rhs)?;
+            self.write(result, value);
+            self.terminate(Terminator::Jump(end));
+            self.seal(end);
+            self.switch(end);
+            return Ok(self.read(result, span));
+        }
+        let rhs = self.expr(rhs)?;
+        Ok(self.emit(Op::Binary(op.value, lhs, rhs), span))
+    }
+
+    fn if_else(
+        &mut self,
+        condition: &'p Token<Expr>,
+        then: &'p [Token<ExprOrStatement>],
+        otherwise: &'p Option<Vec<Token<ExprOrStatement>>>,
+        span: &Range<usize>,
+    ) -> Result<ValueId, BuildError> {
+        let condition = self.expr(condition)?;
+        let result = self.variable();
+        let (then_block, other, end) = (self.block(), self.block(), self.block());
+        self.terminate(Terminator::Branch(condition, then_block, other));
+        self.seal(then_block);
+        self.seal(other);
+        self.switch(then_block);
+        let value = self.body(then, span)?;
+        self.write(result, value);
+        self.terminate(Terminator::Jump(end));
+        self.switch(other);
+        let value = match otherwise {
+            Some(otherwise) => self.body(otherwise, span)?,
+            None => self.constant(Constant::Unit, span),
+        };
+        self.write(result, value);
+        self.terminate(Terminator::Jump(end));
+        self.seal(end);
+        self.switch(end);
+        Ok(self.read(result, span))
+    }
+
+    //builds the body with break and continue going to the given blocks, then jumps to `next`
+    fn looping(
+        &mut self,
+        label: &'p Option<Token<String>>,
+        exit: BlockId,
+        next: BlockId,
+        result: Option<Variable>,
+        body: &'p [Token<ExprOrStatement>],
+        span: &Range<usize>,
+    ) -> Result<(), BuildError> {
+        self.loops.push(Loop {
+            label: label.as_ref().map(|label| label.value.as_str()),
+            exit,
+            next,
+            result,
+        });
+        let result = self.body(body, span);
+        self.loops.pop();
+        result?;
+        self.terminate(Terminator::Jump(next));
+        Ok(())
+    }
+
+    fn target(
+        &self,
+        label: &Option<Token<String>>,
+        span: &Range<usize>,
+    ) -> Result<&Loop<'p>, BuildError> {
+        let found = match label {
+            Some(label) => self
+                .loops
+                .iter()
+                .rev()
+                .find(|target| target.label == Some(label.value.as_str())),
+            None => self.loops.last(),
+        };
+        match (found, label) {
+            (Some(found), _) => Ok(found),
+            (None, Some(label)) => Err(BuildError::Undefined(
+                format!("'{}", label.value),
+                label.span(),
+            )),
+            (None, None) => Err(BuildError::Unsupported(
+                "break outside a loop",
+                span.clone(),
+            )),
+        }
+    }
+
+    fn for_range(
+        &mut self,
+        label: &'p Option<Token<String>>,
+        name: &'p Token<String>,
+        range: &'p Token<Expr>,
+        body: &'p [Token<ExprOrStatement>],
+        span: &Range<usize>,
+    ) -> Result<(), BuildError> {
+        let Expr::Range(start, end, inclusive, step) = &range.value else {
+            return Err(BuildError::Unsupported(
+                "ranges held in values",
+                range.span(),
+            ));
+        };
+        let range_span = range.span();
+        //the bounds are evaluated once, before the body can change what they refer to
+        let index = self.variable();
+        let value = self.expr(start)?;
+        self.write(index, value);
+        let end = self.expr(end)?;
+        let (step, sign) = match step {
+            Some(step) => {
+                let value = self.expr(step)?;
+                let sign = literal_sign(&step.value);
+                //a step that may be zero fails before the first iteration, as in the interpreter
+                if sign.is_none() || sign == Some(Ordering::Equal) {
+                    self.emit(Op::CheckStep(value), &step.span());
+                }
+                (value, sign)
+            }
+            None => {
+                let one = Constant::Literal(Literal::Number(1));
+                (self.constant(one, &range_span), Some(Ordering::Greater))
+            }
+        };
+        let compare = match inclusive {
+            true => BinaryOp::Le,
+            false => BinaryOp::Lt,
+        };
+        //counts up or down by the sign of the step, which is known not to be zero
+        let up = match sign {
+            None => {
+                let zero = self.emit(Op::Binary(BinaryOp::Sub, step, step), &range_span);
+                Some(self.emit(Op::Binary(BinaryOp::Lt, zero, step), &range_span))
+            }
+            Some(_) => None,
+        };
+        let (top, body_block, next, exit) =
+            (self.block(), self.block(), self.block(), self.block());
+        self.terminate(Terminator::Jump(top));
+        self.switch(top);
+        let value = self.read(index, &range_span);
+        let condition = match (sign, up) {
+            (Some(Ordering::Greater), _) => self.emit(Op::Binary(compare, value, end), &range_span),
+            (Some(Ordering::Less), _) => self.emit(Op::Binary(compare, end, value), &range_span),
+            (_, Some(up)) => {
+                let condition = self.variable();
+                let (upwards, downwards, test) = (self.block(), self.block(), self.block());
+                self.terminate(Terminator::Branch(up, upwards, downwards));
+                for block in [upwards, downwards] {
+                    self.seal(block);
+                }
+                self.switch(upwards);
+                let result = self.emit(Op::Binary(compare, value, end), &range_span);
+                self.write(condition, result);
+                self.terminate(Terminator::Jump(test));
+                self.switch(downwards);
+                let result = self.emit(Op::Binary(compare, end, value), &range_span);
+                self.write(condition, result);
+                self.terminate(Terminator::Jump(test));
+                self.seal(test);
+                self.switch(test);
+                self.read(condition, &range_span)
+            }
+            _ => {
+                let never = Constant::Literal(Literal::Bool(false));
+                self.constant(never, &range_span)
+            }
+        };
+        self.terminate(Terminator::Branch(condition, body_block, exit));
+        self.seal(body_block);
+        self.switch(body_block);
+        //the body gets its own variable, so assigning to it does not change the iteration
+        let scope = self.bindings.len();
+        self.bind(&name.value, value);
+        let result = self.looping(label, exit, next, None, body, span);
+        self.bindings.truncate(scope);
+        result?;
+        self.seal(next);
+        self.switch(next);
+        let value = self.read(index, &range_span);
+        let value = self.emit(Op::Binary(BinaryOp::Add, value, step), &range_span);
+        self.write(index, value);
+        self.terminate(Terminator::Jump(top));
+        self.seal(top);
+        self.seal(exit);
+        self.switch(exit);
+        Ok(())
+    }
+
+    fn statement(
+        &mut self,
+        statement: &'p Statement,
+        span: &Range<usize>,
+    ) -> Result<(), BuildError> {
+        match statement {
+            Statement::Let(name, _, value, _) => {
+                let value = self.expr(value)?;
+                self.bind(&name.value, value);
+            }
+            Statement::Assign(place, op, value) => {
+                let Expr::Ident(name) = &place.value else {
+                    return Err(BuildError::Unsupported(
+                        "assigning to anything but a local",
+                        place.span(),
+                    ));
+                };
+                let Some(local) = self.local(name) else {
+                    return Err(BuildError::Undefined(name.clone(), place.span()));
+                };
+                let mut value = self.expr(value)?;
+                if let Some(op) = op {
+                    let current = self.read(local, &place.span());
+                    value = self.emit(Op::Binary(op.value, current, value), span);
+                }
+                self.write(local, value);
+            }
+            Statement::For(label, name, range, body) => {
+                self.for_range(label, name, range, body, span)?
+            }
+            Statement::ForIn(_, _, _, _) => {
+                return Err(BuildError::Unsupported("for in loops", span.clone()))
+            }
+            Statement::While(label, condition, body) => {
+                let (top, body_block, exit) = (self.block(), self.block(), self.block());
+                self.terminate(Terminator::Jump(top));
+                self.switch(top);
+                let value = self.expr(condition)?;
+                self.terminate(Terminator::Branch(value, body_block, exit));
+                self.seal(body_block);
+                self.switch(body_block);
+                self.looping(label, exit, top, None, body, span)?;
+                self.seal(top);
+                self.seal(exit);
+                self.switch(exit);
+            }
+            Statement::Break(label, value) => {
+                let target = self.target(label, span)?;
+                let (exit, result) = (target.exit, target.result);
+                if let Some(result) = result {
+                    let value = match value {
+                        Some(value) => self.expr(value)?,
+                        None => self.constant(Constant::Unit, span),
+                    };
+                    self.write(result, value);
+                }
+                self.terminate(Terminator::Jump(exit));
+            }
+            Statement::Continue(label) => {
+                let next = self.target(label, span)?.next;
+                self.terminate(Terminator::Jump(next));
+            }
+            Statement::Return(value) => {
+                let value = match value {
+                    Some(value) => self.expr(value)?,
+                    None => self.constant(Constant::Unit, span),
+                };
+                self.terminate(Terminator::Return(value));
+            }
+        }
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &'p Token<Expr>) -> Result<ValueId, BuildError> {
+        self.expr_at(&expr.value, &expr.span())
+    }
+
+    fn expr_at(&mut self, expr: &'p Expr, span: &Range<usize>) -> Result<ValueId, BuildError> {
+        let unsupported = |what| Err(BuildError::Unsupported(what, span.clone()));
+        match expr {
+            Expr::Value(literal) => Ok(self.constant(Constant::Literal(literal.clone()), span)),
+            Expr::Ident(name) => self.ident(name, span),
+            Expr::Call(callee, args) => {
+                let callee = self.expr(callee)?;
+                let mut values = Vec::new();
+                for arg in args {
+                    values.push(self.expr(arg)?);
+                }
+                Ok(self.emit(Op::Call(callee, values), span))
+            }
+            Expr::Binary(lhs, op, rhs) => self.binary(lhs, op, rhs, span),
+            Expr::Unary(op, value) => {
+                let value = self.expr(value)?;
+                Ok(self.emit(Op::Unary(op.value, value), span))
+            }
+            Expr::If(condition, then, otherwise) => self.if_else(condition, then, otherwise, span),
+            Expr::Loop(label, body) => {
+                let result = self.variable();
+                let (top, exit) = (self.block(), self.block());
+                self.terminate(Terminator::Jump(top));
+                self.switch(top);
+                self.looping(label, exit, top, Some(result), body, span)?;
+                self.seal(top);
+                self.seal(exit);
+                self.switch(exit);
+                Ok(self.read(result, span))
+            }
+            Expr::Range(_, _, _, _) => unsupported("ranges outside for loops"),
+            Expr::Struct(_, _) | Expr::Field(_, _) => unsupported("structs"),
+            Expr::Variant(_, _, _) | Expr::Match(_, _) => unsupported("enums"),
+            Expr::Array(_) | Expr::Repeat(_, _) | Expr::Index(_, _) => unsupported("arrays"),
+            Expr::Tuple(_) => unsupported("tuples"),
+            Expr::Closure(_) => unsupported("closures"),
+        }
+    }
+}
+
+//removes phis whose operands are all one value or the phi itself, until none are left
+fn remove_trivial_phis(function: &mut Function) {
+    let mut replaced: HashMap<ValueId, ValueId> = HashMap::new();
+    let resolve = |replaced: &HashMap<ValueId, ValueId>, mut value| {
+        while let Some(next) = replaced.get(&value) {
+            value = *next;
+        }
+        value
+    };
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in &mut function.blocks {
+            block.instructions.retain(|instruction| {
+                let Op::Phi(incoming) = &instruction.op else {
+                    return true;
+                };
+                let mut operands = incoming
+                    .iter()
+                    .map(|(_, value)| resolve(&replaced, *value))
+                    .filter(|value| *value != instruction.value);
+                let Some(first) = operands.next() else {
+                    return true;
+                };
+                if operands.any(|value| value != first) {
+                    return true;
+                }
+                replaced.insert(instruction.value, first);
+                changed = true;
+                false
+            });
+        }
+    }
+    function.rewrite_uses(|value| resolve(&replaced, value));
+}
+
+fn build<'p>(
+    fun: &'p Fun,
+    funs: &HashSet<&'p str>,
+    consts: &HashMap<&'p str, &'p Token<Expr>>,
+) -> Result<Function, BuildError> {
+    let mut builder = Builder {
+        funs,
+        consts,
+        blocks: Vec::new(),
+        predecessors: Vec::new(),
+        sealed: Vec::new(),
+        incomplete: HashMap::new(),
+        definitions: HashMap::new(),
+        current: 0,
+        values: 0,
+        variables: 0,
+        bindings: Vec::new(),
+        loops: Vec::new(),
+        evaluating: Vec::new(),
+    };
+    let entry = builder.block();
+    builder.seal(entry);
+    for (index, param) in fun.params.iter().enumerate() {
+        let value = builder.emit(Op::Param(index as u32), &param.span());
+        builder.bind(&param.value.0.value, value);
+    }
+    let name = fun.name.span();
+    let value = builder.body(&fun.body, &name)?;
+    builder.terminate(Terminator::Return(value));
+    //the block started after the return is never used
+    builder.blocks.pop();
+    let blocks = builder
+        .blocks
+        .into_iter()
+        .map(|(instructions, terminator)| Block {
+            instructions,
+            terminator: terminator.expect("every block to be terminated"),
+        });
+    let mut function = Function {
+        name: fun.name.value.clone(),
+        blocks: blocks.collect(),
+    };
+    remove_trivial_phis(&mut function);
+    Ok(function)
+}
+
+/// Builds a function on its own, so it can call only itself and builtins.
+pub fn build_fun(fun: &Fun) -> Result<Function, BuildError> {
+    build(
+        fun,
+        &HashSet::from([fun.name.value.as_str()]),
+        &HashMap::new(),
+    )
+}
+
+/// Builds every function of a program, in order. Constants are built where used.
+pub fn build_program(program: &Program) -> Result<Vec<Function>, BuildError> {
+    let (in_order, consts) = globals(program);
+    let funs = in_order.iter().map(|fun| fun.name.value.as_str()).collect();
+    in_order
+        .into_iter()
+        .map(|fun| build(fun, &funs, &consts))
+        .collect()
+}
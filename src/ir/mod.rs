@@ -0,0 +1,10 @@
+pub mod builder;
+pub mod passes;
+pub mod ssa;
+
+pub use builder::*;
+pub use passes::*;
+pub use ssa::*;
+
+#[cfg(test)]
+pub mod tests;
@@ -0,0 +1,388 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::untyped_language::{binary, unary, BinaryOp, RuntimeValue, UnaryOp, Value as Literal};
+
+use super::*;
+
+fn runtime_value(constant: &Constant) -> Option<RuntimeValue<'static>> {
+    match constant {
+        Constant::Unit => Some(RuntimeValue::Unit),
+        Constant::Literal(literal) => Some(match literal {
+            Literal::Number(value) => RuntimeValue::Int(*value),
+            Literal::I64(value) => RuntimeValue::I64(*value),
+            Literal::U64(value) => RuntimeValue::U64(*value),
+            Literal::Float(value) => RuntimeValue::Float(*value),
+            Literal::Bool(value) => RuntimeValue::Bool(*value),
+            Literal::Char(value) => RuntimeValue::Char(*value),
+            Literal::String(value) => RuntimeValue::String(value.clone()),
+        }),
+        Constant::Function(_) => None,
+    }
+}
+
+fn constant(value: RuntimeValue) -> Option<Constant> {
+    let literal = match value {
+        RuntimeValue::Unit => return Some(Constant::Unit),
+        RuntimeValue::Int(value) => Literal::Number(value),
+        RuntimeValue::I64(value) => Literal::I64(value),
+        RuntimeValue::U64(value) => Literal::U64(value),
+        RuntimeValue::Float(value) => Literal::Float(value),
+        RuntimeValue::Bool(value) => Literal::Bool(value),
+        RuntimeValue::Char(value) => Literal::Char(value),
+        RuntimeValue::String(value) => Literal::String(value),
+        _ => return None,
+    };
+    Some(Constant::Literal(literal))
+}
+
+//the constant an operation always produces, given the constants known so far
+fn fold(op: &Op, constants: &HashMap<ValueId, Constant>) -> Option<Constant> {
+    let value = |value| constants.get(value).and_then(runtime_value);
+    match op {
+        Op::Unary(op, operand) => constant(unary(*op, value(operand)?).ok()?),
+        Op::Binary(op, lhs, rhs) => constant(binary(*op, value(lhs)?, value(rhs)?).ok()?),
+        Op::Phi(incoming) => {
+            let (first, rest) = incoming.split_first()?;
+            let first = constants.get(&first.1)?;
+            let same = rest.iter().all(|(_, value)| {
+                constants
+                    .get(value)
+                    .is_some_and(|constant| constant.same(first))
+            });
+            same.then(|| first.clone())
+        }
+        //a step known not to be zero needs no check
+        Op::CheckStep(step) => match constants.get(step)? {
+            Constant::Literal(Literal::Number(0) | Literal::I64(0) | Literal::U64(0)) => None,
+            Constant::Literal(Literal::Number(_) | Literal::I64(_) | Literal::U64(_)) => {
+                Some(Constant::Unit)
+            }
+            _ => None,
+        },
+        Op::Param(_) | Op::Const(_) | Op::Copy(_) | Op::Call(_, _) => None,
+    }
+}
+
+/// Replaces operations on constants with their results, and branches on constants with jumps.
+/// Operations that would fail at run time are left to fail there.
+pub fn fold_constants(function: &mut Function) -> bool {
+    let mut constants = HashMap::new();
+    let mut changed = false;
+    let mut folding = true;
+    while folding {
+        folding = false;
+        for block in &mut function.blocks {
+            for instruction in &mut block.instructions {
+                match &instruction.op {
+                    Op::Const(constant) => {
+                        constants.insert(instruction.value, constant.clone());
+                    }
+                    //copies are left to copy propagation, but seen through
+                    Op::Copy(value) => {
+                        if let Some(constant) = constants.get(value).cloned() {
+                            folding |= constants.insert(instruction.value, constant).is_none();
+                        }
+                    }
+                    op => {
+                        if let Some(constant) = fold(op, &constants) {
+                            instruction.op = Op::Const(constant);
+                            folding = true;
+                            changed = true;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    for index in 0..function.blocks.len() {
+        let Terminator::Branch(condition, then, otherwise) = function.blocks[index].terminator
+        else {
+            continue;
+        };
+        let Some(Constant::Literal(Literal::Bool(taken))) = constants.get(&condition) else {
+            continue;
+        };
+        let (target, untaken) = match taken {
+            true => (then, otherwise),
+            false => (otherwise, then),
+        };
+        function.blocks[index].terminator = Terminator::Jump(target);
+        if untaken != target {
+            for instruction in &mut function.blocks[untaken as usize].instructions {
+                if let Op::Phi(incoming) = &mut instruction.op {
+                    incoming.retain(|(block, _)| *block as usize != index);
+                }
+            }
+        }
+        changed = true;
+    }
+    changed
+}
+
+/// Replaces uses of copies, and of phis that always give the same value, with the value they
+/// copy.
+pub fn propagate_copies(function: &mut Function) -> bool {
+    let mut replaced: HashMap<ValueId, ValueId> = HashMap::new();
+    for block in &function.blocks {
+        for instruction in &block.instructions {
+            let copied = match &instruction.op {
+                Op::Copy(value) => Some(*value),
+                Op::Phi(incoming) => {
+                    let mut values = incoming
+                        .iter()
+                        .map(|(_, value)| *value)
+                        .filter(|value| *value != instruction.value);
+                    let first = values.next();
+                    first.filter(|first| values.all(|value| value == *first))
+                }
+                _ => None,
+            };
+            if let Some(copied) = copied {
+                replaced.insert(instruction.value, copied);
+            }
+        }
+    }
+    if replaced.is_empty() {
+        return false;
+    }
+    let resolve = |mut value| {
+        //a phi may be replaced by a value that is itself replaced, but never by itself
+        while let Some(next) = replaced.get(&value) {
+            value = *next;
+        }
+        value
+    };
+    for block in &mut function.blocks {
+        block
+            .instructions
+            .retain(|instruction| !replaced.contains_key(&instruction.value));
+    }
+    function.rewrite_uses(resolve);
+    true
+}
+
+/// Removes blocks that cannot be reached, and pure instructions whose values are never used.
+pub fn eliminate_dead_code(function: &mut Function) -> bool {
+    let reachable: HashSet<BlockId> = function.reverse_postorder().into_iter().collect();
+    let mut changed = reachable.len() < function.blocks.len();
+    if changed {
+        function.retain_blocks(|block| reachable.contains(&block));
+    }
+    let mut operands = HashMap::new();
+    let mut live = Vec::new();
+    for block in &function.blocks {
+        for instruction in &block.instructions {
+            operands.insert(instruction.value, instruction.op.operands());
+            if !instruction.op.is_pure() {
+                live.push(instruction.value);
+            }
+        }
+        match block.terminator {
+            Terminator::Branch(value, _, _) | Terminator::Return(value) => live.push(value),
+            Terminator::Jump(_) => {}
+        }
+    }
+    let mut used = HashSet::new();
+    while let Some(value) = live.pop() {
+        if used.insert(value) {
+            live.extend(operands.get(&value).into_iter().flatten());
+        }
+    }
+    for block in &mut function.blocks {
+        let before = block.instructions.len();
+        block
+            .instructions
+            .retain(|instruction| used.contains(&instruction.value));
+        changed |= block.instructions.len() < before;
+    }
+    changed
+}
+
+//what makes two operations the same for common subexpression elimination, with floats
+//compared by their bits
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Unit,
+    Number(i32),
+    I64(i64),
+    U64(u64),
+    Float(u64),
+    Bool(bool),
+    Char(char),
+    String(String),
+    Function(String),
+    Unary(UnaryOp, ValueId),
+    Binary(BinaryOp, ValueId, ValueId),
+}
+
+fn key(op: &Op) -> Option<Key> {
+    Some(match op {
+        Op::Const(Constant::Unit) => Key::Unit,
+        Op::Const(Constant::Literal(literal)) => match literal {
+            Literal::Number(value) => Key::Number(*value),
+            Literal::I64(value) => Key::I64(*value),
+            Literal::U64(value) => Key::U64(*value),
+            Literal::Float(value) => Key::Float(value.to_bits()),
+            Literal::Bool(value) => Key::Bool(*value),
+            Literal::Char(value) => Key::Char(*value),
+            Literal::String(value) => Key::String(value.clone()),
+        },
+        Op::Const(Constant::Function(name)) => Key::Function(name.clone()),
+        Op::Unary(op, value) => Key::Unary(*op, *value),
+        Op::Binary(op, lhs, rhs) => Key::Binary(*op, *lhs, *rhs),
+        _ => return None,
+    })
+}
+
+/// Replaces an operation with a copy of the same operation on the same values when that
+/// dominates it, so that it has already been done.
+pub fn eliminate_common_subexpressions(function: &mut Function) -> bool {
+    let dominators = function.dominators();
+    let mut children = vec![Vec::new(); function.blocks.len()];
+    for (block, dominator) in dominators.iter().enumerate().skip(1) {
+        if let Some(dominator) = dominator {
+            children[*dominator as usize].push(block as BlockId);
+        }
+    }
+    let mut changed = false;
+    let mut available: HashMap<Key, ValueId> = HashMap::new();
+    //each block along with the keys it made available, to forget once its subtree is done
+    let mut stack = vec![(0, None)];
+    while let Some((block, added)) = stack.pop() {
+        if let Some(added) = added {
+            for key in added {
+                available.remove(&key);
+            }
+            continue;
+        }
+        let mut added = Vec::new();
+        for instruction in &mut function.blocks[block as usize].instructions {
+            let Some(key) = key(&instruction.op) else {
+                continue;
+            };
+            match available.get(&key) {
+                Some(value) => {
+                    instruction.op = Op::Copy(*value);
+                    changed = true;
+                }
+                None => {
+                    available.insert(key.clone(), instruction.value);
+                    added.push(key);
+                }
+            }
+        }
+        stack.push((block, Some(added)));
+        for child in children[block as usize].iter().rev() {
+            stack.push((*child, None));
+        }
+    }
+    changed
+}
+
+fn dominates(dominators: &[Option<BlockId>], dominator: BlockId, mut block: BlockId) -> bool {
+    loop {
+        if block == dominator {
+            return true;
+        }
+        match dominators[block as usize] {
+            Some(next) if next != block => block = next,
+            _ => return false,
+        }
+    }
+}
+
+//the header of each loop, with the blocks in it and the one block outside it that jumps to it
+fn loops(function: &Function) -> Vec<(BlockId, HashSet<BlockId>, BlockId)> {
+    let dominators = function.dominators();
+    let predecessors = function.predecessors();
+    let mut bodies: HashMap<BlockId, HashSet<BlockId>> = HashMap::new();
+    for block in function.reverse_postorder() {
+        for header in function.blocks[block as usize].terminator.successors() {
+            if !dominates(&dominators, header, block) {
+                continue;
+            }
+            //everything that reaches the back edge without passing through the header
+            let body = bodies
+                .entry(header)
+                .or_insert_with(|| HashSet::from([header]));
+            let mut pending = vec![block];
+            while let Some(block) = pending.pop() {
+                if body.insert(block) {
+                    pending.extend(&predecessors[block as usize]);
+                }
+            }
+        }
+    }
+    let mut found = Vec::new();
+    for (header, body) in bodies {
+        let mut outside = predecessors[header as usize]
+            .iter()
+            .filter(|predecessor| !body.contains(predecessor));
+        let (Some(&preheader), None) = (outside.next(), outside.next()) else {
+            continue;
+        };
+        if function.blocks[preheader as usize].terminator == Terminator::Jump(header) {
+            found.push((header, body, preheader));
+        }
+    }
+    found.sort_by_key(|(header, _, _)| *header);
+    found
+}
+
+/// Moves operations whose operands are the same on every iteration of a loop to the block
+/// before it. Operations that could fail are moved only from the start of the loop, where they
+/// would have run first anyway.
+pub fn hoist_loop_invariants(function: &mut Function) -> bool {
+    let order = function.reverse_postorder();
+    let mut changed = false;
+    for (header, body, preheader) in loops(function) {
+        let mut defined: HashSet<ValueId> = body
+            .iter()
+            .flat_map(|block| &function.blocks[*block as usize].instructions)
+            .map(|instruction| instruction.value)
+            .collect();
+        let mut hoisted = Vec::new();
+        for block in order.iter().filter(|block| body.contains(block)) {
+            //whether everything left behind so far at the start of the header is pure
+            let mut leading = *block == header;
+            let instructions = std::mem::take(&mut function.blocks[*block as usize].instructions);
+            for instruction in instructions {
+                let invariant = !matches!(instruction.op, Op::Phi(_) | Op::Call(_, _))
+                    && instruction
+                        .op
+                        .operands()
+                        .iter()
+                        .all(|operand| !defined.contains(operand));
+                let pure = instruction.op.is_pure();
+                if invariant && (pure || leading) {
+                    defined.remove(&instruction.value);
+                    hoisted.push(instruction);
+                    continue;
+                }
+                leading &= pure;
+                function.blocks[*block as usize]
+                    .instructions
+                    .push(instruction);
+            }
+        }
+        changed |= !hoisted.is_empty();
+        function.blocks[preheader as usize]
+            .instructions
+            .extend(hoisted);
+    }
+    changed
+}
+
+/// Runs every pass until none of them change anything.
+pub fn optimise(function: &mut Function) {
+    loop {
+        let mut changed = fold_constants(function);
+        changed |= propagate_copies(function);
+        changed |= eliminate_common_subexpressions(function);
+        changed |= hoist_loop_invariants(function);
+        changed |= eliminate_dead_code(function);
+        if !changed {
+            break;
+        }
+    }
+}
@@ -0,0 +1,328 @@
+use std::{
+    collections::HashSet,
+    fmt::{self, Display, Formatter},
+    ops::Range,
+};
+
+use crate::untyped_language::{BinaryOp, UnaryOp, Value as Literal};
+
+/// A value defined by exactly one instruction, written `v0`, `v1`, ...
+pub type ValueId = u32;
+
+/// An index into the blocks of a function, written `b0`, `b1`, ...
+pub type BlockId = u32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Unit,
+    Literal(Literal),
+    Function(String), //a function or builtin, by name
+}
+
+impl Constant {
+    /// Whether two constants are the same value, telling apart floats such as `0.0` and
+    /// `-0.0` that compare equal but behave differently.
+    pub fn same(&self, other: &Constant) -> bool {
+        match (self, other) {
+            (Constant::Literal(Literal::Float(lhs)), Constant::Literal(Literal::Float(rhs))) => {
+                lhs.to_bits() == rhs.to_bits()
+            }
+            _ => self == other,
+        }
+    }
+}
+
+impl Display for Constant {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Constant::Unit => f.write_str("()"),
+            Constant::Literal(literal) => write!(f, "{}", literal),
+            Constant::Function(name) => write!(f, "@{}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    Param(u32),
+    Const(Constant),
+    Copy(ValueId),
+    Unary(UnaryOp, ValueId),
+    Binary(BinaryOp, ValueId, ValueId), //never a short-circuiting operator
+    Call(ValueId, Vec<ValueId>),        //callee, arguments
+    Phi(Vec<(BlockId, ValueId)>),       //the value arriving from each predecessor
+    CheckStep(ValueId),                 //fails if the range step is zero, giving unit
+}
+
+impl Op {
+    /// The values the operation reads.
+    pub fn operands(&self) -> Vec<ValueId> {
+        match self {
+            Op::Param(_) | Op::Const(_) => Vec::new(),
+            Op::Copy(value) | Op::Unary(_, value) | Op::CheckStep(value) => vec![*value],
+            Op::Binary(_, lhs, rhs) => vec![*lhs, *rhs],
+            Op::Call(callee, args) => std::iter::once(callee).chain(args).copied().collect(),
+            Op::Phi(incoming) => incoming.iter().map(|(_, value)| *value).collect(),
+        }
+    }
+
+    fn operands_mut(&mut self) -> Vec<&mut ValueId> {
+        match self {
+            Op::Param(_) | Op::Const(_) => Vec::new(),
+            Op::Copy(value) | Op::Unary(_, value) | Op::CheckStep(value) => vec![value],
+            Op::Binary(_, lhs, rhs) => vec![lhs, rhs],
+            Op::Call(callee, args) => std::iter::once(callee).chain(args).collect(),
+            Op::Phi(incoming) => incoming.iter_mut().map(|(_, value)| value).collect(),
+        }
+    }
+
+    /// Whether the operation can be removed, moved or repeated freely: it has no effects and
+    /// cannot fail. The IR keeps no types, so only equality tests, which are defined between
+    /// any two values, are pure among the operators: every other one fails on operands of the
+    /// wrong type, if not by overflowing or dividing by zero.
+    pub fn is_pure(&self) -> bool {
+        match self {
+            Op::Param(_) | Op::Const(_) | Op::Copy(_) | Op::Phi(_) => true,
+            Op::Binary(op, _, _) => matches!(op, BinaryOp::Eq | BinaryOp::Ne),
+            Op::Unary(_, _) => false,
+            Op::Call(_, _) | Op::CheckStep(_) => false,
+        }
+    }
+}
+
+impl Display for Op {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Op::Param(index) => write!(f, "param {}", index),
+            Op::Const(constant) => write!(f, "{}", constant),
+            Op::Copy(value) => write!(f, "v{}", value),
+            Op::Unary(op, value) => write!(f, "{}v{}", op.symbol(), value),
+            Op::Binary(op, lhs, rhs) => write!(f, "v{} {} v{}", lhs, op.symbol(), rhs),
+            Op::CheckStep(value) => write!(f, "checkstep v{}", value),
+            Op::Call(callee, args) => {
+                write!(f, "call v{}(", callee)?;
+                for (i, arg) in args.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { ", " };
+                    write!(f, "{}v{}", separator, arg)?;
+                }
+                f.write_str(")")
+            }
+            Op::Phi(incoming) => {
+                f.write_str("phi [")?;
+                for (i, (block, value)) in incoming.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { ", " };
+                    write!(f, "{}b{}: v{}", separator, block, value)?;
+                }
+                f.write_str("]")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub value: ValueId, //the value the instruction defines
+    pub op: Op,
+    pub span: Range<usize>, //the source it was built from
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Jump(BlockId),
+    Branch(ValueId, BlockId, BlockId), //condition, if true, if false
+    Return(ValueId),
+}
+
+impl Terminator {
+    /// The blocks control can go to next.
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch(_, then, otherwise) => vec![*then, *otherwise],
+            Terminator::Return(_) => Vec::new(),
+        }
+    }
+
+    fn operands_mut(&mut self) -> Option<&mut ValueId> {
+        match self {
+            Terminator::Jump(_) => None,
+            Terminator::Branch(value, _, _) | Terminator::Return(value) => Some(value),
+        }
+    }
+
+    fn targets_mut(&mut self) -> Vec<&mut BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![target],
+            Terminator::Branch(_, then, otherwise) => vec![then, otherwise],
+            Terminator::Return(_) => Vec::new(),
+        }
+    }
+}
+
+impl Display for Terminator {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Terminator::Jump(target) => write!(f, "jump b{}", target),
+            Terminator::Branch(condition, then, otherwise) => {
+                write!(f, "branch v{}, b{}, b{}", condition, then, otherwise)
+            }
+            Terminator::Return(value) => write!(f, "return v{}", value),
+        }
+    }
+}
+
+/// Straight-line code ending in a jump, branch or return. Phis come first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub instructions: Vec<Instruction>,
+    pub terminator: Terminator,
+}
+
+/// A function in SSA form. Control enters at the first block, and parameters are the values
+/// of [`Op::Param`] instructions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub blocks: Vec<Block>,
+}
+
+impl Function {
+    /// The blocks that can jump to each block, in order.
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut predecessors = vec![Vec::new(); self.blocks.len()];
+        for (index, block) in self.blocks.iter().enumerate() {
+            for successor in block.terminator.successors() {
+                let list: &mut Vec<BlockId> = &mut predecessors[successor as usize];
+                if !list.contains(&(index as BlockId)) {
+                    list.push(index as BlockId);
+                }
+            }
+        }
+        predecessors
+    }
+
+    /// The blocks reachable from the first, each after every block that leads to it other
+    /// than by a back edge.
+    pub fn reverse_postorder(&self) -> Vec<BlockId> {
+        let mut order = Vec::new();
+        let mut visited = HashSet::from([0]);
+        //each block along with how many of its successors have been visited
+        let mut stack = vec![(0, 0)];
+        while let Some((block, next)) = stack.pop() {
+            let successors = self.blocks[block as usize].terminator.successors();
+            match successors.get(next) {
+                Some(&successor) => {
+                    stack.push((block, next + 1));
+                    if visited.insert(successor) {
+                        stack.push((successor, 0));
+                    }
+                }
+                None => order.push(block),
+            }
+        }
+        order.reverse();
+        order
+    }
+
+    /// The immediate dominator of each reachable block, by the algorithm of Cooper, Harvey
+    /// and Kennedy. The first block is its own dominator.
+    pub fn dominators(&self) -> Vec<Option<BlockId>> {
+        let order = self.reverse_postorder();
+        let mut position = vec![usize::MAX; self.blocks.len()];
+        for (index, block) in order.iter().enumerate() {
+            position[*block as usize] = index;
+        }
+        let predecessors = self.predecessors();
+        let mut dominators = vec![None; self.blocks.len()];
+        dominators[0] = Some(0);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in &order[1..] {
+                let mut processed = predecessors[block as usize]
+                    .iter()
+                    .copied()
+                    .filter(|predecessor| dominators[*predecessor as usize].is_some());
+                let Some(first) = processed.next() else {
+                    continue;
+                };
+                let dominator = processed.fold(first, |mut a, mut b| {
+                    while a != b {
+                        while position[a as usize] > position[b as usize] {
+                            a = dominators[a as usize].expect("a processed block");
+                        }
+                        while position[b as usize] > position[a as usize] {
+                            b = dominators[b as usize].expect("a processed block");
+                        }
+                    }
+                    a
+                });
+                if dominators[block as usize] != Some(dominator) {
+                    dominators[block as usize] = Some(dominator);
+                    changed = true;
+                }
+            }
+        }
+        dominators
+    }
+
+    /// Replaces every use of a value, leaving the instructions that define values alone.
+    pub fn rewrite_uses(&mut self, mut f: impl FnMut(ValueId) -> ValueId) {
+        for block in &mut self.blocks {
+            for instruction in &mut block.instructions {
+                for operand in instruction.op.operands_mut() {
+                    *operand = f(*operand);
+                }
+            }
+            if let Some(operand) = block.terminator.operands_mut() {
+                *operand = f(*operand);
+            }
+        }
+    }
+
+    /// Keeps only the blocks `keep` is true for, renumbering the rest. Jumps to removed blocks
+    /// must already be gone, and phis forget the values arriving from them.
+    pub fn retain_blocks(&mut self, keep: impl Fn(BlockId) -> bool) {
+        let mut renumbered = Vec::new();
+        let mut next = 0;
+        for index in 0..self.blocks.len() as BlockId {
+            renumbered.push(keep(index).then(|| {
+                next += 1;
+                next - 1
+            }));
+        }
+        let blocks = std::mem::take(&mut self.blocks);
+        for (index, mut block) in blocks.into_iter().enumerate() {
+            if renumbered[index].is_none() {
+                continue;
+            }
+            for target in block.terminator.targets_mut() {
+                *target = renumbered[*target as usize].expect("a jump to a kept block");
+            }
+            for instruction in &mut block.instructions {
+                if let Op::Phi(incoming) = &mut instruction.op {
+                    incoming.retain(|(block, _)| renumbered[*block as usize].is_some());
+                    for (block, _) in incoming {
+                        *block = renumbered[*block as usize].expect("a kept block");
+                    }
+                }
+            }
+            self.blocks.push(block);
+        }
+    }
+}
+
+/// Writes the function as its name followed by each block and its instructions, one per line.
+impl Display for Function {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "fun {}", self.name)?;
+        for (index, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "b{}:", index)?;
+            for instruction in &block.instructions {
+                writeln!(f, "    v{} = {}", instruction.value, instruction.op)?;
+            }
+            writeln!(f, "    {}", block.terminator)?;
+        }
+        Ok(())
+    }
+}
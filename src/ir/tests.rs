@@ -0,0 +1,545 @@
+use crate::{
+    parser_combinator::*,
+    untyped_language::{pprogram, BinaryOp, UnaryOp, Value as Literal},
+};
+
+use super::*;
+
+fn build(source: &str) -> Vec<Function> {
+    let (program, cont) = pprogram().parse(source.into()).unwrap();
+    assert_eq!(cont.remaining, "");
+    build_program(&program.value).unwrap()
+}
+
+fn dump(functions: &[Function]) -> String {
+    let dumps: Vec<String> = functions.iter().map(Function::to_string).collect();
+    dumps.join("\n")
+}
+
+fn int(value: i32) -> Op {
+    Op::Const(Constant::Literal(Literal::Number(value)))
+}
+
+//a function written out by hand, as each block's values and operations and its terminator
+fn function(blocks: Vec<(Vec<(ValueId, Op)>, Terminator)>) -> Function {
+    let blocks = blocks.into_iter().map(|(instructions, terminator)| Block {
+        instructions: instructions
+            .into_iter()
+            .map(|(value, op)| Instruction {
+                value,
+                op,
+                span: 0..0,
+            })
+            .collect(),
+        terminator,
+    });
+    Function {
+        name: "f".to_string(),
+        blocks: blocks.collect(),
+    }
+}
+
+#[test]
+fn test_build() {
+    let functions = build(
+        "fun sum(n: int) -> int {
+    let total = 0;
+    let i = 0;
+    while i < n {
+        total += i;
+        i += 1;
+    };
+    total;
+}
+fun pick(x: int, y: int) -> int {
+    if x > 0 && y > 0 { x; } else { y; };
+}",
+    );
+    let expected = "fun sum
+b0:
+    v0 = param 0
+    v1 = 0
+    v2 = 0
+    jump b1
+b1:
+    v3 = phi [b0: v2, b2: v9]
+    v6 = phi [b0: v1, b2: v7]
+    v5 = v3 < v0
+    branch v5, b2, b3
+b2:
+    v7 = v6 + v3
+    v8 = 1
+    v9 = v3 + v8
+    v10 = ()
+    jump b1
+b3:
+    return v6
+
+fun pick
+b0:
+    v0 = param 0
+    v1 = param 1
+    v2 = 0
+    v3 = v0 > v2
+    branch v3, b1, b2
+b1:
+    v4 = 0
+    v5 = v1 > v4
+    jump b2
+b2:
+    v6 = phi [b0: v3, b1: v5]
+    branch v6, b3, b4
+b3:
+    jump b5
+b4:
+    jump b5
+b5:
+    v9 = phi [b3: v0, b4: v1]
+    return v9
+";
+    assert_eq!(dump(&functions), expected);
+
+    //a step only known at run time is checked not to be zero, then picks the comparison on
+    //each iteration
+    let functions = build(
+        "fun count(n: int, k: int) -> int {
+    let c = 0;
+    for i = n .. 0 step k { c += 1; };
+    c;
+}",
+    );
+    let expected = "fun count
+b0:
+    v0 = param 0
+    v1 = param 1
+    v2 = 0
+    v3 = 0
+    v4 = checkstep v1
+    v5 = v1 - v1
+    v6 = v5 < v1
+    jump b1
+b1:
+    v7 = phi [b0: v0, b3: v17]
+    v13 = phi [b0: v2, b3: v14]
+    branch v6, b5, b6
+b2:
+    v11 = 1
+    v14 = v13 + v11
+    v15 = ()
+    jump b3
+b3:
+    v17 = v7 + v1
+    jump b1
+b4:
+    return v13
+b5:
+    v8 = v7 < v3
+    jump b7
+b6:
+    v9 = v3 < v7
+    jump b7
+b7:
+    v10 = phi [b5: v8, b6: v9]
+    branch v10, b2, b4
+";
+    assert_eq!(dump(&functions), expected);
+
+    //literal steps, negated or not, need no check unless they are zero
+    let checks = |source: &str| {
+        let functions = build(source);
+        let instructions = functions[0]
+            .blocks
+            .iter()
+            .flat_map(|block| &block.instructions);
+        instructions
+            .filter(|instruction| matches!(instruction.op, Op::CheckStep(_)))
+            .count()
+    };
+    assert_eq!(
+        checks("fun f() -> int { for i = 3 .. 0 step -1 { i; }; 0; }"),
+        0
+    );
+    assert_eq!(
+        checks("fun f() -> int { for i = 0 .. 3 step 2 { i; }; 0; }"),
+        0
+    );
+    assert_eq!(
+        checks("fun f() -> int { for i = 0 .. 3 step 0 { i; }; 0; }"),
+        1
+    );
+}
+
+#[test]
+fn test_build_errors() {
+    let build = |source: &str| {
+        let (program, _) = pprogram().parse(source.into()).unwrap();
+        build_program(&program.value)
+    };
+    let at = |source: &str, text: &str| {
+        let start = source.find(text).unwrap();
+        start..start + text.len()
+    };
+    let source = "fun f() -> int { g(1); }";
+    assert_eq!(
+        build(source),
+        Err(BuildError::Undefined("g".to_string(), at(source, "g")))
+    );
+    let source = "fun f() -> int { let x = [1, 2]; }";
+    assert_eq!(
+        build(source),
+        Err(BuildError::Unsupported("arrays", at(source, "[1, 2]")))
+    );
+    let source = "const A: int = B + 1;\nconst B: int = A;\nfun f() -> int { A; }";
+    let a = source.find("A;").unwrap();
+    assert_eq!(
+        build(source),
+        Err(BuildError::RecursiveConst("A".to_string(), a..a + 1))
+    );
+    let source = "fun f() -> int { loop { break 'outer; }; }";
+    assert_eq!(
+        build(source),
+        Err(BuildError::Undefined(
+            "'outer".to_string(),
+            at(source, "outer")
+        ))
+    );
+}
+
+#[test]
+fn test_fold_constants() {
+    let mut folded = function(vec![
+        (
+            vec![
+                (0, int(2)),
+                (1, int(3)),
+                (2, Op::Binary(BinaryOp::Mul, 0, 1)),
+                (3, Op::Binary(BinaryOp::Gt, 2, 0)),
+            ],
+            Terminator::Branch(3, 1, 2),
+        ),
+        (
+            vec![(4, int(0)), (5, Op::Binary(BinaryOp::Div, 2, 4))],
+            Terminator::Jump(3),
+        ),
+        (vec![(6, Op::Unary(UnaryOp::Neg, 2))], Terminator::Jump(3)),
+        (
+            vec![(7, Op::Phi(vec![(1, 5), (2, 6)]))],
+            Terminator::Return(7),
+        ),
+    ]);
+    assert!(fold_constants(&mut folded));
+    //dividing by zero is left to fail when it runs
+    let expected = "fun f
+b0:
+    v0 = 2
+    v1 = 3
+    v2 = 6
+    v3 = true
+    jump b1
+b1:
+    v4 = 0
+    v5 = v2 / v4
+    jump b3
+b2:
+    v6 = -6
+    jump b3
+b3:
+    v7 = phi [b1: v5, b2: v6]
+    return v7
+";
+    assert_eq!(folded.to_string(), expected);
+    assert!(!fold_constants(&mut folded));
+
+    //a zero step is left to fail when it runs
+    let mut folded = function(vec![(
+        vec![
+            (0, int(2)),
+            (1, Op::CheckStep(0)),
+            (2, int(0)),
+            (3, Op::CheckStep(2)),
+        ],
+        Terminator::Return(0),
+    )]);
+    assert!(fold_constants(&mut folded));
+    let expected = "fun f
+b0:
+    v0 = 2
+    v1 = ()
+    v2 = 0
+    v3 = checkstep v2
+    return v0
+";
+    assert_eq!(folded.to_string(), expected);
+}
+
+#[test]
+fn test_propagate_copies() {
+    let mut propagated = function(vec![
+        (
+            vec![(0, Op::Param(0)), (1, Op::Copy(0)), (2, Op::Copy(1))],
+            Terminator::Jump(1),
+        ),
+        (
+            vec![
+                (3, Op::Phi(vec![(0, 2), (1, 3)])),
+                (4, Op::Binary(BinaryOp::Add, 3, 1)),
+                (5, Op::Binary(BinaryOp::Eq, 4, 2)),
+            ],
+            Terminator::Branch(5, 1, 2),
+        ),
+        (Vec::new(), Terminator::Return(4)),
+    ]);
+    assert!(propagate_copies(&mut propagated));
+    let expected = "fun f
+b0:
+    v0 = param 0
+    jump b1
+b1:
+    v4 = v0 + v0
+    v5 = v4 == v0
+    branch v5, b1, b2
+b2:
+    return v4
+";
+    assert_eq!(propagated.to_string(), expected);
+    assert!(!propagate_copies(&mut propagated));
+}
+
+#[test]
+fn test_eliminate_dead_code() {
+    let mut eliminated = function(vec![
+        (
+            vec![
+                (0, Op::Param(0)),
+                (1, int(1)),
+                (2, Op::Binary(BinaryOp::Add, 0, 1)),
+                (3, Op::Binary(BinaryOp::Ne, 0, 1)),
+                (4, Op::Binary(BinaryOp::Eq, 0, 1)),
+            ],
+            Terminator::Branch(4, 2, 3),
+        ),
+        (vec![(5, int(2))], Terminator::Jump(3)),
+        (Vec::new(), Terminator::Return(0)),
+        (
+            vec![(6, Op::Phi(vec![(0, 1), (1, 5)]))],
+            Terminator::Return(6),
+        ),
+    ]);
+    assert!(eliminate_dead_code(&mut eliminated));
+    //the addition is kept because it could overflow
+    let expected = "fun f
+b0:
+    v0 = param 0
+    v1 = 1
+    v2 = v0 + v1
+    v4 = v0 == v1
+    branch v4, b1, b2
+b1:
+    return v0
+b2:
+    v6 = phi [b0: v1]
+    return v6
+";
+    assert_eq!(eliminated.to_string(), expected);
+    assert!(!eliminate_dead_code(&mut eliminated));
+}
+
+#[test]
+fn test_optimise_keeps_operations_that_can_fail() {
+    let mut functions = build(
+        "fun main() -> int {
+    let unused = 1 < \"a\";
+    let b = 1 & true;
+    let same = 1 == \"a\";
+    3;
+}",
+    );
+    optimise(&mut functions[0]);
+    //comparing or combining values of different types fails, but testing them for equality
+    //does not
+    let expected = "fun main
+b0:
+    v0 = 1
+    v1 = \"a\"
+    v2 = v0 < v1
+    v4 = true
+    v5 = v0 & v4
+    v9 = 3
+    return v9
+";
+    assert_eq!(functions[0].to_string(), expected);
+}
+
+#[test]
+fn test_optimise_tells_zeroes_apart() {
+    let mut functions = build(
+        "fun main(c: bool) -> float {
+    let x = if c { 0.0; } else { -0.0; };
+    let y = -0.0;
+    1.0 / x + 1.0 / y;
+}",
+    );
+    optimise(&mut functions[0]);
+    //0.0 and -0.0 compare equal, but dividing by them gives infinities of different signs
+    let dump = functions[0].to_string();
+    assert!(dump.contains("v3 = phi [b1: v1, b2: v2]"), "{}", dump);
+    assert!(dump.contains("= -inf"), "{}", dump);
+    assert!(!dump.contains("= inf"), "{}", dump);
+}
+
+#[test]
+fn test_eliminate_common_subexpressions() {
+    let mut functions = build(
+        "fun g(x: int, y: int) -> int {
+    let a = x * y;
+    if x > 0 { x * y + (x + y); } else { x + y; };
+}",
+    );
+    assert!(eliminate_common_subexpressions(&mut functions[0]));
+    //the sums in each branch are not shared, as neither branch runs before the other
+    let expected = "fun g
+b0:
+    v0 = param 0
+    v1 = param 1
+    v2 = v0 * v1
+    v3 = 0
+    v4 = v0 > v3
+    branch v4, b1, b2
+b1:
+    v5 = v2
+    v6 = v0 + v1
+    v7 = v5 + v6
+    jump b3
+b2:
+    v8 = v0 + v1
+    jump b3
+b3:
+    v9 = phi [b1: v7, b2: v8]
+    return v9
+";
+    assert_eq!(dump(&functions), expected);
+}
+
+#[test]
+fn test_hoist_loop_invariants() {
+    let mut functions = build(
+        "fun scale(n: int, k: int) -> int {
+    let total = 0;
+    let i = 0;
+    while i < k * n {
+        if k > 100 { total += i * k; } else { total += i; };
+        i += 1;
+    };
+    total;
+}",
+    );
+    assert!(hoist_loop_invariants(&mut functions[0]));
+    //the product in the condition could overflow but is hoisted as the loop always starts
+    //with it, while the one in the body stays where it may not run, as does the comparison,
+    //which could fail on operands of different types
+    let expected = "fun scale
+b0:
+    v0 = param 0
+    v1 = param 1
+    v2 = 0
+    v3 = 0
+    v7 = v1 * v0
+    v9 = 100
+    v16 = ()
+    v14 = ()
+    v18 = 1
+    v21 = ()
+    jump b1
+b1:
+    v4 = phi [b0: v3, b6: v20]
+    v12 = phi [b0: v2, b6: v24]
+    v8 = v4 < v7
+    branch v8, b2, b3
+b2:
+    v10 = v1 > v9
+    branch v10, b4, b5
+b3:
+    return v12
+b4:
+    v11 = v4 * v1
+    v13 = v12 + v11
+    jump b6
+b5:
+    v15 = v12 + v4
+    jump b6
+b6:
+    v17 = phi [b4: v14, b5: v16]
+    v24 = phi [b4: v13, b5: v15]
+    v20 = v4 + v18
+    jump b1
+";
+    assert_eq!(dump(&functions), expected);
+    assert!(!hoist_loop_invariants(&mut functions[0]));
+}
+
+#[test]
+fn test_optimise() {
+    let mut functions = build(
+        "const LIMIT: int = 2 * 5;
+fun f(n: int) -> int {
+    let total = 0;
+    for i = 0 .. n {
+        let unused = i * 2 == 7;
+        if LIMIT > 20 { total += 1000; } else { total += i * LIMIT; };
+    };
+    'outer: loop {
+        if total > LIMIT * LIMIT { break 'outer; };
+        total += LIMIT - 1;
+        continue;
+    };
+    total;
+}",
+    );
+    optimise(&mut functions[0]);
+    //the constant branch and the comparison left unused go, but not the product, which could
+    //overflow
+    let expected = "fun f
+b0:
+    v0 = param 0
+    v1 = 0
+    v3 = 1
+    v6 = 2
+    v12 = 10
+    jump b1
+b1:
+    v4 = phi [b0: v1, b3: v27]
+    v16 = phi [b0: v1, b3: v23]
+    v5 = v4 < v0
+    branch v5, b2, b4
+b2:
+    v7 = v4 * v6
+    jump b5
+b3:
+    v27 = v4 + v3
+    jump b1
+b4:
+    v36 = 100
+    v46 = 9
+    jump b7
+b5:
+    v22 = v4 * v12
+    v23 = v16 + v22
+    jump b6
+b6:
+    jump b3
+b7:
+    v29 = phi [b4: v16, b11: v49]
+    v37 = v29 > v36
+    branch v37, b9, b10
+b8:
+    return v29
+b9:
+    jump b8
+b10:
+    jump b11
+b11:
+    v49 = v29 + v46
+    jump b7
+";
+    assert_eq!(dump(&functions), expected);
+}
@@ -1,5 +1,8 @@
 #![feature(return_position_impl_trait_in_trait)]
 #![feature(associated_type_bounds)]
+pub mod ir;
 pub mod parser_combinator;
+pub mod typed_language;
 pub mod untyped_language;
+pub mod vm;
 pub mod web;
@@ -3,12 +3,14 @@ use ngl::untyped_language::*;
 
 #[tokio::main]
 async fn main() {
-    let fun_binding = pfun();
+    let program = pprogram();
 
     let start = std::time::Instant::now();
 
-    let result = fun_binding.parse(
-        "fun name(param: type, param_x: type_x) -> unit {
+    let result = program.parse(
+        "import std.io;
+
+        fun name(param: type, param_x: type_x) -> unit {
             let x = 1;
             let str = \"hello\";
             call(x, param, function(param_x,4));
@@ -28,5 +30,10 @@ async fn main() {
     let end = std::time::Instant::now();
 
     println!("{:#?} \n\nTook {:?}", result, (end - start));
+    if let Ok((program, _)) = &result {
+        for error in resolve_program(&program.value).errors {
+            println!("{}", error);
+        }
+    }
     ngl::web::app::run().await;
 }
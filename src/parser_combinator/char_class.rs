@@ -0,0 +1,198 @@
+use std::{
+    cmp::Ordering,
+    fmt::{self, Debug, Formatter},
+    ops::{BitOr, Not, RangeInclusive},
+    sync::Arc,
+};
+
+#[derive(Clone)]
+enum Set {
+    Ranges(Vec<RangeInclusive<char>>), //sorted and non-overlapping
+    Predicate(fn(char) -> bool),
+    Union(Arc<Set>, Arc<Set>),
+    Not(Arc<Set>),
+}
+
+impl Set {
+    fn contains(&self, c: char) -> bool {
+        match self {
+            Set::Ranges(ranges) => ranges
+                .binary_search_by(|range| {
+                    if *range.end() < c {
+                        Ordering::Less
+                    } else if *range.start() > c {
+                        Ordering::Greater
+                    } else {
+                        Ordering::Equal
+                    }
+                })
+                .is_ok(),
+            Set::Predicate(predicate) => predicate(c),
+            Set::Union(lhs, rhs) => lhs.contains(c) || rhs.contains(c),
+            Set::Not(set) => !set.contains(c),
+        }
+    }
+}
+
+/// A set of characters with constant time lookup for ASCII.
+/// Classes can be combined with `|` (union) and `!` (negation).
+/// ```
+/// use ngl::parser_combinator::CharClass;
+///
+/// let ident = CharClass::alpha() | CharClass::char('_');
+/// assert!(ident.contains('_'));
+/// assert!(!ident.contains('1'));
+/// assert!((!ident).contains('1'));
+/// ```
+#[derive(Clone)]
+pub struct CharClass {
+    ascii: u128,
+    set: Arc<Set>,
+    description: Arc<str>,
+}
+
+impl CharClass {
+    fn from_set(set: Set, description: impl Into<Arc<str>>) -> Self {
+        let ascii = (0..128u8)
+            .filter(|b| set.contains(*b as char))
+            .fold(0u128, |ascii, b| ascii | 1 << b);
+        Self {
+            ascii,
+            set: Arc::new(set),
+            description: description.into(),
+        }
+    }
+
+    pub fn char(c: char) -> Self {
+        Self::from_set(Set::Ranges(vec![c..=c]), format!("'{}'", c))
+    }
+
+    pub fn chars(chars: &[char]) -> Self {
+        let ranges = chars.iter().map(|c| *c..=*c).collect();
+        let description = chars
+            .iter()
+            .map(|c| format!("'{}'", c))
+            .collect::<Vec<_>>()
+            .join(" or ");
+        Self::from_set(Set::Ranges(merge(ranges)), description)
+    }
+
+    pub fn range(range: RangeInclusive<char>) -> Self {
+        let description = format!("between '{}' and '{}'", range.start(), range.end());
+        Self::from_set(Set::Ranges(vec![range]), description)
+    }
+
+    pub fn ranges(ranges: &[RangeInclusive<char>]) -> Self {
+        let description = ranges
+            .iter()
+            .map(|range| format!("between '{}' and '{}'", range.start(), range.end()))
+            .collect::<Vec<_>>()
+            .join(" or ");
+        Self::from_set(Set::Ranges(merge(ranges.to_vec())), description)
+    }
+
+    /// A class described by `description` whose membership is decided by `predicate`.
+    pub fn predicate(description: &str, predicate: fn(char) -> bool) -> Self {
+        Self::from_set(Set::Predicate(predicate), description)
+    }
+
+    /// ASCII letters.
+    pub fn alpha() -> Self {
+        Self::from_set(Set::Ranges(vec!['A'..='Z', 'a'..='z']), "letter")
+    }
+
+    /// ASCII digits.
+    pub fn digit() -> Self {
+        Self::from_set(Set::Ranges(vec!['0'..='9']), "digit")
+    }
+
+    /// ASCII letters and digits.
+    pub fn alnum() -> Self {
+        let ranges = vec!['0'..='9', 'A'..='Z', 'a'..='z'];
+        Self::from_set(Set::Ranges(ranges), "letter or digit")
+    }
+
+    pub fn whitespace() -> Self {
+        Self::predicate("whitespace", char::is_whitespace)
+    }
+
+    /// Characters that may start a Unicode identifier.
+    pub fn xid_start() -> Self {
+        Self::predicate("identifier start", unicode_ident::is_xid_start)
+    }
+
+    /// Characters that may continue a Unicode identifier.
+    pub fn xid_continue() -> Self {
+        Self::predicate("identifier character", unicode_ident::is_xid_continue)
+    }
+
+    pub fn any() -> Self {
+        Self::from_set(Set::Ranges(vec!['\0'..=char::MAX]), "any character")
+    }
+
+    pub fn union(self, other: CharClass) -> Self {
+        Self {
+            ascii: self.ascii | other.ascii,
+            set: Arc::new(Set::Union(self.set, other.set)),
+            description: format!("{} or {}", self.description, other.description).into(),
+        }
+    }
+
+    pub fn negate(self) -> Self {
+        Self {
+            ascii: !self.ascii,
+            set: Arc::new(Set::Not(self.set)),
+            description: format!("anything but {}", self.description).into(),
+        }
+    }
+
+    #[inline]
+    pub fn contains(&self, c: char) -> bool {
+        let code = c as u32;
+        if code < 128 {
+            self.ascii >> code & 1 == 1
+        } else {
+            self.set.contains(c)
+        }
+    }
+
+    pub fn description(&self) -> &Arc<str> {
+        &self.description
+    }
+}
+
+fn merge(mut ranges: Vec<RangeInclusive<char>>) -> Vec<RangeInclusive<char>> {
+    ranges.sort_by_key(|range| *range.start());
+    let mut merged: Vec<RangeInclusive<char>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if *range.start() as u32 <= *last.end() as u32 + 1 => {
+                if range.end() > last.end() {
+                    *last = *last.start()..=*range.end();
+                }
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+impl BitOr for CharClass {
+    type Output = CharClass;
+    fn bitor(self, rhs: Self) -> Self::Output {
+        self.union(rhs)
+    }
+}
+
+impl Not for CharClass {
+    type Output = CharClass;
+    fn not(self) -> Self::Output {
+        self.negate()
+    }
+}
+
+impl Debug for CharClass {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "CharClass({})", self.description)
+    }
+}
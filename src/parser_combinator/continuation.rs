@@ -16,6 +16,40 @@ impl<'a> ContinuationState<'a> {
         }
     }
 
+    /// State for resuming a parse of `input` part way through, at byte offset `position`.
+    pub fn at(input: &'a str, position: usize) -> Self {
+        let before = &input[..position];
+        let line_number = before.matches('\n').count();
+        let line_position = match before.rfind('\n') {
+            Some(newline) => position - newline - 1,
+            None => position,
+        };
+        Self {
+            remaining: &input[position..],
+            position,
+            line_number,
+            line_position,
+        }
+    }
+
+    /// Moves past the next `abs` bytes, which may span several lines.
+    pub(crate) fn skip(self, abs: usize) -> Self {
+        let consumed = &self.remaining[..abs];
+        let (line_number, line_position) = match consumed.rfind('\n') {
+            Some(newline) => (
+                self.line_number + consumed.matches('\n').count(),
+                abs - newline - 1,
+            ),
+            None => (self.line_number, self.line_position + abs),
+        };
+        Self {
+            remaining: &self.remaining[abs..],
+            position: self.position + abs,
+            line_number,
+            line_position,
+        }
+    }
+
     pub(crate) fn advance(self, abs: usize, new_line: bool) -> Self {
         let (line_number, line_position) = if new_line {
             (self.line_number + 1, 0)
@@ -1,6 +1,7 @@
 use std::{
     fmt::{self, Debug, Display, Formatter},
     ops::{Add, RangeInclusive},
+    sync::Arc,
 };
 
 #[derive(Clone, PartialEq, Debug)]
@@ -9,6 +10,9 @@ pub enum Expected<'a> {
     String(&'a str),
     Any(Vec<char>),
     Range(RangeInclusive<char>),
+    Class(Arc<str>),
+    Regex(&'a str),
+    EndOfInput,
     Or(Box<Expected<'a>>, Box<Expected<'a>>),
     And(Box<Expected<'a>>, Box<Expected<'a>>),
 }
@@ -58,6 +62,9 @@ impl<'a> Display for Expected<'a> {
             Expected::Range(range) => {
                 write!(f, "between '{}' and '{}'", range.start(), range.end())
             }
+            Expected::Class(description) => write!(f, "{}", description),
+            Expected::Regex(pattern) => write!(f, "/{}/", pattern),
+            Expected::EndOfInput => write!(f, "end of input"),
             Expected::Or(lhs, rhs) => write!(f, "{} or {}", lhs, rhs),
             Expected::And(lhs, rhs) => write!(f, "{} and {}", lhs, rhs),
             Expected::Any(chars) => {
@@ -1,13 +1,17 @@
+pub mod char_class;
 pub mod continuation;
 pub mod error;
 #[macro_use]
 pub mod parser;
 pub mod parsers;
+pub mod regex;
 pub mod token;
 
+pub use char_class::*;
 pub use continuation::*;
 pub use error::*;
 pub use parser::*;
+pub use regex::*;
 pub use token::*;
 
 pub use parsers::*;
@@ -15,7 +15,7 @@ impl<'a> Parser<'a, char> for AnyParser<'a> {
         }
 
         let actual = if !input.remaining.is_empty() {
-            &input.remaining[0..1]
+            pactual(input)
         } else {
             " "
         };
@@ -11,13 +11,14 @@ impl<'a> Parser<'a, char> for AnyRangeParser {
         let next_char = input.remaining.chars().next();
         if let Some(next_char) = next_char {
             if self.valid_chars.contains(&next_char) {
-                let parser_state = input.advance(1, next_char == '\n');
-                return Ok((Token::new(next_char, input.position, 1), parser_state));
+                let length = next_char.len_utf8();
+                let parser_state = input.advance(length, next_char == '\n');
+                return Ok((Token::new(next_char, input.position, length), parser_state));
             }
         }
 
         let actual = if !input.remaining.is_empty() {
-            &input.remaining[0..1]
+            pactual(input)
         } else {
             " "
         };
@@ -4,12 +4,13 @@ pub(crate) fn pchar_impl(c: char, input: ContinuationState<'_>) -> ParseResult<'
     let mut chars = input.remaining.chars();
     match chars.next() {
         Some(letter) if letter == c => {
-            let parser_state = input.advance(1, letter == '\n');
-            Ok((Token::new(c, input.position, 1), parser_state))
+            let length = c.len_utf8();
+            let parser_state = input.advance(length, letter == '\n');
+            Ok((Token::new(c, input.position, length), parser_state))
         }
         Some(_) => Err(Error::new(
             c.into(),
-            &input.remaining[0..1],
+            pactual(input),
             input.position,
             input.line_number,
             input.line_position,
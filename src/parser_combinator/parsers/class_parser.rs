@@ -0,0 +1,135 @@
+use super::*;
+
+pub(crate) fn pactual(input: ContinuationState<'_>) -> &str {
+    match input.remaining.chars().next() {
+        Some(c) => &input.remaining[..c.len_utf8()],
+        None => "",
+    }
+}
+
+#[derive(Clone)]
+struct ClassParser {
+    class: CharClass,
+}
+
+impl<'a> Parser<'a, char> for ClassParser {
+    fn parse(&self, input: ContinuationState<'a>) -> ParseResult<'a, char> {
+        match input.remaining.chars().next() {
+            Some(c) if self.class.contains(c) => {
+                let length = c.len_utf8();
+                let parser_state = input.advance(length, c == '\n');
+                Ok((Token::new(c, input.position, length), parser_state))
+            }
+            _ => Err(Error::new(
+                Expected::Class(self.class.description().clone()),
+                pactual(input),
+                input.position,
+                input.line_number,
+                input.line_position,
+            )),
+        }
+    }
+}
+
+/// Matches a single character from a [`CharClass`].
+/// ```
+/// use ngl::parser_combinator::*;
+///
+/// let hex = pclass(CharClass::digit() | CharClass::range('a'..='f'));
+/// let result = hex.parse("f0".into()).unwrap();
+/// assert_eq!(result.0.value, 'f');
+/// assert!(hex.parse("g".into()).is_err());
+/// ```
+pub fn pclass<'a>(class: CharClass) -> impl Parser<'a, char> {
+    ClassParser { class }
+}
+
+/// Matches an ASCII letter.
+pub fn palpha<'a>() -> impl Parser<'a, char> {
+    pclass(CharClass::alpha())
+}
+
+/// Matches an ASCII digit.
+pub fn pdigit<'a>() -> impl Parser<'a, char> {
+    pclass(CharClass::digit())
+}
+
+/// Matches an ASCII letter or digit.
+pub fn palnum<'a>() -> impl Parser<'a, char> {
+    pclass(CharClass::alnum())
+}
+
+/// Matches a character with the Unicode `XID_Start` property.
+pub fn pxid_start<'a>() -> impl Parser<'a, char> {
+    pclass(CharClass::xid_start())
+}
+
+/// Matches a character with the Unicode `XID_Continue` property.
+pub fn pxid_continue<'a>() -> impl Parser<'a, char> {
+    pclass(CharClass::xid_continue())
+}
+
+#[derive(Clone)]
+struct SatisfyParser<F> {
+    predicate: F,
+}
+
+impl<'a, F> Parser<'a, char> for SatisfyParser<F>
+where
+    F: Fn(char) -> bool + Clone,
+{
+    fn parse(&self, input: ContinuationState<'a>) -> ParseResult<'a, char> {
+        match input.remaining.chars().next() {
+            Some(c) if (self.predicate)(c) => {
+                let length = c.len_utf8();
+                let parser_state = input.advance(length, c == '\n');
+                Ok((Token::new(c, input.position, length), parser_state))
+            }
+            _ => Err(Error::new(
+                Expected::Class("character matching predicate".into()),
+                pactual(input),
+                input.position,
+                input.line_number,
+                input.line_position,
+            )),
+        }
+    }
+}
+
+/// Matches a single character for which `predicate` returns true.
+/// ```
+/// use ngl::parser_combinator::*;
+///
+/// let upper = psatisfy(|c| c.is_uppercase());
+/// assert_eq!(upper.parse("Ä".into()).unwrap().0.value, 'Ä');
+/// ```
+pub fn psatisfy<'a, F>(predicate: F) -> impl Parser<'a, char>
+where
+    F: Fn(char) -> bool + Clone + 'a,
+{
+    SatisfyParser { predicate }
+}
+
+#[derive(Clone)]
+struct TakeWhileParser {
+    class: CharClass,
+}
+
+impl<'a> Parser<'a, &'a str> for TakeWhileParser {
+    fn parse(&self, input: ContinuationState<'a>) -> ParseResult<'a, &'a str> {
+        let length = input
+            .remaining
+            .char_indices()
+            .find(|(_, c)| !self.class.contains(*c))
+            .map_or(input.remaining.len(), |(i, _)| i);
+        Ok((
+            Token::new(&input.remaining[..length], input.position, length),
+            input.skip(length),
+        ))
+    }
+}
+
+/// Matches zero or more characters from a [`CharClass`], returning them as a slice of the input.
+pub fn ptake_while<'a>(class: CharClass) -> impl Parser<'a, &'a str> {
+    TakeWhileParser { class }
+}
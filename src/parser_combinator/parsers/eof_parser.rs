@@ -0,0 +1,32 @@
+use super::*;
+
+#[derive(Clone)]
+struct EofParser;
+
+impl<'a> Parser<'a, ()> for EofParser {
+    fn parse(&self, input: ContinuationState<'a>) -> ParseResult<'a, ()> {
+        if input.remaining.is_empty() {
+            Ok((Token::new((), input.position, 0), input))
+        } else {
+            Err(Error::new(
+                Expected::EndOfInput,
+                pactual(input),
+                input.position,
+                input.line_number,
+                input.line_position,
+            ))
+        }
+    }
+}
+
+/// Succeeds only when there is no input left.
+/// ```
+/// use ngl::parser_combinator::*;
+///
+/// let parser = pchar('a').then(peof());
+/// assert!(parser.parse("a".into()).is_ok());
+/// assert!(parser.parse("ab".into()).is_err());
+/// ```
+pub fn peof<'a>() -> impl Parser<'a, ()> {
+    EofParser
+}
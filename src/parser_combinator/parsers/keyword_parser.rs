@@ -0,0 +1,42 @@
+use super::*;
+
+#[derive(Clone)]
+struct KeywordParser<'a> {
+    value: &'a str,
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+impl<'a> Parser<'a, &'a str> for KeywordParser<'a> {
+    fn parse(&self, input: ContinuationState<'a>) -> ParseResult<'a, &'a str> {
+        let (token, cont) = pstring(self.value).parse(input)?;
+        match cont.remaining.chars().next() {
+            Some(c) if is_identifier_char(c) => {
+                let length = self.value.len() + c.len_utf8();
+                Err(Error::new(
+                    self.value.into(),
+                    &input.remaining[..length],
+                    cont.position,
+                    cont.line_number,
+                    cont.line_position,
+                ))
+            }
+            _ => Ok((token, cont)),
+        }
+    }
+}
+
+/// Matches a keyword, which unlike [`pstring`] must not be followed by an identifier character.
+/// ```
+/// use ngl::parser_combinator::*;
+///
+/// let parser = pkeyword("if");
+/// assert!(parser.parse("if x".into()).is_ok());
+/// assert!(parser.parse("if(x)".into()).is_ok());
+/// assert!(parser.parse("iffy".into()).is_err());
+/// ```
+pub fn pkeyword(value: &str) -> impl Parser<'_, &str> {
+    KeywordParser { value }
+}
@@ -13,25 +13,18 @@ where
     fn parse(&self, input: ContinuationState<'a>) -> ParseResult<'a, Vec<Token<Output>>> {
         let mut results = Vec::new();
         let mut cont = input;
-        let mut error = None;
-        while error.is_none() {
-            let result = self.parser.parse(cont);
-            match result {
-                Ok((token, state)) => {
-                    results.push(token);
-                    cont = state;
-                }
-                Err(err) => {
-                    error = Some(err);
-                }
+        while let Ok((token, state)) = self.parser.parse(cont) {
+            //a parser that succeeds without consuming anything would repeat forever
+            let progressed = state.position > cont.position;
+            results.push(token);
+            cont = state;
+            if !progressed {
+                break;
             }
         }
 
         let len = results.len();
-        match error {
-            Some(_) => Ok((Token::new(results, input.position, len), cont)),
-            None => Ok((Token::new(results, input.position, len), input)),
-        }
+        Ok((Token::new(results, input.position, len), cont))
     }
 }
 
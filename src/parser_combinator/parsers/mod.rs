@@ -5,14 +5,20 @@ pub mod any_range_parser;
 pub mod at_least_one_parser;
 pub mod char_parser;
 pub mod choice_parser;
+pub mod class_parser;
 pub mod closure_parser;
+pub mod eof_parser;
+pub mod keyword_parser;
 pub mod left_parser;
 pub mod many_parser;
 pub mod map_parser;
+pub mod one_of_strings_parser;
 pub mod optional_parser;
 pub mod or_parser;
+pub mod regex_parser;
 pub mod right_parser;
 pub mod sep_by_parser;
+pub mod string_ci_parser;
 pub mod string_parser;
 pub mod take_until_parser;
 pub mod then_parser;
@@ -23,14 +29,20 @@ pub use any_range_parser::*;
 pub(crate) use at_least_one_parser::*;
 pub use char_parser::*;
 pub use choice_parser::*;
+pub use class_parser::*;
 pub use closure_parser::parser_from_fn;
+pub use eof_parser::*;
+pub use keyword_parser::*;
 pub(crate) use left_parser::*;
 pub(crate) use many_parser::*;
 pub(crate) use map_parser::*;
+pub use one_of_strings_parser::*;
 pub(crate) use optional_parser::*;
 pub(crate) use or_parser::*;
+pub use regex_parser::*;
 pub(crate) use right_parser::*;
 pub(crate) use sep_by_parser::*;
+pub use string_ci_parser::*;
 pub use string_parser::*;
 pub(crate) use take_until_parser::*;
 pub(crate) use then_parser::*;
@@ -0,0 +1,96 @@
+use super::*;
+use std::sync::Arc;
+
+#[derive(Default)]
+struct TrieNode {
+    children: Vec<(char, usize)>, //sorted by char
+    terminal: bool,
+}
+
+struct Trie {
+    nodes: Vec<TrieNode>,
+}
+
+impl Trie {
+    fn new(values: &[&str]) -> Self {
+        let mut nodes = vec![TrieNode::default()];
+        for value in values {
+            let mut node = 0;
+            for c in value.chars() {
+                node = match nodes[node].children.binary_search_by_key(&c, |(c, _)| *c) {
+                    Ok(i) => nodes[node].children[i].1,
+                    Err(i) => {
+                        let child = nodes.len();
+                        nodes.push(TrieNode::default());
+                        nodes[node].children.insert(i, (c, child));
+                        child
+                    }
+                };
+            }
+            nodes[node].terminal = true;
+        }
+        Self { nodes }
+    }
+
+    //length in bytes of the longest value at the start of input
+    fn longest_match(&self, input: &str) -> Option<usize> {
+        let mut node = &self.nodes[0];
+        let mut longest = node.terminal.then_some(0);
+        for (i, c) in input.char_indices() {
+            match node.children.binary_search_by_key(&c, |(c, _)| *c) {
+                Ok(child) => node = &self.nodes[node.children[child].1],
+                Err(_) => break,
+            }
+            if node.terminal {
+                longest = Some(i + c.len_utf8());
+            }
+        }
+        longest
+    }
+}
+
+#[derive(Clone)]
+struct OneOfStringsParser<'a> {
+    values: &'a [&'a str],
+    trie: Arc<Trie>,
+}
+
+impl<'a> Parser<'a, &'a str> for OneOfStringsParser<'a> {
+    fn parse(&self, input: ContinuationState<'a>) -> ParseResult<'a, &'a str> {
+        match self.trie.longest_match(input.remaining) {
+            Some(length) => Ok((
+                Token::new(&input.remaining[..length], input.position, length),
+                input.skip(length),
+            )),
+            None => {
+                let expected = self
+                    .values
+                    .iter()
+                    .map(|value| Expected::String(value))
+                    .reduce(|lhs, rhs| lhs + rhs)
+                    .unwrap_or(Expected::Any(vec![]));
+                Err(Error::new(
+                    expected,
+                    pactual(input),
+                    input.position,
+                    input.line_number,
+                    input.line_position,
+                ))
+            }
+        }
+    }
+}
+
+/// Matches the longest of several strings in a single pass over the input.
+/// ```
+/// use ngl::parser_combinator::*;
+///
+/// let parser = pone_of_strings(&["<", "<=", "<<"]);
+/// assert_eq!(parser.parse("<= b".into()).unwrap().0.value, "<=");
+/// ```
+pub fn pone_of_strings<'a>(values: &'a [&'a str]) -> impl Parser<'a, &'a str> {
+    OneOfStringsParser {
+        values,
+        trie: Arc::new(Trie::new(values)),
+    }
+}
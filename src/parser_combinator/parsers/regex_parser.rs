@@ -0,0 +1,49 @@
+use super::*;
+use std::sync::Arc;
+
+#[derive(Clone)]
+struct RegexParser<'a> {
+    pattern: &'a str,
+    regex: Arc<Regex>,
+}
+
+impl<'a> Parser<'a, &'a str> for RegexParser<'a> {
+    fn parse(&self, input: ContinuationState<'a>) -> ParseResult<'a, &'a str> {
+        match self.regex.match_len(input.remaining) {
+            Some(length) => Ok((
+                Token::new(&input.remaining[..length], input.position, length),
+                input.skip(length),
+            )),
+            None => Err(Error::new(
+                Expected::Regex(self.pattern),
+                pactual(input),
+                input.position,
+                input.line_number,
+                input.line_position,
+            )),
+        }
+    }
+}
+
+/// Matches the longest prefix of the input accepted by a [`Regex`].
+/// ```
+/// use ngl::parser_combinator::*;
+///
+/// let ident = pregex("[A-Za-z_][A-Za-z0-9_]*");
+/// let result = ident.parse("left_1 = 2".into()).unwrap();
+/// assert_eq!(result.0.value, "left_1");
+/// ```
+///
+/// # Panics
+///
+/// If `pattern` is not a valid regular expression.
+pub fn pregex(pattern: &str) -> impl Parser<'_, &str> {
+    let regex = match Regex::new(pattern) {
+        Ok(regex) => regex,
+        Err(error) => panic!("invalid regex /{}/: {}", pattern, error),
+    };
+    RegexParser {
+        pattern,
+        regex: Arc::new(regex),
+    }
+}
@@ -18,19 +18,22 @@ where
     S: Parser<'a, Seperator> + 'a,
 {
     fn parse(&self, input: ContinuationState<'a>) -> ParseResult<'a, Vec<Token<Output>>> {
-        let parser = self
-            .parser
-            .clone()
-            .then(self.separator.clone())
-            .left()
-            .many()
-            .then(self.parser.clone());
+        let (first, mut cont) = match self.parser.parse(input) {
+            Ok(result) => result,
+            Err(_) => return Ok((Token::new(Vec::new(), input.position, 0), input)),
+        };
 
-        let parser = parser.map(|(mut tokens, token)| {
-            tokens.value.push(token);
-            tokens.value
-        });
-        parser.parse(input)
+        //once a separator has been seen another item must follow it
+        let mut tokens = vec![first];
+        while let Ok((_, after_separator)) = self.separator.parse(cont) {
+            let (token, next) = self.parser.parse(after_separator)?;
+            tokens.push(token);
+            cont = next;
+        }
+
+        let last = tokens.last().unwrap();
+        let length = last.start + last.length - input.position;
+        Ok((Token::new(tokens, input.position, length), cont))
     }
 }
 
@@ -0,0 +1,46 @@
+use super::*;
+
+#[derive(Clone)]
+struct StringCiParser<'a> {
+    value: &'a str,
+}
+
+impl<'a> Parser<'a, &'a str> for StringCiParser<'a> {
+    fn parse(&self, input: ContinuationState<'a>) -> ParseResult<'a, &'a str> {
+        let mut actual = input.remaining.char_indices();
+        let mut length = 0;
+        for expected in self.value.chars() {
+            match actual.next() {
+                Some((i, c)) if c.to_lowercase().eq(expected.to_lowercase()) => {
+                    length = i + c.len_utf8();
+                }
+                next => {
+                    let end = next.map_or(length, |(i, c)| i + c.len_utf8());
+                    let cont = input.skip(length);
+                    return Err(Error::new(
+                        self.value.into(),
+                        &input.remaining[..end],
+                        cont.position,
+                        cont.line_number,
+                        cont.line_position,
+                    ));
+                }
+            }
+        }
+        Ok((
+            Token::new(&input.remaining[..length], input.position, length),
+            input.skip(length),
+        ))
+    }
+}
+
+/// Matches a string ignoring case, returning the text as it appears in the input.
+/// ```
+/// use ngl::parser_combinator::*;
+///
+/// let parser = pstring_ci("select");
+/// assert_eq!(parser.parse("SeLeCt *".into()).unwrap().0.value, "SeLeCt");
+/// ```
+pub fn pstring_ci(value: &str) -> impl Parser<'_, &str> {
+    StringCiParser { value }
+}
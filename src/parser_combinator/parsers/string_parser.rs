@@ -14,7 +14,7 @@ impl<'a> Parser<'a, &'a str> for StringParser<'a> {
             match result {
                 Ok((_, new_cont)) => cont = new_cont,
                 Err(err) => {
-                    let length = err.position - input.position + 1;
+                    let length = err.position - input.position + err.actual.len().max(1);
                     let actual = if input.remaining.len() < length {
                         &input.remaining[0..]
                     } else {
@@ -14,7 +14,7 @@ where
     P: Parser<'a, Until>,
 {
     fn parse(&self, input: ContinuationState<'a>) -> ParseResult<'a, &'a str> {
-        ptakeuntil_impl(&self.until, None, input)
+        ptakeuntil_impl(&self.until, input)
     }
 }
 
@@ -27,25 +27,24 @@ pub(crate) fn ptake_until<'a, Until: Clone + 'a>(
     }
 }
 
-//TODO deal with case where string is never termianted
 fn ptakeuntil_impl<'a, Until: Clone + 'a>(
     until: &impl Parser<'a, Until>,
-    start: Option<ContinuationState<'a>>,
     input: ContinuationState<'a>,
 ) -> ParseResult<'a, &'a str> {
-    let result = until.parse(input);
-    let start = start.unwrap_or(input);
-    match result {
-        Ok((_, cont)) => {
-            let len = cont.position - start.position - 1;
-            Ok((
-                Token::new(&start.remaining[0..len], start.position, len),
-                cont,
-            ))
-        }
-        Err(_) => {
-            let cont = input.advance(1, false); //TODO line advances
-            ptakeuntil_impl(until, Some(start), cont)
+    let mut cont = input;
+    loop {
+        match until.parse(cont) {
+            Ok((_, end)) => {
+                let len = cont.position - input.position;
+                return Ok((
+                    Token::new(&input.remaining[0..len], input.position, len),
+                    end,
+                ));
+            }
+            Err(err) => match cont.remaining.chars().next() {
+                Some(c) => cont = cont.advance(c.len_utf8(), c == '\n'),
+                None => return Err(err),
+            },
         }
     }
 }
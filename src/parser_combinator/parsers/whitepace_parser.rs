@@ -8,13 +8,14 @@ impl<'a> Parser<'a, ()> for WhitespaceParser {
         let next_char = input.remaining.chars().next();
         if let Some(next_char) = next_char {
             if next_char.is_whitespace() {
-                let parser_state = input.advance(1, next_char == '\n');
-                return Ok((Token::new((), input.position, 1), parser_state));
+                let length = next_char.len_utf8();
+                let parser_state = input.advance(length, next_char == '\n');
+                return Ok((Token::new((), input.position, length), parser_state));
             }
         }
 
         let actual = if !input.remaining.is_empty() {
-            &input.remaining[0..1]
+            pactual(input)
         } else {
             " "
         };
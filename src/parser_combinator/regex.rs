@@ -0,0 +1,440 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    iter::Peekable,
+    str::CharIndices,
+};
+
+use super::CharClass;
+
+/// A small regular expression engine used by `pregex`.
+///
+/// Supports literals, `.`, character classes (`[a-z_]`, `[^0-9]`), the escapes
+/// `\d \w \s \D \W \S \n \r \t`, groups (`(...)`, `(?:...)`), alternation and the
+/// quantifiers `* + ? {n} {n,} {n,m}`, with counts of at most 1000. Matches are anchored
+/// at the start of the input and always the longest possible.
+/// ```
+/// use ngl::parser_combinator::Regex;
+///
+/// let regex = Regex::new("[0-9]+(\\.[0-9]+)?").unwrap();
+/// assert_eq!(regex.match_len("3.14 rest"), Some(4));
+/// assert_eq!(regex.match_len("x"), None);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Regex {
+    program: Vec<Inst>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegexError {
+    pub message: &'static str,
+    pub position: usize,
+}
+
+impl Display for RegexError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+//the largest count allowed in `{n}`, `{n,}` and `{n,m}`
+const MAX_REPEAT: u32 = 1000;
+
+//the most instructions a pattern may compile to, since counted repeats are unrolled
+const MAX_PROGRAM: usize = 1 << 16;
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Class(CharClass),
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Char(char),
+    Class(CharClass),
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat(Box<Node>, u32, Option<u32>),
+}
+
+struct RegexParser<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    length: usize,
+}
+
+impl<'a> RegexParser<'a> {
+    fn position(&mut self) -> usize {
+        self.chars.peek().map_or(self.length, |(i, _)| *i)
+    }
+
+    fn error<T>(&mut self, message: &'static str) -> Result<T, RegexError> {
+        let position = self.position();
+        Err(RegexError { message, position })
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.chars.peek().map(|(_, next)| *next) == Some(c) {
+            self.chars.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn alternation(&mut self) -> Result<Node, RegexError> {
+        let mut alternatives = vec![self.concat()?];
+        while self.eat('|') {
+            alternatives.push(self.concat()?);
+        }
+        Ok(if alternatives.len() == 1 {
+            alternatives.remove(0)
+        } else {
+            Node::Alternate(alternatives)
+        })
+    }
+
+    fn concat(&mut self) -> Result<Node, RegexError> {
+        let mut nodes = Vec::new();
+        while let Some((_, c)) = self.chars.peek() {
+            if *c == '|' || *c == ')' {
+                break;
+            }
+            nodes.push(self.repeat()?);
+        }
+        Ok(Node::Concat(nodes))
+    }
+
+    fn repeat(&mut self) -> Result<Node, RegexError> {
+        let mut node = self.atom()?;
+        loop {
+            let (min, max) = if self.eat('*') {
+                (0, None)
+            } else if self.eat('+') {
+                (1, None)
+            } else if self.eat('?') {
+                (0, Some(1))
+            } else if self.eat('{') {
+                self.counted()?
+            } else {
+                return Ok(node);
+            };
+            node = Node::Repeat(Box::new(node), min, max);
+        }
+    }
+
+    fn counted(&mut self) -> Result<(u32, Option<u32>), RegexError> {
+        let position = self.position();
+        let min = self.number()?;
+        let max = if self.eat(',') {
+            match self.chars.peek() {
+                Some((_, '}')) => None,
+                _ => Some(self.number()?),
+            }
+        } else {
+            Some(min)
+        };
+        if !self.eat('}') {
+            return self.error("expected '}'");
+        }
+        match max {
+            Some(max) if max < min => self.error("invalid repetition range"),
+            _ if max.unwrap_or(min) > MAX_REPEAT => Err(RegexError {
+                message: "repetition count too large",
+                position,
+            }),
+            _ => Ok((min, max)),
+        }
+    }
+
+    fn number(&mut self) -> Result<u32, RegexError> {
+        let mut number: Option<u32> = None;
+        while let Some((_, c)) = self.chars.peek() {
+            match c.to_digit(10) {
+                Some(digit) => {
+                    let value = number
+                        .unwrap_or(0)
+                        .checked_mul(10)
+                        .and_then(|n| n.checked_add(digit));
+                    match value {
+                        Some(value) => number = Some(value),
+                        None => return self.error("repetition count too large"),
+                    }
+                    self.chars.next();
+                }
+                None => break,
+            }
+        }
+        match number {
+            Some(number) => Ok(number),
+            None => self.error("expected a number"),
+        }
+    }
+
+    fn atom(&mut self) -> Result<Node, RegexError> {
+        match self.chars.next() {
+            Some((_, '(')) => {
+                if self.eat('?') && !self.eat(':') {
+                    return self.error("expected ':'");
+                }
+                let node = self.alternation()?;
+                if !self.eat(')') {
+                    return self.error("expected ')'");
+                }
+                Ok(node)
+            }
+            Some((_, '[')) => self.class(),
+            Some((_, '.')) => Ok(Node::Class(!CharClass::char('\n'))),
+            Some((_, '\\')) => self.escape(),
+            Some((i, '*' | '+' | '?' | '{')) => Err(RegexError {
+                message: "nothing to repeat",
+                position: i,
+            }),
+            Some((_, c)) => Ok(Node::Char(c)),
+            None => self.error("unexpected end of pattern"),
+        }
+    }
+
+    fn escape(&mut self) -> Result<Node, RegexError> {
+        let class = match self.chars.next() {
+            Some((_, 'd')) => CharClass::digit(),
+            Some((_, 'D')) => !CharClass::digit(),
+            Some((_, 'w')) => CharClass::alnum() | CharClass::char('_'),
+            Some((_, 'W')) => !(CharClass::alnum() | CharClass::char('_')),
+            Some((_, 's')) => CharClass::whitespace(),
+            Some((_, 'S')) => !CharClass::whitespace(),
+            Some((_, c)) => return Ok(Node::Char(escaped(c))),
+            None => return self.error("unexpected end of pattern"),
+        };
+        Ok(Node::Class(class))
+    }
+
+    fn class_char(&mut self) -> Result<char, RegexError> {
+        match self.chars.next() {
+            Some((_, '\\')) => match self.chars.next() {
+                Some((_, c)) => Ok(escaped(c)),
+                None => self.error("unexpected end of pattern"),
+            },
+            Some((_, c)) => Ok(c),
+            None => self.error("unterminated character class"),
+        }
+    }
+
+    fn class(&mut self) -> Result<Node, RegexError> {
+        let negated = self.eat('^');
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            match self.chars.peek() {
+                Some((_, ']')) if !first => {
+                    self.chars.next();
+                    break;
+                }
+                None => return self.error("unterminated character class"),
+                _ => {}
+            }
+            first = false;
+            let start = self.class_char()?;
+            let is_range = self.chars.peek().map(|(_, c)| *c) == Some('-')
+                && self.chars.clone().nth(1).is_some_and(|(_, c)| c != ']');
+            if is_range {
+                self.chars.next();
+                let end = self.class_char()?;
+                if end < start {
+                    return self.error("invalid class range");
+                }
+                ranges.push(start..=end);
+            } else {
+                ranges.push(start..=start);
+            }
+        }
+        let class = CharClass::ranges(&ranges);
+        Ok(Node::Class(if negated { !class } else { class }))
+    }
+}
+
+fn escaped(c: char) -> char {
+    match c {
+        'n' => '\n',
+        'r' => '\r',
+        't' => '\t',
+        '0' => '\0',
+        c => c,
+    }
+}
+
+//the number of instructions a node compiles to, saturating rather than overflowing
+fn size(node: &Node) -> usize {
+    match node {
+        Node::Char(_) | Node::Class(_) => 1,
+        Node::Concat(nodes) => nodes.iter().map(size).fold(0, usize::saturating_add),
+        Node::Alternate(nodes) => {
+            let jumps = 2 * (nodes.len() - 1);
+            nodes.iter().map(size).fold(jumps, usize::saturating_add)
+        }
+        Node::Repeat(node, min, max) => {
+            let size = size(node);
+            let optional = match max {
+                None => size.saturating_add(2),
+                Some(max) => size.saturating_add(1).saturating_mul((max - min) as usize),
+            };
+            size.saturating_mul(*min as usize).saturating_add(optional)
+        }
+    }
+}
+
+fn compile(node: &Node, program: &mut Vec<Inst>) {
+    match node {
+        Node::Char(c) => program.push(Inst::Char(*c)),
+        Node::Class(class) => program.push(Inst::Class(class.clone())),
+        Node::Concat(nodes) => {
+            for node in nodes {
+                compile(node, program);
+            }
+        }
+        Node::Alternate(nodes) => {
+            let mut jumps = Vec::new();
+            for (i, node) in nodes.iter().enumerate() {
+                if i == nodes.len() - 1 {
+                    compile(node, program);
+                } else {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(node, program);
+                    jumps.push(program.len());
+                    program.push(Inst::Jump(0));
+                    program[split] = Inst::Split(split + 1, program.len());
+                }
+            }
+            let end = program.len();
+            for jump in jumps {
+                program[jump] = Inst::Jump(end);
+            }
+        }
+        Node::Repeat(node, min, max) => {
+            for _ in 0..*min {
+                compile(node, program);
+            }
+            match max {
+                None => {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(node, program);
+                    program.push(Inst::Jump(split));
+                    program[split] = Inst::Split(split + 1, program.len());
+                }
+                Some(max) => {
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Split(program.len() + 1, 0));
+                        compile(node, program);
+                    }
+                    let end = program.len();
+                    for split in splits {
+                        program[split] = Inst::Split(split + 1, end);
+                    }
+                }
+            }
+        }
+    }
+}
+
+//program counters reachable without consuming input, each added at most once
+struct Threads {
+    pcs: Vec<usize>,
+    visited: Vec<usize>,
+    seen: Vec<bool>,
+}
+
+impl Threads {
+    fn new(size: usize) -> Self {
+        Self {
+            pcs: Vec::with_capacity(size),
+            visited: Vec::with_capacity(size),
+            seen: vec![false; size],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.pcs.clear();
+        for pc in self.visited.drain(..) {
+            self.seen[pc] = false;
+        }
+    }
+
+    fn add(&mut self, program: &[Inst], pc: usize) {
+        if self.seen[pc] {
+            return;
+        }
+        self.seen[pc] = true;
+        self.visited.push(pc);
+        match program[pc] {
+            Inst::Jump(target) => self.add(program, target),
+            Inst::Split(first, second) => {
+                self.add(program, first);
+                self.add(program, second);
+            }
+            _ => self.pcs.push(pc),
+        }
+    }
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Self, RegexError> {
+        let mut parser = RegexParser {
+            chars: pattern.char_indices().peekable(),
+            length: pattern.len(),
+        };
+        let node = parser.alternation()?;
+        if parser.chars.peek().is_some() {
+            return parser.error("unmatched ')'");
+        }
+        if size(&node) >= MAX_PROGRAM {
+            return Err(RegexError {
+                message: "pattern too large",
+                position: 0,
+            });
+        }
+        let mut program = Vec::new();
+        compile(&node, &mut program);
+        program.push(Inst::Match);
+        Ok(Self { program })
+    }
+
+    /// Length in bytes of the longest match at the start of `input`.
+    pub fn match_len(&self, input: &str) -> Option<usize> {
+        let program = &self.program;
+        let mut current = Threads::new(program.len());
+        let mut next = Threads::new(program.len());
+        let mut longest = None;
+        current.add(program, 0);
+
+        let mut chars = input.char_indices();
+        loop {
+            let next_char = chars.next();
+            let position = next_char.map_or(input.len(), |(i, _)| i);
+            for &pc in current.pcs.iter() {
+                let matched = match (&program[pc], next_char) {
+                    (Inst::Match, _) => {
+                        longest = Some(position);
+                        false
+                    }
+                    (Inst::Char(expected), Some((_, c))) => *expected == c,
+                    (Inst::Class(class), Some((_, c))) => class.contains(c),
+                    _ => false,
+                };
+                if matched {
+                    next.add(program, pc + 1);
+                }
+            }
+            if next.pcs.is_empty() {
+                return longest;
+            }
+            current.clear();
+            std::mem::swap(&mut current, &mut next);
+        }
+    }
+}
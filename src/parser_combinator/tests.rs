@@ -487,7 +487,6 @@ fn test_psepby() {
     assert_eq!(result, expected);
 }
 
-/*
 #[test]
 fn test_psepby_no_input() {
     let parser = pchar('1').sep_by(pchar(','));
@@ -507,7 +506,6 @@ fn test_psepby_no_input() {
     ));
     assert_eq!(result, expected);
 }
-*/
 
 #[test]
 fn test_psepby_missing_trail() {
@@ -516,3 +514,406 @@ fn test_psepby_missing_trail() {
     let expected = Err(Error::new(Expected::Char('1'), "", 4, 0, 4));
     assert_eq!(result, expected);
 }
+
+#[test]
+fn test_pclass_success() {
+    let parser = palpha();
+    let result = parser.parse("b1".into());
+    let expected = Ok((
+        Token {
+            value: 'b',
+            start: 0,
+            length: 1,
+        },
+        ContinuationState {
+            remaining: "1",
+            position: 1,
+            line_number: 0,
+            line_position: 1,
+        },
+    ));
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_pclass_fail() {
+    let parser = pdigit();
+    let result = parser.parse("a".into());
+    let expected = Err(Error::new(Expected::Class("digit".into()), "a", 0, 0, 0));
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_pclass_union_and_negation() {
+    let class = CharClass::alpha() | CharClass::chars(&['_', '$']);
+    assert!(class.contains('$'));
+    assert!(class.contains('Z'));
+    assert!(!class.contains('1'));
+
+    let not_class = !class;
+    assert!(not_class.contains('1'));
+    assert!(not_class.contains('é'));
+    assert!(!not_class.contains('_'));
+    assert_eq!(
+        not_class.description().as_ref(),
+        "anything but letter or '_' or '$'"
+    );
+}
+
+#[test]
+fn test_pclass_ranges_merge() {
+    let class = CharClass::ranges(&['d'..='f', 'a'..='c', 'α'..='ω']);
+    assert!(class.contains('a'));
+    assert!(class.contains('f'));
+    assert!(class.contains('β'));
+    assert!(!class.contains('g'));
+    assert!(!class.contains('Ω'));
+}
+
+#[test]
+fn test_pxid_unicode() {
+    let parser = pxid_start().then(pxid_continue().many());
+    let result = parser.parse("δx1 ".into()).unwrap();
+    assert_eq!(result.0.value.0.value, 'δ');
+    assert_eq!(result.0.value.1.value.len(), 2);
+    assert_eq!(result.1.remaining, " ");
+    assert_eq!(result.1.position, 4);
+}
+
+#[test]
+fn test_pxid_fail_multibyte() {
+    let parser = pxid_start();
+    let result = parser.parse("€".into());
+    let expected = Err(Error::new(
+        Expected::Class("identifier start".into()),
+        "€",
+        0,
+        0,
+        0,
+    ));
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_psatisfy() {
+    let parser = psatisfy(|c| c.is_ascii_punctuation());
+    assert_eq!(parser.parse("!".into()).unwrap().0.value, '!');
+    assert!(parser.parse("a".into()).is_err());
+}
+
+#[test]
+fn test_ptake_while() {
+    let parser = ptake_while(CharClass::digit());
+    let result = parser.parse("123abc".into());
+    let expected = Ok((
+        Token {
+            value: "123",
+            start: 0,
+            length: 3,
+        },
+        ContinuationState {
+            remaining: "abc",
+            position: 3,
+            line_number: 0,
+            line_position: 3,
+        },
+    ));
+    assert_eq!(result, expected);
+
+    let result = parser.parse("abc".into()).unwrap();
+    assert_eq!(result.0.value, "");
+}
+
+#[test]
+fn test_pregex_success() {
+    let parser = pregex("[0-9]+(\\.[0-9]+)?([eE][+-]?\\d+)?");
+    let result = parser.parse("1.5e-3;".into());
+    let expected = Ok((
+        Token {
+            value: "1.5e-3",
+            start: 0,
+            length: 6,
+        },
+        ContinuationState {
+            remaining: ";",
+            position: 6,
+            line_number: 0,
+            line_position: 6,
+        },
+    ));
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_pregex_longest_alternative() {
+    let parser = pregex("a|ab|abc");
+    assert_eq!(parser.parse("abcd".into()).unwrap().0.value, "abc");
+}
+
+#[test]
+fn test_pregex_counted_repetition() {
+    let parser = pregex("x{2,3}");
+    assert_eq!(parser.parse("xxxxx".into()).unwrap().0.value, "xxx");
+    assert!(parser.parse("x".into()).is_err());
+    assert_eq!(pregex("x{2}").parse("xxx".into()).unwrap().0.value, "xx");
+    assert_eq!(pregex("x{1,}").parse("xxx".into()).unwrap().0.value, "xxx");
+}
+
+#[test]
+fn test_pregex_negated_class_spans_lines() {
+    let parser = pregex("[^;]*");
+    let result = parser.parse("a\nbc;".into()).unwrap();
+    assert_eq!(result.0.value, "a\nbc");
+    assert_eq!(result.1.line_number, 1);
+    assert_eq!(result.1.line_position, 2);
+}
+
+#[test]
+fn test_pregex_fail() {
+    let parser = pregex("[a-z]+");
+    let result = parser.parse("1".into());
+    let expected = Err(Error::new(Expected::Regex("[a-z]+"), "1", 0, 0, 0));
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_pregex_empty_loop() {
+    let parser = pregex("(a*)*b");
+    assert_eq!(parser.parse("aab".into()).unwrap().0.value, "aab");
+}
+
+#[test]
+fn test_regex_invalid() {
+    assert!(Regex::new("(ab").is_err());
+    assert!(Regex::new("ab)").is_err());
+    assert!(Regex::new("[ab").is_err());
+    assert!(Regex::new("*a").is_err());
+    assert!(Regex::new("a{3,1}").is_err());
+    assert_eq!(
+        Regex::new("a{").unwrap_err(),
+        RegexError {
+            message: "expected a number",
+            position: 2,
+        }
+    );
+    assert_eq!(
+        Regex::new("ab{100000000}").unwrap_err(),
+        RegexError {
+            message: "repetition count too large",
+            position: 3,
+        }
+    );
+    assert!(Regex::new("a{2,1001}").is_err());
+    assert!(Regex::new("a{1000}").is_ok());
+    assert_eq!(
+        Regex::new("((a{1000}){1000}){1000}").unwrap_err(),
+        RegexError {
+            message: "pattern too large",
+            position: 0,
+        }
+    );
+}
+
+#[test]
+fn test_pkeyword_success() {
+    let parser = pkeyword("if");
+    let result = parser.parse("if(x)".into());
+    let expected = Ok((
+        Token {
+            value: "if",
+            start: 0,
+            length: 2,
+        },
+        ContinuationState {
+            remaining: "(x)",
+            position: 2,
+            line_number: 0,
+            line_position: 2,
+        },
+    ));
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_pkeyword_identifier_prefix() {
+    let parser = pkeyword("if");
+    let result = parser.parse("iffy".into());
+    let expected = Err(Error::new("if".into(), "iff", 2, 0, 2));
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_pkeyword_eof() {
+    let parser = pkeyword("if");
+    assert_eq!(parser.parse("if".into()).unwrap().0.value, "if");
+}
+
+#[test]
+fn test_pstring_ci_success() {
+    let parser = pstring_ci("Hello");
+    let result = parser.parse("hELLO world".into());
+    let expected = Ok((
+        Token {
+            value: "hELLO",
+            start: 0,
+            length: 5,
+        },
+        ContinuationState {
+            remaining: " world",
+            position: 5,
+            line_number: 0,
+            line_position: 5,
+        },
+    ));
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_pstring_ci_fail() {
+    let parser = pstring_ci("Hello");
+    let result = parser.parse("HELP".into());
+    let expected = Err(Error::new("Hello".into(), "HELP", 3, 0, 3));
+    assert_eq!(result, expected);
+
+    let result = parser.parse("HEL".into());
+    let expected = Err(Error::new("Hello".into(), "HEL", 3, 0, 3));
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_pstring_ci_unicode() {
+    let parser = pstring_ci("straße");
+    assert_eq!(parser.parse("STRAßE".into()).unwrap().0.value, "STRAßE");
+}
+
+#[test]
+fn test_pone_of_strings_longest() {
+    let parser = pone_of_strings(&["=", "==", "=>", "!="]);
+    let result = parser.parse("==>".into());
+    let expected = Ok((
+        Token {
+            value: "==",
+            start: 0,
+            length: 2,
+        },
+        ContinuationState {
+            remaining: ">",
+            position: 2,
+            line_number: 0,
+            line_position: 2,
+        },
+    ));
+    assert_eq!(result, expected);
+    assert_eq!(parser.parse("=x".into()).unwrap().0.value, "=");
+}
+
+#[test]
+fn test_pone_of_strings_partial_match_backtracks() {
+    let parser = pone_of_strings(&["a", "abcd"]);
+    assert_eq!(parser.parse("abc".into()).unwrap().0.value, "a");
+}
+
+#[test]
+fn test_pone_of_strings_fail() {
+    let parser = pone_of_strings(&["let", "fun", "for"]);
+    let result = parser.parse("if".into());
+    let expected = Err(Error::new(
+        Expected::Or(
+            Box::new(Expected::Or(Box::new("let".into()), Box::new("fun".into()))),
+            Box::new("for".into()),
+        ),
+        "i",
+        0,
+        0,
+        0,
+    ));
+    assert_eq!(result, expected);
+    assert_eq!(
+        result.unwrap_err().expected.to_string(),
+        "'let' or 'fun' or 'for'"
+    );
+}
+
+#[test]
+fn test_take_until_unterminated() {
+    let parser = pchar('"').take_until();
+    let result = parser.parse("abc".into());
+    let expected = Err(Error::new(Expected::Char('"'), "", 3, 0, 3));
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_pchar_multibyte() {
+    let parser = pchar('é').then(pchar('a'));
+    let result = parser.parse("éb".into());
+    let expected = Err(Error::new(Expected::Char('a'), "b", 2, 0, 2));
+    assert_eq!(result, expected);
+
+    let result = pchar('a').parse("é".into());
+    let expected = Err(Error::new(Expected::Char('a'), "é", 0, 0, 0));
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_pstring_multibyte_wrong_letter() {
+    let parser = pstring("ab");
+    let result = parser.parse("aé".into());
+    let expected = Err(Error::new("ab".into(), "aé", 1, 0, 1));
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_pmany_zero_width() {
+    let parser = pchar('a').optional().many();
+    let result = parser.parse("aab".into()).unwrap();
+    assert_eq!(result.0.value.len(), 3);
+    assert_eq!(result.1.remaining, "b");
+}
+
+mod no_panic_properties {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn primitives_never_panic(input in "\\PC*") {
+            let input = input.as_str();
+            let _ = pchar('é').parse(input.into());
+            let _ = pstring("héllo").parse(input.into());
+            let _ = pany(&['a', 'ß', '\n']).parse(input.into());
+            let _ = pany_range('a'..='z').parse(input.into());
+            let _ = pws().many().parse(input.into());
+            let _ = pclass(!CharClass::alpha()).parse(input.into());
+            let _ = pxid_start().then(pxid_continue().many()).parse(input.into());
+            let _ = psatisfy(|c| c.is_uppercase()).parse(input.into());
+            let _ = ptake_while(CharClass::alnum()).parse(input.into());
+            let _ = pregex("[^x]*x|(ab)+").parse(input.into());
+            let _ = pkeyword("if").parse(input.into());
+            let _ = pstring_ci("straße").parse(input.into());
+            let _ = pone_of_strings(&["<", "<=", "é"]).parse(input.into());
+            let _ = pchar('"').take_until().parse(input.into());
+            let _ = pchar('a').sep_by(pchar(',')).parse(input.into());
+            let _ = pdigit().many1().between(pchar('('), pchar(')')).parse(input.into());
+        }
+
+        #[test]
+        fn regex_never_panics(pattern in "\\PC{0,12}", input in "\\PC{0,16}") {
+            if let Ok(regex) = Regex::new(&pattern) {
+                let _ = regex.match_len(&input);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_peof() {
+    let parser = pchar('a').then(peof()).left();
+    let result = parser.parse("ab".into());
+    let expected = Err(Error::new(Expected::EndOfInput, "b", 1, 0, 1));
+    assert_eq!(result, expected);
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "Expected end of input but got b at line: 1, column: 2"
+    );
+    assert!(parser.parse("a".into()).is_ok());
+}
@@ -1,3 +1,5 @@
+use std::ops::Range;
+
 #[derive(Debug, PartialEq)]
 pub struct Token<T> {
     pub value: T,
@@ -13,6 +15,11 @@ impl<T> Token<T> {
             length,
         }
     }
+
+    /// The source the token was parsed from.
+    pub fn span(&self) -> Range<usize> {
+        self.start..self.start + self.length
+    }
 }
 
 impl<T: Clone> Clone for Token<T> {
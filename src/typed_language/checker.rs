@@ -0,0 +1,1090 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    ops::Range,
+};
+
+use crate::{
+    parser_combinator::Token,
+    untyped_language::{literal_parser::TO_STRING, *},
+};
+
+use super::*;
+
+/// A type error. Spans are byte ranges of the offending expression, pattern or type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeError {
+    UnknownType(String, Range<usize>),
+    RecursiveAlias(String, Range<usize>),
+    Mismatch(Type, Type, Range<usize>), //expected, found, expression
+    ArityMismatch(usize, usize, Range<usize>), //expected, found, call or variant
+    NotCallable(Type, Range<usize>),
+    ConditionNotBool(Type, Range<usize>),
+    BranchMismatch(Type, Type, Range<usize>), //first branch, other branch, if or match
+    NonIntegerBound(Type, Range<usize>),
+    InvalidOperand(&'static str, Type, Range<usize>), //operator, operand type, operand
+    UnknownField(Type, String, Range<usize>),         //also unknown variants
+    NotIndexable(Type, Range<usize>),
+    NotIterable(Type, Range<usize>),
+    PatternMismatch(Type, Range<usize>), //scrutinee type, pattern
+    Conflict(Type, Range<usize>, Type, Range<usize>), //inferred type, where, found type, where
+    InfiniteType(Type, Type, Range<usize>), //variable, type containing it, expression
+}
+
+impl Display for TypeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            TypeError::UnknownType(name, span) => write!(f, "unknown type {} at {:?}", name, span),
+            TypeError::RecursiveAlias(name, span) => {
+                write!(f, "type alias {} refers to itself at {:?}", name, span)
+            }
+            TypeError::Mismatch(expected, found, span) => {
+                write!(f, "expected {} but found {} at {:?}", expected, found, span)
+            }
+            TypeError::ArityMismatch(expected, found, span) => write!(
+                f,
+                "expected {} arguments but found {} at {:?}",
+                expected, found, span
+            ),
+            TypeError::NotCallable(type_, span) => {
+                write!(f, "cannot call a value of type {} at {:?}", type_, span)
+            }
+            TypeError::ConditionNotBool(type_, span) => {
+                write!(f, "condition at {:?} is {} rather than bool", span, type_)
+            }
+            TypeError::BranchMismatch(first, other, span) => write!(
+                f,
+                "branches at {:?} have different types {} and {}",
+                span, first, other
+            ),
+            TypeError::NonIntegerBound(type_, span) => {
+                write!(
+                    f,
+                    "range bound at {:?} is {} rather than an integer",
+                    span, type_
+                )
+            }
+            TypeError::InvalidOperand(op, type_, span) => {
+                write!(f, "cannot apply {} to {} at {:?}", op, type_, span)
+            }
+            TypeError::UnknownField(type_, name, span) => {
+                write!(f, "{} has no member {} at {:?}", type_, name, span)
+            }
+            TypeError::NotIndexable(type_, span) => {
+                write!(f, "cannot index into {} at {:?}", type_, span)
+            }
+            TypeError::NotIterable(type_, span) => {
+                write!(f, "cannot iterate over {} at {:?}", type_, span)
+            }
+            TypeError::PatternMismatch(type_, span) => {
+                write!(
+                    f,
+                    "pattern at {:?} cannot match a value of type {}",
+                    span, type_
+                )
+            }
+            TypeError::Conflict(expected, expected_span, found, span) => write!(
+                f,
+                "expected {} as inferred at {:?} but found {} at {:?}",
+                expected, expected_span, found, span
+            ),
+            TypeError::InfiniteType(var, type_, span) => write!(
+                f,
+                "cannot construct the infinite type {} = {} at {:?}",
+                var, type_, span
+            ),
+        }
+    }
+}
+
+impl TypeError {
+    fn map_types(self, f: impl Fn(&Type) -> Type) -> Self {
+        match self {
+            TypeError::Mismatch(expected, found, span) => {
+                TypeError::Mismatch(f(&expected), f(&found), span)
+            }
+            TypeError::NotCallable(type_, span) => TypeError::NotCallable(f(&type_), span),
+            TypeError::ConditionNotBool(type_, span) => {
+                TypeError::ConditionNotBool(f(&type_), span)
+            }
+            TypeError::BranchMismatch(first, other, span) => {
+                TypeError::BranchMismatch(f(&first), f(&other), span)
+            }
+            TypeError::NonIntegerBound(type_, span) => TypeError::NonIntegerBound(f(&type_), span),
+            TypeError::InvalidOperand(op, type_, span) => {
+                TypeError::InvalidOperand(op, f(&type_), span)
+            }
+            TypeError::UnknownField(type_, name, span) => {
+                TypeError::UnknownField(f(&type_), name, span)
+            }
+            TypeError::NotIndexable(type_, span) => TypeError::NotIndexable(f(&type_), span),
+            TypeError::NotIterable(type_, span) => TypeError::NotIterable(f(&type_), span),
+            TypeError::PatternMismatch(type_, span) => TypeError::PatternMismatch(f(&type_), span),
+            TypeError::Conflict(expected, expected_span, found, span) => {
+                TypeError::Conflict(f(&expected), expected_span, f(&found), span)
+            }
+            TypeError::InfiniteType(var, type_, span) => {
+                TypeError::InfiniteType(var, f(&type_), span)
+            }
+            error => error,
+        }
+    }
+}
+
+/// The type inferred for a `let` binding, a parameter or a pattern binding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BindingType {
+    pub name: String,
+    pub binding: Range<usize>,
+    pub type_: Type,
+}
+
+/// The result of checking a program: the types of its bindings, in the order they are
+/// bound, and the errors found.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TypeCheck {
+    pub bindings: Vec<BindingType>,
+    pub errors: Vec<TypeError>,
+}
+
+struct LoopScope<'t> {
+    label: Option<&'t str>,
+    breaks: Option<Type>, //the type of the first break, and so of a `loop`
+}
+
+#[derive(Default)]
+struct Checker<'t> {
+    alias_targets: HashMap<&'t str, &'t Token<TypeExpr>>,
+    aliases: HashMap<&'t str, Type>,
+    resolving: Vec<&'t str>, //aliases being resolved, to catch cycles
+    structs: HashMap<&'t str, Vec<(&'t str, Type)>>,
+    enums: HashMap<&'t str, Vec<(&'t str, Vec<Type>)>>,
+    globals: HashMap<&'t str, Type>,
+    fun_generics: HashMap<&'t str, Vec<&'t str>>,
+    generics: Vec<&'t str>,           //type parameters in scope
+    bindings: Vec<(&'t str, Scheme)>, //innermost last
+    returns: Vec<Type>,               //return type of each enclosing function or closure
+    loops: Vec<LoopScope<'t>>,
+    types: Substitution,
+    check: TypeCheck,
+}
+
+//replaces the type parameters of a generic function with the types they take in one use
+fn substitute(type_: &Type, instantiation: &HashMap<&str, Type>) -> Type {
+    let substitute_all = |types: &[Type]| {
+        types
+            .iter()
+            .map(|type_| substitute(type_, instantiation))
+            .collect()
+    };
+    match type_ {
+        Type::Param(name) => instantiation
+            .get(name.as_str())
+            .cloned()
+            .unwrap_or_else(|| type_.clone()),
+        Type::Tuple(types) => Type::Tuple(substitute_all(types)),
+        Type::Array(element, length) => {
+            Type::Array(Box::new(substitute(element, instantiation)), *length)
+        }
+        Type::Range(bound) => Type::Range(Box::new(substitute(bound, instantiation))),
+        Type::Function(params, ret) => Type::Function(
+            substitute_all(params),
+            Box::new(substitute(ret, instantiation)),
+        ),
+        type_ => type_.clone(),
+    }
+}
+
+//the known one of two types, preferring the first
+fn known(first: Type, other: Type) -> Type {
+    match first {
+        Type::Unknown => other,
+        first => first,
+    }
+}
+
+impl<'t> Checker<'t> {
+    //the types inferred by the end of checking replace the variables standing for them
+    fn finish(self) -> TypeCheck {
+        let types = self.types;
+        let bindings = self.check.bindings.into_iter().map(|binding| BindingType {
+            type_: types.apply(&binding.type_),
+            ..binding
+        });
+        let errors = self.check.errors.into_iter();
+        TypeCheck {
+            bindings: bindings.collect(),
+            errors: errors
+                .map(|error| error.map_types(|type_| types.apply(type_)))
+                .collect(),
+        }
+    }
+
+    fn error(&mut self, error: TypeError) {
+        self.check.errors.push(error);
+    }
+
+    //unifies two types, reporting infinite types; false if they conflict
+    fn unify(&mut self, expected: &Type, found: &Type, span: &Range<usize>) -> bool {
+        match self.types.unify(expected, found, span.clone()) {
+            Ok(()) => true,
+            Err(UnifyError::Mismatch) => false,
+            Err(UnifyError::Infinite(var, type_)) => {
+                self.error(TypeError::InfiniteType(Type::Var(var), type_, span.clone()));
+                true
+            }
+        }
+    }
+
+    //a conflict with an inferred type is reported along with where it was inferred
+    fn expect(&mut self, expected: &Type, found: Type, span: Range<usize>) {
+        let origin = self.types.origin(expected);
+        if self.unify(expected, &found, &span) {
+            return;
+        }
+        let expected = self.types.apply(expected);
+        let found = self.types.apply(&found);
+        match origin {
+            Some(origin) => self.error(TypeError::Conflict(expected, origin, found, span)),
+            None => self.error(TypeError::Mismatch(expected, found, span)),
+        }
+    }
+
+    //an integer, defaulting to int if not yet inferred
+    fn integer(&mut self, type_: &Type, span: &Range<usize>) -> bool {
+        if let Type::Var(_) = self.types.prune(type_) {
+            self.unify(&Type::Int, type_, span);
+        }
+        self.types.prune(type_).is_integer()
+    }
+
+    fn alias(&mut self, name: &'t str, span: Range<usize>) -> Type {
+        if let Some(type_) = self.aliases.get(name) {
+            return type_.clone();
+        }
+        if self.resolving.contains(&name) {
+            self.error(TypeError::RecursiveAlias(name.to_string(), span));
+            return Type::Unknown;
+        }
+        self.resolving.push(name);
+        let type_ = self.resolve(self.alias_targets[name]);
+        self.resolving.pop();
+        self.aliases.insert(name, type_.clone());
+        type_
+    }
+
+    fn resolve(&mut self, type_: &'t Token<TypeExpr>) -> Type {
+        match &type_.value {
+            TypeExpr::Named(name) => {
+                if self.generics.contains(&name.as_str()) {
+                    Type::Param(name.clone())
+                } else if let Some(builtin) = Type::builtin(name) {
+                    builtin
+                } else if self.alias_targets.contains_key(name.as_str()) {
+                    self.alias(name, type_.span())
+                } else if self.structs.contains_key(name.as_str()) {
+                    Type::Struct(name.clone())
+                } else if self.enums.contains_key(name.as_str()) {
+                    Type::Enum(name.clone())
+                } else {
+                    self.error(TypeError::UnknownType(name.clone(), type_.span()));
+                    Type::Unknown
+                }
+            }
+            //there are no generic types to apply yet
+            TypeExpr::Applied(name, _) => {
+                self.error(TypeError::UnknownType(name.value.clone(), name.span()));
+                Type::Unknown
+            }
+            TypeExpr::Function(params, ret) => {
+                let params = params.iter().map(|param| self.resolve(param)).collect();
+                Type::Function(params, Box::new(self.resolve(ret)))
+            }
+            TypeExpr::Tuple(types) if types.is_empty() => Type::Unit,
+            TypeExpr::Tuple(types) => {
+                Type::Tuple(types.iter().map(|type_| self.resolve(type_)).collect())
+            }
+            TypeExpr::Array(element, length) => {
+                Type::Array(Box::new(self.resolve(element)), *length)
+            }
+        }
+    }
+
+    fn signature(&mut self, fun: &'t Fun) -> Type {
+        self.generics = fun
+            .generics
+            .iter()
+            .map(|g| g.value.0.value.as_str())
+            .collect();
+        let params = fun
+            .params
+            .iter()
+            .map(|param| self.resolve(&param.value.1))
+            .collect();
+        let ret = self.resolve(&fun.return_type);
+        self.generics.clear();
+        Type::Function(params, Box::new(ret))
+    }
+
+    fn declare_fun(&mut self, fun: &'t Fun) -> Type {
+        let signature = self.signature(fun);
+        let generics = fun.generics.iter().map(|g| g.value.0.value.as_str());
+        self.fun_generics
+            .insert(&fun.name.value, generics.collect());
+        self.globals.insert(&fun.name.value, signature.clone());
+        signature
+    }
+
+    fn bind(&mut self, name: &'t str, type_: Type, span: Range<usize>) {
+        self.bind_scheme(name, Scheme::mono(type_), span);
+    }
+
+    fn bind_scheme(&mut self, name: &'t str, scheme: Scheme, span: Range<usize>) {
+        self.check.bindings.push(BindingType {
+            name: name.to_string(),
+            binding: span,
+            type_: scheme.type_.clone(),
+        });
+        self.bindings.push((name, scheme));
+    }
+
+    //generalises over the variables not fixed by anything in scope
+    fn generalize(&self, type_: Type) -> Scheme {
+        let mut env = Vec::new();
+        for (_, scheme) in &self.bindings {
+            let mut vars = Vec::new();
+            self.types.free_vars(&scheme.type_, &mut vars);
+            env.extend(vars.into_iter().filter(|var| !scheme.vars.contains(var)));
+        }
+        let breaks = self.loops.iter().filter_map(|scope| scope.breaks.as_ref());
+        for type_ in self.returns.iter().chain(breaks) {
+            self.types.free_vars(type_, &mut env);
+        }
+        self.types.generalize(type_, &env)
+    }
+
+    //each use of a generic function or a generalised binding gets fresh type variables,
+    //and names that are not bound anywhere are left to name resolution
+    fn lookup(&mut self, name: &str) -> Type {
+        if let Some((_, scheme)) = self.bindings.iter().rev().find(|(bound, _)| *bound == name) {
+            let scheme = scheme.clone();
+            return self.types.instantiate(&scheme);
+        }
+        match self.globals.get(name).cloned() {
+            Some(type_) => {
+                let generics = self.fun_generics.get(name).cloned().unwrap_or_default();
+                let instantiation = generics
+                    .into_iter()
+                    .map(|generic| (generic, self.types.fresh()))
+                    .collect();
+                substitute(&type_, &instantiation)
+            }
+            None if name == TO_STRING => {
+                Type::Function(vec![self.types.fresh()], Box::new(Type::String))
+            }
+            None => Type::Unknown,
+        }
+    }
+
+    fn fun(&mut self, fun: &'t Fun, signature: &Type) {
+        let Type::Function(params, ret) = signature else {
+            return;
+        };
+        self.generics = fun
+            .generics
+            .iter()
+            .map(|g| g.value.0.value.as_str())
+            .collect();
+        let scope = self.bindings.len();
+        for (param, type_) in fun.params.iter().zip(params) {
+            self.bind(&param.value.0.value, type_.clone(), param.span());
+        }
+        self.fun_body(&fun.body, ret);
+        self.bindings.truncate(scope);
+        self.generics.clear();
+    }
+
+    //a unit function may end with an expression of any type
+    fn fun_body(&mut self, body: &'t [Token<ExprOrStatement>], ret: &Type) -> Type {
+        self.returns.push(ret.clone());
+        let type_ = self.body(body);
+        self.returns.pop();
+        if let Some(last) = body.last() {
+            if matches!(last.value, ExprOrStatement::Expr(_)) && self.types.prune(ret) != Type::Unit
+            {
+                self.expect(ret, type_.clone(), last.span());
+            }
+        }
+        type_
+    }
+
+    //the type of the last item if it is an expression, otherwise unit
+    fn body(&mut self, body: &'t [Token<ExprOrStatement>]) -> Type {
+        let scope = self.bindings.len();
+        let mut type_ = Type::Unit;
+        for item in body {
+            type_ = match &item.value {
+                ExprOrStatement::Expr(expr) => self.expr_at(expr, item.span()),
+                ExprOrStatement::Statement(statement) => {
+                    self.statement(statement, item.span());
+                    Type::Unit
+                }
+            };
+        }
+        self.bindings.truncate(scope);
+        type_
+    }
+
+    fn condition(&mut self, condition: &'t Token<Expr>) {
+        let type_ = self.expr(condition);
+        if !self.unify(&Type::Bool, &type_, &condition.span()) {
+            self.error(TypeError::ConditionNotBool(type_, condition.span()));
+        }
+    }
+
+    fn bound(&mut self, bound: &'t Token<Expr>) -> Type {
+        let type_ = self.expr(bound);
+        if !self.integer(&type_, &bound.span()) {
+            self.error(TypeError::NonIntegerBound(type_, bound.span()));
+            return Type::Unknown;
+        }
+        type_
+    }
+
+    fn looped(
+        &mut self,
+        label: &'t Option<Token<String>>,
+        body: &'t [Token<ExprOrStatement>],
+    ) -> Option<Type> {
+        self.loops.push(LoopScope {
+            label: label.as_ref().map(|label| label.value.as_str()),
+            breaks: None,
+        });
+        self.body(body);
+        self.loops.pop().and_then(|scope| scope.breaks)
+    }
+
+    fn statement(&mut self, statement: &'t Statement, span: Range<usize>) {
+        match statement {
+            Statement::Let(name, annotation, value, mutable) => {
+                let mut type_ = self.expr(value);
+                if let Some(annotation) = annotation {
+                    let annotated = self.resolve(annotation);
+                    self.expect(&annotated, type_, value.span());
+                    type_ = annotated;
+                }
+                //only closures are generalised, as a mutable binding must keep one type
+                let scheme = match value.value {
+                    Expr::Closure(_) if !mutable => self.generalize(type_),
+                    _ => Scheme::mono(type_),
+                };
+                self.bind_scheme(&name.value, scheme, span);
+            }
+            Statement::Assign(place, operator, value) => {
+                let place_type = self.expr(place);
+                let value_type = self.expr(value);
+                let value_type = match operator {
+                    Some(operator) => {
+                        let operands = (place_type.clone(), value_type);
+                        self.binary(operator.value, operands, place.span(), value.span())
+                    }
+                    None => value_type,
+                };
+                self.expect(&place_type, value_type, value.span());
+            }
+            Statement::For(label, name, range, body) => {
+                let range_type = self.expr(range);
+                let bound = match self.types.prune(&range_type) {
+                    Type::Range(bound) => *bound,
+                    Type::Var(_) => {
+                        let bound = Type::Range(Box::new(Type::Int));
+                        self.unify(&bound, &range_type, &range.span());
+                        Type::Int
+                    }
+                    Type::Unknown => Type::Unknown,
+                    type_ => {
+                        let error = TypeError::NonIntegerBound(type_, range.span());
+                        self.error(error);
+                        Type::Unknown
+                    }
+                };
+                let scope = self.bindings.len();
+                self.bind(&name.value, bound, name.span());
+                self.looped(label, body);
+                self.bindings.truncate(scope);
+            }
+            Statement::ForIn(label, name, iterable, body) => {
+                let iterable_type = self.expr(iterable);
+                //an array, range and string can all be iterated, so a variable is left unknown
+                let element = match self.types.prune(&iterable_type) {
+                    Type::Array(element, _) | Type::Range(element) => *element,
+                    Type::String => Type::Char,
+                    Type::Var(_) | Type::Unknown => Type::Unknown,
+                    type_ => {
+                        self.error(TypeError::NotIterable(type_, iterable.span()));
+                        Type::Unknown
+                    }
+                };
+                let scope = self.bindings.len();
+                self.bind(&name.value, element, name.span());
+                self.looped(label, body);
+                self.bindings.truncate(scope);
+            }
+            Statement::While(label, condition, body) => {
+                self.condition(condition);
+                self.looped(label, body);
+            }
+            Statement::Break(label, value) => {
+                let type_ = match value {
+                    Some(value) => self.expr(value),
+                    None => Type::Unit,
+                };
+                let label = label.as_ref().map(|label| label.value.as_str());
+                let target = match label {
+                    None => self.loops.last_mut(),
+                    Some(label) => self
+                        .loops
+                        .iter_mut()
+                        .rev()
+                        .find(|scope| scope.label == Some(label)),
+                };
+                //breaks outside of a loop are reported by validation
+                let Some(target) = target else {
+                    return;
+                };
+                match target.breaks.clone() {
+                    None => target.breaks = Some(type_),
+                    Some(expected) => self.expect(&expected, type_, span),
+                }
+            }
+            Statement::Continue(_) => {}
+            Statement::Return(value) => {
+                let (type_, span) = match value {
+                    Some(value) => (self.expr(value), value.span()),
+                    None => (Type::Unit, span),
+                };
+                if let Some(expected) = self.returns.last().cloned() {
+                    self.expect(&expected, type_, span);
+                }
+            }
+        }
+    }
+
+    fn operand(&mut self, op: &'static str, valid: bool, type_: &Type, span: Range<usize>) {
+        if !valid {
+            self.error(TypeError::InvalidOperand(op, type_.clone(), span));
+        }
+    }
+
+    fn binary(
+        &mut self,
+        op: BinaryOp,
+        (lhs, rhs): (Type, Type),
+        lhs_span: Range<usize>,
+        rhs_span: Range<usize>,
+    ) -> Type {
+        let operator = op.symbol();
+        let operand = self.types.prune(&lhs);
+        let valid = match op {
+            BinaryOp::And | BinaryOp::Or => {
+                let valid = self.unify(&Type::Bool, &lhs, &lhs_span);
+                self.operand(operator, valid, &lhs, lhs_span);
+                let valid = self.unify(&Type::Bool, &rhs, &rhs_span);
+                self.operand(operator, valid, &rhs, rhs_span);
+                return Type::Bool;
+            }
+            //shifted by any integer type
+            BinaryOp::Shl | BinaryOp::Shr => {
+                let valid = self.integer(&lhs, &lhs_span);
+                self.operand(operator, valid, &lhs, lhs_span);
+                let valid = self.integer(&rhs, &rhs_span);
+                self.operand(operator, valid, &rhs, rhs_span);
+                return lhs;
+            }
+            BinaryOp::Eq | BinaryOp::Ne => true,
+            //the operand types are not known yet, so they only have to agree
+            _ if matches!(operand, Type::Var(_)) => true,
+            BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => {
+                operand.is_numeric() || matches!(operand, Type::Char | Type::String)
+            }
+            BinaryOp::Add => operand.is_numeric() || operand == Type::String,
+            BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => operand.is_numeric(),
+            BinaryOp::BitAnd | BinaryOp::BitOr | BinaryOp::BitXor => {
+                operand.is_integer() || operand == Type::Bool
+            }
+        };
+        self.operand(operator, valid, &lhs, lhs_span);
+        if valid {
+            self.expect(&lhs, rhs.clone(), rhs_span);
+        }
+        match op {
+            BinaryOp::Eq
+            | BinaryOp::Ne
+            | BinaryOp::Lt
+            | BinaryOp::Le
+            | BinaryOp::Gt
+            | BinaryOp::Ge => Type::Bool,
+            _ if valid => known(lhs, rhs),
+            _ => Type::Unknown,
+        }
+    }
+
+    fn call(
+        &mut self,
+        callee: &'t Token<Expr>,
+        args: &'t [Token<Expr>],
+        span: Range<usize>,
+    ) -> Type {
+        let callee_type = self.expr(callee);
+        let arg_types: Vec<_> = args.iter().map(|arg| self.expr(arg)).collect();
+        let (params, ret) = match self.types.prune(&callee_type) {
+            Type::Function(params, ret) => (params, ret),
+            //a callee not known to be a function is inferred to take the arguments given
+            Type::Var(_) => {
+                let params: Vec<_> = args.iter().map(|_| self.types.fresh()).collect();
+                let ret = Box::new(self.types.fresh());
+                let function = Type::Function(params.clone(), ret.clone());
+                self.unify(&function, &callee_type, &callee.span());
+                (params, ret)
+            }
+            Type::Unknown => return Type::Unknown,
+            type_ => {
+                self.error(TypeError::NotCallable(type_, callee.span()));
+                return Type::Unknown;
+            }
+        };
+        if params.len() != args.len() {
+            self.error(TypeError::ArityMismatch(params.len(), args.len(), span));
+            return *ret;
+        }
+
+        for ((param, arg), type_) in params.iter().zip(args).zip(arg_types) {
+            self.expect(param, type_, arg.span());
+        }
+        *ret
+    }
+
+    fn closure(&mut self, closure: &'t Closure) -> Type {
+        let scope = self.bindings.len();
+        let mut params = Vec::new();
+        for param in &closure.params {
+            let ClosureParameter(name, type_) = &param.value;
+            let type_ = match type_ {
+                Some(type_) => self.resolve(type_),
+                None => self.types.fresh(),
+            };
+            self.bind(&name.value, type_.clone(), param.span());
+            params.push(type_);
+        }
+        let ret = match &closure.return_type {
+            Some(ret) => self.resolve(ret),
+            None => self.types.fresh(),
+        };
+        //breaks and continues cannot reach loops outside the closure
+        let loops = std::mem::take(&mut self.loops);
+        let body = self.fun_body(&closure.body, &ret);
+        self.loops = loops;
+        self.bindings.truncate(scope);
+        //a body ending in a statement without returning anything returns unit
+        if let Type::Var(_) = self.types.prune(&ret) {
+            self.unify(&ret, &body, &closure.body.last().unwrap().span());
+        }
+        Type::Function(params, Box::new(ret))
+    }
+
+    //the shape a pattern gives a scrutinee whose type is not known yet
+    fn pattern_type(&mut self, pattern: &Pattern) -> Option<Type> {
+        match pattern {
+            Pattern::Wildcard | Pattern::Binding(_) => None,
+            Pattern::Value(value) => Some(Type::of_value(value)),
+            Pattern::Tuple(patterns) if patterns.is_empty() => Some(Type::Unit),
+            Pattern::Tuple(patterns) => Some(Type::Tuple(
+                patterns.iter().map(|_| self.types.fresh()).collect(),
+            )),
+            Pattern::Struct(name, _, _) => Some(Type::Struct(name.value.clone())),
+            Pattern::Variant(name, _, _) => Some(Type::Enum(name.value.clone())),
+        }
+    }
+
+    fn pattern(&mut self, pattern: &'t Token<Pattern>, type_: &Type) {
+        if let Type::Var(_) = self.types.prune(type_) {
+            if let Some(shape) = self.pattern_type(&pattern.value) {
+                self.unify(type_, &shape, &pattern.span());
+            }
+        }
+        let type_ = &self.types.prune(type_);
+        match (&pattern.value, type_) {
+            (Pattern::Wildcard, _) => {}
+            (Pattern::Binding(name), _) => self.bind(name, type_.clone(), pattern.span()),
+            (Pattern::Value(value), _)
+                if self.unify(type_, &Type::of_value(value), &pattern.span()) => {}
+            (Pattern::Tuple(patterns), Type::Unit) if patterns.is_empty() => {}
+            (Pattern::Tuple(patterns), Type::Tuple(types)) if patterns.len() == types.len() => {
+                for (pattern, type_) in patterns.iter().zip(types) {
+                    self.pattern(pattern, type_);
+                }
+            }
+            (Pattern::Struct(name, fields, _), Type::Struct(struct_name))
+                if name.value == *struct_name =>
+            {
+                let declared = self.structs.get(struct_name.as_str()).cloned();
+                for (field, pattern) in fields {
+                    let field_type = declared
+                        .iter()
+                        .flatten()
+                        .find(|(declared, _)| *declared == field.value)
+                        .map(|(_, type_)| type_.clone());
+                    if field_type.is_none() {
+                        let error = TypeError::UnknownField(
+                            type_.clone(),
+                            field.value.clone(),
+                            field.span(),
+                        );
+                        self.error(error);
+                    }
+                    self.pattern(pattern, &field_type.unwrap_or(Type::Unknown));
+                }
+            }
+            (Pattern::Variant(enum_name, variant, payload), Type::Enum(name))
+                if enum_name.value == *name =>
+            {
+                let types = self.variant(name, variant, payload.len(), pattern.span());
+                for (pattern, type_) in payload.iter().zip(types) {
+                    self.pattern(pattern, &type_);
+                }
+            }
+            (Pattern::Value(_), Type::Unknown) => {}
+            (Pattern::Tuple(patterns), Type::Unknown)
+            | (Pattern::Variant(_, _, patterns), Type::Unknown) => {
+                for pattern in patterns {
+                    self.pattern(pattern, &Type::Unknown);
+                }
+            }
+            (Pattern::Struct(_, fields, _), Type::Unknown) => {
+                for (_, pattern) in fields {
+                    self.pattern(pattern, &Type::Unknown);
+                }
+            }
+            //bind the names in the pattern so they are not reported again
+            _ => {
+                self.error(TypeError::PatternMismatch(type_.clone(), pattern.span()));
+                self.pattern(pattern, &Type::Unknown);
+            }
+        }
+    }
+
+    //the payload types of a variant, reporting unknown variants and payloads of the wrong length
+    fn variant(
+        &mut self,
+        enum_name: &str,
+        variant: &Token<String>,
+        length: usize,
+        span: Range<usize>,
+    ) -> Vec<Type> {
+        let types = self.enums.get(enum_name).and_then(|variants| {
+            variants
+                .iter()
+                .find(|(name, _)| *name == variant.value)
+                .map(|(_, types)| types.clone())
+        });
+        match types {
+            Some(types) if types.len() == length => types,
+            Some(types) => {
+                self.error(TypeError::ArityMismatch(types.len(), length, span));
+                vec![Type::Unknown; length]
+            }
+            None => {
+                let type_ = Type::Enum(enum_name.to_string());
+                let error = TypeError::UnknownField(type_, variant.value.clone(), variant.span());
+                self.error(error);
+                vec![Type::Unknown; length]
+            }
+        }
+    }
+
+    //the type both branches have, reporting them if they differ
+    fn branches(&mut self, first: Type, other: Type, span: Range<usize>) -> Type {
+        if !self.unify(&first, &other, &span) && !self.unify(&other, &first, &span) {
+            self.error(TypeError::BranchMismatch(first.clone(), other, span));
+            return first;
+        }
+        known(first, other)
+    }
+
+    fn expr(&mut self, expr: &'t Token<Expr>) -> Type {
+        self.expr_at(&expr.value, expr.span())
+    }
+
+    fn expr_at(&mut self, expr: &'t Expr, span: Range<usize>) -> Type {
+        match expr {
+            Expr::Value(value) => Type::of_value(value),
+            Expr::Ident(name) => self.lookup(name),
+            Expr::Call(callee, args) => self.call(callee, args, span),
+            Expr::Range(start, end, _, step) => {
+                let bound = self.bound(start);
+                let end_type = self.bound(end);
+                self.expect(&bound, end_type, end.span());
+                if let Some(step) = step {
+                    let step_type = self.bound(step);
+                    self.expect(&bound, step_type, step.span());
+                }
+                Type::Range(Box::new(bound))
+            }
+            Expr::If(condition, body, else_body) => {
+                self.condition(condition);
+                let type_ = self.body(body);
+                match else_body {
+                    Some(else_body) => {
+                        let else_type = self.body(else_body);
+                        self.branches(type_, else_type, span)
+                    }
+                    None => Type::Unit,
+                }
+            }
+            Expr::Binary(lhs, op, rhs) => {
+                let operands = (self.expr(lhs), self.expr(rhs));
+                self.binary(op.value, operands, lhs.span(), rhs.span())
+            }
+            Expr::Unary(op, operand) => {
+                let type_ = self.expr(operand);
+                let type_ = self.types.prune(&type_);
+                let valid = match op.value {
+                    _ if matches!(type_, Type::Var(_)) => true,
+                    UnaryOp::Neg => type_.is_numeric() && type_ != Type::U64,
+                    UnaryOp::Not => type_.is_integer() || type_ == Type::Bool,
+                };
+                let operator = op.value.symbol();
+                self.operand(operator, valid, &type_, operand.span());
+                type_
+            }
+            //a loop without a break never produces a value
+            Expr::Loop(label, body) => self.looped(label, body).unwrap_or(Type::Unknown),
+            Expr::Struct(name, fields) => {
+                let declared = self.structs.get(name.value.as_str()).cloned();
+                if declared.is_none() {
+                    self.error(TypeError::UnknownType(name.value.clone(), name.span()));
+                }
+                let type_ = Type::Struct(name.value.clone());
+                for (field, value) in fields {
+                    let value_type = self.expr(value);
+                    let Some(declared) = &declared else {
+                        continue;
+                    };
+                    match declared.iter().find(|(name, _)| *name == field.value) {
+                        Some((_, field_type)) => self.expect(field_type, value_type, value.span()),
+                        None => {
+                            let error = TypeError::UnknownField(
+                                type_.clone(),
+                                field.value.clone(),
+                                field.span(),
+                            );
+                            self.error(error);
+                        }
+                    }
+                }
+                match declared {
+                    Some(_) => type_,
+                    None => Type::Unknown,
+                }
+            }
+            Expr::Field(value, name) => {
+                let type_ = self.expr(value);
+                let type_ = self.types.prune(&type_);
+                let field = match &type_ {
+                    //fields cannot be looked up before the type is known
+                    Type::Var(_) | Type::Unknown => return Type::Unknown,
+                    Type::Struct(struct_name) => {
+                        self.structs.get(struct_name.as_str()).and_then(|fields| {
+                            fields
+                                .iter()
+                                .find(|(field, _)| *field == name.value)
+                                .map(|(_, type_)| type_.clone())
+                        })
+                    }
+                    Type::Tuple(types) => name
+                        .value
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|position| types.get(position).cloned()),
+                    _ => None,
+                };
+                field.unwrap_or_else(|| {
+                    let error = TypeError::UnknownField(type_, name.value.clone(), name.span());
+                    self.error(error);
+                    Type::Unknown
+                })
+            }
+            Expr::Variant(enum_name, variant, payload) => {
+                let types: Vec<_> = payload.iter().map(|value| self.expr(value)).collect();
+                if !self.enums.contains_key(enum_name.value.as_str()) {
+                    let error = TypeError::UnknownType(enum_name.value.clone(), enum_name.span());
+                    self.error(error);
+                    return Type::Unknown;
+                }
+                let declared = self.variant(&enum_name.value, variant, payload.len(), span);
+                for ((declared, value), type_) in declared.iter().zip(payload).zip(types) {
+                    self.expect(declared, type_, value.span());
+                }
+                Type::Enum(enum_name.value.clone())
+            }
+            Expr::Match(scrutinee, arms) => {
+                let scrutinee = self.expr(scrutinee);
+                let mut type_ = None;
+                for arm in arms {
+                    let scope = self.bindings.len();
+                    self.pattern(&arm.value.pattern, &scrutinee);
+                    if let Some(guard) = &arm.value.guard {
+                        self.condition(guard);
+                    }
+                    let arm_type = self.body(&arm.value.body);
+                    self.bindings.truncate(scope);
+                    type_ = Some(match type_ {
+                        None => arm_type,
+                        Some(first) => self.branches(first, arm_type, arm.span()),
+                    });
+                }
+                type_.unwrap_or(Type::Unknown)
+            }
+            Expr::Array(values) => {
+                let element = self.types.fresh();
+                for value in values {
+                    let type_ = self.expr(value);
+                    self.expect(&element, type_, value.span());
+                }
+                Type::Array(Box::new(element), Some(values.len()))
+            }
+            Expr::Repeat(value, count) => {
+                let element = self.expr(value);
+                let count_type = self.expr(count);
+                if !self.integer(&count_type, &count.span()) {
+                    self.error(TypeError::Mismatch(Type::Int, count_type, count.span()));
+                }
+                let length = match count.value {
+                    Expr::Value(Value::Number(length)) => usize::try_from(length).ok(),
+                    _ => None,
+                };
+                Type::Array(Box::new(element), length)
+            }
+            Expr::Tuple(values) if values.is_empty() => Type::Unit,
+            Expr::Tuple(values) => {
+                Type::Tuple(values.iter().map(|value| self.expr(value)).collect())
+            }
+            Expr::Index(value, index) => {
+                let type_ = self.expr(value);
+                let type_ = self.types.prune(&type_);
+                let index_type = self.expr(index);
+                let index_type = self.types.prune(&index_type);
+                let (element, slice) = match type_ {
+                    Type::Array(element, _) => (*element.clone(), Type::Array(element, None)),
+                    Type::String => (Type::Char, Type::String),
+                    //arrays and strings can both be indexed, so a variable is left unknown
+                    Type::Var(_) | Type::Unknown => (Type::Unknown, Type::Unknown),
+                    type_ => {
+                        self.error(TypeError::NotIndexable(type_, value.span()));
+                        (Type::Unknown, Type::Unknown)
+                    }
+                };
+                match index_type {
+                    Type::Range(_) => slice,
+                    index_type if self.integer(&index_type, &index.span()) => element,
+                    index_type => {
+                        self.error(TypeError::Mismatch(Type::Int, index_type, index.span()));
+                        element
+                    }
+                }
+            }
+            Expr::Closure(closure) => self.closure(closure),
+        }
+    }
+}
+
+/// Checks the types in a function, with only built in types and the function itself in scope.
+pub fn check_fun(fun: &Fun) -> TypeCheck {
+    let mut checker = Checker::default();
+    let signature = checker.declare_fun(fun);
+    checker.fun(fun, &signature);
+    checker.finish()
+}
+
+/// Resolves the types named in a program's declarations and signatures, then checks the
+/// body of every function and the value of every constant against them. Names that are not
+/// declared anywhere are given an unknown type, which is accepted everywhere, so that each
+/// mistake is reported once.
+pub fn check_program(program: &Program) -> TypeCheck {
+    let mut checker = Checker::default();
+    //declared types may refer to each other in any order
+    for item in &program.items {
+        match &item.value {
+            Item::Type(name, target) => {
+                checker.alias_targets.insert(&name.value, target);
+            }
+            Item::Struct(name, _) => {
+                checker.structs.insert(&name.value, Vec::new());
+            }
+            Item::Enum(name, _) => {
+                checker.enums.insert(&name.value, Vec::new());
+            }
+            _ => {}
+        }
+    }
+    for item in &program.items {
+        match &item.value {
+            Item::Type(name, _) => {
+                checker.alias(&name.value, name.span());
+            }
+            Item::Struct(name, fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|field| {
+                        (
+                            field.value.0.value.as_str(),
+                            checker.resolve(&field.value.1),
+                        )
+                    })
+                    .collect();
+                checker.structs.insert(&name.value, fields);
+            }
+            Item::Enum(name, variants) => {
+                let variants = variants
+                    .iter()
+                    .map(|variant| {
+                        let Variant(variant_name, payload) = &variant.value;
+                        let payload = payload.iter().map(|type_| checker.resolve(type_)).collect();
+                        (variant_name.value.as_str(), payload)
+                    })
+                    .collect();
+                checker.enums.insert(&name.value, variants);
+            }
+            _ => {}
+        }
+    }
+
+    //functions and constants are visible throughout the program, wherever they are declared
+    let mut signatures = Vec::new();
+    for item in &program.items {
+        match &item.value {
+            Item::Fun(fun) => signatures.push(checker.declare_fun(fun)),
+            Item::Const(name, type_, _) => {
+                let type_ = checker.resolve(type_);
+                checker.globals.insert(&name.value, type_.clone());
+                signatures.push(type_);
+            }
+            _ => {}
+        }
+    }
+    let items = program
+        .items
+        .iter()
+        .filter(|item| matches!(item.value, Item::Fun(_) | Item::Const(_, _, _)));
+    for (item, signature) in items.zip(&signatures) {
+        match &item.value {
+            Item::Fun(fun) => checker.fun(fun, signature),
+            Item::Const(_, _, value) => {
+                let type_ = checker.expr(value);
+                checker.expect(signature, type_, value.span());
+            }
+            _ => {}
+        }
+    }
+    checker.finish()
+}
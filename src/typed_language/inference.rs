@@ -0,0 +1,193 @@
+use std::{collections::HashMap, ops::Range};
+
+use super::*;
+
+/// A type generalised over some of its type variables, so that a `let` bound closure can be
+/// used at a different type each time it is named.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scheme {
+    pub vars: Vec<usize>,
+    pub type_: Type,
+}
+
+impl Scheme {
+    /// A scheme that is not generalised over anything.
+    pub fn mono(type_: Type) -> Self {
+        Self {
+            vars: Vec::new(),
+            type_,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnifyError {
+    Mismatch,
+    Infinite(usize, Type), //variable, type containing it
+}
+
+/// The types inferred for type variables so far, along with the span of the expression each
+/// was inferred from.
+#[derive(Debug, Clone, Default)]
+pub struct Substitution {
+    vars: Vec<Option<(Type, Range<usize>)>>,
+}
+
+impl Substitution {
+    pub fn fresh(&mut self) -> Type {
+        self.vars.push(None);
+        Type::Var(self.vars.len() - 1)
+    }
+
+    fn bound(&self, var: usize) -> Option<&(Type, Range<usize>)> {
+        self.vars[var].as_ref()
+    }
+
+    /// Follows the variables a type is bound to until reaching a type that is not a bound
+    /// variable, without looking inside it.
+    pub fn prune(&self, type_: &Type) -> Type {
+        match type_ {
+            Type::Var(var) => match self.bound(*var) {
+                Some((bound, _)) => self.prune(bound),
+                None => type_.clone(),
+            },
+            type_ => type_.clone(),
+        }
+    }
+
+    /// Replaces every bound variable in a type with what it is bound to.
+    pub fn apply(&self, type_: &Type) -> Type {
+        let apply_all = |types: &[Type]| types.iter().map(|type_| self.apply(type_)).collect();
+        match self.prune(type_) {
+            Type::Tuple(types) => Type::Tuple(apply_all(&types)),
+            Type::Array(element, length) => Type::Array(Box::new(self.apply(&element)), length),
+            Type::Range(bound) => Type::Range(Box::new(self.apply(&bound))),
+            Type::Function(params, ret) => {
+                Type::Function(apply_all(&params), Box::new(self.apply(&ret)))
+            }
+            type_ => type_,
+        }
+    }
+
+    /// Where the type a variable is bound to was inferred, for types that are bound variables.
+    pub fn origin(&self, type_: &Type) -> Option<Range<usize>> {
+        let Type::Var(var) = type_ else {
+            return None;
+        };
+        let (bound, span) = self.bound(*var)?;
+        self.origin(bound).or_else(|| Some(span.clone()))
+    }
+
+    /// The unbound variables in a type, each once and in order of appearance.
+    pub fn free_vars(&self, type_: &Type, vars: &mut Vec<usize>) {
+        match self.prune(type_) {
+            Type::Var(var) if !vars.contains(&var) => vars.push(var),
+            Type::Tuple(types) => {
+                for type_ in &types {
+                    self.free_vars(type_, vars);
+                }
+            }
+            Type::Array(inner, _) | Type::Range(inner) => self.free_vars(&inner, vars),
+            Type::Function(params, ret) => {
+                for param in &params {
+                    self.free_vars(param, vars);
+                }
+                self.free_vars(&ret, vars);
+            }
+            _ => {}
+        }
+    }
+
+    /// Makes a value of type `found` usable where `expected` is, binding variables in either
+    /// to the span of the expression being unified. An array of fixed length can be used
+    /// where the length is not given. Variables bound before a mismatch stay bound.
+    pub fn unify(
+        &mut self,
+        expected: &Type,
+        found: &Type,
+        span: Range<usize>,
+    ) -> Result<(), UnifyError> {
+        match (self.prune(expected), self.prune(found)) {
+            (Type::Unknown, _) | (_, Type::Unknown) => Ok(()),
+            (Type::Var(expected), Type::Var(found)) if expected == found => Ok(()),
+            (Type::Var(var), type_) | (type_, Type::Var(var)) => self.bind(var, type_, span),
+            (Type::Tuple(expected), Type::Tuple(found)) if expected.len() == found.len() => {
+                for (expected, found) in expected.iter().zip(&found) {
+                    self.unify(expected, found, span.clone())?;
+                }
+                Ok(())
+            }
+            (Type::Array(expected, length), Type::Array(found, found_length))
+                if length.is_none() || length == found_length =>
+            {
+                self.unify(&expected, &found, span)
+            }
+            (Type::Range(expected), Type::Range(found)) => self.unify(&expected, &found, span),
+            (Type::Function(params, ret), Type::Function(found_params, found_ret))
+                if params.len() == found_params.len() =>
+            {
+                //a function can be passed where one taking the same parameters is expected
+                for (expected, found) in params.iter().zip(&found_params) {
+                    self.unify(found, expected, span.clone())?;
+                }
+                self.unify(&ret, &found_ret, span)
+            }
+            (expected, found) if expected == found => Ok(()),
+            _ => Err(UnifyError::Mismatch),
+        }
+    }
+
+    //the occurs check, so that no variable is bound to a type containing itself
+    fn bind(&mut self, var: usize, type_: Type, span: Range<usize>) -> Result<(), UnifyError> {
+        let mut vars = Vec::new();
+        self.free_vars(&type_, &mut vars);
+        if vars.contains(&var) {
+            return Err(UnifyError::Infinite(var, self.apply(&type_)));
+        }
+        self.vars[var] = Some((type_, span));
+        Ok(())
+    }
+
+    /// Generalises a type over the variables in it that do not appear in `env`. A type with
+    /// nothing to generalise over is kept as it is, so that where its variables were inferred
+    /// can still be reported.
+    pub fn generalize(&self, type_: Type, env: &[usize]) -> Scheme {
+        let mut vars = Vec::new();
+        self.free_vars(&type_, &mut vars);
+        vars.retain(|var| !env.contains(var));
+        match vars.is_empty() {
+            true => Scheme::mono(type_),
+            false => Scheme {
+                vars,
+                type_: self.apply(&type_),
+            },
+        }
+    }
+
+    /// A type for one use of a scheme, with a fresh variable for each it is generalised over.
+    pub fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let fresh: HashMap<_, _> = scheme.vars.iter().map(|&var| (var, self.fresh())).collect();
+        replace_vars(&scheme.type_, &fresh)
+    }
+}
+
+fn replace_vars(type_: &Type, fresh: &HashMap<usize, Type>) -> Type {
+    let replace_all = |types: &[Type]| {
+        types
+            .iter()
+            .map(|type_| replace_vars(type_, fresh))
+            .collect()
+    };
+    match type_ {
+        Type::Var(var) => fresh.get(var).cloned().unwrap_or(Type::Var(*var)),
+        Type::Tuple(types) => Type::Tuple(replace_all(types)),
+        Type::Array(element, length) => {
+            Type::Array(Box::new(replace_vars(element, fresh)), *length)
+        }
+        Type::Range(bound) => Type::Range(Box::new(replace_vars(bound, fresh))),
+        Type::Function(params, ret) => {
+            Type::Function(replace_all(params), Box::new(replace_vars(ret, fresh)))
+        }
+        type_ => type_.clone(),
+    }
+}
@@ -0,0 +1,10 @@
+pub mod checker;
+pub mod inference;
+pub mod types;
+
+pub use checker::*;
+pub use inference::*;
+pub use types::*;
+
+#[cfg(test)]
+pub mod tests;
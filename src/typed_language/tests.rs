@@ -0,0 +1,436 @@
+use crate::{parser_combinator::*, untyped_language::*};
+
+use super::*;
+
+fn check(source: &str) -> TypeCheck {
+    let (program, cont) = pprogram().parse(source.into()).unwrap();
+    assert_eq!(cont.remaining, "");
+    check_program(&program.value)
+}
+
+fn errors(source: &str) -> Vec<TypeError> {
+    check(source).errors
+}
+
+//the type of every binding with the given name, in order
+fn binding_types(check: &TypeCheck, name: &str) -> Vec<String> {
+    check
+        .bindings
+        .iter()
+        .filter(|binding| binding.name == name)
+        .map(|binding| binding.type_.to_string())
+        .collect()
+}
+
+fn at(source: &str, text: &str) -> std::ops::Range<usize> {
+    let start = source.find(text).unwrap();
+    start..start + text.len()
+}
+
+#[test]
+fn test_infers_binding_types() {
+    let source = "struct Point { x: float, y: float }
+fun main(n: int) -> unit {
+    let a = n * 2;
+    let s = \"total {a}\";
+    let xs = [1, 2, 3];
+    let grid = [xs; 4];
+    let pair = (a < 3, 'c', Point { x: 1.0, y: 2.0 });
+    let y = pair.2.y;
+    let f = |x: int| x + 1;
+    let g = fun(x: int) -> bool { x > 0; };
+    let r = f(a) .. 10;
+    let slice = xs[0 .. 2];
+    let unit = ();
+}";
+    let check = check(source);
+    assert_eq!(check.errors, vec![]);
+    let types: Vec<_> = [
+        "n", "a", "s", "xs", "grid", "pair", "y", "f", "g", "r", "slice", "unit",
+    ]
+    .iter()
+    .flat_map(|name| binding_types(&check, name))
+    .collect();
+    assert_eq!(
+        types,
+        vec![
+            "int",
+            "int",
+            "string",
+            "[int; 3]",
+            "[[int; 3]; 4]",
+            "(bool, char, Point)",
+            "float",
+            "fun(int) -> int",
+            "fun(int) -> bool",
+            "range of int",
+            "[int]",
+            "unit"
+        ]
+    );
+}
+
+#[test]
+fn test_unknown_types() {
+    let source = "type Id = int;
+fun f(a: Id, b: Strng) -> List<int> {
+    a;
+}";
+    assert_eq!(
+        errors(source),
+        vec![
+            TypeError::UnknownType("Strng".to_string(), at(source, "Strng")),
+            TypeError::UnknownType("List".to_string(), at(source, "List")),
+        ]
+    );
+}
+
+#[test]
+fn test_recursive_alias() {
+    let source = "type A = (int, B);
+type B = [A];";
+    let a = source.rfind('A').unwrap();
+    assert_eq!(
+        errors(source),
+        vec![TypeError::RecursiveAlias("A".to_string(), a..a + 1)]
+    );
+}
+
+#[test]
+fn test_call_arity_and_arguments() {
+    let source = "fun add(a: int, b: int) -> int {
+    a + b;
+}
+fun main() -> unit {
+    add(1);
+    add(1, true);
+    let n = 3;
+    n(1);
+}";
+    assert_eq!(
+        errors(source),
+        vec![
+            TypeError::ArityMismatch(2, 1, at(source, "add(1)")),
+            TypeError::Mismatch(Type::Int, Type::Bool, at(source, "true")),
+            TypeError::NotCallable(Type::Int, source.rfind("n(1)").map(|n| n..n + 1).unwrap()),
+        ]
+    );
+}
+
+#[test]
+fn test_conditions_and_branches() {
+    let source = "fun main(n: int) -> unit {
+    let x = if n { 1; } else { 2; };
+    while n - 1 { n; };
+    let y = if n > 0 { 1; } else { \"one\"; };
+    match n {
+        0 => true,
+        _ => 1
+    };
+}";
+    let condition = source.find("n {").unwrap();
+    //the span of an `if` starts at its condition
+    assert_eq!(
+        errors(source),
+        vec![
+            TypeError::ConditionNotBool(Type::Int, condition..condition + 1),
+            TypeError::ConditionNotBool(Type::Int, at(source, "n - 1")),
+            TypeError::BranchMismatch(
+                Type::Int,
+                Type::String,
+                at(source, "n > 0 { 1; } else { \"")
+            ),
+            TypeError::BranchMismatch(Type::Bool, Type::Int, at(source, "_ => 1")),
+        ]
+    );
+}
+
+#[test]
+fn test_for_bounds_are_integers() {
+    let source = "fun main(s: string) -> unit {
+    for i = 0 .. 10 step 2 { i; };
+    for j = 0.5 ..= s { j; };
+    for c in s { c; };
+    for k in 3 { k; };
+}";
+    let check = check(source);
+    let end = source.find("s {").unwrap();
+    assert_eq!(
+        check.errors,
+        vec![
+            TypeError::NonIntegerBound(Type::Float, at(source, "0.5")),
+            TypeError::NonIntegerBound(Type::String, end..end + 1),
+            TypeError::NotIterable(Type::Int, at(source, "3")),
+        ]
+    );
+    assert_eq!(binding_types(&check, "i"), vec!["int"]);
+    assert_eq!(binding_types(&check, "c"), vec!["char"]);
+}
+
+#[test]
+fn test_range_step_matches_bounds() {
+    let source = "fun main(n: u64) -> unit {
+    for i = 0 .. 10 step 1i64 { i; };
+    let r = 0u64 .. n step 2u64;
+    for j = 0i64 .. 10i64 step 0.5 { j; };
+}";
+    let check = check(source);
+    assert_eq!(
+        check.errors,
+        vec![
+            TypeError::Mismatch(Type::Int, Type::I64, at(source, "1i64")),
+            TypeError::NonIntegerBound(Type::Float, at(source, "0.5")),
+        ]
+    );
+    assert_eq!(binding_types(&check, "r"), vec!["range of u64"]);
+}
+
+#[test]
+fn test_operands() {
+    let source = "fun main(a: int, b: bool, c: u64) -> unit {
+    a + b;
+    b && a;
+    -c;
+    \"x\" * 2;
+    a << c;
+    a == 1;
+}";
+    let position = |text| source.find(text).unwrap();
+    assert_eq!(
+        errors(source),
+        vec![
+            TypeError::Mismatch(Type::Int, Type::Bool, position("b;")..position("b;") + 1),
+            TypeError::InvalidOperand("&&", Type::Int, position("a;")..position("a;") + 1),
+            TypeError::InvalidOperand("-", Type::U64, position("c;")..position("c;") + 1),
+            //string literal spans exclude the quotes
+            TypeError::InvalidOperand("*", Type::String, position("x")..position("x") + 1),
+        ]
+    );
+}
+
+#[test]
+fn test_structs_enums_and_patterns() {
+    let source = "struct Point { x: int, y: int }
+enum Shape { Circle(Point, int), Empty }
+fun area(shape: Shape) -> int {
+    let p = Point { x: 1, z: 2 };
+    let q = p.w;
+    let c = Shape::Circle(p, true);
+    match shape {
+        Shape::Circle(Point { x, .. }, radius) => x * radius,
+        Shape::Square(side) => side,
+        (a, b) => a,
+        Shape::Empty => 0
+    };
+}";
+    let check = check(source);
+    let point = Type::Struct("Point".to_string());
+    let shape = Type::Enum("Shape".to_string());
+    assert_eq!(
+        check.errors,
+        vec![
+            TypeError::UnknownField(point.clone(), "z".to_string(), at(source, "z")),
+            TypeError::UnknownField(point, "w".to_string(), at(source, "w")),
+            TypeError::Mismatch(Type::Int, Type::Bool, at(source, "true")),
+            TypeError::UnknownField(shape.clone(), "Square".to_string(), at(source, "Square")),
+            TypeError::PatternMismatch(shape, at(source, "a, b")),
+        ]
+    );
+    assert_eq!(binding_types(&check, "x"), vec!["int"]);
+    assert_eq!(binding_types(&check, "radius"), vec!["int"]);
+    assert_eq!(binding_types(&check, "side"), vec!["_"]);
+}
+
+#[test]
+fn test_return_types() {
+    let source = "fun f(n: int) -> bool {
+    if n > 0 {
+        return 1;
+    };
+    n;
+}
+fun g() -> unit {
+    return;
+}
+const LIMIT: float = 10;";
+    let one = source.find("1;").unwrap();
+    let n = source.find("n;").unwrap();
+    assert_eq!(
+        errors(source),
+        vec![
+            TypeError::Mismatch(Type::Bool, Type::Int, one..one + 1),
+            TypeError::Mismatch(Type::Bool, Type::Int, n..n + 1),
+            TypeError::Mismatch(Type::Float, Type::Int, at(source, "10")),
+        ]
+    );
+}
+
+#[test]
+fn test_generic_calls() {
+    let source = "fun id<T>(x: T) -> T {
+    x;
+}
+fun apply<A, B>(f: fun(A) -> B, a: A) -> B {
+    f(a);
+}
+fun main() -> unit {
+    let n = id(1);
+    let s = apply(|x: int| \"{x}\", 2);
+    let pair = id((n, s));
+    id(true) + 1;
+}";
+    let check = check(source);
+    assert_eq!(
+        check.errors,
+        vec![TypeError::InvalidOperand(
+            "+",
+            Type::Bool,
+            at(source, "id(true)")
+        )]
+    );
+    assert_eq!(binding_types(&check, "n"), vec!["int"]);
+    assert_eq!(binding_types(&check, "s"), vec!["string"]);
+    assert_eq!(binding_types(&check, "pair"), vec!["(int, string)"]);
+    assert_eq!(binding_types(&check, "x"), vec!["T", "int"]);
+}
+
+#[test]
+fn test_check_fun() {
+    let (fun, _) = pfun()
+        .parse("fun fact(n: int) -> int { if n < 2 { 1; } else { n * fact(n - 1); }; }".into())
+        .unwrap();
+    assert_eq!(
+        check_fun(&fun.value),
+        TypeCheck {
+            bindings: vec![BindingType {
+                name: "n".to_string(),
+                binding: 9..15,
+                type_: Type::Int,
+            }],
+            errors: vec![],
+        }
+    );
+}
+
+#[test]
+fn test_type_error_messages() {
+    let errors = errors("fun f(a: int) -> unit { a(1, 2); if a { a; }; }");
+    let messages: Vec<_> = errors.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "cannot call a value of type int at 24..25",
+            "condition at 36..37 is int rather than bool",
+        ]
+    );
+}
+
+#[test]
+fn test_infers_unannotated_lets_and_closures() {
+    let source = "fun main() -> unit {
+    let inc = |x| x + 1;
+    let twice = |f, x| f(f(x));
+    let n = twice(inc, 1);
+    let empty = [];
+    let xs: [int] = [1, 2];
+    let first = |pair| match pair { (a, _) => a };
+    let c = first(('c', true));
+    let s: string = 1;
+}";
+    let check = check(source);
+    assert_eq!(
+        check.errors,
+        vec![TypeError::Mismatch(
+            Type::String,
+            Type::Int,
+            source.rfind('1').map(|one| one..one + 1).unwrap()
+        )]
+    );
+    assert_eq!(binding_types(&check, "inc"), vec!["fun(int) -> int"]);
+    assert_eq!(binding_types(&check, "n"), vec!["int"]);
+    assert_eq!(binding_types(&check, "xs"), vec!["[int]"]);
+    assert_eq!(binding_types(&check, "c"), vec!["char"]);
+    assert_eq!(binding_types(&check, "s"), vec!["string"]);
+    assert!(binding_types(&check, "empty")[0].starts_with("[?"));
+}
+
+#[test]
+fn test_let_polymorphism() {
+    let source = "fun main() -> unit {
+    let id = |x| x;
+    let n = id(1);
+    let b = id(true);
+    let swap = |p, q| (q, p);
+    let pair = swap(1, \"one\");
+    let mut mono = |x| x;
+    mono(1);
+    mono(true);
+}";
+    let check = check(source);
+    //a mutable binding is not generalised, so its parameter is fixed by the first call
+    let one = source.find("1);\n    mono").unwrap();
+    assert_eq!(
+        check.errors,
+        vec![TypeError::Conflict(
+            Type::Int,
+            one..one + 1,
+            Type::Bool,
+            source.rfind("true").map(|t| t..t + 4).unwrap()
+        )]
+    );
+    assert_eq!(binding_types(&check, "n"), vec!["int"]);
+    assert_eq!(binding_types(&check, "b"), vec!["bool"]);
+    assert_eq!(binding_types(&check, "pair"), vec!["(string, int)"]);
+}
+
+#[test]
+fn test_conflicts_show_where_each_type_was_inferred() {
+    let source = "fun main() -> unit {
+    let check = |x| {
+        let y: int = x;
+        x == true;
+    };
+    let xs = [1, 'a'];
+}";
+    let x = source.find("x;").unwrap();
+    let errors = errors(source);
+    assert_eq!(
+        errors,
+        vec![
+            TypeError::Conflict(Type::Int, x..x + 1, Type::Bool, at(source, "true")),
+            TypeError::Conflict(Type::Int, at(source, "1"), Type::Char, at(source, "'a'")),
+        ]
+    );
+    assert_eq!(
+        errors[0].to_string(),
+        format!(
+            "expected int as inferred at {:?} but found bool at {:?}",
+            x..x + 1,
+            at(source, "true")
+        )
+    );
+}
+
+#[test]
+fn test_occurs_check() {
+    let source = "fun main() -> unit {
+    let apply_self = |f| f(f);
+}";
+    let f = source.rfind('f').unwrap();
+    let errors = errors(source);
+    assert_eq!(
+        errors,
+        vec![TypeError::InfiniteType(
+            Type::Var(2),
+            Type::Function(vec![Type::Var(2)], Box::new(Type::Var(3))),
+            f..f + 1
+        )]
+    );
+    assert_eq!(
+        errors[0].to_string(),
+        format!(
+            "cannot construct the infinite type ?2 = fun(?2) -> ?3 at {:?}",
+            f..f + 1
+        )
+    );
+}
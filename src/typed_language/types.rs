@@ -0,0 +1,103 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::untyped_language::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Int,
+    I64,
+    U64,
+    Float,
+    Bool,
+    Char,
+    String,
+    Unit, //also written `()`
+    Tuple(Vec<Type>),
+    Array(Box<Type>, Option<usize>), //element type, length if fixed
+    Range(Box<Type>),                //bound type
+    Function(Vec<Type>, Box<Type>),  //parameter types, return type
+    Struct(String),
+    Enum(String),
+    Param(String), //type parameter of a generic function
+    Var(usize),    //type variable, standing for a type still being inferred
+    Unknown,       //not known, so accepted anywhere rather than reported twice
+}
+
+impl Type {
+    pub fn of_value(value: &Value) -> Type {
+        match value {
+            Value::Number(_) => Type::Int,
+            Value::I64(_) => Type::I64,
+            Value::U64(_) => Type::U64,
+            Value::Float(_) => Type::Float,
+            Value::Bool(_) => Type::Bool,
+            Value::Char(_) => Type::Char,
+            Value::String(_) => Type::String,
+        }
+    }
+
+    /// The built in type a name refers to, if any.
+    pub fn builtin(name: &str) -> Option<Type> {
+        match name {
+            "int" => Some(Type::Int),
+            "i64" => Some(Type::I64),
+            "u64" => Some(Type::U64),
+            "float" => Some(Type::Float),
+            "bool" => Some(Type::Bool),
+            "char" => Some(Type::Char),
+            "string" => Some(Type::String),
+            "unit" => Some(Type::Unit),
+            _ => None,
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Type::Int | Type::I64 | Type::U64 | Type::Unknown)
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        self.is_integer() || *self == Type::Float
+    }
+}
+
+fn write_separated(f: &mut Formatter, types: &[Type]) -> fmt::Result {
+    for (i, type_) in types.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{}", type_)?;
+    }
+    Ok(())
+}
+
+/// Prints a type as it is written in source.
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Type::Int => f.write_str("int"),
+            Type::I64 => f.write_str("i64"),
+            Type::U64 => f.write_str("u64"),
+            Type::Float => f.write_str("float"),
+            Type::Bool => f.write_str("bool"),
+            Type::Char => f.write_str("char"),
+            Type::String => f.write_str("string"),
+            Type::Unit => f.write_str("unit"),
+            Type::Tuple(types) => {
+                f.write_str("(")?;
+                write_separated(f, types)?;
+                f.write_str(")")
+            }
+            Type::Array(element, None) => write!(f, "[{}]", element),
+            Type::Array(element, Some(length)) => write!(f, "[{}; {}]", element, length),
+            Type::Range(bound) => write!(f, "range of {}", bound),
+            Type::Function(params, ret) => {
+                f.write_str("fun(")?;
+                write_separated(f, params)?;
+                write!(f, ") -> {}", ret)
+            }
+            Type::Struct(name) | Type::Enum(name) | Type::Param(name) => f.write_str(name),
+            Type::Var(var) => write!(f, "?{}", var),
+            Type::Unknown => f.write_str("_"),
+        }
+    }
+}
@@ -3,27 +3,212 @@ use crate::parser_combinator::Token;
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Number(i32),
+    I64(i64),
+    U64(u64),
+    Float(f64),
     Bool(bool),
+    Char(char),
     String(String),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And, //short-circuits
+    Or,  //short-circuits
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+}
+
+impl BinaryOp {
+    pub const ALL: [BinaryOp; 18] = [
+        BinaryOp::Add,
+        BinaryOp::Sub,
+        BinaryOp::Mul,
+        BinaryOp::Div,
+        BinaryOp::Rem,
+        BinaryOp::Eq,
+        BinaryOp::Ne,
+        BinaryOp::Lt,
+        BinaryOp::Le,
+        BinaryOp::Gt,
+        BinaryOp::Ge,
+        BinaryOp::And,
+        BinaryOp::Or,
+        BinaryOp::BitAnd,
+        BinaryOp::BitOr,
+        BinaryOp::BitXor,
+        BinaryOp::Shl,
+        BinaryOp::Shr,
+    ];
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::BitOr => "|",
+            BinaryOp::BitXor => "^",
+            BinaryOp::Shl => "<<",
+            BinaryOp::Shr => ">>",
+        }
+    }
+
+    /// Higher binds tighter. Every binary operator is left associative.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Eq
+            | BinaryOp::Ne
+            | BinaryOp::Lt
+            | BinaryOp::Le
+            | BinaryOp::Gt
+            | BinaryOp::Ge => 3,
+            BinaryOp::BitOr => 4,
+            BinaryOp::BitXor => 5,
+            BinaryOp::BitAnd => 6,
+            BinaryOp::Shl | BinaryOp::Shr => 7,
+            BinaryOp::Add | BinaryOp::Sub => 8,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => 9,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnaryOp {
+    Neg,
+    Not, //logical for bools, bitwise for integers
+}
+
+impl UnaryOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            UnaryOp::Neg => "-",
+            UnaryOp::Not => "!",
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     Value(Value),
-    Ident(String), //should this be token<string>?
-    Call(Token<String>, Vec<Token<Expr>>),
-    Range(Box<Token<Expr>>, Box<Token<Expr>>),
+    Ident(String),                            //should this be token<string>?
+    Call(Box<Token<Expr>>, Vec<Token<Expr>>), //callee, arguments
+    Range(
+        Box<Token<Expr>>,
+        Box<Token<Expr>>,
+        bool,
+        Option<Box<Token<Expr>>>,
+    ), //start, end, inclusive, step
     If(
         Box<Token<Expr>>,
         Vec<Token<ExprOrStatement>>,
         Option<Vec<Token<ExprOrStatement>>>,
     ),
+    Binary(Box<Token<Expr>>, Token<BinaryOp>, Box<Token<Expr>>),
+    Unary(Token<UnaryOp>, Box<Token<Expr>>),
+    Loop(Option<Token<String>>, Vec<Token<ExprOrStatement>>), //label, body
+    Struct(Token<String>, Vec<(Token<String>, Token<Expr>)>), //name, field values
+    Field(Box<Token<Expr>>, Token<String>),                   //value, field name or tuple position
+    Variant(Token<String>, Token<String>, Vec<Token<Expr>>),  //enum, variant, payload
+    Match(Box<Token<Expr>>, Vec<Token<MatchArm>>),            //scrutinee, arms
+    Array(Vec<Token<Expr>>),
+    Repeat(Box<Token<Expr>>, Box<Token<Expr>>), //value, count
+    Tuple(Vec<Token<Expr>>),
+    Index(Box<Token<Expr>>, Box<Token<Expr>>), //value, index or range of indices
+    Closure(Closure),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeExpr {
+    Named(String),
+    Applied(Token<String>, Vec<Token<TypeExpr>>), //generic type, type arguments
+    Function(Vec<Token<TypeExpr>>, Box<Token<TypeExpr>>), //parameter types, return type
+    Tuple(Vec<Token<TypeExpr>>),
+    Array(Box<Token<TypeExpr>>, Option<usize>), //element type, length if fixed
+}
+
+/// A type parameter of a generic function, such as `T: Ord + Display`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenericParameter(pub Token<String>, pub Vec<Token<TypeExpr>>); //name, bounds
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClosureParameter(pub Token<String>, pub Option<Token<TypeExpr>>); //name, type if given
+
+/// `|x: int| x + 1`, or `fun(x) -> int { ... }` when the return type is given.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Closure {
+    pub params: Vec<Token<ClosureParameter>>,
+    pub return_type: Option<Token<TypeExpr>>,
+    pub body: Vec<Token<ExprOrStatement>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Wildcard,
+    Value(Value),
+    Binding(String),
+    Tuple(Vec<Token<Pattern>>),
+    Struct(Token<String>, Vec<(Token<String>, Token<Pattern>)>, bool), //name, fields, ignores the rest with `..`
+    Variant(Token<String>, Token<String>, Vec<Token<Pattern>>),        //enum, variant, payload
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchArm {
+    pub pattern: Token<Pattern>,
+    pub guard: Option<Token<Expr>>,
+    pub body: Vec<Token<ExprOrStatement>>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Statement {
-    Let(Token<String>, Token<Expr>),
-    For(Token<String>, Token<Expr>, Vec<Token<ExprOrStatement>>),
+    Let(Token<String>, Option<Token<TypeExpr>>, Token<Expr>, bool), //name, type if given, value, mutable
+    Assign(Token<Expr>, Option<Token<BinaryOp>>, Token<Expr>), //place, compound operator, value
+    For(
+        Option<Token<String>>,
+        Token<String>,
+        Token<Expr>,
+        Vec<Token<ExprOrStatement>>,
+    ), //label, name, range, body
+    ForIn(
+        Option<Token<String>>,
+        Token<String>,
+        Token<Expr>,
+        Vec<Token<ExprOrStatement>>,
+    ), //label, name, iterable, body
+    While(
+        Option<Token<String>>,
+        Token<Expr>,
+        Vec<Token<ExprOrStatement>>,
+    ), //label, condition, body
+    Break(Option<Token<String>>, Option<Token<Expr>>),         //label, value
+    Continue(Option<Token<String>>),                           //label
+    Return(Option<Token<Expr>>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -33,12 +218,34 @@ pub enum ExprOrStatement {
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct Parameter(pub Token<String>, pub Token<String>); //name, type
+pub struct Parameter(pub Token<String>, pub Token<TypeExpr>); //name, type
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field(pub Token<String>, pub Token<TypeExpr>); //name, type
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant(pub Token<String>, pub Vec<Token<TypeExpr>>); //name, payload types
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Fun {
     pub name: Token<String>,
+    pub generics: Vec<Token<GenericParameter>>,
     pub params: Vec<Token<Parameter>>,
     pub body: Vec<Token<ExprOrStatement>>,
-    pub return_type: Token<String>,
+    pub return_type: Token<TypeExpr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item {
+    Fun(Fun),
+    Const(Token<String>, Token<TypeExpr>, Token<Expr>), //name, type, value
+    Type(Token<String>, Token<TypeExpr>),               //name, aliased type
+    Import(Vec<Token<String>>),                         //path
+    Struct(Token<String>, Vec<Token<Field>>),           //name, fields
+    Enum(Token<String>, Vec<Token<Variant>>),           //name, variants
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub items: Vec<Token<Item>>,
 }
@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+
+use crate::parser_combinator::Token;
+
+use super::*;
+
+/// The struct fields and enum variants declared by a program, which matches are checked against.
+#[derive(Debug, Default)]
+pub struct Declarations<'t> {
+    structs: HashMap<&'t str, Vec<&'t str>>,        //field names
+    enums: HashMap<&'t str, Vec<(&'t str, usize)>>, //variant names and payload lengths
+}
+
+impl<'t> Declarations<'t> {
+    pub fn new(program: &'t Program) -> Self {
+        let mut declarations = Self::default();
+        for item in &program.items {
+            match &item.value {
+                Item::Struct(name, fields) => {
+                    let fields = fields
+                        .iter()
+                        .map(|field| field.value.0.value.as_str())
+                        .collect();
+                    declarations.structs.insert(&name.value, fields);
+                }
+                Item::Enum(name, variants) => {
+                    let variants = variants
+                        .iter()
+                        .map(|variant| (variant.value.0.value.as_str(), variant.value.1.len()))
+                        .collect();
+                    declarations.enums.insert(&name.value, variants);
+                }
+                _ => {}
+            }
+        }
+        declarations
+    }
+
+    //undeclared structs are taken to have the fields their patterns mention
+    fn fields(&self, name: &'t str, rows: &[Row<'t>]) -> Vec<&'t str> {
+        if let Some(fields) = self.structs.get(name) {
+            return fields.clone();
+        }
+        let mut fields = Vec::new();
+        for row in rows {
+            let Pattern::Struct(struct_name, field_patterns, _) = row[0] else {
+                continue;
+            };
+            if struct_name.value != name {
+                continue;
+            }
+            for (field, _) in field_patterns {
+                if !fields.contains(&field.value.as_str()) {
+                    fields.push(&field.value);
+                }
+            }
+        }
+        fields
+    }
+
+    fn payload_length(&self, enum_name: &str, variant: &str, patterns: usize) -> usize {
+        self.enums
+            .get(enum_name)
+            .and_then(|variants| variants.iter().find(|(name, _)| *name == variant))
+            .map_or(patterns, |(_, length)| *length)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Constructor<'t> {
+    Value(&'t Value),
+    Tuple(usize),
+    Struct(&'t str, Vec<&'t str>),    //name, fields
+    Variant(&'t str, &'t str, usize), //enum, variant, payload length
+}
+
+static WILDCARD: Pattern = Pattern::Wildcard;
+static TRUE: Value = Value::Bool(true);
+static FALSE: Value = Value::Bool(false);
+
+//one pattern per column still to be matched
+type Row<'t> = Vec<&'t Pattern>;
+
+fn unspanned<T>(value: T) -> Token<T> {
+    Token::new(value, 0, 0)
+}
+
+fn is_wildcard(pattern: &Pattern) -> bool {
+    matches!(pattern, Pattern::Wildcard | Pattern::Binding(_))
+}
+
+impl<'t> Constructor<'t> {
+    fn arity(&self) -> usize {
+        match self {
+            Constructor::Value(_) => 0,
+            Constructor::Tuple(length) | Constructor::Variant(_, _, length) => *length,
+            Constructor::Struct(_, fields) => fields.len(),
+        }
+    }
+
+    //the pattern for this constructor applied to patterns for each of its fields
+    fn apply(&self, fields: Vec<Pattern>) -> Pattern {
+        match self {
+            Constructor::Value(value) => Pattern::Value((*value).clone()),
+            Constructor::Tuple(_) => Pattern::Tuple(fields.into_iter().map(unspanned).collect()),
+            Constructor::Struct(name, names) => {
+                let rest = fields.iter().any(is_wildcard);
+                let fields = names
+                    .iter()
+                    .zip(fields)
+                    .filter(|(_, pattern)| !is_wildcard(pattern))
+                    .map(|(name, pattern)| (unspanned(name.to_string()), unspanned(pattern)))
+                    .collect();
+                Pattern::Struct(unspanned(name.to_string()), fields, rest)
+            }
+            Constructor::Variant(enum_name, variant, _) => Pattern::Variant(
+                unspanned(enum_name.to_string()),
+                unspanned(variant.to_string()),
+                fields.into_iter().map(unspanned).collect(),
+            ),
+        }
+    }
+
+    //the rest of the row if its first pattern matches this constructor, with the fields in its place
+    fn specialize(&self, row: &Row<'t>) -> Option<Row<'t>> {
+        let (head, rest) = row.split_first()?;
+        let mut fields: Row<'t> = match (head, self) {
+            (Pattern::Wildcard | Pattern::Binding(_), _) => vec![&WILDCARD; self.arity()],
+            (Pattern::Value(value), Constructor::Value(constructor)) if value == *constructor => {
+                Vec::new()
+            }
+            (Pattern::Tuple(patterns), Constructor::Tuple(length)) if patterns.len() == *length => {
+                patterns.iter().map(|pattern| &pattern.value).collect()
+            }
+            (Pattern::Struct(name, patterns, _), Constructor::Struct(constructor, fields))
+                if name.value == *constructor =>
+            {
+                let field = |field: &&str| {
+                    let pattern = patterns.iter().find(|(name, _)| name.value == *field);
+                    pattern.map_or(&WILDCARD, |(_, pattern)| &pattern.value)
+                };
+                fields.iter().map(field).collect()
+            }
+            (
+                Pattern::Variant(enum_name, variant, patterns),
+                Constructor::Variant(constructor_enum, constructor_variant, length),
+            ) if enum_name.value == *constructor_enum && variant.value == *constructor_variant => {
+                let field = |i| {
+                    patterns
+                        .get(i)
+                        .map_or(&WILDCARD, |pattern: &Token<Pattern>| &pattern.value)
+                };
+                (0..*length).map(field).collect()
+            }
+            _ => return None,
+        };
+        fields.extend_from_slice(rest);
+        Some(fields)
+    }
+}
+
+//the constructors the first column uses, and all of those of its type if they can be listed
+fn constructors<'t>(
+    declarations: &Declarations<'t>,
+    rows: &[Row<'t>],
+) -> (Vec<Constructor<'t>>, Option<Vec<Constructor<'t>>>) {
+    let mut used = Vec::new();
+    for row in rows {
+        let constructor = match row[0] {
+            Pattern::Wildcard | Pattern::Binding(_) => continue,
+            Pattern::Value(value) => Constructor::Value(value),
+            Pattern::Tuple(patterns) => Constructor::Tuple(patterns.len()),
+            Pattern::Struct(name, _, _) => {
+                Constructor::Struct(&name.value, declarations.fields(&name.value, rows))
+            }
+            Pattern::Variant(enum_name, variant, patterns) => Constructor::Variant(
+                &enum_name.value,
+                &variant.value,
+                declarations.payload_length(&enum_name.value, &variant.value, patterns.len()),
+            ),
+        };
+        if !used.contains(&constructor) {
+            used.push(constructor);
+        }
+    }
+
+    let all = match used.first() {
+        None => None,
+        Some(Constructor::Value(Value::Bool(_))) => {
+            Some(vec![Constructor::Value(&TRUE), Constructor::Value(&FALSE)])
+        }
+        //numbers, chars and strings need a wildcard
+        Some(Constructor::Value(_)) => None,
+        Some(constructor @ (Constructor::Tuple(_) | Constructor::Struct(_, _))) => {
+            Some(vec![constructor.clone()])
+        }
+        //undeclared enums are left to name resolution, and assumed to have only the variants matched
+        Some(Constructor::Variant(enum_name, _, _)) => match declarations.enums.get(enum_name) {
+            Some(variants) => Some(
+                variants
+                    .iter()
+                    .map(|(variant, length)| Constructor::Variant(enum_name, variant, *length))
+                    .collect(),
+            ),
+            None => Some(used.clone()),
+        },
+    };
+    (used, all)
+}
+
+//rows of patterns, one for each column, that none of the rows match
+fn missing<'t>(
+    declarations: &Declarations<'t>,
+    rows: &[Row<'t>],
+    width: usize,
+) -> Vec<Vec<Pattern>> {
+    if width == 0 {
+        return match rows.is_empty() {
+            true => vec![Vec::new()],
+            false => Vec::new(),
+        };
+    }
+
+    let (used, all) = constructors(declarations, rows);
+    let uncovered: Vec<_> = all
+        .iter()
+        .flatten()
+        .filter(|constructor| !used.contains(constructor))
+        .collect();
+
+    //every constructor is matched, so the values missed are those missed by the fields of one
+    if let Some(all) = all.as_ref().filter(|_| uncovered.is_empty()) {
+        let mut result = Vec::new();
+        for constructor in all {
+            let arity = constructor.arity();
+            let rows: Vec<_> = rows
+                .iter()
+                .filter_map(|row| constructor.specialize(row))
+                .collect();
+            for mut witness in missing(declarations, &rows, arity + width - 1) {
+                let rest = witness.split_off(arity);
+                witness = [vec![constructor.apply(witness)], rest].concat();
+                result.push(witness);
+            }
+        }
+        return result;
+    }
+
+    //otherwise only the rows starting with a wildcard can match the constructors left out
+    let heads = match all {
+        Some(_) => uncovered
+            .iter()
+            .map(|constructor| constructor.apply(vec![Pattern::Wildcard; constructor.arity()]))
+            .collect(),
+        None => vec![Pattern::Wildcard],
+    };
+    let rows: Vec<_> = rows
+        .iter()
+        .filter(|row| is_wildcard(row[0]))
+        .map(|row| row[1..].to_vec())
+        .collect();
+    let mut result = Vec::new();
+    for witness in missing(declarations, &rows, width - 1) {
+        for head in &heads {
+            result.push([vec![head.clone()], witness.clone()].concat());
+        }
+    }
+    result
+}
+
+/// Patterns for the values none of the arms of a match cover, empty if it is exhaustive.
+/// Arms with a guard are not counted, as the guard may be false.
+pub fn missing_patterns<'t>(
+    declarations: &Declarations<'t>,
+    arms: &'t [Token<MatchArm>],
+) -> Vec<Pattern> {
+    let rows: Vec<Row> = arms
+        .iter()
+        .filter(|arm| arm.value.guard.is_none())
+        .map(|arm| vec![&arm.value.pattern.value])
+        .collect();
+    missing(declarations, &rows, 1)
+        .into_iter()
+        .map(|mut witness| witness.remove(0))
+        .collect()
+}
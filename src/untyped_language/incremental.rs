@@ -0,0 +1,402 @@
+use std::ops::Range;
+
+use crate::parser_combinator::*;
+
+use super::*;
+
+/// A change to the source text: the bytes in `range` are replaced by `replacement`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub replacement: String,
+}
+
+impl TextEdit {
+    pub fn new(range: Range<usize>, replacement: impl Into<String>) -> Self {
+        Self {
+            range,
+            replacement: replacement.into(),
+        }
+    }
+
+    /// The source with the edit made, or None if `range` does not lie on char boundaries
+    /// within it.
+    pub fn apply(&self, source: &str) -> Option<String> {
+        if self.range.start > self.range.end {
+            return None;
+        }
+        let before = source.get(..self.range.start)?;
+        let after = source.get(self.range.end..)?;
+        let mut result = String::with_capacity(source.len() + self.replacement.len());
+        result.push_str(before);
+        result.push_str(&self.replacement);
+        result.push_str(after);
+        Some(result)
+    }
+
+    fn delta(&self) -> isize {
+        self.replacement.len() as isize - self.range.len() as isize
+    }
+}
+
+/// A parsed function together with the source span of every item in its body,
+/// so that an edit can be applied by reparsing only the items it touches.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseTree {
+    pub fun: Token<Fun>,
+    items: Vec<Range<usize>>,
+    end: usize,
+}
+
+impl ParseTree {
+    /// Source span of each body item, including its terminator and trailing whitespace.
+    pub fn items(&self) -> &[Range<usize>] {
+        &self.items
+    }
+
+    /// Position just after the function, including trailing whitespace.
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    fn try_reparse(&self, edit: &TextEdit, source: &str) -> Option<ParseTree> {
+        let first_item = self.items.first()?;
+        let last_item = self.items.last()?;
+        if edit.range.start < first_item.start || edit.range.end > last_item.end {
+            return None;
+        }
+
+        //an edit touching the boundary of an item can change how it ends, so it is damaged too
+        let damaged =
+            |item: &Range<usize>| item.start <= edit.range.end && edit.range.start <= item.end;
+        let first = self.items.iter().position(damaged)?;
+        let last = self.items.iter().rposition(damaged)?;
+
+        let delta = edit.delta();
+        let start = self.items[first].start;
+        let end = shift(self.items[last].end, delta);
+
+        let mut body = self.fun.value.body[..first].to_vec();
+        let mut items = self.items[..first].to_vec();
+
+        let item_parser = pspanned_item();
+        let mut cont = ContinuationState::at(source, start);
+        while cont.position < end {
+            let (token, next) = item_parser.parse(cont).ok()?;
+            let (item, span) = token.value;
+            body.push(item);
+            items.push(span);
+            cont = next;
+        }
+        if cont.position != end || (body.is_empty() && last + 1 == self.items.len()) {
+            return None;
+        }
+
+        for (item, span) in self.fun.value.body[last + 1..]
+            .iter()
+            .zip(self.items[last + 1..].iter())
+        {
+            let mut item = item.clone();
+            item.shift(delta);
+            body.push(item);
+            items.push(shift(span.start, delta)..shift(span.end, delta));
+        }
+
+        //the length of a repeated parser counts its items rather than bytes
+        let mut fun = self.fun.clone();
+        fun.length = fun.length - fun.value.body.len() + body.len();
+        fun.value.body = body;
+
+        Some(ParseTree {
+            fun,
+            items,
+            end: shift(self.end, delta),
+        })
+    }
+}
+
+/// Parses a function, recording the spans needed by [`reparse`].
+pub fn parse(source: &str) -> Result<ParseTree, Error<'_>> {
+    let (fun, cont) = pspanned_fun().parse(source.into())?;
+    let (fun, items) = fun.value;
+    Ok(ParseTree {
+        fun,
+        items,
+        end: cont.position,
+    })
+}
+
+/// Parses `source`, the result of applying `edit` to the text `tree` was parsed from.
+/// Body items untouched by the edit are reused; only the damaged ones are reparsed.
+/// Edits outside the body, or that change where an item ends, fall back to a full parse.
+pub fn reparse<'a>(
+    tree: &ParseTree,
+    edit: &TextEdit,
+    source: &'a str,
+) -> Result<ParseTree, Error<'a>> {
+    match tree.try_reparse(edit, source) {
+        Some(tree) => Ok(tree),
+        None => parse(source),
+    }
+}
+
+fn pspanned_item<'a>() -> impl Parser<'a, (Token<ExprOrStatement>, Range<usize>)> {
+    let item = pbody_item();
+    parser_from_fn(move |input: ContinuationState<'a>| {
+        let (token, cont) = item.parse(input)?;
+        let (start, length) = (token.start, token.length);
+        let span = input.position..cont.position;
+        Ok((Token::new((token, span), start, length), cont))
+    })
+}
+
+//mirrors pfun, keeping the span of each body item
+fn pspanned_fun<'a>() -> impl Parser<'a, (Token<Fun>, Vec<Range<usize>>)> {
+    let plbrace = pchar('{').ws();
+    let prbrace = pchar('}').ws();
+    let pbody = pspanned_item().many1().between(plbrace, prbrace);
+
+    let fun_binding = pfun_header().then(pbody);
+    parser_from_fn(move |input| {
+        let (token, cont) = fun_binding.parse(input)?;
+        let (header, body) = token.value;
+        let (name, generics, params, return_type) = header.value;
+        let (body, items) = body.value.into_iter().map(|item| item.value).unzip();
+        let fun = Fun {
+            name,
+            generics,
+            params,
+            body,
+            return_type,
+        };
+        let fun = Token::new(fun, token.start, token.length);
+        Ok((Token::new((fun, items), token.start, token.length), cont))
+    })
+}
+
+fn shift(position: usize, delta: isize) -> usize {
+    (position as isize + delta) as usize
+}
+
+/// Moves every source position in a syntax tree by a fixed amount.
+pub(crate) trait Shift {
+    fn shift(&mut self, delta: isize);
+}
+
+impl<T: Shift> Shift for Token<T> {
+    fn shift(&mut self, delta: isize) {
+        self.start = shift(self.start, delta);
+        self.value.shift(delta);
+    }
+}
+
+impl<T: Shift> Shift for Box<T> {
+    fn shift(&mut self, delta: isize) {
+        self.as_mut().shift(delta);
+    }
+}
+
+impl<T: Shift> Shift for Option<T> {
+    fn shift(&mut self, delta: isize) {
+        if let Some(value) = self {
+            value.shift(delta);
+        }
+    }
+}
+
+impl<T: Shift> Shift for Vec<T> {
+    fn shift(&mut self, delta: isize) {
+        for value in self.iter_mut() {
+            value.shift(delta);
+        }
+    }
+}
+
+impl<A: Shift, B: Shift> Shift for (A, B) {
+    fn shift(&mut self, delta: isize) {
+        self.0.shift(delta);
+        self.1.shift(delta);
+    }
+}
+
+impl Shift for String {
+    fn shift(&mut self, _delta: isize) {}
+}
+
+impl Shift for Value {
+    fn shift(&mut self, _delta: isize) {}
+}
+
+impl Shift for BinaryOp {
+    fn shift(&mut self, _delta: isize) {}
+}
+
+impl Shift for UnaryOp {
+    fn shift(&mut self, _delta: isize) {}
+}
+
+impl Shift for Expr {
+    fn shift(&mut self, delta: isize) {
+        match self {
+            Expr::Value(value) => value.shift(delta),
+            Expr::Ident(name) => name.shift(delta),
+            Expr::Call(callee, args) => {
+                callee.shift(delta);
+                args.shift(delta);
+            }
+            Expr::Range(start, end, _, step) => {
+                start.shift(delta);
+                end.shift(delta);
+                step.shift(delta);
+            }
+            Expr::If(condition, body, else_body) => {
+                condition.shift(delta);
+                body.shift(delta);
+                else_body.shift(delta);
+            }
+            Expr::Binary(lhs, op, rhs) => {
+                lhs.shift(delta);
+                op.shift(delta);
+                rhs.shift(delta);
+            }
+            Expr::Unary(op, operand) => {
+                op.shift(delta);
+                operand.shift(delta);
+            }
+            Expr::Loop(label, body) => {
+                label.shift(delta);
+                body.shift(delta);
+            }
+            Expr::Struct(name, fields) => {
+                name.shift(delta);
+                fields.shift(delta);
+            }
+            Expr::Field(value, name) => {
+                value.shift(delta);
+                name.shift(delta);
+            }
+            Expr::Variant(enum_name, variant, payload) => {
+                enum_name.shift(delta);
+                variant.shift(delta);
+                payload.shift(delta);
+            }
+            Expr::Match(scrutinee, arms) => {
+                scrutinee.shift(delta);
+                arms.shift(delta);
+            }
+            Expr::Array(values) | Expr::Tuple(values) => values.shift(delta),
+            Expr::Repeat(value, count) => {
+                value.shift(delta);
+                count.shift(delta);
+            }
+            Expr::Index(value, index) => {
+                value.shift(delta);
+                index.shift(delta);
+            }
+            Expr::Closure(closure) => closure.shift(delta),
+        }
+    }
+}
+
+impl Shift for TypeExpr {
+    fn shift(&mut self, delta: isize) {
+        match self {
+            TypeExpr::Named(_) => {}
+            TypeExpr::Applied(name, arguments) => {
+                name.shift(delta);
+                arguments.shift(delta);
+            }
+            TypeExpr::Function(params, return_type) => {
+                params.shift(delta);
+                return_type.shift(delta);
+            }
+            TypeExpr::Tuple(types) => types.shift(delta),
+            TypeExpr::Array(element, _) => element.shift(delta),
+        }
+    }
+}
+
+impl Shift for ClosureParameter {
+    fn shift(&mut self, delta: isize) {
+        self.0.shift(delta);
+        self.1.shift(delta);
+    }
+}
+
+impl Shift for Closure {
+    fn shift(&mut self, delta: isize) {
+        self.params.shift(delta);
+        self.return_type.shift(delta);
+        self.body.shift(delta);
+    }
+}
+
+impl Shift for Pattern {
+    fn shift(&mut self, delta: isize) {
+        match self {
+            Pattern::Wildcard | Pattern::Value(_) | Pattern::Binding(_) => {}
+            Pattern::Tuple(patterns) => patterns.shift(delta),
+            Pattern::Struct(name, fields, _) => {
+                name.shift(delta);
+                fields.shift(delta);
+            }
+            Pattern::Variant(enum_name, variant, payload) => {
+                enum_name.shift(delta);
+                variant.shift(delta);
+                payload.shift(delta);
+            }
+        }
+    }
+}
+
+impl Shift for MatchArm {
+    fn shift(&mut self, delta: isize) {
+        self.pattern.shift(delta);
+        self.guard.shift(delta);
+        self.body.shift(delta);
+    }
+}
+
+impl Shift for Statement {
+    fn shift(&mut self, delta: isize) {
+        match self {
+            Statement::Let(name, type_, value, _) => {
+                name.shift(delta);
+                type_.shift(delta);
+                value.shift(delta);
+            }
+            Statement::Assign(place, operator, value) => {
+                place.shift(delta);
+                operator.shift(delta);
+                value.shift(delta);
+            }
+            Statement::For(label, name, range, body)
+            | Statement::ForIn(label, name, range, body) => {
+                label.shift(delta);
+                name.shift(delta);
+                range.shift(delta);
+                body.shift(delta);
+            }
+            Statement::While(label, condition, body) => {
+                label.shift(delta);
+                condition.shift(delta);
+                body.shift(delta);
+            }
+            Statement::Break(label, value) => {
+                label.shift(delta);
+                value.shift(delta);
+            }
+            Statement::Continue(label) => label.shift(delta),
+            Statement::Return(value) => value.shift(delta),
+        }
+    }
+}
+
+impl Shift for ExprOrStatement {
+    fn shift(&mut self, delta: isize) {
+        match self {
+            ExprOrStatement::Expr(expr) => expr.shift(delta),
+            ExprOrStatement::Statement(statement) => statement.shift(delta),
+        }
+    }
+}
@@ -0,0 +1,916 @@
+use std::{
+    cell::RefCell,
+    cmp::Ordering,
+    collections::HashMap,
+    io::{self, Write},
+    ops::Range,
+    rc::Rc,
+};
+
+use crate::parser_combinator::Token;
+
+use super::*;
+
+/// The number of nested calls allowed by default, so that runaway recursion is reported
+/// rather than overflowing the stack.
+pub const MAX_DEPTH: usize = 128;
+
+/// The longest array `[value; count]` makes, so that a huge count is reported rather than
+/// aborting on allocation.
+pub const MAX_REPEAT: usize = 1 << 24;
+
+//how evaluation leaves an expression other than by producing a value
+enum Unwind<'p> {
+    Break(Option<&'p str>, RuntimeValue<'p>), //label, value
+    Continue(Option<&'p str>),                //label
+    Return(RuntimeValue<'p>),
+    Error(RuntimeError),
+}
+
+impl From<RuntimeError> for Unwind<'_> {
+    fn from(error: RuntimeError) -> Self {
+        Unwind::Error(error)
+    }
+}
+
+type Eval<'p, T = RuntimeValue<'p>> = Result<T, Unwind<'p>>;
+
+//whether a break or continue naming `target` leaves the loop with `label`
+fn targets(label: &Option<Token<String>>, target: Option<&str>) -> bool {
+    target.is_none() || label.as_ref().map(|label| label.value.as_str()) == target
+}
+
+//the value a loop breaks with after one run of its body, or none to keep going
+fn looped<'p>(
+    label: &Option<Token<String>>,
+    result: Eval<'p>,
+) -> Eval<'p, Option<RuntimeValue<'p>>> {
+    match result {
+        Ok(_) => Ok(None),
+        Err(Unwind::Break(target, value)) if targets(label, target) => Ok(Some(value)),
+        Err(Unwind::Continue(target)) if targets(label, target) => Ok(None),
+        Err(unwind) => Err(unwind),
+    }
+}
+
+macro_rules! integer_op {
+    ($op:expr, $lhs:expr, $rhs:expr, $type_name:expr) => {{
+        let (op, lhs, rhs) = ($op, $lhs, $rhs);
+        let overflow = RuntimeErrorKind::Overflow(op.symbol());
+        match op {
+            BinaryOp::Add => lhs.checked_add(rhs).ok_or(overflow),
+            BinaryOp::Sub => lhs.checked_sub(rhs).ok_or(overflow),
+            BinaryOp::Mul => lhs.checked_mul(rhs).ok_or(overflow),
+            BinaryOp::Div | BinaryOp::Rem if rhs == 0 => Err(RuntimeErrorKind::DivisionByZero),
+            BinaryOp::Div => lhs.checked_div(rhs).ok_or(overflow),
+            BinaryOp::Rem => lhs.checked_rem(rhs).ok_or(overflow),
+            BinaryOp::BitAnd => Ok(lhs & rhs),
+            BinaryOp::BitOr => Ok(lhs | rhs),
+            BinaryOp::BitXor => Ok(lhs ^ rhs),
+            op => Err(RuntimeErrorKind::InvalidOperand(op.symbol(), $type_name)),
+        }
+    }};
+}
+
+macro_rules! shift_op {
+    ($op:expr, $lhs:expr, $amount:expr) => {{
+        let overflow = RuntimeErrorKind::Overflow($op.symbol());
+        match $op {
+            BinaryOp::Shl => $lhs.checked_shl($amount).ok_or(overflow),
+            _ => $lhs.checked_shr($amount).ok_or(overflow),
+        }
+    }};
+}
+
+fn compare(lhs: &RuntimeValue, rhs: &RuntimeValue) -> Option<Option<Ordering>> {
+    match (lhs, rhs) {
+        (RuntimeValue::Int(lhs), RuntimeValue::Int(rhs)) => Some(lhs.partial_cmp(rhs)),
+        (RuntimeValue::I64(lhs), RuntimeValue::I64(rhs)) => Some(lhs.partial_cmp(rhs)),
+        (RuntimeValue::U64(lhs), RuntimeValue::U64(rhs)) => Some(lhs.partial_cmp(rhs)),
+        (RuntimeValue::Float(lhs), RuntimeValue::Float(rhs)) => Some(lhs.partial_cmp(rhs)),
+        (RuntimeValue::Char(lhs), RuntimeValue::Char(rhs)) => Some(lhs.partial_cmp(rhs)),
+        (RuntimeValue::String(lhs), RuntimeValue::String(rhs)) => Some(lhs.partial_cmp(rhs)),
+        _ => None,
+    }
+}
+
+//operands of different types are reported as a mismatch, otherwise as the wrong type
+fn invalid(op: BinaryOp, lhs: &RuntimeValue, rhs: &RuntimeValue) -> RuntimeErrorKind {
+    match lhs.type_name() == rhs.type_name() {
+        true => RuntimeErrorKind::InvalidOperand(op.symbol(), lhs.type_name()),
+        false => RuntimeErrorKind::Mismatched(lhs.type_name(), rhs.type_name()),
+    }
+}
+
+/// Applies a binary operator to two values. Integer arithmetic is checked, and comparisons
+/// with NaN are false.
+pub fn binary<'p>(
+    op: BinaryOp,
+    lhs: RuntimeValue<'p>,
+    rhs: RuntimeValue<'p>,
+) -> Result<RuntimeValue<'p>, RuntimeErrorKind> {
+    use RuntimeValue::*;
+    match (op, lhs, rhs) {
+        (BinaryOp::Eq, lhs, rhs) => Ok(Bool(lhs == rhs)),
+        (BinaryOp::Ne, lhs, rhs) => Ok(Bool(lhs != rhs)),
+        (BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge, lhs, rhs) => {
+            let ordering = compare(&lhs, &rhs).ok_or_else(|| invalid(op, &lhs, &rhs))?;
+            Ok(Bool(ordering.is_some_and(|ordering| match op {
+                BinaryOp::Lt => ordering.is_lt(),
+                BinaryOp::Le => ordering.is_le(),
+                BinaryOp::Gt => ordering.is_gt(),
+                _ => ordering.is_ge(),
+            })))
+        }
+        //shifted by any integer type
+        (BinaryOp::Shl | BinaryOp::Shr, lhs, rhs) => {
+            let Some((amount, _)) = rhs.integer() else {
+                return Err(RuntimeErrorKind::Mismatched("integer", rhs.type_name()));
+            };
+            let amount =
+                u32::try_from(amount).map_err(|_| RuntimeErrorKind::Overflow(op.symbol()))?;
+            match lhs {
+                Int(lhs) => shift_op!(op, lhs, amount).map(Int),
+                I64(lhs) => shift_op!(op, lhs, amount).map(I64),
+                U64(lhs) => shift_op!(op, lhs, amount).map(U64),
+                lhs => Err(RuntimeErrorKind::InvalidOperand(
+                    op.symbol(),
+                    lhs.type_name(),
+                )),
+            }
+        }
+        (op, Int(lhs), Int(rhs)) => integer_op!(op, lhs, rhs, "int").map(Int),
+        (op, I64(lhs), I64(rhs)) => integer_op!(op, lhs, rhs, "i64").map(I64),
+        (op, U64(lhs), U64(rhs)) => integer_op!(op, lhs, rhs, "u64").map(U64),
+        (BinaryOp::Add, Float(lhs), Float(rhs)) => Ok(Float(lhs + rhs)),
+        (BinaryOp::Sub, Float(lhs), Float(rhs)) => Ok(Float(lhs - rhs)),
+        (BinaryOp::Mul, Float(lhs), Float(rhs)) => Ok(Float(lhs * rhs)),
+        (BinaryOp::Div, Float(lhs), Float(rhs)) => Ok(Float(lhs / rhs)),
+        (BinaryOp::Rem, Float(lhs), Float(rhs)) => Ok(Float(lhs % rhs)),
+        (BinaryOp::Add, String(lhs), String(rhs)) => Ok(String(lhs + &rhs)),
+        (BinaryOp::And | BinaryOp::BitAnd, Bool(lhs), Bool(rhs)) => Ok(Bool(lhs & rhs)),
+        (BinaryOp::Or | BinaryOp::BitOr, Bool(lhs), Bool(rhs)) => Ok(Bool(lhs | rhs)),
+        (BinaryOp::BitXor, Bool(lhs), Bool(rhs)) => Ok(Bool(lhs ^ rhs)),
+        (op, lhs, rhs) => Err(invalid(op, &lhs, &rhs)),
+    }
+}
+
+/// Applies a unary operator to a value.
+pub fn unary(op: UnaryOp, value: RuntimeValue) -> Result<RuntimeValue, RuntimeErrorKind> {
+    use RuntimeValue::*;
+    let overflow = || RuntimeErrorKind::Overflow(op.symbol());
+    match (op, value) {
+        (UnaryOp::Neg, Int(value)) => value.checked_neg().map(Int).ok_or_else(overflow),
+        (UnaryOp::Neg, I64(value)) => value.checked_neg().map(I64).ok_or_else(overflow),
+        (UnaryOp::Neg, Float(value)) => Ok(Float(-value)),
+        (UnaryOp::Not, Bool(value)) => Ok(Bool(!value)),
+        (UnaryOp::Not, Int(value)) => Ok(Int(!value)),
+        (UnaryOp::Not, I64(value)) => Ok(I64(!value)),
+        (UnaryOp::Not, U64(value)) => Ok(U64(!value)),
+        (op, value) => Err(RuntimeErrorKind::InvalidOperand(
+            op.symbol(),
+            value.type_name(),
+        )),
+    }
+}
+
+//a step from a binding into one of its fields or elements, for assignments
+enum Accessor<'p> {
+    Field(&'p Token<String>),  //struct field or tuple position
+    Index(i128, Range<usize>), //index, span of the index
+}
+
+fn element<'v, 'p>(
+    value: &'v mut RuntimeValue<'p>,
+    accessor: &Accessor<'p>,
+) -> Result<&'v mut RuntimeValue<'p>, (RuntimeErrorKind, Range<usize>)> {
+    match (value, accessor) {
+        (RuntimeValue::Struct(_, fields), Accessor::Field(name)) => fields
+            .iter_mut()
+            .find(|(field, _)| *field == name.value)
+            .map(|(_, value)| value)
+            .ok_or_else(|| {
+                (
+                    RuntimeErrorKind::UnknownField(name.value.clone()),
+                    name.span(),
+                )
+            }),
+        (RuntimeValue::Tuple(values), Accessor::Field(name)) => {
+            let position = name.value.parse::<usize>().ok();
+            position
+                .and_then(|position| values.get_mut(position))
+                .ok_or_else(|| {
+                    (
+                        RuntimeErrorKind::UnknownField(name.value.clone()),
+                        name.span(),
+                    )
+                })
+        }
+        (RuntimeValue::Array(values), Accessor::Index(index, span)) => {
+            let length = values.len();
+            usize::try_from(*index)
+                .ok()
+                .and_then(|index| values.get_mut(index))
+                .ok_or_else(|| {
+                    (
+                        RuntimeErrorKind::IndexOutOfBounds(*index, length),
+                        span.clone(),
+                    )
+                })
+        }
+        (value, Accessor::Field(name)) => Err((
+            RuntimeErrorKind::InvalidOperand(".", value.type_name()),
+            name.span(),
+        )),
+        (value, Accessor::Index(_, span)) => Err((
+            RuntimeErrorKind::InvalidOperand("[]", value.type_name()),
+            span.clone(),
+        )),
+    }
+}
+
+//the index range a slice covers, checked against the length being sliced
+fn slice_bounds(range: &RangeValue, length: usize) -> Result<Range<usize>, RuntimeErrorKind> {
+    if range.step != 1 {
+        let message = format!("cannot slice with a step of {}", range.step);
+        return Err(RuntimeErrorKind::InvalidArgument(message));
+    }
+    let end = range.end + range.inclusive as i128;
+    let out_of_bounds = |index| RuntimeErrorKind::IndexOutOfBounds(index, length);
+    if range.start < 0 || range.start > end {
+        return Err(out_of_bounds(range.start));
+    }
+    if end > length as i128 {
+        return Err(out_of_bounds(end));
+    }
+    Ok(range.start as usize..end as usize)
+}
+
+/// Runs the functions of a program by walking their syntax trees, writing what they print
+/// to `W`.
+pub struct Interpreter<'p, W = io::Stdout> {
+    funs: HashMap<&'p str, &'p Fun>,
+    consts: HashMap<&'p str, &'p Token<Expr>>,
+    const_values: HashMap<&'p str, RuntimeValue<'p>>,
+    evaluating: Vec<&'p str>, //constants being evaluated, to catch cycles
+    structs: HashMap<&'p str, Vec<&'p str>>, //field names in declared order
+    bindings: Vec<(&'p str, Cell<'p>)>, //of the running function, innermost last
+    frames: Vec<Frame>,
+    pub max_depth: usize,
+    output: W,
+}
+
+impl<'p> Interpreter<'p> {
+    pub fn new(program: &'p Program) -> Self {
+        Self::with_output(program, io::stdout())
+    }
+}
+
+impl<'p, W: Write> Interpreter<'p, W> {
+    pub fn with_output(program: &'p Program, output: W) -> Self {
+        let mut interpreter = Self {
+            funs: HashMap::new(),
+            consts: HashMap::new(),
+            const_values: HashMap::new(),
+            evaluating: Vec::new(),
+            structs: HashMap::new(),
+            bindings: Vec::new(),
+            frames: Vec::new(),
+            max_depth: MAX_DEPTH,
+            output,
+        };
+        for item in &program.items {
+            match &item.value {
+                Item::Fun(fun) => {
+                    interpreter.funs.insert(&fun.name.value, fun);
+                }
+                Item::Const(name, _, value) => {
+                    interpreter.consts.insert(&name.value, value);
+                }
+                Item::Struct(name, fields) => {
+                    let fields = fields.iter().map(|field| field.value.0.value.as_str());
+                    interpreter.structs.insert(&name.value, fields.collect());
+                }
+                Item::Type(_, _) | Item::Import(_) | Item::Enum(_, _) => {}
+            }
+        }
+        interpreter
+    }
+
+    pub fn into_output(self) -> W {
+        self.output
+    }
+
+    /// Calls the program's `main` function with no arguments.
+    pub fn run(&mut self) -> Result<RuntimeValue<'p>, RuntimeError> {
+        self.call("main", Vec::new())
+    }
+
+    /// Calls a function or builtin by name.
+    pub fn call(
+        &mut self,
+        name: &str,
+        args: Vec<RuntimeValue<'p>>,
+    ) -> Result<RuntimeValue<'p>, RuntimeError> {
+        let callee = match self.lookup(name, 0..0) {
+            Ok(callee) => callee,
+            Err(Unwind::Error(error)) => return Err(error),
+            Err(_) => RuntimeValue::Unit,
+        };
+        self.call_value(callee, args, None)
+    }
+
+    fn error(&self, kind: RuntimeErrorKind, span: Range<usize>) -> RuntimeError {
+        RuntimeError {
+            kind,
+            span,
+            trace: self.frames.iter().rev().cloned().collect(),
+        }
+    }
+
+    fn fail<T>(&self, kind: RuntimeErrorKind, span: Range<usize>) -> Eval<'p, T> {
+        Err(Unwind::Error(self.error(kind, span)))
+    }
+
+    fn bind(&mut self, name: &'p str, value: RuntimeValue<'p>) {
+        self.bindings.push((name, Rc::new(RefCell::new(value))));
+    }
+
+    //runs f in a scope of its own, however it leaves
+    fn scoped<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let scope = self.bindings.len();
+        let result = f(self);
+        self.bindings.truncate(scope);
+        result
+    }
+
+    fn lookup(&mut self, name: &str, span: Range<usize>) -> Eval<'p> {
+        let local = self.bindings.iter().rev().find(|(bound, _)| *bound == name);
+        if let Some((_, cell)) = local {
+            return Ok(cell.borrow().clone());
+        }
+        if let Some(value) = self.constant(name, span.clone())? {
+            return Ok(value);
+        }
+        if let Some(fun) = self.funs.get(name) {
+            return Ok(RuntimeValue::Function(fun));
+        }
+        match Builtin::from_name(name) {
+            Some(builtin) => Ok(RuntimeValue::Builtin(builtin)),
+            None => self.fail(RuntimeErrorKind::Undefined(name.to_string()), span),
+        }
+    }
+
+    //constants are evaluated when first used, with nothing but globals in scope
+    fn constant(&mut self, name: &str, span: Range<usize>) -> Eval<'p, Option<RuntimeValue<'p>>> {
+        if let Some(value) = self.const_values.get(name) {
+            return Ok(Some(value.clone()));
+        }
+        let Some((&name, &value)) = self.consts.get_key_value(name) else {
+            return Ok(None);
+        };
+        if self.evaluating.contains(&name) {
+            return self.fail(RuntimeErrorKind::RecursiveConst(name.to_string()), span);
+        }
+        self.evaluating.push(name);
+        let bindings = std::mem::take(&mut self.bindings);
+        let result = self.expr(value);
+        self.bindings = bindings;
+        self.evaluating.pop();
+        let value = result?;
+        self.const_values.insert(name, value.clone());
+        Ok(Some(value))
+    }
+
+    fn call_value(
+        &mut self,
+        callee: RuntimeValue<'p>,
+        args: Vec<RuntimeValue<'p>>,
+        call: Option<Range<usize>>,
+    ) -> Result<RuntimeValue<'p>, RuntimeError> {
+        let span = call.clone().unwrap_or(0..0);
+        match callee {
+            RuntimeValue::Function(fun) => {
+                let span = call.clone().unwrap_or_else(|| fun.name.span());
+                let params = fun.params.iter().map(|param| param.value.0.value.as_str());
+                let scope = Vec::new();
+                let frame = Frame {
+                    function: fun.name.value.clone(),
+                    call,
+                };
+                self.invoke(frame, span, scope, params.collect(), args, &fun.body)
+            }
+            RuntimeValue::Closure(closure) => {
+                let params = closure.closure.params.iter();
+                let params = params.map(|param| param.value.0.value.as_str());
+                let frame = Frame {
+                    function: "<closure>".to_string(),
+                    call,
+                };
+                let scope = closure.captured.clone();
+                let body = &closure.closure.body;
+                self.invoke(frame, span, scope, params.collect(), args, body)
+            }
+            RuntimeValue::Builtin(builtin) => {
+                if args.len() != builtin.arity() {
+                    let kind = RuntimeErrorKind::ArityMismatch(builtin.arity(), args.len());
+                    return Err(self.error(kind, span));
+                }
+                match builtin {
+                    Builtin::Print | Builtin::Println => {
+                        let newline = if builtin == Builtin::Println {
+                            "\n"
+                        } else {
+                            ""
+                        };
+                        write!(self.output, "{}{}", args[0], newline).map_err(|error| {
+                            self.error(RuntimeErrorKind::Output(error.to_string()), span)
+                        })?;
+                        Ok(RuntimeValue::Unit)
+                    }
+                    builtin => builtin.apply(&args).map_err(|kind| self.error(kind, span)),
+                }
+            }
+            callee => Err(self.error(RuntimeErrorKind::NotCallable(callee.type_name()), span)),
+        }
+    }
+
+    //runs a function or closure body with its parameters bound to the arguments
+    fn invoke(
+        &mut self,
+        frame: Frame,
+        span: Range<usize>,
+        scope: Vec<(&'p str, Cell<'p>)>,
+        params: Vec<&'p str>,
+        args: Vec<RuntimeValue<'p>>,
+        body: &'p [Token<ExprOrStatement>],
+    ) -> Result<RuntimeValue<'p>, RuntimeError> {
+        if params.len() != args.len() {
+            let kind = RuntimeErrorKind::ArityMismatch(params.len(), args.len());
+            return Err(self.error(kind, span));
+        }
+        if self.frames.len() >= self.max_depth {
+            return Err(self.error(RuntimeErrorKind::StackOverflow, span));
+        }
+        self.frames.push(frame);
+        let bindings = std::mem::replace(&mut self.bindings, scope);
+        for (param, arg) in params.into_iter().zip(args) {
+            self.bind(param, arg);
+        }
+        let result = self.body(body);
+        self.bindings = bindings;
+        self.frames.pop();
+        //breaks and continues outside of a loop are reported by validation
+        match result {
+            Ok(value) | Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Error(error)) => Err(error),
+            Err(Unwind::Break(_, _) | Unwind::Continue(_)) => Ok(RuntimeValue::Unit),
+        }
+    }
+
+    //the value of the last item if it is an expression, otherwise unit
+    fn body(&mut self, body: &'p [Token<ExprOrStatement>]) -> Eval<'p> {
+        self.scoped(|this| {
+            let mut value = RuntimeValue::Unit;
+            for item in body {
+                value = match &item.value {
+                    ExprOrStatement::Expr(expr) => this.expr_at(expr, item.span())?,
+                    ExprOrStatement::Statement(statement) => {
+                        this.statement(statement, item.span())?;
+                        RuntimeValue::Unit
+                    }
+                };
+            }
+            Ok(value)
+        })
+    }
+
+    fn bool(&mut self, expr: &'p Token<Expr>) -> Eval<'p, bool> {
+        match self.expr(expr)? {
+            RuntimeValue::Bool(value) => Ok(value),
+            value => self.fail(
+                RuntimeErrorKind::Mismatched("bool", value.type_name()),
+                expr.span(),
+            ),
+        }
+    }
+
+    fn integer(&mut self, expr: &'p Token<Expr>) -> Eval<'p, (i128, Integer)> {
+        let value = self.expr(expr)?;
+        match value.integer() {
+            Some(integer) => Ok(integer),
+            None => self.fail(
+                RuntimeErrorKind::Mismatched("integer", value.type_name()),
+                expr.span(),
+            ),
+        }
+    }
+
+    //runs a loop body once for each value, with `name` bound to it
+    fn for_each(
+        &mut self,
+        label: &'p Option<Token<String>>,
+        name: &'p str,
+        values: impl Iterator<Item = Result<RuntimeValue<'p>, RuntimeErrorKind>>,
+        body: &'p [Token<ExprOrStatement>],
+        span: Range<usize>,
+    ) -> Eval<'p, ()> {
+        for value in values {
+            let value = value.map_err(|kind| self.error(kind, span.clone()))?;
+            let result = self.scoped(|this| {
+                this.bind(name, value);
+                this.body(body)
+            });
+            if looped(label, result)?.is_some() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn range_values(
+        range: RangeValue,
+    ) -> impl Iterator<Item = Result<RuntimeValue<'p>, RuntimeErrorKind>> {
+        let kind = range.kind;
+        range.values().map(move |value| {
+            RuntimeValue::from_integer(value, kind).ok_or(RuntimeErrorKind::Overflow(".."))
+        })
+    }
+
+    //the binding a place is in, and the fields and elements leading to it
+    fn place(&mut self, place: &'p Token<Expr>) -> Eval<'p, (Cell<'p>, Vec<Accessor<'p>>)> {
+        match &place.value {
+            Expr::Ident(name) => {
+                let local = self.bindings.iter().rev().find(|(bound, _)| bound == name);
+                match local {
+                    Some((_, cell)) => Ok((cell.clone(), Vec::new())),
+                    None => {
+                        let message = format!("cannot assign to {}", name);
+                        self.fail(RuntimeErrorKind::InvalidArgument(message), place.span())
+                    }
+                }
+            }
+            Expr::Field(value, name) => {
+                let (cell, mut path) = self.place(value)?;
+                path.push(Accessor::Field(name));
+                Ok((cell, path))
+            }
+            Expr::Index(value, index) => {
+                let (cell, mut path) = self.place(value)?;
+                let (index_value, _) = self.integer(index)?;
+                path.push(Accessor::Index(index_value, index.span()));
+                Ok((cell, path))
+            }
+            _ => self.fail(
+                RuntimeErrorKind::InvalidOperand("=", "expression"),
+                place.span(),
+            ),
+        }
+    }
+
+    fn with_place<T>(
+        &self,
+        (cell, path): &(Cell<'p>, Vec<Accessor<'p>>),
+        f: impl FnOnce(&mut RuntimeValue<'p>) -> T,
+    ) -> Eval<'p, T> {
+        let mut root = cell.borrow_mut();
+        let mut target = &mut *root;
+        for accessor in path {
+            target = element(target, accessor).map_err(|(kind, span)| self.error(kind, span))?;
+        }
+        Ok(f(target))
+    }
+
+    fn statement(&mut self, statement: &'p Statement, span: Range<usize>) -> Eval<'p, ()> {
+        match statement {
+            Statement::Let(name, _, value, _) => {
+                let value = self.expr(value)?;
+                self.bind(&name.value, value);
+            }
+            Statement::Assign(place, operator, value) => {
+                let value = self.expr(value)?;
+                let place = self.place(place)?;
+                let value = match operator {
+                    Some(operator) => {
+                        let current = self.with_place(&place, |current| current.clone())?;
+                        binary(operator.value, current, value)
+                            .map_err(|kind| self.error(kind, span))?
+                    }
+                    None => value,
+                };
+                self.with_place(&place, |current| *current = value)?;
+            }
+            Statement::For(label, name, range, body) => {
+                let values = match self.expr(range)? {
+                    RuntimeValue::Range(range) => Self::range_values(range),
+                    value => {
+                        let kind = RuntimeErrorKind::Mismatched("range", value.type_name());
+                        return self.fail(kind, range.span());
+                    }
+                };
+                self.for_each(label, &name.value, values, body, range.span())?;
+            }
+            Statement::ForIn(label, name, iterable, body) => {
+                let values: Box<dyn Iterator<Item = _>> = match self.expr(iterable)? {
+                    RuntimeValue::Array(values) => Box::new(values.into_iter().map(Ok)),
+                    RuntimeValue::Range(range) => Box::new(Self::range_values(range)),
+                    RuntimeValue::String(value) => {
+                        let chars: Vec<_> = value.chars().map(RuntimeValue::Char).collect();
+                        Box::new(chars.into_iter().map(Ok))
+                    }
+                    value => {
+                        let kind = RuntimeErrorKind::Mismatched(
+                            "array, range or string",
+                            value.type_name(),
+                        );
+                        return self.fail(kind, iterable.span());
+                    }
+                };
+                self.for_each(label, &name.value, values, body, iterable.span())?;
+            }
+            Statement::While(label, condition, body) => {
+                while self.bool(condition)? {
+                    if looped(label, self.body(body))?.is_some() {
+                        break;
+                    }
+                }
+            }
+            Statement::Break(label, value) => {
+                let value = match value {
+                    Some(value) => self.expr(value)?,
+                    None => RuntimeValue::Unit,
+                };
+                let label = label.as_ref().map(|label| label.value.as_str());
+                return Err(Unwind::Break(label, value));
+            }
+            Statement::Continue(label) => {
+                let label = label.as_ref().map(|label| label.value.as_str());
+                return Err(Unwind::Continue(label));
+            }
+            Statement::Return(value) => {
+                let value = match value {
+                    Some(value) => self.expr(value)?,
+                    None => RuntimeValue::Unit,
+                };
+                return Err(Unwind::Return(value));
+            }
+        }
+        Ok(())
+    }
+
+    //binds the names in a pattern as it goes, so a failed match leaves some bound
+    fn pattern(&mut self, pattern: &'p Token<Pattern>, value: &RuntimeValue<'p>) -> bool {
+        match (&pattern.value, value) {
+            (Pattern::Wildcard, _) => true,
+            (Pattern::Binding(name), value) => {
+                self.bind(name, value.clone());
+                true
+            }
+            (Pattern::Value(literal), value) => RuntimeValue::from(literal) == *value,
+            (Pattern::Tuple(patterns), RuntimeValue::Unit) => patterns.is_empty(),
+            (Pattern::Tuple(patterns), RuntimeValue::Tuple(values)) => {
+                patterns.len() == values.len()
+                    && patterns
+                        .iter()
+                        .zip(values)
+                        .all(|(pattern, value)| self.pattern(pattern, value))
+            }
+            (Pattern::Struct(name, fields, _), RuntimeValue::Struct(struct_name, values)) => {
+                name.value == *struct_name
+                    && fields.iter().all(|(field, pattern)| {
+                        let value = values.iter().find(|(name, _)| *name == field.value);
+                        value.is_some_and(|(_, value)| self.pattern(pattern, value))
+                    })
+            }
+            (
+                Pattern::Variant(enum_name, variant, patterns),
+                RuntimeValue::Variant(value_enum, value_variant, values),
+            ) => {
+                enum_name.value == *value_enum
+                    && variant.value == *value_variant
+                    && patterns.len() == values.len()
+                    && patterns
+                        .iter()
+                        .zip(values)
+                        .all(|(pattern, value)| self.pattern(pattern, value))
+            }
+            _ => false,
+        }
+    }
+
+    fn index(
+        &self,
+        value: RuntimeValue<'p>,
+        index: RuntimeValue<'p>,
+        value_span: Range<usize>,
+        index_span: Range<usize>,
+    ) -> Eval<'p> {
+        let out_of_bounds = |index, length| RuntimeErrorKind::IndexOutOfBounds(index, length);
+        let not_an_index = |index: &RuntimeValue| {
+            RuntimeErrorKind::Mismatched("integer or range", index.type_name())
+        };
+        let result = match (value, index) {
+            (RuntimeValue::Array(values), RuntimeValue::Range(range)) => {
+                slice_bounds(&range, values.len())
+                    .map(|bounds| RuntimeValue::Array(values[bounds].to_vec()))
+            }
+            (RuntimeValue::String(value), RuntimeValue::Range(range)) => {
+                let chars: Vec<_> = value.chars().collect();
+                slice_bounds(&range, chars.len())
+                    .map(|bounds| RuntimeValue::String(chars[bounds].iter().collect()))
+            }
+            (RuntimeValue::Array(values), index) => match index.integer() {
+                Some((index, _)) => usize::try_from(index)
+                    .ok()
+                    .and_then(|position| values.get(position).cloned())
+                    .ok_or(out_of_bounds(index, values.len())),
+                None => Err(not_an_index(&index)),
+            },
+            (RuntimeValue::String(value), index) => match index.integer() {
+                Some((index, _)) => usize::try_from(index)
+                    .ok()
+                    .and_then(|position| value.chars().nth(position))
+                    .map(RuntimeValue::Char)
+                    .ok_or(out_of_bounds(index, value.chars().count())),
+                None => Err(not_an_index(&index)),
+            },
+            (value, _) => {
+                let kind = RuntimeErrorKind::InvalidOperand("[]", value.type_name());
+                return self.fail(kind, value_span);
+            }
+        };
+        result.or_else(|kind| self.fail(kind, index_span))
+    }
+
+    fn expr(&mut self, expr: &'p Token<Expr>) -> Eval<'p> {
+        self.expr_at(&expr.value, expr.span())
+    }
+
+    fn expr_at(&mut self, expr: &'p Expr, span: Range<usize>) -> Eval<'p> {
+        match expr {
+            Expr::Value(value) => Ok(value.into()),
+            Expr::Ident(name) => self.lookup(name, span),
+            Expr::Call(callee, args) => {
+                let callee = self.expr(callee)?;
+                let args = args
+                    .iter()
+                    .map(|arg| self.expr(arg))
+                    .collect::<Result<_, _>>()?;
+                Ok(self.call_value(callee, args, Some(span))?)
+            }
+            Expr::Range(start, end, inclusive, step) => {
+                let (start, kind) = self.integer(start)?;
+                let (end, _) = self.integer(end)?;
+                let step = match step {
+                    Some(step) => match self.integer(step)? {
+                        (0, _) => {
+                            let message = "range step cannot be zero".to_string();
+                            return self
+                                .fail(RuntimeErrorKind::InvalidArgument(message), step.span());
+                        }
+                        (step, _) => step,
+                    },
+                    None => 1,
+                };
+                Ok(RuntimeValue::Range(RangeValue {
+                    start,
+                    end,
+                    inclusive: *inclusive,
+                    step,
+                    kind,
+                }))
+            }
+            Expr::If(condition, body, else_body) => {
+                let condition = self.bool(condition)?;
+                match (condition, else_body) {
+                    (true, None) => self.body(body).map(|_| RuntimeValue::Unit),
+                    (true, Some(_)) => self.body(body),
+                    (false, Some(else_body)) => self.body(else_body),
+                    (false, None) => Ok(RuntimeValue::Unit),
+                }
+            }
+            Expr::Binary(lhs, op, rhs) if matches!(op.value, BinaryOp::And | BinaryOp::Or) => {
+                let lhs = self.bool(lhs)?;
+                match (op.value, lhs) {
+                    (BinaryOp::And, false) => Ok(RuntimeValue::Bool(false)),
+                    (BinaryOp::Or, true) => Ok(RuntimeValue::Bool(true)),
+                    _ => self.bool(rhs).map(RuntimeValue::Bool),
+                }
+            }
+            Expr::Binary(lhs, op, rhs) => {
+                let lhs = self.expr(lhs)?;
+                let rhs = self.expr(rhs)?;
+                binary(op.value, lhs, rhs).or_else(|kind| self.fail(kind, span))
+            }
+            Expr::Unary(op, operand) => {
+                let value = self.expr(operand)?;
+                unary(op.value, value).or_else(|kind| self.fail(kind, span))
+            }
+            Expr::Loop(label, body) => loop {
+                if let Some(value) = looped(label, self.body(body))? {
+                    return Ok(value);
+                }
+            },
+            Expr::Struct(name, fields) => {
+                let mut values = Vec::with_capacity(fields.len());
+                for (field, value) in fields {
+                    values.push((field, self.expr(value)?));
+                }
+                //fields are kept in the order they are declared in, so equal structs compare equal
+                let declared = self.structs.get(name.value.as_str());
+                let position = |field: &str| {
+                    declared.and_then(|declared| declared.iter().position(|name| *name == field))
+                };
+                if let Some((field, _)) = values
+                    .iter()
+                    .find(|(field, _)| declared.is_some() && position(&field.value).is_none())
+                {
+                    let kind = RuntimeErrorKind::UnknownField(field.value.clone());
+                    return self.fail(kind, field.span());
+                }
+                values.sort_by_key(|(field, _)| position(&field.value));
+                let values = values
+                    .into_iter()
+                    .map(|(field, value)| (field.value.as_str(), value));
+                Ok(RuntimeValue::Struct(&name.value, values.collect()))
+            }
+            Expr::Field(value, name) => {
+                let mut value = self.expr(value)?;
+                let field = element(&mut value, &Accessor::Field(name))
+                    .map(|field| field.clone())
+                    .map_err(|(kind, span)| self.error(kind, span))?;
+                Ok(field)
+            }
+            Expr::Variant(enum_name, variant, payload) => {
+                let payload = payload
+                    .iter()
+                    .map(|value| self.expr(value))
+                    .collect::<Result<_, _>>()?;
+                Ok(RuntimeValue::Variant(
+                    &enum_name.value,
+                    &variant.value,
+                    payload,
+                ))
+            }
+            Expr::Match(scrutinee, arms) => {
+                let value = self.expr(scrutinee)?;
+                for arm in arms {
+                    let result = self.scoped(|this| {
+                        if !this.pattern(&arm.value.pattern, &value) {
+                            return None;
+                        }
+                        if let Some(guard) = &arm.value.guard {
+                            match this.bool(guard) {
+                                Ok(true) => {}
+                                Ok(false) => return None,
+                                Err(unwind) => return Some(Err(unwind)),
+                            }
+                        }
+                        Some(this.body(&arm.value.body))
+                    });
+                    if let Some(result) = result {
+                        return result;
+                    }
+                }
+                self.fail(RuntimeErrorKind::NoMatchingArm, scrutinee.span())
+            }
+            Expr::Array(values) => {
+                let values = values
+                    .iter()
+                    .map(|value| self.expr(value))
+                    .collect::<Result<_, _>>()?;
+                Ok(RuntimeValue::Array(values))
+            }
+            Expr::Repeat(value, count) => {
+                let value = self.expr(value)?;
+                let (count_value, _) = self.integer(count)?;
+                match usize::try_from(count_value) {
+                    Ok(count) if count <= MAX_REPEAT => Ok(RuntimeValue::Array(vec![value; count])),
+                    _ => {
+                        let message = format!("cannot repeat a value {} times", count_value);
+                        self.fail(RuntimeErrorKind::InvalidArgument(message), count.span())
+                    }
+                }
+            }
+            Expr::Tuple(values) if values.is_empty() => Ok(RuntimeValue::Unit),
+            Expr::Tuple(values) => {
+                let values = values
+                    .iter()
+                    .map(|value| self.expr(value))
+                    .collect::<Result<_, _>>()?;
+                Ok(RuntimeValue::Tuple(values))
+            }
+            Expr::Index(value, index) => {
+                let value_span = value.span();
+                let index_span = index.span();
+                let value = self.expr(value)?;
+                let index = self.expr(index)?;
+                self.index(value, index, value_span, index_span)
+            }
+            Expr::Closure(closure) => Ok(RuntimeValue::Closure(Rc::new(ClosureValue {
+                closure,
+                captured: self.bindings.clone(),
+            }))),
+        }
+    }
+}
@@ -1,73 +1,66 @@
+use std::cell::Cell;
+
 use crate::parser_combinator::*;
 use crate::pchoice;
 
 use super::*;
 
-const FUN: &str = "fun";
-const _LET: &str = "let";
-const _IF: &str = "if";
-const _ELSE: &str = "else";
+pub(crate) const FUN: &str = "fun";
+const LET: &str = "let";
+const MUT: &str = "mut";
+const IF: &str = "if";
+const ELSE: &str = "else";
+const FOR: &str = "for";
+const IN: &str = "in";
+const WHILE: &str = "while";
+const LOOP: &str = "loop";
+const BREAK: &str = "break";
+const CONTINUE: &str = "continue";
+const RETURN: &str = "return";
+pub(crate) const CONST: &str = "const";
+pub(crate) const TYPE: &str = "type";
+pub(crate) const IMPORT: &str = "import";
+pub(crate) const STRUCT: &str = "struct";
+pub(crate) const ENUM: &str = "enum";
+const MATCH: &str = "match";
+const STEP: &str = "step";
 const TRUE: &str = "true";
 const FALSE: &str = "false";
-const _RESERVED: [&str; 6] = [FUN, _LET, _IF, _ELSE, TRUE, FALSE];
-
-pub(crate) fn pint<'a>() -> impl Parser<'a, Value> {
-    let any_number = pany_range('0'..='9');
-    let many_numbers = any_number.many1();
-    let number_parser = pchar('-').optional().then(many_numbers);
-    let pnumber = number_parser.map(move |(negate, value)| {
-        let string: String = value.value.into_iter().map(|c| c.value).collect();
-        let number = string.parse::<i32>().unwrap();
-        match negate.value {
-            Some(_) => -number,
-            None => number,
-        }
-    });
-    pnumber.map(Value::Number)
-}
+const _RESERVED: [&str; 21] = [
+    FUN, LET, MUT, IF, ELSE, FOR, IN, WHILE, LOOP, BREAK, CONTINUE, RETURN, STEP, CONST, TYPE,
+    IMPORT, STRUCT, ENUM, MATCH, TRUE, FALSE,
+];
+const WILDCARD: &str = "_";
 
 fn pbool<'a>() -> impl Parser<'a, Value> {
-    let ptrue = pstring(TRUE).map(|_| true);
-    let pfalse = pstring(FALSE).map(|_| false);
+    let ptrue = pkeyword(TRUE).map(|_| true);
+    let pfalse = pkeyword(FALSE).map(|_| false);
     ptrue.or(pfalse).map(Value::Bool)
 }
 
-pub fn pquoted_string<'a>() -> impl Parser<'a, Value> {
-    let pquote = pchar('"');
-    let pstring = pquote.clone().then(pquote.take_until()).right();
-    pstring.map(|string| Value::String(string.to_string()))
-}
-
 fn pvalue<'a>() -> impl Parser<'a, Value> {
-    pchoice!(pint(), pbool(), pquoted_string())
+    pchoice!(pnumber(), pbool(), pchar_literal(), praw_string())
 }
 
-fn prange<'a>() -> impl Parser<'a, Expr> {
-    let pstart = pexpr().ws();
-    let pend = pexpr().ws();
-    let pmid = pstring("..").ws();
-    pstart
-        .then(pmid)
-        .left()
-        .then(pend)
-        .map(|(start, end)| Expr::Range(Box::new(start), Box::new(end)))
+//widens the token to start where the parser did, so that a declaration spans its keyword
+fn pfrom_start<'a, T: Clone + 'a>(parser: impl Parser<'a, T> + 'a) -> impl Parser<'a, T> {
+    parser_from_fn(move |input: ContinuationState<'a>| {
+        let (token, cont) = parser.parse(input)?;
+        let length = token.start + token.length - input.position;
+        Ok((Token::new(token.value, input.position, length), cont))
+    })
 }
 
 //TODO disallow reserved words
 pub fn pidentifier<'a>() -> impl Parser<'a, String> {
-    let ident = pany_range('a'..='z')
-        .or(pany_range('A'..='Z'))
-        .or(pchar('_'));
-    let alpha_numeric = pany_range('a'..='z')
-        .or(pany_range('A'..='Z'))
-        .or(pany_range('0'..='9'))
-        .or(pchar('_'))
-        .many();
+    let ident = pclass(CharClass::alpha() | CharClass::char('_'));
+    let alpha_numeric = ptake_while(CharClass::alnum() | CharClass::char('_'));
     let ident = ident.then(alpha_numeric);
 
     ident.map(|(start, rest)| {
-        let mut result: String = rest.value.into_iter().map(|c| c.value).collect();
-        result.insert(0, start.value);
+        let mut result = String::with_capacity(rest.length + 1);
+        result.push(start.value);
+        result.push_str(rest.value);
         result
     })
 }
@@ -76,10 +69,123 @@ pub fn pterminator<'a>() -> impl Parser<'a, ()> {
     pchar(';').map(|_| ()).ws()
 }
 
+fn pfunction_type<'a>() -> impl Parser<'a, TypeExpr> {
+    let lparen = pchar('(').ws();
+    let rparen = pchar(')').ws();
+    let params = ptype().ws().sep_by(pchar(',').ws());
+    let function_binding = pkeyword(FUN).ws();
+    let function_binding = function_binding.then(params.between(lparen, rparen));
+    let function_binding = function_binding.then(pstring("->").ws()).left();
+    let function_binding = function_binding.then(ptype());
+
+    function_binding.map(|(params, return_type)| {
+        TypeExpr::Function(params.value.1.value, Box::new(return_type))
+    })
+}
+
+//`(a, b)`, or `()`; a single parenthesised type is that type
+fn ptuple_type<'a>() -> impl Parser<'a, TypeExpr> {
+    let lparen = pchar('(').ws();
+    let rparen = pchar(')').ws();
+    let types = ptype().ws().sep_by(pchar(',').ws()).between(lparen, rparen);
+    parser_from_fn(move |input| {
+        let (token, cont) = types.parse(input)?;
+        let mut types = token.value;
+        let type_ = match types.len() {
+            1 => types.pop().unwrap().value,
+            _ => TypeExpr::Tuple(types),
+        };
+        let length = cont.position - input.position;
+        Ok((Token::new(type_, input.position, length), cont))
+    })
+}
+
+//fails rather than overflowing
+fn parray_length<'a>() -> impl Parser<'a, usize> {
+    let digits = ptuple_position();
+    parser_from_fn(move |input: ContinuationState<'a>| {
+        let (token, cont) = digits.parse(input)?;
+        match token.value.parse() {
+            Ok(length) => Ok((Token::new(length, token.start, token.length), cont)),
+            Err(_) => Err(Error::new(
+                Expected::Class("array length".into()),
+                pactual(input),
+                input.position,
+                input.line_number,
+                input.line_position,
+            )),
+        }
+    })
+}
+
+//`[int]`, or `[int; 3]` for arrays of a fixed length
+fn parray_type<'a>() -> impl Parser<'a, TypeExpr> {
+    let lbracket = pchar('[').ws();
+    let rbracket = pchar(']').ws();
+    let length = pchar(';').ws().then(parray_length()).right().ws();
+    let element = ptype().ws().then(pmaybe(length));
+    parser_from_fn(move |input| {
+        let (_, cont) = lbracket.parse(input)?;
+        let (element, cont) = element.parse(cont)?;
+        let (_, cont) = rbracket.parse(cont)?;
+        let (element, length) = element.value;
+        let array = TypeExpr::Array(Box::new(element), length.value.map(|length| length.value));
+        Ok((
+            Token::new(array, input.position, cont.position - input.position),
+            cont,
+        ))
+    })
+}
+
+//`int`, or a generic type applied to type arguments such as `Map<string, int>`
+fn pnamed_type<'a>() -> impl Parser<'a, TypeExpr> {
+    let arguments = ptype().ws().sep_by(pchar(',').ws());
+    let arguments = arguments.between(pchar('<').ws(), pchar('>'));
+    pidentifier()
+        .then(pmaybe(arguments))
+        .map(|(name, arguments)| match arguments.value {
+            Some(arguments) => TypeExpr::Applied(name, arguments.value),
+            None => TypeExpr::Named(name.value),
+        })
+}
+
+/// A type: a name such as `int`, a generic type such as `List<int>`, a function type
+/// such as `fun(int, bool) -> int`, a tuple type such as `(int, bool)`, or an array
+/// type such as `[int]` or `[int; 3]`.
+pub fn ptype<'a>() -> impl Parser<'a, TypeExpr> {
+    pchoice!(
+        pfunction_type(),
+        ptuple_type(),
+        parray_type(),
+        pnamed_type()
+    )
+}
+
+fn pgeneric_param<'a>() -> impl Parser<'a, GenericParameter> {
+    let bounds = ptype().ws().sep_by(pchar('+').ws());
+    let bounds = pchar(':').ws().then(bounds).right();
+    pidentifier()
+        .ws()
+        .then(pmaybe(bounds))
+        .map(|(name, bounds)| {
+            let bounds = bounds.value.map(|bounds| bounds.value);
+            GenericParameter(name, bounds.unwrap_or_default())
+        })
+}
+
+/// The type parameters of a generic function: `<T, U: Ord + Display>`.
+pub fn pgenerics<'a>() -> impl Parser<'a, Vec<Token<GenericParameter>>> {
+    let langle = pchar('<').ws();
+    let rangle = pchar('>').ws();
+    pgeneric_param()
+        .sep_by(pchar(',').ws())
+        .between(langle, rangle)
+}
+
 pub fn pparam<'a>() -> impl Parser<'a, Parameter> {
     let param_binding = pidentifier().ws();
     let param_binding = param_binding.then(pchar(':').ws()).left();
-    let param_binding = param_binding.then(pidentifier()).ws();
+    let param_binding = param_binding.then(ptype()).ws();
     param_binding.map(|(name, type_)| Parameter(name, type_))
 }
 
@@ -94,30 +200,158 @@ pub fn pparams<'a>() -> impl Parser<'a, Vec<Token<Parameter>>> {
 }
 
 pub fn plet<'a>() -> impl Parser<'a, Statement> {
-    let let_binding = pstring("let").ws();
-    let let_binding = let_binding.then(pidentifier()).right().ws();
+    let let_binding = pkeyword(LET).ws();
+    let let_binding = let_binding.then(pkeyword(MUT).ws().optional()).right();
+    let let_binding = let_binding.then(pidentifier()).ws();
+    let type_ = pmaybe(pchar(':').ws().then(ptype()).right());
+    let let_binding = let_binding.then(type_).ws();
     let let_binding = let_binding.then(pchar('=').ws()).left();
     let let_binding = let_binding.then(pexpr()).ws();
-    let_binding.map(|(name, value)| Statement::Let(name, value))
+    let let_binding = let_binding.map(|(binding, value)| {
+        let (mutable_and_name, type_) = binding.value;
+        let (mutable, name) = mutable_and_name.value;
+        Statement::Let(name, type_.value, value, mutable.value.is_some())
+    });
+    pfrom_start(let_binding)
+}
+
+//assignable expressions
+pub fn pplace<'a>() -> impl Parser<'a, Expr> {
+    ppostfix(pidentifier().map(Expr::Ident).ws())
+}
+
+const ASSIGNMENT_OPERATORS: [&str; 11] = [
+    "=", "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=", "<<=", ">>=",
+];
+
+//the binary operator of a compound assignment, if any
+fn passignment_operator<'a>() -> impl Parser<'a, Option<BinaryOp>> {
+    let operator = poperator(&ASSIGNMENT_OPERATORS).ws();
+    operator.map(|symbol| {
+        let symbol = &symbol[..symbol.len() - 1];
+        BinaryOp::ALL
+            .iter()
+            .find(|op| op.symbol() == symbol)
+            .copied()
+    })
 }
 
+pub fn passign<'a>() -> impl Parser<'a, Statement> {
+    let assign_binding = pplace().then(passignment_operator());
+    let assign_binding = assign_binding.then(pexpr()).ws();
+    assign_binding.map(|(place_and_operator, value)| {
+        let (place, operator) = place_and_operator.value;
+        let operator = operator
+            .value
+            .map(|op| Token::new(op, operator.start, operator.length));
+        Statement::Assign(place, operator, value)
+    })
+}
+
+//like optional, but keeps the position of the parsed value
+//fails rather than backtracking if the parser got past its first character
+fn pmaybe<'a, T: Clone + 'a>(parser: impl Parser<'a, T> + 'a) -> impl Parser<'a, Option<Token<T>>> {
+    parser_from_fn(
+        move |input: ContinuationState<'a>| match parser.parse(input) {
+            Ok((token, cont)) => {
+                let (start, length) = (token.start, token.length);
+                Ok((Token::new(Some(token), start, length), cont))
+            }
+            Err(error) if error.position > input.position => Err(error),
+            Err(_) => Ok((Token::new(None, input.position, 0), input)),
+        },
+    )
+}
+
+pub fn plabel<'a>() -> impl Parser<'a, String> {
+    let label = pchar('\'').then(pidentifier()).right();
+    //fails as a whole, and leaves `'a'` to be parsed as a char literal
+    parser_from_fn(
+        move |input: ContinuationState<'a>| match label.parse(input) {
+            Ok((token, cont)) if !cont.remaining.starts_with('\'') => Ok((token, cont)),
+            _ => Err(Error::new(
+                Expected::Class("label".into()),
+                pactual(input),
+                input.position,
+                input.line_number,
+                input.line_position,
+            )),
+        },
+    )
+}
+
+fn plabel_declaration<'a>() -> impl Parser<'a, Option<Token<String>>> {
+    pmaybe(plabel().then(pchar(':').ws()).left())
+}
+
+//`for i = range` counts through a range, `for x in iterable` visits each element
 pub fn pfor<'a>() -> impl Parser<'a, Statement> {
-    let for_binding = pstring("for").ws();
-    let for_binding = for_binding.then(pidentifier()).right().ws();
-    let for_binding = for_binding.then(pchar('=').ws()).left();
-    let for_binding = for_binding.then(prange()).ws();
+    let for_binding = plabel_declaration().then(pkeyword(FOR).ws()).left();
+    let for_binding = for_binding.then(pidentifier()).ws();
+    let each = pchar('=').map(|_| false).or(pkeyword(IN).map(|_| true));
+    let for_binding = for_binding.then(each.ws());
+    let for_binding = for_binding.then(pexpr()).ws();
     let for_binding = for_binding.then(pbody().ws());
 
-    for_binding.map(|(name_and_expr, body)| {
-        Statement::For(name_and_expr.value.0, name_and_expr.value.1, body.value)
+    for_binding.map(|(header, body)| {
+        let (binding, range) = header.value;
+        let (label_and_name, each) = binding.value;
+        let (label, name) = label_and_name.value;
+        match each.value {
+            true => Statement::ForIn(label.value, name, range, body.value),
+            false => Statement::For(label.value, name, range, body.value),
+        }
     })
 }
 
+pub fn pwhile<'a>() -> impl Parser<'a, Statement> {
+    let while_binding = plabel_declaration().then(pkeyword(WHILE).ws()).left();
+    let while_binding = while_binding.then(pexpr()).ws();
+    let while_binding = while_binding.then(pbody().ws());
+
+    while_binding.map(|(label_and_condition, body)| {
+        let (label, condition) = label_and_condition.value;
+        Statement::While(label.value, condition, body.value)
+    })
+}
+
+pub fn ploop<'a>() -> impl Parser<'a, Expr> {
+    let loop_binding = plabel_declaration().then(pkeyword(LOOP).ws()).left();
+    let loop_binding = loop_binding.then(pbody().ws());
+
+    loop_binding.map(|(label, body)| Expr::Loop(label.value, body.value))
+}
+
+pub fn pbreak<'a>() -> impl Parser<'a, Statement> {
+    let break_binding = pkeyword(BREAK).ws();
+    let break_binding = break_binding.then(pmaybe(plabel().ws()));
+    let break_binding = break_binding.then(pmaybe(pexpr()));
+
+    break_binding.map(|(keyword_and_label, value)| {
+        let (_, label) = keyword_and_label.value;
+        Statement::Break(label.value, value.value)
+    })
+}
+
+pub fn pcontinue<'a>() -> impl Parser<'a, Statement> {
+    let continue_binding = pkeyword(CONTINUE).ws();
+    let continue_binding = continue_binding.then(pmaybe(plabel().ws()));
+
+    continue_binding.map(|(_, label)| Statement::Continue(label.value))
+}
+
+pub fn preturn<'a>() -> impl Parser<'a, Statement> {
+    let return_binding = pkeyword(RETURN).ws();
+    let return_binding = return_binding.then(pmaybe(pexpr()));
+
+    return_binding.map(|(_, value)| Statement::Return(value.value))
+}
+
 pub fn pif<'a>() -> impl Parser<'a, Expr> {
-    let if_binding = pstring("if").ws();
+    let if_binding = pkeyword(IF).ws();
     let if_binding = if_binding.then(pexpr()).right().ws();
     let if_binding = if_binding.then(pbody().ws());
-    let else_binding = pstring("else").ws();
+    let else_binding = pkeyword(ELSE).ws();
     let else_binding = else_binding.then(pbody().ws()).right();
     let else_binding = else_binding.optional();
 
@@ -132,63 +366,682 @@ pub fn pif<'a>() -> impl Parser<'a, Expr> {
         })
 }
 
-pub fn pcall<'a>() -> impl Parser<'a, Expr> {
-    let call_binding = pidentifier().ws();
+fn pfield_value<'a>() -> impl Parser<'a, (Token<String>, Token<Expr>)> {
+    let field = pidentifier().ws().then(pchar(':').ws()).left();
+    field.then(pexpr())
+}
+
+pub fn pstruct_literal<'a>() -> impl Parser<'a, Expr> {
+    let lbrace = pchar('{').ws();
+    let rbrace = pchar('}').ws();
+    let fields = pfield_value().sep_by(pchar(',').ws());
+    let fields = fields.between(lbrace, rbrace);
+
+    pidentifier().ws().then(fields).map(|(name, fields)| {
+        let fields = fields.value.into_iter().map(|field| field.value).collect();
+        Expr::Struct(name, fields)
+    })
+}
+
+//enum, variant
+fn pvariant_path<'a>() -> impl Parser<'a, (Token<String>, Token<String>)> {
+    let path = pidentifier().then(pstring("::")).left();
+    path.then(pidentifier()).ws()
+}
+
+//a variant without a payload is written without parentheses
+pub fn pvariant<'a>() -> impl Parser<'a, Expr> {
     let lparen = pchar('(').ws();
     let rparen = pchar(')').ws();
+    let payload = pexpr().sep_by(pchar(',').ws());
+    let payload = payload.between(lparen, rparen);
 
-    let expr = pexpr();
+    pvariant_path()
+        .then(payload.optional())
+        .map(|(path, payload)| {
+            let (enum_name, variant) = path.value;
+            Expr::Variant(enum_name, variant, payload.value.unwrap_or_default())
+        })
+}
 
-    let params = expr.sep_by(pchar(',').ws());
-    let params = params.between(lparen, rparen);
+//a single expression is the same as a body holding only that expression
+fn pblock_or_expr<'a>() -> impl Parser<'a, Vec<Token<ExprOrStatement>>> {
+    let expr = pexpr().map(ExprOrStatement::Expr);
+    let single = parser_from_fn(move |input| {
+        let (token, cont) = expr.parse(input)?;
+        let (start, length) = (token.start, token.length);
+        Ok((Token::new(vec![token], start, length), cont))
+    });
+    pbody().ws().or(single)
+}
 
-    call_binding
-        .then(params)
-        .ws()
-        .map(|(name, params)| Expr::Call(name, params.value))
+pub fn pmatch_arm<'a>() -> impl Parser<'a, MatchArm> {
+    let guard = pkeyword(IF).ws().then(pexpr()).right();
+    let arm_binding = ppattern().then(pmaybe(guard));
+    let arm_binding = arm_binding.then(pstring("=>").ws()).left();
+    let arm_binding = arm_binding.then(pblock_or_expr());
+
+    arm_binding.map(|(pattern_and_guard, body)| {
+        let (pattern, guard) = pattern_and_guard.value;
+        MatchArm {
+            pattern,
+            guard: guard.value,
+            body: body.value,
+        }
+    })
 }
 
-pub fn pexpr<'a>() -> impl Parser<'a, Expr> {
+pub fn pmatch<'a>() -> impl Parser<'a, Expr> {
+    let lbrace = pchar('{').ws();
+    let rbrace = pchar('}').ws();
+    let arms = pmatch_arm().sep_by(pchar(',').ws());
+    let arms = arms.between(lbrace, rbrace);
+    let match_binding = pkeyword(MATCH).ws();
+    let match_binding = match_binding.then(pexpr()).right().ws();
+
+    match_binding
+        .then(arms)
+        .map(|(scrutinee, arms)| Expr::Match(Box::new(scrutinee), arms.value))
+}
+
+fn pclosure_param<'a>() -> impl Parser<'a, ClosureParameter> {
+    let type_ = pmaybe(pchar(':').ws().then(ptype()).right());
+    let param_binding = pidentifier().ws().then(type_).ws();
+    param_binding.map(|(name, type_)| ClosureParameter(name, type_.value))
+}
+
+pub fn pclosure<'a>() -> impl Parser<'a, Expr> {
+    let comma = || pchar(',').ws();
+    let no_params = pstring("||").ws().map(|_| Vec::new());
+    //kept as pairs rather than using between, so the span starts at the opening delimiter
+    let pipe_params = pchar('|').ws().then(pclosure_param().sep_by(comma()));
+    let pipe_params = pipe_params.then(pchar('|').ws()).left();
+    let pipe_params = no_params.or(pipe_params.map(|(_, params)| params.value));
+    let pipe = pipe_params
+        .then(pblock_or_expr())
+        .map(|(params, body)| Closure {
+            params: params.value,
+            return_type: None,
+            body: body.value,
+        });
+
+    let fun_params = pclosure_param().sep_by(comma());
+    let fun_params = fun_params.between(pchar('(').ws(), pchar(')').ws());
+    let fun_binding = pkeyword(FUN).ws().then(fun_params);
+    let fun_binding = fun_binding.then(pstring("->").ws()).left();
+    let fun_binding = fun_binding.then(ptype()).ws();
+    let fun_binding = fun_binding.then(pbody());
+    let fun = fun_binding.map(|(params_and_type, body)| {
+        let (params, return_type) = params_and_type.value;
+        Closure {
+            params: params.value.1.value,
+            return_type: Some(return_type),
+            body: body.value,
+        }
+    });
+
+    pipe.or(fun).map(Expr::Closure)
+}
+
+//parenthesised sub-expressions keep the span of the parentheses
+//`(e)` is e itself, while `()` and `(a, b)` are tuples
+fn pparenthesised<'a>() -> impl Parser<'a, Expr> {
+    let lparen = pchar('(').ws();
+    let rparen = pchar(')').ws();
+    let parenthesised = pexpr().sep_by(pchar(',').ws()).between(lparen, rparen);
+    parser_from_fn(move |input| {
+        let (token, cont) = parenthesised.parse(input)?;
+        let mut values = token.value;
+        let value = match values.len() {
+            1 => values.pop().unwrap().value,
+            _ => Expr::Tuple(values),
+        };
+        let length = cont.position - input.position;
+        Ok((Token::new(value, input.position, length), cont))
+    })
+}
+
+//`[a, b, c]`, or `[value; count]` for count copies of value
+fn parray<'a>() -> impl Parser<'a, Expr> {
+    let lbracket = pchar('[').ws();
+    let rbracket = pchar(']').ws();
+    let first = pmaybe(pexpr());
+    let count = pchar(';').ws().then(pexpr()).right();
+    let rest = pchar(',').ws().then(pexpr()).right().many();
+    parser_from_fn(move |input| {
+        let (_, cont) = lbracket.parse(input)?;
+        let (first, cont) = first.parse(cont)?;
+        let (value, cont) = match first.value {
+            None => (Expr::Array(Vec::new()), cont),
+            Some(value) => match count.parse(cont) {
+                Ok((count, cont)) => (Expr::Repeat(Box::new(value), Box::new(count)), cont),
+                Err(_) => {
+                    let (rest, cont) = rest.parse(cont)?;
+                    let values = [vec![value], rest.value].concat();
+                    (Expr::Array(values), cont)
+                }
+            },
+        };
+        let (_, cont) = rbracket.parse(cont)?;
+        let length = cont.position - input.position;
+        Ok((Token::new(value, input.position, length), cont))
+    })
+}
+
+fn patom<'a>() -> impl Parser<'a, Expr> {
     let value = pvalue().map(Expr::Value);
     pchoice!(
         value,
+        pstring_expr(),
         pif(),
-        pcall(),
-        pidentifier().map(Expr::Ident) //prange()
+        ploop(),
+        pmatch(),
+        pvariant(),
+        pstruct_literal(),
+        pclosure(),
+        pidentifier().map(Expr::Ident),
+        pparenthesised(),
+        parray()
     )
     .ws()
 }
 
-pub fn pstatement<'a>() -> impl Parser<'a, Statement> {
-    pchoice!(pfor(), plet()).ws()
+//the position of a tuple element, as in `pair.0`
+fn ptuple_position<'a>() -> impl Parser<'a, String> {
+    let digits = pclass(CharClass::digit()).then(ptake_while(CharClass::digit()));
+    digits.map(|(first, rest)| format!("{}{}", first.value, rest.value))
 }
 
-pub fn pbody<'a>() -> impl Parser<'a, Vec<Token<ExprOrStatement>>> {
-    let plbrace = pchar('{').ws();
-    let prbrace = pchar('}').ws();
+//calls, field accesses and indexing, which bind tighter than any operator
+fn ppostfix<'a>(atom: impl Parser<'a, Expr> + 'a) -> impl Parser<'a, Expr> {
+    let field = pchar('.')
+        .then(pidentifier().or(ptuple_position()))
+        .right()
+        .ws();
+    let lbracket = pchar('[').ws();
+    let index = pexpr();
+    let rbracket = pchar(']').ws();
+    let lparen = pchar('(').ws();
+    let args = pexpr().sep_by(pchar(',').ws());
+    let rparen = pchar(')').ws();
+    parser_from_fn(move |input| {
+        let (mut value, mut cont) = atom.parse(input)?;
+        let start = value.start;
+        loop {
+            if let Ok((name, next)) = field.parse(cont) {
+                let length = name.start + name.length - start;
+                value = Token::new(Expr::Field(Box::new(value), name), start, length);
+                cont = next;
+            } else if let Ok((_, next)) = lbracket.parse(cont) {
+                let (index, next) = index.parse(next)?;
+                let (bracket, next) = rbracket.parse(next)?;
+                let length = bracket.start + bracket.length - start;
+                let indexed = Expr::Index(Box::new(value), Box::new(index));
+                value = Token::new(indexed, start, length);
+                cont = next;
+            } else if let Ok((_, next)) = lparen.parse(cont) {
+                let (args, next) = args.parse(next)?;
+                let (paren, next) = rparen.parse(next)?;
+                let length = paren.start + paren.length - start;
+                let call = Expr::Call(Box::new(value), args.value);
+                value = Token::new(call, start, length);
+                cont = next;
+            } else {
+                return Ok((value, cont));
+            }
+        }
+    })
+}
+
+fn punary_operator<'a>() -> impl Parser<'a, UnaryOp> {
+    let neg = pchar('-').map(|_| UnaryOp::Neg);
+    let not = pchar('!').map(|_| UnaryOp::Not);
+    neg.or(not).ws()
+}
+
+//every expression nested in another is parsed by a recursive call through punary, so deeper
+//nesting is an error rather than a stack overflow
+const MAX_NESTING: usize = 32;
+
+thread_local! {
+    static NESTING: Cell<usize> = const { Cell::new(0) };
+}
+
+//an expression being parsed on this thread, counted until it is dropped
+struct Nested;
+
+impl Nested {
+    fn enter(input: ContinuationState<'_>) -> Result<Nested, Error<'_>> {
+        let depth = NESTING.with(|nesting| nesting.replace(nesting.get() + 1));
+        let nested = Nested;
+        if depth >= MAX_NESTING {
+            return Err(Error::new(
+                Expected::Class(format!("at most {} nested expressions", MAX_NESTING).into()),
+                pactual(input),
+                input.position,
+                input.line_number,
+                input.line_position,
+            ));
+        }
+        Ok(nested)
+    }
+}
+
+impl Drop for Nested {
+    fn drop(&mut self) {
+        NESTING.with(|nesting| nesting.set(nesting.get() - 1));
+    }
+}
+
+//a negative number literal is an atom rather than a negated one
+fn punary<'a>() -> impl Parser<'a, Expr> {
+    let unary = pchoice!(
+        ppostfix(patom()),
+        punary_operator()
+            .then(punary())
+            .map(|(op, operand)| Expr::Unary(op, Box::new(operand)))
+    );
+    parser_from_fn(move |input| {
+        let _nested = Nested::enter(input)?;
+        unary.parse(input)
+    })
+}
+
+const BINARY_OPERATORS: [&str; 18] = [
+    "+", "-", "*", "/", "%", "==", "!=", "<", "<=", ">", ">=", "&&", "||", "&", "|", "^", "<<",
+    ">>",
+];
+
+//an operator directly followed by `=` is the start of a longer one, such as `+` in `+=`
+fn poperator<'a>(operators: &'a [&'a str]) -> impl Parser<'a, &'a str> {
+    let operator = pone_of_strings(operators);
+    parser_from_fn(move |input: ContinuationState<'a>| {
+        let (token, cont) = operator.parse(input)?;
+        if cont.remaining.starts_with('=') {
+            let actual = &input.remaining[..token.length + 1];
+            return Err(Error::new(
+                Expected::Class("operator".into()),
+                actual,
+                input.position,
+                input.line_number,
+                input.line_position,
+            ));
+        }
+        Ok((token, cont))
+    })
+}
+
+fn pbinary_operator<'a>() -> impl Parser<'a, BinaryOp> {
+    let operator = poperator(&BINARY_OPERATORS).ws();
+    operator.map(|symbol| {
+        *BinaryOp::ALL
+            .iter()
+            .find(|op| op.symbol() == symbol)
+            .unwrap()
+    })
+}
+
+//precedence climbing: only operators binding at least as tight as min_precedence are consumed
+fn pbinary_rest<'a>(
+    operand: &impl Parser<'a, Expr>,
+    operator: &impl Parser<'a, BinaryOp>,
+    input: ContinuationState<'a>,
+    min_precedence: u8,
+) -> ParseResult<'a, Expr> {
+    let (mut lhs, mut cont) = operand.parse(input)?;
+    while let Ok((op, next)) = operator.parse(cont) {
+        let precedence = op.value.precedence();
+        if precedence < min_precedence {
+            break;
+        }
+        let (rhs, next) = pbinary_rest(operand, operator, next, precedence + 1)?;
+        let start = lhs.start;
+        let length = rhs.start + rhs.length - start;
+        let binary = Expr::Binary(Box::new(lhs), op, Box::new(rhs));
+        lhs = Token::new(binary, start, length);
+        cont = next;
+    }
+    Ok((lhs, cont))
+}
 
+fn pbinary<'a>() -> impl Parser<'a, Expr> {
+    let operand = punary();
+    let operator = pbinary_operator();
+    parser_from_fn(move |input| pbinary_rest(&operand, &operator, input, 0))
+}
+
+const RANGE_OPERATORS: [&str; 2] = ["..", "..="];
+
+//inclusive, end
+fn prange_end<'a>() -> impl Parser<'a, (bool, Token<Expr>)> {
+    let operator = pone_of_strings(&RANGE_OPERATORS).ws();
+    let inclusive = operator.map(|operator| operator == "..=");
+    inclusive
+        .then(pbinary())
+        .map(|(inclusive, end)| (inclusive.value, end))
+}
+
+//ranges bind looser than any binary operator and do not chain, so `a + 1 .. b` needs no parentheses
+pub fn pexpr<'a>() -> impl Parser<'a, Expr> {
+    let start = pbinary();
+    let end = prange_end();
+    let step = pkeyword(STEP).ws().then(pbinary()).right();
+    parser_from_fn(move |input| {
+        let (start, cont) = start.parse(input)?;
+        let Ok((end, cont)) = end.parse(cont) else {
+            return Ok((start, cont));
+        };
+        let (inclusive, end) = end.value;
+        let (step, cont) = match step.parse(cont) {
+            Ok((step, cont)) => (Some(step), cont),
+            Err(_) => (None, cont),
+        };
+        let range_start = start.start;
+        let last = step.as_ref().unwrap_or(&end);
+        let length = last.start + last.length - range_start;
+        let range = Expr::Range(
+            Box::new(start),
+            Box::new(end),
+            inclusive,
+            step.map(Box::new),
+        );
+        Ok((Token::new(range, range_start, length), cont))
+    })
+}
+
+fn pbinding_pattern<'a>() -> impl Parser<'a, Pattern> {
+    pidentifier().map(|name| match name.as_str() {
+        WILDCARD => Pattern::Wildcard,
+        _ => Pattern::Binding(name),
+    })
+}
+
+fn ppatterns<'a>() -> impl Parser<'a, Vec<Token<Pattern>>> {
+    let lparen = pchar('(').ws();
+    let rparen = pchar(')').ws();
+    ppattern().sep_by(pchar(',').ws()).between(lparen, rparen)
+}
+
+//`(p)` is p itself rather than a tuple of one
+fn ptuple_pattern<'a>() -> impl Parser<'a, Pattern> {
+    ppatterns().map(|mut patterns| match patterns.len() {
+        1 => patterns.pop().unwrap().value,
+        _ => Pattern::Tuple(patterns),
+    })
+}
+
+fn pvariant_pattern<'a>() -> impl Parser<'a, Pattern> {
+    pvariant_path()
+        .then(ppatterns().optional())
+        .map(|(path, payload)| {
+            let (enum_name, variant) = path.value;
+            Pattern::Variant(enum_name, variant, payload.value.unwrap_or_default())
+        })
+}
+
+//a field of a struct pattern, or None for the `..` ignoring the rest, which must come last
+fn pfield_pattern<'a>() -> impl Parser<'a, Option<(Token<String>, Token<Pattern>)>> {
+    let rest = pstring("..").ws();
+    let rest = parser_from_fn(move |input: ContinuationState<'a>| {
+        let (token, cont) = rest.parse(input)?;
+        if !cont.remaining.starts_with('}') {
+            return Err(Error::new(
+                Expected::Char('}'),
+                pactual(cont),
+                cont.position,
+                cont.line_number,
+                cont.line_position,
+            ));
+        }
+        Ok((Token::new(None, token.start, token.length), cont))
+    });
+    let name = pidentifier().ws();
+    let pattern = pmaybe(pchar(':').ws().then(ppattern()).right());
+    let field = parser_from_fn(move |input| {
+        let (name, cont) = name.parse(input)?;
+        let (pattern, cont) = pattern.parse(cont)?;
+        //`x` is shorthand for `x: x`
+        let pattern = pattern.value.unwrap_or_else(|| {
+            let binding = Pattern::Binding(name.value.clone());
+            Token::new(binding, name.start, name.length)
+        });
+        let length = pattern.start + pattern.length - name.start;
+        Ok((
+            Token::new(Some((name.clone(), pattern)), name.start, length),
+            cont,
+        ))
+    });
+    rest.or(field)
+}
+
+fn pstruct_pattern<'a>() -> impl Parser<'a, Pattern> {
+    let lbrace = pchar('{').ws();
+    let rbrace = pchar('}').ws();
+    let fields = pfield_pattern().sep_by(pchar(',').ws());
+    let fields = fields.between(lbrace, rbrace);
+
+    pidentifier().ws().then(fields).map(|(name, fields)| {
+        let mut fields = fields.value;
+        let rest = fields.last().is_some_and(|field| field.value.is_none());
+        if rest {
+            fields.pop();
+        }
+        let fields = fields.into_iter().filter_map(|field| field.value).collect();
+        Pattern::Struct(name, fields, rest)
+    })
+}
+
+pub fn ppattern<'a>() -> impl Parser<'a, Pattern> {
+    pchoice!(
+        pvariant_pattern(),
+        pstruct_pattern(),
+        pvalue().or(pquoted_string()).map(Pattern::Value),
+        ptuple_pattern(),
+        pbinding_pattern()
+    )
+    .ws()
+}
+
+pub fn pstatement<'a>() -> impl Parser<'a, Statement> {
+    pchoice!(
+        pfor(),
+        pwhile(),
+        plet(),
+        pbreak(),
+        pcontinue(),
+        preturn(),
+        passign()
+    )
+    .ws()
+}
+
+pub fn pbody_item<'a>() -> impl Parser<'a, ExprOrStatement> {
     let expr = pexpr().map(ExprOrStatement::Expr);
     let statement = pstatement().map(ExprOrStatement::Statement);
     let expr_or_statement = statement.or(expr);
-    let expr_or_statement = expr_or_statement.then(pterminator()).left();
+    expr_or_statement.then(pterminator()).left()
+}
 
-    let pexprorstatement = expr_or_statement.many1();
+pub fn pbody<'a>() -> impl Parser<'a, Vec<Token<ExprOrStatement>>> {
+    let plbrace = pchar('{').ws();
+    let prbrace = pchar('}').ws();
+
+    let pexprorstatement = pbody_item().many1();
     pexprorstatement.between(plbrace, prbrace)
 }
 
-pub fn pfun<'a>() -> impl Parser<'a, Fun> {
-    let fun_binding = pstring(FUN).ws();
+//name, type parameters, params, return type
+pub(crate) type FunHeader = (
+    Token<String>,
+    Vec<Token<GenericParameter>>,
+    Vec<Token<Parameter>>,
+    Token<TypeExpr>,
+);
+
+pub(crate) fn pfun_header<'a>() -> impl Parser<'a, FunHeader> {
+    let fun_binding = pkeyword(FUN).ws();
     let fun_binding = fun_binding.then(pidentifier()).right().ws();
+    let fun_binding = fun_binding.then(pmaybe(pgenerics()));
     let fun_binding = fun_binding.then(pparams()).ws();
     let fun_binding = fun_binding.then(pstring("->").ws()).left();
-    let fun_binding = fun_binding.then(pidentifier()).ws();
-    let fun_binding = fun_binding.then(pbody());
+    let fun_binding = fun_binding.then(ptype()).ws();
 
-    let fun_binding = fun_binding.map(|(name_and_params, body)| Fun {
-        name: name_and_params.value.0.value.0,
-        params: name_and_params.value.0.value.1.value,
-        body: body.value,
-        return_type: name_and_params.value.1,
+    fun_binding.map(|(signature, return_type)| {
+        let (name_and_generics, params) = signature.value;
+        let (name, generics) = name_and_generics.value;
+        let generics = generics.value.map(|generics| generics.value);
+        (
+            name,
+            generics.unwrap_or_default(),
+            params.value,
+            return_type,
+        )
+    })
+}
+
+pub fn pfun<'a>() -> impl Parser<'a, Fun> {
+    let fun_binding = pfun_header().then(pbody());
+
+    let fun_binding = fun_binding.map(|(header, body)| {
+        let (name, generics, params, return_type) = header.value;
+        Fun {
+            name,
+            generics,
+            params,
+            body: body.value,
+            return_type,
+        }
     });
     fun_binding
 }
+
+pub fn pconst<'a>() -> impl Parser<'a, Item> {
+    let const_binding = pkeyword(CONST).ws();
+    let const_binding = const_binding.then(pidentifier()).right().ws();
+    let const_binding = const_binding.then(pchar(':').ws()).left();
+    let const_binding = const_binding.then(ptype()).ws();
+    let const_binding = const_binding.then(pchar('=').ws()).left();
+    let const_binding = const_binding.then(pexpr()).then(pterminator()).left();
+
+    let const_binding = const_binding.map(|(name_and_type, value)| {
+        let (name, type_) = name_and_type.value;
+        Item::Const(name, type_, value)
+    });
+    pfrom_start(const_binding)
+}
+
+pub fn ptype_declaration<'a>() -> impl Parser<'a, Item> {
+    let type_binding = pkeyword(TYPE).ws();
+    let type_binding = type_binding.then(pidentifier()).right().ws();
+    let type_binding = type_binding.then(pchar('=').ws()).left();
+    let type_binding = type_binding.then(ptype()).ws();
+    let type_binding = type_binding.then(pterminator()).left();
+
+    type_binding.map(|(name, type_)| Item::Type(name, type_))
+}
+
+pub fn pimport<'a>() -> impl Parser<'a, Item> {
+    let import_binding = pkeyword(IMPORT).ws();
+    let path = pidentifier().sep_by(pchar('.')).at_least_one().ws();
+    let import_binding = import_binding.then(path).right();
+    let import_binding = import_binding.then(pterminator()).left();
+
+    import_binding.map(Item::Import)
+}
+
+pub fn pstruct<'a>() -> impl Parser<'a, Item> {
+    let lbrace = pchar('{').ws();
+    let rbrace = pchar('}').ws();
+    let field = pparam().map(|Parameter(name, type_)| Field(name, type_));
+    let fields = field.sep_by(pchar(',').ws()).between(lbrace, rbrace);
+    let struct_binding = pkeyword(STRUCT).ws();
+    let struct_binding = struct_binding.then(pidentifier()).right().ws();
+
+    struct_binding
+        .then(fields)
+        .map(|(name, fields)| Item::Struct(name, fields.value))
+}
+
+fn pvariant_declaration<'a>() -> impl Parser<'a, Variant> {
+    let lparen = pchar('(').ws();
+    let rparen = pchar(')').ws();
+    let payload = ptype().ws().sep_by(pchar(',').ws());
+    let payload = payload.between(lparen, rparen);
+
+    pidentifier()
+        .ws()
+        .then(payload.optional())
+        .map(|(name, payload)| Variant(name, payload.value.unwrap_or_default()))
+}
+
+pub fn penum<'a>() -> impl Parser<'a, Item> {
+    let lbrace = pchar('{').ws();
+    let rbrace = pchar('}').ws();
+    let variants = pvariant_declaration().sep_by(pchar(',').ws());
+    let variants = variants.between(lbrace, rbrace);
+    let enum_binding = pkeyword(ENUM).ws();
+    let enum_binding = enum_binding.then(pidentifier()).right().ws();
+
+    enum_binding
+        .then(variants)
+        .map(|(name, variants)| Item::Enum(name, variants.value))
+}
+
+pub fn pitem<'a>() -> impl Parser<'a, Item> {
+    let fun = pfun().map(Item::Fun);
+    let constant = pconst();
+    let type_declaration = ptype_declaration();
+    let import = pimport();
+    let struct_declaration = pstruct();
+    let enum_declaration = penum();
+    parser_from_fn(move |input: ContinuationState<'a>| {
+        let alternatives: [&dyn Fn(ContinuationState<'a>) -> ParseResult<'a, Item>; 6] = [
+            &|input| fun.parse(input),
+            &|input| constant.parse(input),
+            &|input| type_declaration.parse(input),
+            &|input| import.parse(input),
+            &|input| struct_declaration.parse(input),
+            &|input| enum_declaration.parse(input),
+        ];
+        //report the item that got furthest, rather than the last one tried
+        let mut furthest: Option<Error<'a>> = None;
+        for alternative in alternatives {
+            let error = match alternative(input) {
+                Ok(result) => return Ok(result),
+                Err(error) => error,
+            };
+            furthest = Some(match furthest {
+                Some(furthest) if furthest.position > error.position => furthest,
+                Some(furthest) if furthest.position == error.position => furthest + error,
+                _ => error,
+            });
+        }
+        Err(furthest.unwrap())
+    })
+}
+
+/// Parses a whole source file: any number of top-level items followed by the end of the input.
+pub fn pprogram<'a>() -> impl Parser<'a, Program> {
+    let leading_whitespace = pws().many();
+    let item = pitem();
+    parser_from_fn(move |input: ContinuationState<'a>| {
+        let (_, mut cont) = leading_whitespace.parse(input)?;
+        let mut items = Vec::new();
+        loop {
+            let eof_error = match peof().parse(cont) {
+                Ok(_) => break,
+                Err(error) => error,
+            };
+            match item.parse(cont) {
+                Ok((token, next)) => {
+                    items.push(token);
+                    cont = next;
+                }
+                Err(error) if error.position > cont.position => return Err(error),
+                Err(error) => return Err(eof_error + error),
+            }
+        }
+        let length = cont.position - input.position;
+        Ok((Token::new(Program { items }, input.position, length), cont))
+    })
+}
@@ -0,0 +1,273 @@
+use std::{ops::Range, sync::OnceLock};
+
+use crate::parser_combinator::*;
+
+use super::*;
+
+const NUMBER_PATTERN: &str = "-?(0x[0-9a-fA-F_]+|0o[0-7_]+|0b[01_]+|[0-9][0-9_]*(\\.[0-9][0-9_]*)?([eE][+-]?[0-9][0-9_]*)?)(i64|u64)?";
+
+fn number_regex() -> &'static Regex {
+    static NUMBER: OnceLock<Regex> = OnceLock::new();
+    NUMBER.get_or_init(|| Regex::new(NUMBER_PATTERN).unwrap())
+}
+
+fn literal_error<'a>(
+    expected: Expected<'a>,
+    actual: &'a str,
+    input: ContinuationState<'a>,
+) -> Error<'a> {
+    Error::new(
+        expected,
+        actual,
+        input.position,
+        input.line_number,
+        input.line_position,
+    )
+}
+
+//the value of a literal matched by NUMBER_PATTERN, or a description of what it should have been
+fn number_value(text: &str) -> Result<Value, &'static str> {
+    let (text, suffix) = match text.len().checked_sub(3) {
+        Some(split) if text.ends_with("i64") || text.ends_with("u64") => text.split_at(split),
+        _ => (text, ""),
+    };
+    let (sign, digits) = match text.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", text),
+    };
+    let (radix, digits) = match digits.get(..2) {
+        Some("0x") => (16, &digits[2..]),
+        Some("0o") => (8, &digits[2..]),
+        Some("0b") => (2, &digits[2..]),
+        _ => (10, digits),
+    };
+    let digits = format!("{}{}", sign, digits.replace('_', ""));
+
+    if radix == 10 && digits.contains(['.', 'e', 'E']) {
+        return match suffix {
+            "" => digits.parse().map(Value::Float).map_err(|_| "float"),
+            _ => Err("integer"),
+        };
+    }
+    let number = i128::from_str_radix(&digits, radix);
+    match suffix {
+        "i64" => number
+            .ok()
+            .and_then(|number| i64::try_from(number).ok())
+            .map(Value::I64)
+            .ok_or("64-bit integer"),
+        "u64" => number
+            .ok()
+            .and_then(|number| u64::try_from(number).ok())
+            .map(Value::U64)
+            .ok_or("unsigned 64-bit integer"),
+        _ => number
+            .ok()
+            .and_then(|number| i32::try_from(number).ok())
+            .map(Value::Number)
+            .ok_or("32-bit integer"),
+    }
+}
+
+/// Integer and float literals: `42`, `-1_000`, `0xff`, `0b1010`, `0o17`, `5i64`, `7u64`, `1.5e-3`.
+/// Integers without a suffix are 32-bit.
+pub fn pnumber<'a>() -> impl Parser<'a, Value> {
+    parser_from_fn(move |input: ContinuationState<'a>| {
+        let Some(length) = number_regex().match_len(input.remaining) else {
+            return Err(literal_error(
+                Expected::Class("number".into()),
+                pactual(input),
+                input,
+            ));
+        };
+        let text = &input.remaining[..length];
+        match number_value(text) {
+            Ok(value) => Ok((
+                Token::new(value, input.position, length),
+                input.skip(length),
+            )),
+            Err(expected) => Err(literal_error(Expected::Class(expected.into()), text, input)),
+        }
+    })
+}
+
+//the character after a backslash, and the number of bytes the escape takes up
+fn escape(remaining: &str) -> Option<(char, usize)> {
+    let mut chars = remaining.chars();
+    let c = match chars.next()? {
+        'n' => '\n',
+        'r' => '\r',
+        't' => '\t',
+        '0' => '\0',
+        c @ ('\\' | '"' | '\'' | '{' | '}') => c,
+        'u' => {
+            let hex = remaining.strip_prefix("u{")?;
+            let end = hex.find('}')?;
+            let code = u32::from_str_radix(&hex[..end], 16).ok()?;
+            return Some((char::from_u32(code)?, end + 3));
+        }
+        _ => return None,
+    };
+    Some((c, 1))
+}
+
+//parses the escape sequence at the start of input, which begins with a backslash
+fn pescape<'a>(input: ContinuationState<'a>) -> Result<(char, ContinuationState<'a>), Error<'a>> {
+    match escape(&input.remaining[1..]) {
+        Some((c, length)) => Ok((c, input.skip(length + 1))),
+        None => {
+            let length = 1 + input.remaining[1..]
+                .chars()
+                .next()
+                .map_or(0, char::len_utf8);
+            let actual = &input.remaining[..length];
+            Err(literal_error(
+                Expected::Class("escape sequence".into()),
+                actual,
+                input,
+            ))
+        }
+    }
+}
+
+/// A character literal such as `'a'`, `'\n'` or `'\u{1F600}'`.
+pub fn pchar_literal<'a>() -> impl Parser<'a, Value> {
+    parser_from_fn(move |input: ContinuationState<'a>| {
+        let (_, cont) = pchar('\'').parse(input)?;
+        let (c, cont) = match cont.remaining.chars().next() {
+            Some('\\') => pescape(cont)?,
+            Some(c) if c != '\'' && c != '\n' => (c, cont.skip(c.len_utf8())),
+            _ => {
+                let expected = Expected::Class("character".into());
+                return Err(literal_error(expected, pactual(cont), cont));
+            }
+        };
+        let (_, cont) = pchar('\'').parse(cont)?;
+        let length = cont.position - input.position;
+        Ok((Token::new(Value::Char(c), input.position, length), cont))
+    })
+}
+
+/// A raw string such as `r"C:\path"` or `r#"say "hi""#`, with no escapes or interpolation.
+pub fn praw_string<'a>() -> impl Parser<'a, Value> {
+    parser_from_fn(move |input: ContinuationState<'a>| {
+        let (_, cont) = pchar('r').parse(input)?;
+        let hashes = cont.remaining.len() - cont.remaining.trim_start_matches('#').len();
+        let (_, cont) = pchar('"').parse(cont.skip(hashes))?;
+        let terminator = format!("\"{}", "#".repeat(hashes));
+        let Some(end) = cont.remaining.find(&terminator) else {
+            let end = cont.skip(cont.remaining.len());
+            return Err(literal_error(Expected::Char('"'), "", end));
+        };
+        let value = Value::String(cont.remaining[..end].to_string());
+        let token = Token::new(value, cont.position, end);
+        Ok((token, cont.skip(end + terminator.len())))
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum StringPart {
+    Text(String),
+    Interpolation(Token<Expr>),
+}
+
+fn push_text(parts: &mut Vec<Token<StringPart>>, text: &mut String, span: Range<usize>) {
+    if !text.is_empty() {
+        let part = StringPart::Text(std::mem::take(text));
+        parts.push(Token::new(part, span.start, span.len()));
+    }
+}
+
+//a quoted string with escapes, split into text and `{expr}` interpolations if allowed
+//the token covers the contents, without the quotes
+fn pstring_parts<'a>(interpolate: bool) -> impl Parser<'a, Vec<Token<StringPart>>> {
+    parser_from_fn(move |input: ContinuationState<'a>| {
+        let (_, mut cont) = pchar('"').parse(input)?;
+        let start = cont.position;
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut text_start = cont.position;
+        loop {
+            match cont.remaining.chars().next() {
+                None => return Err(literal_error(Expected::Char('"'), "", cont)),
+                Some('"') => {
+                    push_text(&mut parts, &mut text, text_start..cont.position);
+                    break;
+                }
+                Some('\\') => {
+                    let (c, next) = pescape(cont)?;
+                    text.push(c);
+                    cont = next;
+                }
+                Some('{') if !interpolate => {
+                    let expected = Expected::Class("escaped '{'".into());
+                    return Err(literal_error(expected, "{", cont));
+                }
+                Some('{') => {
+                    push_text(&mut parts, &mut text, text_start..cont.position);
+                    let expr = pchar('{').ws().then(pexpr()).right();
+                    let (expr, next) = expr.parse(cont)?;
+                    let (_, next) = pchar('}').parse(next)?;
+                    let length = next.position - cont.position;
+                    let part = StringPart::Interpolation(expr);
+                    parts.push(Token::new(part, cont.position, length));
+                    cont = next;
+                    text_start = cont.position;
+                }
+                Some(c) => {
+                    text.push(c);
+                    cont = cont.skip(c.len_utf8());
+                }
+            }
+        }
+        let length = cont.position - start;
+        Ok((Token::new(parts, start, length), cont.skip(1)))
+    })
+}
+
+/// A quoted string with escapes, such as `"say \"hi\"\n"`. Braces must be escaped.
+pub fn pquoted_string<'a>() -> impl Parser<'a, Value> {
+    pstring_parts(false).map(|parts| {
+        let text = parts.into_iter().map(|part| match part.value {
+            StringPart::Text(text) => text,
+            StringPart::Interpolation(_) => unreachable!("interpolation is disabled"),
+        });
+        Value::String(text.collect())
+    })
+}
+
+pub(crate) const TO_STRING: &str = "to_string";
+
+/// A string literal. Interpolations are desugared into concatenation, so that
+/// `"x is {x}!"` becomes `"x is " + to_string(x) + "!"`.
+pub fn pstring_expr<'a>() -> impl Parser<'a, Expr> {
+    let parts = pstring_parts(true);
+    parser_from_fn(move |input: ContinuationState<'a>| {
+        let (token, cont) = parts.parse(input)?;
+        let parts = token.value.into_iter().map(|part| {
+            let (start, length) = (part.start, part.length);
+            let expr = match part.value {
+                StringPart::Text(text) => Expr::Value(Value::String(text)),
+                StringPart::Interpolation(expr) => {
+                    let name = Expr::Ident(TO_STRING.to_string());
+                    Expr::Call(Box::new(Token::new(name, start, 0)), vec![expr])
+                }
+            };
+            Token::new(expr, start, length)
+        });
+        let concatenated = parts.reduce(|lhs, rhs| {
+            let (start, length) = (lhs.start, rhs.start + rhs.length - lhs.start);
+            let add = Token::new(BinaryOp::Add, rhs.start, 0);
+            Token::new(
+                Expr::Binary(Box::new(lhs), add, Box::new(rhs)),
+                start,
+                length,
+            )
+        });
+        let expr = match concatenated {
+            Some(expr) => expr.value,
+            None => Expr::Value(Value::String(String::new())),
+        };
+        Ok((Token::new(expr, token.start, token.length), cont))
+    })
+}
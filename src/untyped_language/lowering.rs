@@ -0,0 +1,76 @@
+use std::{cmp::Ordering, collections::HashMap, ops::Range};
+
+use crate::parser_combinator::Token;
+
+use super::*;
+
+/// The sign of a range step given as a literal, possibly negated, or None if it is only known
+/// at run time. A step of zero fails when the range is made, as in the interpreter.
+pub fn literal_sign(expr: &Expr) -> Option<Ordering> {
+    match expr {
+        Expr::Value(Value::Number(value)) => Some(value.cmp(&0)),
+        Expr::Value(Value::I64(value)) => Some(value.cmp(&0)),
+        Expr::Value(Value::U64(value)) => Some(value.cmp(&0)),
+        Expr::Unary(op, value) if op.value == UnaryOp::Neg => {
+            literal_sign(&value.value).map(Ordering::reverse)
+        }
+        _ => None,
+    }
+}
+
+/// The functions of a program in order, and the value of each constant by name.
+pub fn globals(program: &Program) -> (Vec<&Fun>, HashMap<&str, &Token<Expr>>) {
+    let mut funs = Vec::new();
+    let mut consts = HashMap::new();
+    for item in &program.items {
+        match &item.value {
+            Item::Fun(fun) => funs.push(fun),
+            Item::Const(name, _, value) => {
+                consts.insert(name.value.as_str(), value);
+            }
+            _ => {}
+        }
+    }
+    (funs, consts)
+}
+
+/// A backend that lowers constants where they are used rather than once, as the bytecode
+/// compiler and the IR builder do.
+pub trait InlineConsts<'p> {
+    type Binding;
+    type Output;
+    type Error;
+
+    /// The constant with the given name, and its value.
+    fn definition(&self, name: &str) -> Option<(&'p str, &'p Token<Expr>)>;
+
+    /// The constants being inlined, innermost last.
+    fn evaluating(&mut self) -> &mut Vec<&'p str>;
+
+    /// The locals in scope, innermost last.
+    fn bindings(&mut self) -> &mut Vec<(&'p str, Self::Binding)>;
+
+    fn lower(&mut self, expr: &'p Token<Expr>) -> Result<Self::Output, Self::Error>;
+
+    /// The error for a constant whose value refers to itself.
+    fn recursive(name: &str, span: &Range<usize>) -> Self::Error;
+
+    /// Lowers the value of the constant `name`, with nothing but globals in scope, or gives
+    /// None if there is no such constant.
+    fn inline_const(
+        &mut self,
+        name: &str,
+        span: &Range<usize>,
+    ) -> Option<Result<Self::Output, Self::Error>> {
+        let (name, value) = self.definition(name)?;
+        if self.evaluating().contains(&name) {
+            return Some(Err(Self::recursive(name, span)));
+        }
+        self.evaluating().push(name);
+        let bindings = std::mem::take(self.bindings());
+        let result = self.lower(value);
+        *self.bindings() = bindings;
+        self.evaluating().pop();
+        Some(result)
+    }
+}
@@ -1,8 +1,23 @@
 pub mod ast;
+pub mod exhaustiveness;
+pub mod incremental;
+pub mod interpreter;
 pub mod language_parser;
+pub mod literal_parser;
+pub mod lowering;
+pub mod parallel;
+pub mod printer;
+pub mod resolution;
+pub mod runtime;
+pub mod validation;
 
 pub use ast::*;
+pub use interpreter::*;
 pub use language_parser::*;
+pub use literal_parser::*;
+pub use resolution::*;
+pub use runtime::*;
+pub use validation::*;
 
 #[cfg(test)]
 pub mod tests;
@@ -0,0 +1,219 @@
+use std::{
+    ops::Range,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use crate::parser_combinator::*;
+
+use super::*;
+
+/// The top-level items of a compilation unit along with the errors from items that failed
+/// to parse, both in source order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompilationUnit<'a> {
+    pub items: Vec<Token<Item>>,
+    pub errors: Vec<Error<'a>>,
+}
+
+impl<'a> CompilationUnit<'a> {
+    fn from_results(results: impl IntoIterator<Item = ParseResult<'a, Item>>) -> Self {
+        let mut items = Vec::new();
+        let mut errors = Vec::new();
+        for result in results {
+            match result {
+                Ok((token, _)) => items.push(token),
+                Err(error) => errors.push(error),
+            }
+        }
+        Self { items, errors }
+    }
+}
+
+//what the scanner in split_items is inside of
+#[derive(PartialEq)]
+enum Nesting {
+    Braces,
+    Parens,
+    Brackets,
+    String,
+    Interpolation,
+}
+
+//length of the char or raw string literal at the start of bytes, if there is one
+fn literal_length(bytes: &[u8]) -> Option<usize> {
+    match bytes {
+        [b'\'', b'\\', _, rest @ ..] => rest.iter().position(|&b| b == b'\'').map(|end| end + 4),
+        [b'\'', rest @ ..] => {
+            let c = std::str::from_utf8(&rest[..rest.len().min(4)])
+                .or_else(|error| std::str::from_utf8(&rest[..error.valid_up_to()]))
+                .ok()?
+                .chars()
+                .next()?;
+            let after = 1 + c.len_utf8();
+            (bytes.get(after) == Some(&b'\'')).then_some(after + 1)
+        }
+        [b'r', rest @ ..] => {
+            let hashes = rest.iter().take_while(|&&b| b == b'#').count();
+            if rest.get(hashes) != Some(&b'"') {
+                return None;
+            }
+            let mut terminator = vec![b'"'];
+            terminator.extend(std::iter::repeat_n(b'#', hashes));
+            let contents = &rest[hashes + 1..];
+            let end = contents
+                .windows(terminator.len())
+                .position(|window| window == terminator.as_slice())?;
+            Some(1 + hashes + 1 + end + terminator.len())
+        }
+        _ => None,
+    }
+}
+
+//whether what comes next is a name or a type, such as after `->` or `struct`, so that a
+//keyword there is not the start of an item, as keywords are not reserved
+fn names_follow(before: &[u8]) -> bool {
+    let is_ident = |b: &u8| b.is_ascii_alphanumeric() || *b == b'_';
+    let Some(last) = before.iter().rposition(|b| !b.is_ascii_whitespace()) else {
+        return false;
+    };
+    if b":<>,=.+".contains(&before[last]) {
+        return true;
+    }
+    let start = before[..=last]
+        .iter()
+        .rposition(|b| !is_ident(b))
+        .map_or(0, |start| start + 1);
+    let word = &before[start..=last];
+    [FUN, CONST, TYPE, IMPORT, STRUCT, ENUM]
+        .iter()
+        .any(|keyword| keyword.as_bytes() == word)
+}
+
+/// Splits a compilation unit at the start of each top-level item, without parsing it.
+/// Any text before the first item is returned as an item of its own so that it gets reported.
+pub fn split_items(source: &str) -> Vec<Range<usize>> {
+    let bytes = source.as_bytes();
+    let is_ident = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+    let keywords = [FUN, CONST, TYPE, IMPORT, STRUCT, ENUM].map(str::as_bytes);
+    let mut starts = Vec::new();
+    let mut nesting = Vec::new();
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        let after_ident = i > 0 && is_ident(bytes[i - 1]);
+        if nesting.last() == Some(&Nesting::String) {
+            match b {
+                b'\\' => i += 1,
+                b'"' => {
+                    nesting.pop();
+                }
+                b'{' => nesting.push(Nesting::Interpolation),
+                _ => {}
+            }
+            i += 1;
+            continue;
+        }
+        match b {
+            b'\'' | b'r' if !after_ident => {
+                if let Some(length) = literal_length(&bytes[i..]) {
+                    i += length;
+                    continue;
+                }
+            }
+            b'"' => nesting.push(Nesting::String),
+            b'{' => nesting.push(Nesting::Braces),
+            b'(' => nesting.push(Nesting::Parens),
+            b'[' => nesting.push(Nesting::Brackets),
+            //a stray closer is ignored, and a brace closes whatever is left open inside it
+            b')' if nesting.last() == Some(&Nesting::Parens) => {
+                nesting.pop();
+            }
+            b']' if nesting.last() == Some(&Nesting::Brackets) => {
+                nesting.pop();
+            }
+            b'}' => while let Some(Nesting::Parens | Nesting::Brackets) = nesting.pop() {},
+            _ if nesting.is_empty() && !after_ident && !names_follow(&bytes[..i]) => {
+                //`fun(` starts a closure or function type rather than an item
+                let is_keyword = |keyword: &&[u8]| {
+                    let after = i + keyword.len();
+                    let next = bytes[after.min(bytes.len())..]
+                        .iter()
+                        .find(|b| !b.is_ascii_whitespace());
+                    bytes[i..].starts_with(keyword)
+                        && (after == bytes.len() || !is_ident(bytes[after]))
+                        && next != Some(&b'(')
+                };
+                if keywords.iter().any(is_keyword) {
+                    starts.push(i);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    let mut items = Vec::with_capacity(starts.len() + 1);
+    let first = starts.first().copied().unwrap_or(source.len());
+    if !source[..first].trim().is_empty() {
+        items.push(0..first);
+    }
+    let ends = starts.iter().skip(1).copied().chain(Some(source.len()));
+    items.extend(starts.iter().zip(ends).map(|(&start, end)| start..end));
+    items
+}
+
+fn parse_item<'a>(
+    parser: &impl Parser<'a, Item>,
+    source: &'a str,
+    item: Range<usize>,
+) -> ParseResult<'a, Item> {
+    let input = ContinuationState::at(&source[..item.end], item.start);
+    let (token, cont) = parser.parse(input)?;
+    peof().parse(cont)?;
+    Ok((token, cont))
+}
+
+/// Parses every top-level item of a compilation unit in turn.
+pub fn parse_unit(source: &str) -> CompilationUnit<'_> {
+    let parser = pitem();
+    let results = split_items(source)
+        .into_iter()
+        .map(|item| parse_item(&parser, source, item));
+    CompilationUnit::from_results(results)
+}
+
+/// Parses the top-level items of a compilation unit on `threads` worker threads.
+/// The result is the same as [`parse_unit`].
+pub fn parse_unit_parallel(source: &str, threads: usize) -> CompilationUnit<'_> {
+    let items = split_items(source);
+    let next = AtomicUsize::new(0);
+
+    let mut results: Vec<_> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.max(1))
+            .map(|_| {
+                scope.spawn(|| {
+                    let parser = pitem();
+                    let mut results = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(index) else {
+                            break;
+                        };
+                        results.push((index, parse_item(&parser, source, item.clone())));
+                    }
+                    results
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+
+    results.sort_by_key(|(index, _)| *index);
+    CompilationUnit::from_results(results.into_iter().map(|(_, result)| result))
+}
@@ -0,0 +1,497 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::parser_combinator::Token;
+
+use super::*;
+
+const INDENT: &str = "    ";
+
+fn write_indent(f: &mut Formatter, indent: usize) -> fmt::Result {
+    for _ in 0..indent {
+        f.write_str(INDENT)?;
+    }
+    Ok(())
+}
+
+fn write_separated<T: Display>(f: &mut Formatter, items: &[Token<T>]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{}", item.value)?;
+    }
+    Ok(())
+}
+
+fn write_body(f: &mut Formatter, body: &[Token<ExprOrStatement>], indent: usize) -> fmt::Result {
+    f.write_str("{\n")?;
+    for item in body {
+        write_indent(f, indent + 1)?;
+        write_item(f, &item.value, indent + 1)?;
+        f.write_str(";\n")?;
+    }
+    write_indent(f, indent)?;
+    f.write_str("}")
+}
+
+fn write_label(f: &mut Formatter, label: &Option<Token<String>>) -> fmt::Result {
+    match label {
+        Some(label) => write!(f, "'{}: ", label.value),
+        None => Ok(()),
+    }
+}
+
+fn write_item(f: &mut Formatter, item: &ExprOrStatement, indent: usize) -> fmt::Result {
+    match item {
+        ExprOrStatement::Expr(expr) => write_expr(f, expr, indent),
+        ExprOrStatement::Statement(statement) => write_statement(f, statement, indent),
+    }
+}
+
+fn write_expr(f: &mut Formatter, expr: &Expr, indent: usize) -> fmt::Result {
+    match expr {
+        Expr::Value(value) => write!(f, "{}", value),
+        Expr::Ident(name) => f.write_str(name),
+        Expr::Call(callee, args) => {
+            //(E::V)() rather than E::V(), which would parse as the variant with no payload
+            match &callee.value {
+                Expr::Variant(_, _, payload) if payload.is_empty() => {
+                    write!(f, "({})", callee.value)?
+                }
+                callee => write_operand(f, callee, u8::MAX, indent)?,
+            }
+            f.write_str("(")?;
+            write_separated(f, args)?;
+            f.write_str(")")
+        }
+        Expr::Range(start, end, inclusive, step) => {
+            write_operand(f, &start.value, 1, indent)?;
+            f.write_str(if *inclusive { " ..= " } else { " .. " })?;
+            write_operand(f, &end.value, 1, indent)?;
+            if let Some(step) = step {
+                f.write_str(" step ")?;
+                write_operand(f, &step.value, 1, indent)?;
+            }
+            Ok(())
+        }
+        Expr::If(condition, body, else_body) => {
+            f.write_str("if ")?;
+            write_expr(f, &condition.value, indent)?;
+            f.write_str(" ")?;
+            write_body(f, body, indent)?;
+            if let Some(else_body) = else_body {
+                f.write_str(" else ")?;
+                write_body(f, else_body, indent)?;
+            }
+            Ok(())
+        }
+        Expr::Loop(label, body) => {
+            write_label(f, label)?;
+            f.write_str("loop ")?;
+            write_body(f, body, indent)
+        }
+        Expr::Struct(name, fields) => {
+            write!(f, "{} {{ ", name.value)?;
+            for (i, (field, value)) in fields.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{}: ", field.value)?;
+                write_expr(f, &value.value, indent)?;
+            }
+            f.write_str(if fields.is_empty() { "}" } else { " }" })
+        }
+        Expr::Field(value, name) => {
+            //(1).0 rather than 1.0, which would parse as a float
+            if let Expr::Value(Value::Number(_)) = value.value {
+                write!(f, "({})", value.value)?;
+            } else {
+                write_operand(f, &value.value, u8::MAX, indent)?;
+            }
+            write!(f, ".{}", name.value)
+        }
+        Expr::Array(values) => {
+            f.write_str("[")?;
+            write_separated(f, values)?;
+            f.write_str("]")
+        }
+        Expr::Repeat(value, count) => {
+            f.write_str("[")?;
+            write_expr(f, &value.value, indent)?;
+            f.write_str("; ")?;
+            write_expr(f, &count.value, indent)?;
+            f.write_str("]")
+        }
+        Expr::Tuple(values) => {
+            f.write_str("(")?;
+            write_separated(f, values)?;
+            f.write_str(")")
+        }
+        Expr::Index(value, index) => {
+            write_operand(f, &value.value, u8::MAX, indent)?;
+            f.write_str("[")?;
+            write_expr(f, &index.value, indent)?;
+            f.write_str("]")
+        }
+        Expr::Variant(enum_name, variant, payload) => {
+            write!(f, "{}::{}", enum_name.value, variant.value)?;
+            if !payload.is_empty() {
+                f.write_str("(")?;
+                write_separated(f, payload)?;
+                f.write_str(")")?;
+            }
+            Ok(())
+        }
+        Expr::Match(scrutinee, arms) => {
+            f.write_str("match ")?;
+            write_expr(f, &scrutinee.value, indent)?;
+            f.write_str(" {\n")?;
+            for (i, arm) in arms.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(",\n")?;
+                }
+                write_indent(f, indent + 1)?;
+                write_arm(f, &arm.value, indent + 1)?;
+            }
+            f.write_str("\n")?;
+            write_indent(f, indent)?;
+            f.write_str("}")
+        }
+        Expr::Closure(closure) => write_closure(f, closure, indent),
+        Expr::Binary(lhs, op, rhs) => {
+            let precedence = op.value.precedence();
+            write_operand(f, &lhs.value, precedence, indent)?;
+            write!(f, " {} ", op.value)?;
+            write_operand(f, &rhs.value, precedence + 1, indent)
+        }
+        Expr::Unary(op, operand) => {
+            write!(f, "{}", op.value)?;
+            //-(1) rather than -1, which would parse as a literal
+            if starts_with_number(&operand.value) {
+                f.write_str("(")?;
+                write_expr(f, &operand.value, indent)?;
+                f.write_str(")")
+            } else {
+                write_operand(f, &operand.value, UNARY_PRECEDENCE, indent)
+            }
+        }
+    }
+}
+
+fn starts_with_number(expr: &Expr) -> bool {
+    match expr {
+        Expr::Value(Value::Number(_) | Value::I64(_) | Value::U64(_) | Value::Float(_)) => true,
+        Expr::Field(value, _) | Expr::Index(value, _) => starts_with_number(&value.value),
+        _ => false,
+    }
+}
+
+//closures with a return type can only be written in the `fun` form
+fn write_closure(f: &mut Formatter, closure: &Closure, indent: usize) -> fmt::Result {
+    if let Some(return_type) = &closure.return_type {
+        f.write_str("fun(")?;
+        write_separated(f, &closure.params)?;
+        write!(f, ") -> {} ", return_type.value)?;
+        return write_body(f, &closure.body, indent);
+    }
+    f.write_str("|")?;
+    write_separated(f, &closure.params)?;
+    f.write_str("| ")?;
+    match closure.body.as_slice() {
+        [Token {
+            value: ExprOrStatement::Expr(expr),
+            ..
+        }] => write_expr(f, expr, indent),
+        body => write_body(f, body, indent),
+    }
+}
+
+fn write_arm(f: &mut Formatter, arm: &MatchArm, indent: usize) -> fmt::Result {
+    write!(f, "{}", arm.pattern.value)?;
+    if let Some(guard) = &arm.guard {
+        f.write_str(" if ")?;
+        write_expr(f, &guard.value, indent)?;
+    }
+    f.write_str(" => ")?;
+    match arm.body.as_slice() {
+        [Token {
+            value: ExprOrStatement::Expr(expr),
+            ..
+        }] => write_expr(f, expr, indent),
+        body => write_body(f, body, indent),
+    }
+}
+
+//binds tighter than any binary operator
+const UNARY_PRECEDENCE: u8 = 10;
+
+fn precedence(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Binary(_, op, _) => op.value.precedence(),
+        Expr::Unary(_, _) => UNARY_PRECEDENCE,
+        Expr::Range(_, _, _, _) | Expr::Closure(_) => 0,
+        _ => u8::MAX,
+    }
+}
+
+//parenthesises an operand that binds looser than its position requires
+fn write_operand(f: &mut Formatter, expr: &Expr, min_precedence: u8, indent: usize) -> fmt::Result {
+    if precedence(expr) < min_precedence {
+        f.write_str("(")?;
+        write_expr(f, expr, indent)?;
+        f.write_str(")")
+    } else {
+        write_expr(f, expr, indent)
+    }
+}
+
+fn write_statement(f: &mut Formatter, statement: &Statement, indent: usize) -> fmt::Result {
+    match statement {
+        Statement::Let(name, type_, value, mutable) => {
+            f.write_str(if *mutable { "let mut " } else { "let " })?;
+            f.write_str(&name.value)?;
+            if let Some(type_) = type_ {
+                write!(f, ": {}", type_.value)?;
+            }
+            f.write_str(" = ")?;
+            write_expr(f, &value.value, indent)
+        }
+        Statement::Assign(place, operator, value) => {
+            write_expr(f, &place.value, indent)?;
+            match operator {
+                Some(operator) => write!(f, " {}= ", operator.value)?,
+                None => f.write_str(" = ")?,
+            }
+            write_expr(f, &value.value, indent)
+        }
+        Statement::For(label, name, range, body) => {
+            write_label(f, label)?;
+            write!(f, "for {} = ", name.value)?;
+            write_expr(f, &range.value, indent)?;
+            f.write_str(" ")?;
+            write_body(f, body, indent)
+        }
+        Statement::ForIn(label, name, iterable, body) => {
+            write_label(f, label)?;
+            write!(f, "for {} in ", name.value)?;
+            write_expr(f, &iterable.value, indent)?;
+            f.write_str(" ")?;
+            write_body(f, body, indent)
+        }
+        Statement::While(label, condition, body) => {
+            write_label(f, label)?;
+            f.write_str("while ")?;
+            write_expr(f, &condition.value, indent)?;
+            f.write_str(" ")?;
+            write_body(f, body, indent)
+        }
+        Statement::Break(label, value) => {
+            f.write_str("break")?;
+            if let Some(label) = label {
+                write!(f, " '{}", label.value)?;
+            }
+            if let Some(value) = value {
+                f.write_str(" ")?;
+                write_expr(f, &value.value, indent)?;
+            }
+            Ok(())
+        }
+        Statement::Continue(label) => {
+            f.write_str("continue")?;
+            if let Some(label) = label {
+                write!(f, " '{}", label.value)?;
+            }
+            Ok(())
+        }
+        Statement::Return(value) => {
+            f.write_str("return")?;
+            if let Some(value) = value {
+                f.write_str(" ")?;
+                write_expr(f, &value.value, indent)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+//writes c as it would appear between quotes
+fn write_escaped(f: &mut Formatter, c: char, quote: char) -> fmt::Result {
+    match c {
+        '\n' => f.write_str("\\n"),
+        '\r' => f.write_str("\\r"),
+        '\t' => f.write_str("\\t"),
+        '\0' => f.write_str("\\0"),
+        '\\' => f.write_str("\\\\"),
+        '{' | '}' if quote == '"' => write!(f, "\\{}", c),
+        c if c == quote => write!(f, "\\{}", c),
+        c if c.is_control() => write!(f, "\\u{{{:x}}}", c as u32),
+        c => write!(f, "{}", c),
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Value::Number(number) => write!(f, "{}", number),
+            Value::I64(number) => write!(f, "{}i64", number),
+            Value::U64(number) => write!(f, "{}u64", number),
+            //debug formatting always includes a `.` or exponent, so it reads back as a float
+            Value::Float(number) => write!(f, "{:?}", number),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Char(c) => {
+                f.write_str("'")?;
+                write_escaped(f, *c, '\'')?;
+                f.write_str("'")
+            }
+            Value::String(string) => {
+                f.write_str("\"")?;
+                for c in string.chars() {
+                    write_escaped(f, c, '"')?;
+                }
+                f.write_str("\"")
+            }
+        }
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Pattern::Wildcard => f.write_str("_"),
+            Pattern::Value(value) => write!(f, "{}", value),
+            Pattern::Binding(name) => f.write_str(name),
+            Pattern::Tuple(patterns) => {
+                f.write_str("(")?;
+                write_separated(f, patterns)?;
+                f.write_str(")")
+            }
+            Pattern::Struct(name, fields, rest) => {
+                write!(f, "{} {{ ", name.value)?;
+                for (i, (field, pattern)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    match &pattern.value {
+                        Pattern::Binding(binding) if *binding == field.value => {
+                            f.write_str(binding)?
+                        }
+                        pattern => write!(f, "{}: {}", field.value, pattern)?,
+                    }
+                }
+                match (*rest, fields.is_empty()) {
+                    (true, true) => f.write_str(".. }"),
+                    (true, false) => f.write_str(", .. }"),
+                    (false, true) => f.write_str("}"),
+                    (false, false) => f.write_str(" }"),
+                }
+            }
+            Pattern::Variant(enum_name, variant, payload) => {
+                write!(f, "{}::{}", enum_name.value, variant.value)?;
+                if !payload.is_empty() {
+                    f.write_str("(")?;
+                    write_separated(f, payload)?;
+                    f.write_str(")")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Display for BinaryOp {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+impl Display for UnaryOp {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write_expr(f, self, 0)
+    }
+}
+
+impl Display for Statement {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write_statement(f, self, 0)
+    }
+}
+
+impl Display for ExprOrStatement {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write_item(f, self, 0)
+    }
+}
+
+impl Display for TypeExpr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            TypeExpr::Named(name) => f.write_str(name),
+            TypeExpr::Applied(name, arguments) => {
+                write!(f, "{}<", name.value)?;
+                write_separated(f, arguments)?;
+                f.write_str(">")
+            }
+            TypeExpr::Function(params, return_type) => {
+                f.write_str("fun(")?;
+                write_separated(f, params)?;
+                write!(f, ") -> {}", return_type.value)
+            }
+            TypeExpr::Tuple(types) => {
+                f.write_str("(")?;
+                write_separated(f, types)?;
+                f.write_str(")")
+            }
+            TypeExpr::Array(element, None) => write!(f, "[{}]", element.value),
+            TypeExpr::Array(element, Some(length)) => write!(f, "[{}; {}]", element.value, length),
+        }
+    }
+}
+
+impl Display for GenericParameter {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(&self.0.value)?;
+        for (i, bound) in self.1.iter().enumerate() {
+            f.write_str(if i == 0 { ": " } else { " + " })?;
+            write!(f, "{}", bound.value)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for ClosureParameter {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(&self.0.value)?;
+        match &self.1 {
+            Some(type_) => write!(f, ": {}", type_.value),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Display for Parameter {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.0.value, self.1.value)
+    }
+}
+
+/// Prints a function as source text that parses back to the same tree.
+impl Display for Fun {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "fun {}", self.name.value)?;
+        if !self.generics.is_empty() {
+            f.write_str("<")?;
+            write_separated(f, &self.generics)?;
+            f.write_str(">")?;
+        }
+        f.write_str("(")?;
+        write_separated(f, &self.params)?;
+        write!(f, ") -> {} ", self.return_type.value)?;
+        write_body(f, &self.body, 0)
+    }
+}
@@ -0,0 +1,394 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    ops::Range,
+};
+
+use crate::parser_combinator::Token;
+
+use super::*;
+
+/// A name that does not refer to anything, or a binding that is likely a mistake.
+/// Spans are byte ranges of the use or binding.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResolutionError {
+    Undefined(String, Range<usize>),
+    UnusedShadowed(String, Range<usize>, Range<usize>), //name, shadowed binding, shadowing binding
+    DuplicateParameter(String, Range<usize>, Range<usize>), //name, parameter, earlier parameter
+}
+
+impl ResolutionError {
+    //where the error is reported, for ordering
+    fn span(&self) -> &Range<usize> {
+        match self {
+            ResolutionError::Undefined(_, span)
+            | ResolutionError::UnusedShadowed(_, _, span)
+            | ResolutionError::DuplicateParameter(_, span, _) => span,
+        }
+    }
+}
+
+impl Display for ResolutionError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ResolutionError::Undefined(name, span) => {
+                write!(f, "{} is not defined at {:?}", name, span)
+            }
+            ResolutionError::UnusedShadowed(name, shadowed, span) => write!(
+                f,
+                "{} bound at {:?} is shadowed at {:?} without being used",
+                name, shadowed, span
+            ),
+            ResolutionError::DuplicateParameter(name, span, earlier) => write!(
+                f,
+                "parameter {} at {:?} is already declared at {:?}",
+                name, span, earlier
+            ),
+        }
+    }
+}
+
+/// Identifies a symbol by its position in [`Resolution::symbols`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SymbolId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+    Builtin,
+    Import, //the last segment of an import path
+    Fun,
+    Const,
+    Param, //of a function or closure
+    Local, //let, loop variable or pattern binding
+}
+
+/// A name declared in a program. Locals are declared by their whole `let` statement.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub declared: Range<usize>,
+}
+
+/// A use of a name and the symbol it refers to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub span: Range<usize>,
+    pub symbol: SymbolId,
+}
+
+/// The symbols a program declares, every use of a name in source order along with the
+/// symbol it refers to, and the errors found in source order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Resolution {
+    pub symbols: Vec<Symbol>,
+    pub references: Vec<Reference>,
+    pub errors: Vec<ResolutionError>,
+}
+
+impl Resolution {
+    pub fn symbol(&self, id: SymbolId) -> &Symbol {
+        &self.symbols[id.0]
+    }
+
+    /// The symbol the name used at `span` refers to, if it refers to one.
+    pub fn symbol_at(&self, span: &Range<usize>) -> Option<&Symbol> {
+        self.references
+            .iter()
+            .find(|reference| reference.span == *span)
+            .map(|reference| self.symbol(reference.symbol))
+    }
+}
+
+#[derive(Default)]
+struct Resolver<'t> {
+    scope: Vec<(&'t str, SymbolId)>,    //innermost last
+    used: Vec<bool>,                    //by symbol
+    shadows: Vec<(SymbolId, SymbolId)>, //shadowed, shadowing
+    resolution: Resolution,
+}
+
+impl<'t> Resolver<'t> {
+    fn declare(&mut self, name: &'t str, kind: SymbolKind, span: Range<usize>) -> SymbolId {
+        let id = SymbolId(self.resolution.symbols.len());
+        self.resolution.symbols.push(Symbol {
+            name: name.to_string(),
+            kind,
+            declared: span,
+        });
+        self.used.push(false);
+        self.scope.push((name, id));
+        id
+    }
+
+    fn lookup(&self, name: &str) -> Option<SymbolId> {
+        self.scope
+            .iter()
+            .rev()
+            .find(|(bound, _)| *bound == name)
+            .map(|(_, id)| *id)
+    }
+
+    //whether a shadowed local was used is only known once the whole program is resolved
+    fn bind(&mut self, name: &'t str, kind: SymbolKind, span: Range<usize>) {
+        let shadowed = self.lookup(name).filter(|shadowed| {
+            matches!(
+                self.resolution.symbol(*shadowed).kind,
+                SymbolKind::Param | SymbolKind::Local
+            )
+        });
+        let id = self.declare(name, kind, span);
+        if let Some(shadowed) = shadowed {
+            self.shadows.push((shadowed, id));
+        }
+    }
+
+    fn bind_pattern(&mut self, pattern: &'t Token<Pattern>) {
+        match &pattern.value {
+            Pattern::Wildcard | Pattern::Value(_) => {}
+            Pattern::Binding(name) => self.bind(name, SymbolKind::Local, pattern.span()),
+            Pattern::Tuple(patterns) | Pattern::Variant(_, _, patterns) => {
+                for pattern in patterns {
+                    self.bind_pattern(pattern);
+                }
+            }
+            Pattern::Struct(_, fields, _) => {
+                for (_, pattern) in fields {
+                    self.bind_pattern(pattern);
+                }
+            }
+        }
+    }
+
+    fn params(&mut self, params: impl IntoIterator<Item = (&'t Token<String>, Range<usize>)>) {
+        let mut declared: Vec<(&str, Range<usize>)> = Vec::new();
+        for (name, span) in params {
+            match declared.iter().find(|(earlier, _)| *earlier == name.value) {
+                Some((_, earlier)) => {
+                    let error = ResolutionError::DuplicateParameter(
+                        name.value.clone(),
+                        span.clone(),
+                        earlier.clone(),
+                    );
+                    self.resolution.errors.push(error);
+                    self.declare(&name.value, SymbolKind::Param, span);
+                }
+                None => {
+                    declared.push((&name.value, span.clone()));
+                    self.bind(&name.value, SymbolKind::Param, span);
+                }
+            }
+        }
+    }
+
+    fn refer(&mut self, name: &str, span: Range<usize>) {
+        match self.lookup(name) {
+            Some(symbol) => {
+                self.used[symbol.0] = true;
+                self.resolution.references.push(Reference { span, symbol });
+            }
+            None => {
+                let error = ResolutionError::Undefined(name.to_string(), span);
+                self.resolution.errors.push(error);
+            }
+        }
+    }
+
+    fn fun(&mut self, fun: &'t Fun) {
+        let scope = self.scope.len();
+        let params = fun
+            .params
+            .iter()
+            .map(|param| (&param.value.0, param.span()));
+        self.params(params);
+        self.body(&fun.body);
+        self.scope.truncate(scope);
+    }
+
+    fn closure(&mut self, closure: &'t Closure) {
+        let scope = self.scope.len();
+        let params = closure
+            .params
+            .iter()
+            .map(|param| (&param.value.0, param.span()));
+        self.params(params);
+        self.body(&closure.body);
+        self.scope.truncate(scope);
+    }
+
+    fn body(&mut self, body: &'t [Token<ExprOrStatement>]) {
+        let scope = self.scope.len();
+        for item in body {
+            match &item.value {
+                ExprOrStatement::Expr(expr) => self.expr_at(expr, item.span()),
+                ExprOrStatement::Statement(statement) => self.statement(statement, item.span()),
+            }
+        }
+        self.scope.truncate(scope);
+    }
+
+    fn statement(&mut self, statement: &'t Statement, span: Range<usize>) {
+        match statement {
+            //the value is resolved first, so it refers to any binding being shadowed
+            Statement::Let(name, _, value, _) => {
+                self.expr(value);
+                self.bind(&name.value, SymbolKind::Local, span);
+            }
+            Statement::Assign(place, _, value) => {
+                self.expr(place);
+                self.expr(value);
+            }
+            Statement::For(_, name, range, body) | Statement::ForIn(_, name, range, body) => {
+                self.expr(range);
+                let scope = self.scope.len();
+                self.bind(&name.value, SymbolKind::Local, name.span());
+                self.body(body);
+                self.scope.truncate(scope);
+            }
+            Statement::While(_, condition, body) => {
+                self.expr(condition);
+                self.body(body);
+            }
+            Statement::Break(_, value) | Statement::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            Statement::Continue(_) => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &'t Token<Expr>) {
+        self.expr_at(&expr.value, expr.span())
+    }
+
+    fn expr_at(&mut self, expr: &'t Expr, span: Range<usize>) {
+        match expr {
+            Expr::Value(_) => {}
+            Expr::Ident(name) => self.refer(name, span),
+            Expr::Call(callee, args) => {
+                self.expr(callee);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Expr::Range(start, end, _, step) => {
+                self.expr(start);
+                self.expr(end);
+                if let Some(step) = step {
+                    self.expr(step);
+                }
+            }
+            Expr::If(condition, body, else_body) => {
+                self.expr(condition);
+                self.body(body);
+                if let Some(else_body) = else_body {
+                    self.body(else_body);
+                }
+            }
+            Expr::Binary(lhs, _, rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            Expr::Unary(_, operand) => self.expr(operand),
+            Expr::Loop(_, body) => self.body(body),
+            Expr::Struct(_, fields) => {
+                for (_, value) in fields {
+                    self.expr(value);
+                }
+            }
+            Expr::Field(value, _) => self.expr(value),
+            Expr::Variant(_, _, values) | Expr::Array(values) | Expr::Tuple(values) => {
+                for value in values {
+                    self.expr(value);
+                }
+            }
+            Expr::Repeat(value, count) => {
+                self.expr(value);
+                self.expr(count);
+            }
+            Expr::Index(value, index) => {
+                self.expr(value);
+                self.expr(index);
+            }
+            Expr::Match(scrutinee, arms) => {
+                self.expr(scrutinee);
+                for arm in arms {
+                    let scope = self.scope.len();
+                    self.bind_pattern(&arm.value.pattern);
+                    if let Some(guard) = &arm.value.guard {
+                        self.expr(guard);
+                    }
+                    self.body(&arm.value.body);
+                    self.scope.truncate(scope);
+                }
+            }
+            Expr::Closure(closure) => self.closure(closure),
+        }
+    }
+
+    //names starting with an underscore are meant to go unused
+    fn finish(mut self) -> Resolution {
+        for (shadowed, shadowing) in self.shadows {
+            let symbol = self.resolution.symbol(shadowed);
+            if !self.used[shadowed.0] && !symbol.name.starts_with('_') {
+                let error = ResolutionError::UnusedShadowed(
+                    symbol.name.clone(),
+                    symbol.declared.clone(),
+                    self.resolution.symbol(shadowing).declared.clone(),
+                );
+                self.resolution.errors.push(error);
+            }
+        }
+        let errors = &mut self.resolution.errors;
+        errors.sort_by_key(|error| error.span().start);
+        self.resolution
+    }
+}
+
+fn builtins() -> Resolver<'static> {
+    let mut resolver = Resolver::default();
+    for builtin in Builtin::ALL {
+        resolver.declare(builtin.name(), SymbolKind::Builtin, 0..0);
+    }
+    resolver
+}
+
+/// Resolves the names used in a function, with only built in names and the function itself
+/// in scope.
+pub fn resolve_fun(fun: &Fun) -> Resolution {
+    let mut resolver = builtins();
+    resolver.declare(&fun.name.value, SymbolKind::Fun, fun.name.span());
+    resolver.fun(fun);
+    resolver.finish()
+}
+
+/// Resolves every name used in a program to the function, constant, import, parameter or
+/// local binding it refers to. Functions, constants and imports are visible throughout the
+/// program; parameters and locals from where they are bound to the end of their body.
+pub fn resolve_program(program: &Program) -> Resolution {
+    let mut resolver = builtins();
+    for item in &program.items {
+        match &item.value {
+            Item::Fun(fun) => {
+                resolver.declare(&fun.name.value, SymbolKind::Fun, fun.name.span());
+            }
+            Item::Const(name, _, _) => {
+                resolver.declare(&name.value, SymbolKind::Const, name.span());
+            }
+            Item::Import(path) => {
+                if let Some(last) = path.last() {
+                    resolver.declare(&last.value, SymbolKind::Import, last.span());
+                }
+            }
+            Item::Type(_, _) | Item::Struct(_, _) | Item::Enum(_, _) => {}
+        }
+    }
+    for item in &program.items {
+        match &item.value {
+            Item::Fun(fun) => resolver.fun(fun),
+            Item::Const(_, _, value) => resolver.expr(value),
+            Item::Type(_, _) | Item::Import(_) | Item::Struct(_, _) | Item::Enum(_, _) => {}
+        }
+    }
+    resolver.finish()
+}
@@ -0,0 +1,421 @@
+use std::{
+    cell::RefCell,
+    fmt::{self, Display, Formatter},
+    ops::Range,
+    rc::Rc,
+};
+
+use super::*;
+
+/// The integer types, which ranges and integer arithmetic are generic over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Integer {
+    Int,
+    I64,
+    U64,
+}
+
+/// `start .. end` or `start ..= end`, counting by `step`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeValue {
+    pub start: i128,
+    pub end: i128,
+    pub inclusive: bool,
+    pub step: i128,
+    pub kind: Integer, //the type of the bounds, and so of each value in the range
+}
+
+impl RangeValue {
+    /// The values in the range, in order. A step of zero gives no values.
+    pub fn values(self) -> impl Iterator<Item = i128> {
+        let mut next = self.start;
+        std::iter::from_fn(move || {
+            let more = match (self.step > 0, self.inclusive) {
+                _ if self.step == 0 => false,
+                (true, true) => next <= self.end,
+                (true, false) => next < self.end,
+                (false, true) => next >= self.end,
+                (false, false) => next > self.end,
+            };
+            let value = next;
+            next += self.step;
+            more.then_some(value)
+        })
+    }
+}
+
+/// A binding's value, shared with the closures that capture it.
+pub type Cell<'p> = Rc<RefCell<RuntimeValue<'p>>>;
+
+/// A closure along with the bindings in scope where it was created.
+#[derive(Debug)]
+pub struct ClosureValue<'p> {
+    pub closure: &'p Closure,
+    pub captured: Vec<(&'p str, Cell<'p>)>,
+}
+
+//closures are only equal to themselves
+impl PartialEq for ClosureValue<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// A value while a program runs. Arrays, tuples and structs are copied on assignment.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeValue<'p> {
+    Int(i32),
+    I64(i64),
+    U64(u64),
+    Float(f64),
+    Bool(bool),
+    Char(char),
+    String(String),
+    Unit,
+    Tuple(Vec<RuntimeValue<'p>>),
+    Array(Vec<RuntimeValue<'p>>),
+    Range(RangeValue),
+    Struct(&'p str, Vec<(&'p str, RuntimeValue<'p>)>), //name, fields in declared order
+    Variant(&'p str, &'p str, Vec<RuntimeValue<'p>>),  //enum, variant, payload
+    Function(&'p Fun),
+    Builtin(Builtin),
+    Closure(Rc<ClosureValue<'p>>),
+}
+
+impl<'p> RuntimeValue<'p> {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            RuntimeValue::Int(_) => "int",
+            RuntimeValue::I64(_) => "i64",
+            RuntimeValue::U64(_) => "u64",
+            RuntimeValue::Float(_) => "float",
+            RuntimeValue::Bool(_) => "bool",
+            RuntimeValue::Char(_) => "char",
+            RuntimeValue::String(_) => "string",
+            RuntimeValue::Unit => "unit",
+            RuntimeValue::Tuple(_) => "tuple",
+            RuntimeValue::Array(_) => "array",
+            RuntimeValue::Range(_) => "range",
+            RuntimeValue::Struct(_, _) => "struct",
+            RuntimeValue::Variant(_, _, _) => "enum",
+            RuntimeValue::Function(_) | RuntimeValue::Builtin(_) | RuntimeValue::Closure(_) => {
+                "function"
+            }
+        }
+    }
+
+    pub fn integer(&self) -> Option<(i128, Integer)> {
+        match self {
+            RuntimeValue::Int(value) => Some((*value as i128, Integer::Int)),
+            RuntimeValue::I64(value) => Some((*value as i128, Integer::I64)),
+            RuntimeValue::U64(value) => Some((*value as i128, Integer::U64)),
+            _ => None,
+        }
+    }
+
+    /// An integer of the given type, if it is in range.
+    pub fn from_integer(value: i128, kind: Integer) -> Option<Self> {
+        match kind {
+            Integer::Int => i32::try_from(value).ok().map(RuntimeValue::Int),
+            Integer::I64 => i64::try_from(value).ok().map(RuntimeValue::I64),
+            Integer::U64 => u64::try_from(value).ok().map(RuntimeValue::U64),
+        }
+    }
+}
+
+impl From<&Value> for RuntimeValue<'_> {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::Number(value) => RuntimeValue::Int(*value),
+            Value::I64(value) => RuntimeValue::I64(*value),
+            Value::U64(value) => RuntimeValue::U64(*value),
+            Value::Float(value) => RuntimeValue::Float(*value),
+            Value::Bool(value) => RuntimeValue::Bool(*value),
+            Value::Char(value) => RuntimeValue::Char(*value),
+            Value::String(value) => RuntimeValue::String(value.clone()),
+        }
+    }
+}
+
+fn write_separated(f: &mut Formatter, values: &[RuntimeValue]) -> fmt::Result {
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{}", value)?;
+    }
+    Ok(())
+}
+
+/// Prints a value the way `print` and `to_string` show it: strings and chars without quotes.
+impl Display for RuntimeValue<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            RuntimeValue::Int(value) => write!(f, "{}", value),
+            RuntimeValue::I64(value) => write!(f, "{}", value),
+            RuntimeValue::U64(value) => write!(f, "{}", value),
+            RuntimeValue::Float(value) => write!(f, "{:?}", value),
+            RuntimeValue::Bool(value) => write!(f, "{}", value),
+            RuntimeValue::Char(value) => write!(f, "{}", value),
+            RuntimeValue::String(value) => f.write_str(value),
+            RuntimeValue::Unit => f.write_str("()"),
+            RuntimeValue::Tuple(values) => {
+                f.write_str("(")?;
+                write_separated(f, values)?;
+                f.write_str(")")
+            }
+            RuntimeValue::Array(values) => {
+                f.write_str("[")?;
+                write_separated(f, values)?;
+                f.write_str("]")
+            }
+            RuntimeValue::Range(range) => {
+                let operator = if range.inclusive { "..=" } else { ".." };
+                write!(f, "{} {} {}", range.start, operator, range.end)?;
+                match range.step {
+                    1 => Ok(()),
+                    step => write!(f, " step {}", step),
+                }
+            }
+            RuntimeValue::Struct(name, fields) => {
+                write!(f, "{} {{ ", name)?;
+                for (i, (field, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}: {}", field, value)?;
+                }
+                f.write_str(" }")
+            }
+            RuntimeValue::Variant(name, variant, payload) => {
+                write!(f, "{}::{}", name, variant)?;
+                if payload.is_empty() {
+                    return Ok(());
+                }
+                f.write_str("(")?;
+                write_separated(f, payload)?;
+                f.write_str(")")
+            }
+            RuntimeValue::Function(fun) => write!(f, "<fun {}>", fun.name.value),
+            RuntimeValue::Builtin(builtin) => write!(f, "<fun {}>", builtin.name()),
+            RuntimeValue::Closure(_) => f.write_str("<closure>"),
+        }
+    }
+}
+
+/// What went wrong while running a program.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeErrorKind {
+    Undefined(String),
+    InvalidOperand(&'static str, &'static str), //operator, operand type
+    Mismatched(&'static str, &'static str),     //expected type, found type
+    DivisionByZero,
+    Overflow(&'static str), //operator
+    IndexOutOfBounds(i128, usize),
+    NotCallable(&'static str),
+    ArityMismatch(usize, usize), //expected, found
+    UnknownField(String),
+    NoMatchingArm,
+    StackOverflow,
+    RecursiveConst(String),
+    InvalidArgument(String),
+    Output(String), //writing printed output failed
+}
+
+impl Display for RuntimeErrorKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            RuntimeErrorKind::Undefined(name) => write!(f, "{} is not defined", name),
+            RuntimeErrorKind::InvalidOperand(op, type_) => {
+                write!(f, "cannot apply {} to {}", op, type_)
+            }
+            RuntimeErrorKind::Mismatched(expected, found) => {
+                write!(f, "expected {} but found {}", expected, found)
+            }
+            RuntimeErrorKind::DivisionByZero => f.write_str("division by zero"),
+            RuntimeErrorKind::Overflow(op) => write!(f, "{} overflowed", op),
+            RuntimeErrorKind::IndexOutOfBounds(index, length) => {
+                write!(f, "index {} is out of bounds for length {}", index, length)
+            }
+            RuntimeErrorKind::NotCallable(type_) => write!(f, "cannot call a {}", type_),
+            RuntimeErrorKind::ArityMismatch(expected, found) => {
+                write!(f, "expected {} arguments but found {}", expected, found)
+            }
+            RuntimeErrorKind::UnknownField(name) => write!(f, "no member {}", name),
+            RuntimeErrorKind::NoMatchingArm => f.write_str("no match arm matches the value"),
+            RuntimeErrorKind::StackOverflow => f.write_str("too many nested calls"),
+            RuntimeErrorKind::RecursiveConst(name) => {
+                write!(f, "constant {} refers to itself", name)
+            }
+            RuntimeErrorKind::InvalidArgument(message) => f.write_str(message),
+            RuntimeErrorKind::Output(message) => write!(f, "cannot write output: {}", message),
+        }
+    }
+}
+
+/// A function being run when an error happened, and where it was called from.
+/// The function a program is started with has no call site.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub function: String,
+    pub call: Option<Range<usize>>,
+}
+
+/// A runtime error, the span of the expression that caused it, and the calls it happened
+/// in, innermost first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub span: Range<usize>,
+    pub trace: Vec<Frame>,
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} at {:?}", self.kind, self.span)?;
+        for frame in &self.trace {
+            write!(f, "\n    in {}", frame.function)?;
+            if let Some(call) = &frame.call {
+                write!(f, " called at {:?}", call)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The functions every program can call without declaring them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    Print,
+    Println,
+    Len,
+    ToString,
+    Contains,
+    Substring,
+    Split,
+    Trim,
+    ToUpper,
+    ToLower,
+    Chars,
+    ParseInt,
+}
+
+impl Builtin {
+    pub const ALL: [Builtin; 12] = [
+        Builtin::Print,
+        Builtin::Println,
+        Builtin::Len,
+        Builtin::ToString,
+        Builtin::Contains,
+        Builtin::Substring,
+        Builtin::Split,
+        Builtin::Trim,
+        Builtin::ToUpper,
+        Builtin::ToLower,
+        Builtin::Chars,
+        Builtin::ParseInt,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Builtin::Print => "print",
+            Builtin::Println => "println",
+            Builtin::Len => "len",
+            Builtin::ToString => literal_parser::TO_STRING,
+            Builtin::Contains => "contains",
+            Builtin::Substring => "substring",
+            Builtin::Split => "split",
+            Builtin::Trim => "trim",
+            Builtin::ToUpper => "to_upper",
+            Builtin::ToLower => "to_lower",
+            Builtin::Chars => "chars",
+            Builtin::ParseInt => "parse_int",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Builtin> {
+        Builtin::ALL
+            .into_iter()
+            .find(|builtin| builtin.name() == name)
+    }
+
+    pub fn arity(&self) -> usize {
+        match self {
+            Builtin::Contains | Builtin::Split => 2,
+            Builtin::Substring => 3,
+            _ => 1,
+        }
+    }
+
+    /// Applies a builtin other than `print` and `println`, which need somewhere to print to.
+    pub fn apply<'p>(
+        &self,
+        args: &[RuntimeValue<'p>],
+    ) -> Result<RuntimeValue<'p>, RuntimeErrorKind> {
+        let string = |value: &RuntimeValue| match value {
+            RuntimeValue::String(value) => Ok(value.clone()),
+            value => Err(RuntimeErrorKind::Mismatched("string", value.type_name())),
+        };
+        let index = |value: &RuntimeValue| match value.integer() {
+            Some((index, _)) => Ok(index),
+            None => Err(RuntimeErrorKind::Mismatched("int", value.type_name())),
+        };
+        let strings = |values: Vec<String>| {
+            RuntimeValue::Array(values.into_iter().map(RuntimeValue::String).collect())
+        };
+        match (self, args) {
+            (Builtin::Len, [value]) => {
+                let length = match value {
+                    RuntimeValue::String(value) => value.chars().count(),
+                    RuntimeValue::Array(values) | RuntimeValue::Tuple(values) => values.len(),
+                    value => {
+                        return Err(RuntimeErrorKind::Mismatched(
+                            "string or array",
+                            value.type_name(),
+                        ))
+                    }
+                };
+                i32::try_from(length)
+                    .map(RuntimeValue::Int)
+                    .map_err(|_| RuntimeErrorKind::Overflow("len"))
+            }
+            (Builtin::ToString, [value]) => Ok(RuntimeValue::String(value.to_string())),
+            (Builtin::Contains, [RuntimeValue::Array(values), value]) => {
+                Ok(RuntimeValue::Bool(values.contains(value)))
+            }
+            (Builtin::Contains, [value, part]) => {
+                Ok(RuntimeValue::Bool(string(value)?.contains(&string(part)?)))
+            }
+            (Builtin::Substring, [value, start, end]) => {
+                let chars: Vec<_> = string(value)?.chars().collect();
+                let (start, end) = (index(start)?, index(end)?);
+                if end < start || end > chars.len() as i128 {
+                    return Err(RuntimeErrorKind::IndexOutOfBounds(end, chars.len()));
+                }
+                if start < 0 {
+                    return Err(RuntimeErrorKind::IndexOutOfBounds(start, chars.len()));
+                }
+                let substring = &chars[start as usize..end as usize];
+                Ok(RuntimeValue::String(substring.iter().collect()))
+            }
+            (Builtin::Split, [value, separator]) => {
+                let (value, separator) = (string(value)?, string(separator)?);
+                let parts = value.split(separator.as_str());
+                Ok(strings(parts.map(str::to_string).collect()))
+            }
+            (Builtin::Trim, [value]) => Ok(RuntimeValue::String(string(value)?.trim().to_string())),
+            (Builtin::ToUpper, [value]) => Ok(RuntimeValue::String(string(value)?.to_uppercase())),
+            (Builtin::ToLower, [value]) => Ok(RuntimeValue::String(string(value)?.to_lowercase())),
+            (Builtin::Chars, [value]) => Ok(RuntimeValue::Array(
+                string(value)?.chars().map(RuntimeValue::Char).collect(),
+            )),
+            (Builtin::ParseInt, [value]) => {
+                let value = string(value)?;
+                value.trim().parse().map(RuntimeValue::Int).map_err(|_| {
+                    RuntimeErrorKind::InvalidArgument(format!("cannot parse {:?} as int", value))
+                })
+            }
+            _ => Err(RuntimeErrorKind::ArityMismatch(self.arity(), args.len())),
+        }
+    }
+}
@@ -4,7 +4,7 @@ use super::*;
 
 #[test]
 fn test_pint_1() {
-    let parser = pint();
+    let parser = pnumber();
     let result = parser.parse("1234567890".into());
     let expected = Ok((
         Token {
@@ -24,7 +24,7 @@ fn test_pint_1() {
 
 #[test]
 fn test_pint_2() {
-    let parser = pint();
+    let parser = pnumber();
     let result = parser.parse("-123".into());
     let expected = Ok((
         Token {
@@ -70,7 +70,7 @@ fn test_param() {
         Token {
             value: Parameter(
                 Token::new("left".to_string(), 0, 4),
-                Token::new("right".to_string(), 7, 5),
+                Token::new(TypeExpr::Named("right".to_string()), 7, 5),
             ),
             start: 0,
             length: 12,
@@ -95,7 +95,7 @@ fn test_params() {
                 Token::new(
                     Parameter(
                         Token::new("left".to_string(), 1, 4),
-                        Token::new("right".to_string(), 8, 5),
+                        Token::new(TypeExpr::Named("right".to_string()), 8, 5),
                     ),
                     1,
                     12,
@@ -103,7 +103,7 @@ fn test_params() {
                 Token::new(
                     Parameter(
                         Token::new("lefty".to_string(), 15, 5),
-                        Token::new("righty".to_string(), 21, 6),
+                        Token::new(TypeExpr::Named("righty".to_string()), 21, 6),
                     ),
                     15,
                     12,
@@ -130,10 +130,12 @@ fn test_let() {
         Token {
             value: Statement::Let(
                 Token::new("x".to_string(), 4, 1),
+                None,
                 Token::new(Expr::Value(Value::Number(1)), 8, 1),
+                false,
             ),
-            start: 4,
-            length: 5,
+            start: 0,
+            length: 9,
         },
         ContinuationState {
             remaining: "",
@@ -231,13 +233,7 @@ fn test_identifier_fail() {
     //identifiers cannot start with a number
     let result = parser.parse("1left".into());
     let expected = Err(Error::new(
-        Expected::Or(
-            Box::new(Expected::Or(
-                Box::new(('a'..='z').into()),
-                Box::new(('A'..='Z').into()),
-            )),
-            Box::new('_'.into()),
-        ),
+        Expected::Class("letter or '_'".into()),
         "1",
         0,
         0,
@@ -248,19 +244,19 @@ fn test_identifier_fail() {
 
 #[test]
 fn test_call() {
-    let parser = pcall();
+    let parser = pexpr();
     let result = parser.parse("left(a,b)".into());
     let expected = Ok((
         Token {
             value: Expr::Call(
-                Token::new("left".to_string(), 0, 4),
+                Box::new(Token::new(Expr::Ident("left".to_string()), 0, 4)),
                 vec![
                     Token::new(Expr::Ident("a".to_string()), 5, 1),
                     Token::new(Expr::Ident("b".to_string()), 7, 1),
                 ],
             ),
             start: 0,
-            length: 8,
+            length: 9,
         },
         ContinuationState {
             remaining: "",
@@ -274,29 +270,29 @@ fn test_call() {
 
 #[test]
 fn test_call_recur() {
-    let parser = pcall();
+    let parser = pexpr();
     let result = parser.parse("left(a,left(b, c))".into());
     let expected = Ok((
         Token {
             value: Expr::Call(
-                Token::new("left".to_string(), 0, 4),
+                Box::new(Token::new(Expr::Ident("left".to_string()), 0, 4)),
                 vec![
                     Token::new(Expr::Ident("a".to_string()), 5, 1),
                     Token::new(
                         Expr::Call(
-                            Token::new("left".to_string(), 7, 4),
+                            Box::new(Token::new(Expr::Ident("left".to_string()), 7, 4)),
                             vec![
                                 Token::new(Expr::Ident("b".to_string()), 12, 1),
                                 Token::new(Expr::Ident("c".to_string()), 15, 1),
                             ],
                         ),
                         7,
-                        9,
+                        10,
                     ),
                 ],
             ),
             start: 0,
-            length: 16,
+            length: 18,
         },
         ContinuationState {
             remaining: "",
@@ -307,3 +303,2624 @@ fn test_call_recur() {
     ));
     assert_eq!(result, expected);
 }
+
+const INCREMENTAL_SOURCE: &str = "fun name(param: type) -> unit {
+    let x = 1;
+    call(x);
+    let y = 2;
+}";
+
+#[test]
+fn test_incremental_parse_matches_pfun() {
+    let tree = incremental::parse(INCREMENTAL_SOURCE).unwrap();
+    let (fun, _) = pfun().parse(INCREMENTAL_SOURCE.into()).unwrap();
+    assert_eq!(tree.fun, fun);
+    assert_eq!(tree.items().len(), 3);
+}
+
+#[test]
+fn test_incremental_edit_inside_statement() {
+    let tree = incremental::parse(INCREMENTAL_SOURCE).unwrap();
+    let start = INCREMENTAL_SOURCE.find("call(x)").unwrap() + 5;
+    let edit = incremental::TextEdit::new(start..start + 1, "x, 100");
+    let source = edit.apply(INCREMENTAL_SOURCE).unwrap();
+
+    let result = incremental::reparse(&tree, &edit, &source).unwrap();
+    let expected = incremental::parse(&source).unwrap();
+    assert_eq!(result, expected);
+    assert_eq!(result.items()[0], tree.items()[0]);
+}
+
+#[test]
+fn test_incremental_edit_removes_statement() {
+    let tree = incremental::parse(INCREMENTAL_SOURCE).unwrap();
+    let start = INCREMENTAL_SOURCE.find("call(x);").unwrap();
+    let edit = incremental::TextEdit::new(start..start + "call(x);".len(), "");
+    let source = edit.apply(INCREMENTAL_SOURCE).unwrap();
+
+    let result = incremental::reparse(&tree, &edit, &source).unwrap();
+    assert_eq!(result, incremental::parse(&source).unwrap());
+    assert_eq!(result.items().len(), 2);
+}
+
+#[test]
+fn test_incremental_edit_in_header() {
+    let tree = incremental::parse(INCREMENTAL_SOURCE).unwrap();
+    let edit = incremental::TextEdit::new(4..8, "renamed");
+    let source = edit.apply(INCREMENTAL_SOURCE).unwrap();
+
+    let result = incremental::reparse(&tree, &edit, &source).unwrap();
+    assert_eq!(result.fun.value.name.value, "renamed");
+    assert_eq!(result, incremental::parse(&source).unwrap());
+}
+
+#[test]
+fn test_incremental_edit_breaks_body() {
+    let tree = incremental::parse(INCREMENTAL_SOURCE).unwrap();
+    let start = INCREMENTAL_SOURCE.find("let y").unwrap();
+    let edit = incremental::TextEdit::new(start..start, "let = ");
+    let source = edit.apply(INCREMENTAL_SOURCE).unwrap();
+
+    let result = incremental::reparse(&tree, &edit, &source);
+    assert_eq!(result, incremental::parse(&source));
+    assert!(result.is_err());
+}
+
+mod incremental_properties {
+    use super::*;
+    use proptest::prelude::*;
+
+    const KEYWORDS: [&str; 12] = [
+        "fun", "let", "mut", "if", "else", "for", "in", "loop", "step", "type", "enum", "true",
+    ];
+
+    fn name() -> impl Strategy<Value = String> {
+        "[a-z]{1,4}".prop_filter("keywords are not names", |name| {
+            !KEYWORDS.contains(&name.as_str())
+        })
+    }
+
+    fn statement() -> impl Strategy<Value = String> {
+        prop_oneof![
+            (name(), 0..1000i32).prop_map(|(name, value)| format!("let {name} = {value};")),
+            (name(), name()).prop_map(|(name, arg)| format!("{name}({arg}, 1);")),
+            name().prop_map(|name| format!("if true {{ {name}(1); }} else {{ {name}(2); }};")),
+            name().prop_map(|name| format!("for {name} = 0 .. 10 {{ call({name}); }};")),
+        ]
+    }
+
+    fn source() -> impl Strategy<Value = String> {
+        prop::collection::vec(statement(), 1..8).prop_map(|statements| {
+            format!(
+                "fun name(param: type) -> unit {{\n    {}\n}}",
+                statements.join("\n    ")
+            )
+        })
+    }
+
+    fn replacement() -> impl Strategy<Value = String> {
+        prop_oneof![statement(), "[a-z0-9 ;(){}=.,\n]{0,6}", Just(String::new()),]
+    }
+
+    proptest! {
+        #[test]
+        fn incremental_matches_from_scratch(
+            source in source(),
+            start in any::<prop::sample::Index>(),
+            length in 0..12usize,
+            replacement in replacement(),
+        ) {
+            let tree = incremental::parse(&source).unwrap();
+            let start = start.index(source.len() + 1);
+            let end = (start + length).min(source.len());
+            let edit = incremental::TextEdit::new(start..end, replacement);
+            let edited = edit.apply(&source).unwrap();
+
+            let result = incremental::reparse(&tree, &edit, &edited);
+            prop_assert_eq!(result, incremental::parse(&edited));
+        }
+
+        #[test]
+        fn apply_keeps_to_char_boundaries(
+            source in "[aé€😀 ]{0,8}",
+            start in 0..40usize,
+            end in 0..40usize,
+            replacement in "[bü]{0,3}",
+        ) {
+            let edit = incremental::TextEdit::new(start..end, replacement.clone());
+            let valid = start <= end
+                && end <= source.len()
+                && source.is_char_boundary(start)
+                && source.is_char_boundary(end);
+            match edit.apply(&source) {
+                Some(edited) => {
+                    prop_assert!(valid);
+                    let expected = format!("{}{}{}", &source[..start], replacement, &source[end..]);
+                    prop_assert_eq!(edited, expected);
+                }
+                None => prop_assert!(!valid),
+            }
+        }
+    }
+}
+
+const UNIT_SOURCE: &str = "fun first(a: int) -> int {
+    call(a);
+}
+fun second(c: int) -> unit {
+    let s = \"fun in a string \\{\";
+}
+
+fun third(b: int) -> unit {
+    if true { call(b); };
+}
+";
+
+#[test]
+fn test_split_items() {
+    let items = parallel::split_items(UNIT_SOURCE);
+    let starts: Vec<_> = items
+        .iter()
+        .map(|item| &UNIT_SOURCE[item.start..item.start + 10])
+        .collect();
+    assert_eq!(starts, vec!["fun first(", "fun second", "fun third("]);
+    assert_eq!(items.last().unwrap().end, UNIT_SOURCE.len());
+}
+
+#[test]
+fn test_split_items_leading_garbage() {
+    let items = parallel::split_items("garbage fun f() -> unit { call(1); }");
+    assert_eq!(items, vec![0..8, 8..36]);
+}
+
+#[test]
+fn test_parse_unit() {
+    let unit = parallel::parse_unit(UNIT_SOURCE);
+    let names: Vec<_> = unit
+        .items
+        .iter()
+        .map(|item| match &item.value {
+            Item::Fun(fun) => fun.name.value.as_str(),
+            _ => panic!("expected a function"),
+        })
+        .collect();
+    assert_eq!(names, vec!["first", "second", "third"]);
+    assert!(unit.errors.is_empty());
+
+    let second = UNIT_SOURCE.find("fun second").unwrap();
+    let (item, _) = pitem()
+        .parse(ContinuationState::at(UNIT_SOURCE, second))
+        .unwrap();
+    assert_eq!(unit.items[1], item);
+}
+
+#[test]
+fn test_parse_unit_errors_in_source_order() {
+    let source = "fun a(x: int) -> unit { call(1); }
+fun b(x: int) -> unit { call(; }
+fun c(x: int) -> unit { call(3); } trailing
+fun d(x: int) -> unit { call(4); }";
+    let unit = parallel::parse_unit(source);
+    assert_eq!(unit.items.len(), 2);
+    assert_eq!(unit.errors.len(), 2);
+    assert_eq!(unit.errors[0].line_number, 1);
+    assert_eq!(unit.errors[1].line_number, 2);
+    assert_eq!(unit.errors[1].actual, "t");
+}
+
+#[test]
+fn test_parse_unit_parallel_matches_sequential() {
+    let source = UNIT_SOURCE.repeat(50) + "fun broken(x: int -> unit { }";
+    let sequential = parallel::parse_unit(&source);
+    for threads in [1, 2, 8] {
+        assert_eq!(parallel::parse_unit_parallel(&source, threads), sequential);
+    }
+    assert_eq!(sequential.items.len(), 150);
+    assert_eq!(sequential.errors.len(), 1);
+}
+
+#[test]
+fn test_keyword_prefixed_identifiers() {
+    let parser = pbody();
+    let result = parser.parse("{ let iffy = truthy; for_each(falsey); letter; }".into());
+    let (body, _) = result.unwrap();
+    let values: Vec<_> = body.value.into_iter().map(|item| item.value).collect();
+    assert_eq!(
+        values,
+        vec![
+            ExprOrStatement::Statement(Statement::Let(
+                Token::new("iffy".to_string(), 6, 4),
+                None,
+                Token::new(Expr::Ident("truthy".to_string()), 13, 6),
+                false,
+            )),
+            ExprOrStatement::Expr(Expr::Call(
+                Box::new(Token::new(Expr::Ident("for_each".to_string()), 21, 8)),
+                vec![Token::new(Expr::Ident("falsey".to_string()), 30, 6)],
+            )),
+            ExprOrStatement::Expr(Expr::Ident("letter".to_string())),
+        ]
+    );
+}
+
+#[test]
+fn test_keyword_bool() {
+    let parser = pexpr();
+    let result = parser.parse("true".into()).unwrap();
+    assert_eq!(result.0.value, Expr::Value(Value::Bool(true)));
+}
+
+#[test]
+fn test_pint_overflow() {
+    let parser = pnumber();
+    let result = parser.parse("2147483648".into());
+    let expected = Err(Error::new(
+        Expected::Class("32-bit integer".into()),
+        "2147483648",
+        0,
+        0,
+        0,
+    ));
+    assert_eq!(result, expected);
+    assert_eq!(
+        parser.parse("-2147483648".into()).unwrap().0.value,
+        Value::Number(i32::MIN)
+    );
+}
+
+#[test]
+fn test_pquoted_unterminated() {
+    let parser = pquoted_string();
+    let result = parser.parse("\"abc".into());
+    let expected = Err(Error::new(Expected::Char('"'), "", 4, 0, 4));
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_pquoted_multibyte() {
+    let parser = pquoted_string();
+    let result = parser.parse("\"héllo\nwörld\" x".into()).unwrap();
+    assert_eq!(result.0.value, Value::String("héllo\nwörld".to_string()));
+    assert_eq!(result.1.remaining, " x");
+    assert_eq!(result.1.line_number, 1);
+}
+
+#[test]
+fn test_print_fun() {
+    let (fun, _) = pfun().parse(INCREMENTAL_SOURCE.into()).unwrap();
+    assert_eq!(fun.value.to_string(), INCREMENTAL_SOURCE);
+}
+
+//prints every binary and unary expression in parentheses to make the grouping visible
+fn grouped(expr: &Expr) -> String {
+    match expr {
+        Expr::Binary(lhs, op, rhs) => {
+            format!(
+                "({} {} {})",
+                grouped(&lhs.value),
+                op.value,
+                grouped(&rhs.value)
+            )
+        }
+        Expr::Unary(op, operand) => format!("({}{})", op.value, grouped(&operand.value)),
+        Expr::Range(start, end, inclusive, step) => {
+            let operator = if *inclusive { "..=" } else { ".." };
+            let mut range = format!(
+                "{} {} {}",
+                grouped(&start.value),
+                operator,
+                grouped(&end.value)
+            );
+            if let Some(step) = step {
+                range = format!("{} step {}", range, grouped(&step.value));
+            }
+            range
+        }
+        Expr::Call(callee, args) => {
+            let args: Vec<_> = args.iter().map(|arg| grouped(&arg.value)).collect();
+            format!("{}({})", grouped(&callee.value), args.join(", "))
+        }
+        Expr::Field(value, name) => format!("({}.{})", grouped(&value.value), name.value),
+        Expr::Index(value, index) => {
+            format!("({}[{}])", grouped(&value.value), grouped(&index.value))
+        }
+        expr => expr.to_string(),
+    }
+}
+
+fn parse_grouped(source: &str) -> String {
+    let (expr, cont) = pexpr().parse(source.into()).unwrap();
+    assert_eq!(cont.remaining, "");
+    grouped(&expr.value)
+}
+
+#[test]
+fn test_precedence() {
+    assert_eq!(parse_grouped("1 + 2 * 3"), "(1 + (2 * 3))");
+    assert_eq!(parse_grouped("1 * 2 + 3"), "((1 * 2) + 3)");
+    assert_eq!(parse_grouped("a || b && c"), "(a || (b && c))");
+    assert_eq!(parse_grouped("a == b && c < d"), "((a == b) && (c < d))");
+    assert_eq!(parse_grouped("1 << 2 + 3"), "(1 << (2 + 3))");
+    assert_eq!(parse_grouped("a & b == c"), "((a & b) == c)");
+    assert_eq!(parse_grouped("a | b ^ c & d"), "(a | (b ^ (c & d)))");
+    assert_eq!(parse_grouped("a % b * c / d"), "(((a % b) * c) / d)");
+}
+
+#[test]
+fn test_left_associativity() {
+    assert_eq!(parse_grouped("1 - 2 - 3"), "((1 - 2) - 3)");
+    assert_eq!(parse_grouped("8 / 4 / 2"), "((8 / 4) / 2)");
+    assert_eq!(parse_grouped("a < b == c"), "((a < b) == c)");
+    assert_eq!(parse_grouped("a >> 1 << 2"), "((a >> 1) << 2)");
+}
+
+#[test]
+fn test_parentheses() {
+    assert_eq!(parse_grouped("(1 + 2) * 3"), "((1 + 2) * 3)");
+    assert_eq!(parse_grouped("1 - (2 - 3)"), "(1 - (2 - 3))");
+    assert_eq!(parse_grouped("((a))"), "a");
+    assert_eq!(parse_grouped("call(x + 1, (y))"), "call((x + 1), y)");
+}
+
+#[test]
+fn test_nesting_limit() {
+    let nested = |depth: usize| format!("{}x{}", "(".repeat(depth), ")".repeat(depth));
+    assert_eq!(parse_grouped(&nested(31)), "x");
+    let source = nested(32);
+    assert!(pexpr().parse(source.as_str().into()).is_err());
+    let source = "-".repeat(40) + "x";
+    assert!(pexpr().parse(source.as_str().into()).is_err());
+    //only expressions within one another count, not those side by side
+    let source = vec![nested(31); 40].join(" + ");
+    assert!(pexpr().parse(source.as_str().into()).is_ok());
+}
+
+#[test]
+fn test_unary() {
+    assert_eq!(parse_grouped("-x * y"), "((-x) * y)");
+    assert_eq!(parse_grouped("-(y)"), "(-y)");
+    assert_eq!(parse_grouped("!done && x"), "((!done) && x)");
+    assert_eq!(parse_grouped("!!a"), "(!(!a))");
+    assert_eq!(parse_grouped("- -x"), "(-(-x))");
+    assert_eq!(parse_grouped("-(1 + 2) * 3"), "((-(1 + 2)) * 3)");
+}
+
+#[test]
+fn test_negative_literals() {
+    assert_eq!(parse_grouped("a - -1"), "(a - -1)");
+    assert_eq!(parse_grouped("a-1"), "(a - 1)");
+    assert_eq!(parse_grouped("-1 * 2"), "(-1 * 2)");
+    assert_eq!(parse_grouped("- 1"), "(-1)");
+    let (expr, _) = pexpr().parse("- 1".into()).unwrap();
+    assert!(matches!(expr.value, Expr::Unary(_, _)));
+}
+
+#[test]
+fn test_longest_operator() {
+    assert_eq!(parse_grouped("a<=b"), "(a <= b)");
+    assert_eq!(parse_grouped("a<<b"), "(a << b)");
+    assert_eq!(parse_grouped("a&&b&c"), "(a && (b & c))");
+    assert_eq!(parse_grouped("a||b|c"), "(a || (b | c))");
+    assert_eq!(parse_grouped("a!=!b"), "(a != (!b))");
+}
+
+#[test]
+fn test_binary_span() {
+    let (expr, _) = pexpr().parse("1 + 2 * 3;".into()).unwrap();
+    assert_eq!((expr.start, expr.length), (0, 9));
+    let Expr::Binary(lhs, op, rhs) = expr.value else {
+        panic!("expected a binary expression");
+    };
+    assert_eq!((lhs.start, lhs.length), (0, 1));
+    assert_eq!(op, Token::new(BinaryOp::Add, 2, 1));
+    assert_eq!((rhs.start, rhs.length), (4, 5));
+}
+
+#[test]
+fn test_binary_missing_operand() {
+    let result = pexpr().parse("1 + ;".into());
+    let error = result.unwrap_err();
+    assert_eq!(error.position, 4);
+}
+
+#[test]
+fn test_print_operators() {
+    let source = "fun f(a: int) -> bool {
+    let b = -(a + 1) * 2 - -(3);
+    a - (b - 1) < 10 && !(a == b);
+}";
+    let (fun, _) = pfun().parse(source.into()).unwrap();
+    assert_eq!(fun.value.to_string(), source);
+}
+
+#[test]
+fn test_range() {
+    let (expr, cont) = pexpr().parse("0 .. 10;".into()).unwrap();
+    assert_eq!(cont.remaining, ";");
+    assert_eq!((expr.start, expr.length), (0, 7));
+    let expected = Expr::Range(
+        Box::new(Token::new(Expr::Value(Value::Number(0)), 0, 1)),
+        Box::new(Token::new(Expr::Value(Value::Number(10)), 5, 2)),
+        false,
+        None,
+    );
+    assert_eq!(expr.value, expected);
+}
+
+#[test]
+fn test_range_inclusive_with_step() {
+    let (expr, _) = pexpr().parse("1..=n step 2".into()).unwrap();
+    assert_eq!((expr.start, expr.length), (0, 12));
+    let Expr::Range(start, end, inclusive, step) = expr.value else {
+        panic!("expected a range");
+    };
+    assert_eq!(start.value, Expr::Value(Value::Number(1)));
+    assert_eq!(end.value, Expr::Ident("n".to_string()));
+    assert!(inclusive);
+    assert_eq!(step.unwrap().value, Expr::Value(Value::Number(2)));
+}
+
+#[test]
+fn test_range_binds_loosest() {
+    assert_eq!(parse_grouped("a + 1 .. b * 2"), "(a + 1) .. (b * 2)");
+    assert_eq!(
+        parse_grouped("0 ..= len(xs) - 1 step -1"),
+        "0 ..= (len(xs) - 1) step -1"
+    );
+    assert_eq!(parse_grouped("(0 .. 2) == r"), "(0 .. 2 == r)");
+}
+
+#[test]
+fn test_range_does_not_chain() {
+    let (_, cont) = pexpr().parse("0 .. 1 .. 2".into()).unwrap();
+    assert_eq!(cont.remaining, ".. 2");
+}
+
+#[test]
+fn test_range_as_value() {
+    let source = "fun f(n: int) -> unit {
+    let r = 0 .. n + 1;
+    call(0 ..= 9 step 3, r);
+    for i = r {
+        call(i);
+    };
+    for i = 0 .. (n - 1) * 2 step n {
+        call(i);
+    };
+}";
+    let (fun, cont) = pfun().parse(source.into()).unwrap();
+    assert_eq!(cont.remaining, "");
+    assert_eq!(fun.value.to_string(), source);
+    let ExprOrStatement::Statement(Statement::For(_, _, range, _)) = &fun.value.body[3].value
+    else {
+        panic!("expected a for loop");
+    };
+    assert!(matches!(range.value, Expr::Range(_, _, false, Some(_))));
+}
+
+const LOOPS_SOURCE: &str = "fun f(n: int) -> int {
+    let found = 'search: loop {
+        'rows: for i = 0 .. n {
+            while i < n {
+                if i == 3 {
+                    continue 'rows;
+                };
+                break;
+            };
+            if i == n {
+                break 'search i;
+            };
+        };
+        break -1;
+    };
+    return found;
+}";
+
+#[test]
+fn test_loops() {
+    let (fun, cont) = pfun().parse(LOOPS_SOURCE.into()).unwrap();
+    assert_eq!(cont.remaining, "");
+    assert_eq!(fun.value.to_string(), LOOPS_SOURCE);
+    assert_eq!(validate_fun(&fun.value), vec![]);
+
+    let ExprOrStatement::Statement(Statement::Let(_, _, value, _)) = &fun.value.body[0].value
+    else {
+        panic!("expected a let");
+    };
+    let Expr::Loop(Some(label), body) = &value.value else {
+        panic!("expected a labelled loop");
+    };
+    assert_eq!(*label, Token::new("search".to_string(), 40, 6));
+    assert!(matches!(
+        &body[0].value,
+        ExprOrStatement::Statement(Statement::For(Some(label), _, _, _)) if label.value == "rows"
+    ));
+    assert_eq!(
+        fun.value.body[1].value,
+        ExprOrStatement::Statement(Statement::Return(Some(Token::new(
+            Expr::Ident("found".to_string()),
+            353,
+            5
+        ))))
+    );
+}
+
+#[test]
+fn test_break_and_continue() {
+    let parse = |source| pstatement().parse(source).unwrap().0.value;
+    assert_eq!(parse("break;".into()), Statement::Break(None, None));
+    assert_eq!(
+        parse("break 'outer;".into()),
+        Statement::Break(Some(Token::new("outer".to_string(), 7, 5)), None)
+    );
+    assert_eq!(
+        parse("break x + 1;".into()),
+        Statement::Break(
+            None,
+            Some(Token::new(
+                Expr::Binary(
+                    Box::new(Token::new(Expr::Ident("x".to_string()), 6, 1)),
+                    Token::new(BinaryOp::Add, 8, 1),
+                    Box::new(Token::new(Expr::Value(Value::Number(1)), 10, 1)),
+                ),
+                6,
+                5
+            ))
+        )
+    );
+    assert_eq!(parse("continue;".into()), Statement::Continue(None));
+    assert_eq!(parse("return;".into()), Statement::Return(None));
+}
+
+#[test]
+fn test_keyword_prefixed_loop_identifiers() {
+    let (item, _) = pbody_item().parse("breaking;".into()).unwrap();
+    assert_eq!(
+        item.value,
+        ExprOrStatement::Expr(Expr::Ident("breaking".to_string()))
+    );
+    let (item, _) = pbody_item().parse("loops(1);".into()).unwrap();
+    assert!(matches!(
+        item.value,
+        ExprOrStatement::Expr(Expr::Call(_, _))
+    ));
+}
+
+#[test]
+fn test_break_value_error_is_not_swallowed() {
+    let error = pbreak().parse("break 1 + ;".into()).unwrap_err();
+    assert_eq!(error.position, 10);
+}
+
+fn validate_source(source: &str) -> Vec<ValidationError> {
+    let (fun, _) = pfun().parse(source.into()).unwrap();
+    validate_fun(&fun.value)
+}
+
+#[test]
+fn test_validate_break_outside_loop() {
+    let source = "fun f() -> unit {
+    if done {
+        break;
+    };
+    continue;
+}";
+    assert_eq!(
+        validate_source(source),
+        vec![
+            ValidationError::BreakOutsideLoop(40..45),
+            ValidationError::ContinueOutsideLoop(58..66),
+        ]
+    );
+}
+
+#[test]
+fn test_validate_labels() {
+    let source = "fun f() -> unit {
+    'outer: while true {
+        loop {
+            break 'outer;
+        };
+        continue 'inner;
+    };
+}";
+    assert_eq!(
+        validate_source(source),
+        vec![ValidationError::UndeclaredLabel(
+            "inner".to_string(),
+            113..118
+        )]
+    );
+}
+
+#[test]
+fn test_validate_break_value() {
+    let source = "fun f() -> unit {
+    'outer: loop {
+        for i = 0 .. 10 {
+            break i;
+            break 'outer i;
+        };
+    };
+}";
+    assert_eq!(
+        validate_source(source),
+        vec![ValidationError::BreakValueOutsideLoop(75..82)]
+    );
+}
+
+#[test]
+fn test_validate_program() {
+    let source = "const X: int = loop { break 1; };
+fun f() -> unit {
+    break;
+}";
+    let (program, _) = pprogram().parse(source.into()).unwrap();
+    assert_eq!(
+        validate_program(&program.value),
+        vec![ValidationError::BreakOutsideLoop(56..61)]
+    );
+}
+
+#[test]
+fn test_let_with_type() {
+    let (statement, _) = plet().parse("let xs: [int] = [1];".into()).unwrap();
+    assert_eq!(
+        statement.value,
+        Statement::Let(
+            Token::new("xs".to_string(), 4, 2),
+            Some(Token::new(
+                TypeExpr::Array(
+                    Box::new(Token::new(TypeExpr::Named("int".to_string()), 9, 3)),
+                    None
+                ),
+                8,
+                6
+            )),
+            Token::new(
+                Expr::Array(vec![Token::new(Expr::Value(Value::Number(1)), 17, 1)]),
+                16,
+                3
+            ),
+            false,
+        )
+    );
+    let (statement, _) = plet()
+        .parse("let mut f: fun(int) -> int = |x| x;".into())
+        .unwrap();
+    assert_eq!(
+        statement.value.to_string(),
+        "let mut f: fun(int) -> int = |x| x"
+    );
+}
+
+#[test]
+fn test_let_mut() {
+    let (statement, _) = plet().parse("let mut x = 1;".into()).unwrap();
+    assert_eq!(
+        statement.value,
+        Statement::Let(
+            Token::new("x".to_string(), 8, 1),
+            None,
+            Token::new(Expr::Value(Value::Number(1)), 12, 1),
+            true,
+        )
+    );
+    let (statement, _) = plet().parse("let mutable = 1;".into()).unwrap();
+    assert!(
+        matches!(statement.value, Statement::Let(name, _, _, false) if name.value == "mutable")
+    );
+}
+
+#[test]
+fn test_assign() {
+    let (statement, cont) = pstatement().parse("x = x + 1;".into()).unwrap();
+    assert_eq!(cont.remaining, ";");
+    assert_eq!((statement.start, statement.length), (0, 9));
+    let Statement::Assign(place, None, value) = statement.value else {
+        panic!("expected an assignment");
+    };
+    assert_eq!(place, Token::new(Expr::Ident("x".to_string()), 0, 1));
+    assert!(matches!(value.value, Expr::Binary(_, _, _)));
+}
+
+#[test]
+fn test_compound_assign() {
+    let parse = |source| match pstatement().parse(source).unwrap().0.value {
+        Statement::Assign(_, operator, _) => operator.map(|operator| operator.value),
+        statement => panic!("expected an assignment, got {:?}", statement),
+    };
+    assert_eq!(parse("x += 1".into()), Some(BinaryOp::Add));
+    assert_eq!(parse("x -= 1".into()), Some(BinaryOp::Sub));
+    assert_eq!(parse("x*=2".into()), Some(BinaryOp::Mul));
+    assert_eq!(parse("x <<= 2".into()), Some(BinaryOp::Shl));
+    assert_eq!(parse("x >>= 2".into()), Some(BinaryOp::Shr));
+    assert_eq!(parse("x ^= y".into()), Some(BinaryOp::BitXor));
+    assert_eq!(parse("x = -1".into()), None);
+}
+
+#[test]
+fn test_comparison_is_not_assignment() {
+    let (item, _) = pbody_item().parse("x == 1;".into()).unwrap();
+    assert!(matches!(
+        item.value,
+        ExprOrStatement::Expr(Expr::Binary(_, op, _)) if op.value == BinaryOp::Eq
+    ));
+    let (item, _) = pbody_item().parse("x <= 1;".into()).unwrap();
+    assert!(matches!(item.value, ExprOrStatement::Expr(_)));
+    assert!(pexpr().parse("x + = 1".into()).is_err());
+}
+
+#[test]
+fn test_print_assignments() {
+    let source = "fun f(n: int) -> int {
+    let mut total = 0;
+    for i = 0 .. n {
+        total += i * 2;
+    };
+    total = total % 7;
+    total;
+}";
+    let (fun, _) = pfun().parse(source.into()).unwrap();
+    assert_eq!(fun.value.to_string(), source);
+    assert_eq!(validate_fun(&fun.value), vec![]);
+}
+
+#[test]
+fn test_validate_assign_to_immutable() {
+    let source = "fun f() -> unit {
+    let x = 1;
+    x += 2;
+}";
+    let errors = validate_source(source);
+    assert_eq!(
+        errors,
+        vec![ValidationError::AssignToImmutable(
+            "x".to_string(),
+            37..43,
+            22..31
+        )]
+    );
+    assert_eq!(&source[22..31], "let x = 1");
+    assert_eq!(&source[37..43], "x += 2");
+}
+
+#[test]
+fn test_validate_shadowing_and_scopes() {
+    let source = "fun f(n: int) -> unit {
+    let x = 1;
+    let mut x = x;
+    x = 2;
+    if true {
+        let mut n = 0;
+        n = 1;
+    };
+    n = 3;
+    for i = 0 .. n {
+        i = 4;
+    };
+}";
+    let errors = validate_source(source);
+    let param = source.find("n: int").unwrap();
+    let assign_n = source.find("n = 3").unwrap();
+    let loop_variable = source.find("i = 0").unwrap();
+    let assign_i = source.find("i = 4").unwrap();
+    assert_eq!(
+        errors,
+        vec![
+            ValidationError::AssignToImmutable(
+                "n".to_string(),
+                assign_n..assign_n + 5,
+                param..param + 6
+            ),
+            ValidationError::AssignToImmutable(
+                "i".to_string(),
+                assign_i..assign_i + 5,
+                loop_variable..loop_variable + 1
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_validate_assign_to_const() {
+    let source = "fun f() -> unit {
+    LIMIT = 1;
+    unknown = 2;
+}
+const LIMIT: int = 10;";
+    let (program, _) = pprogram().parse(source.into()).unwrap();
+    let errors = validate_program(&program.value);
+    let limit = source.find("const").unwrap();
+    assert_eq!(
+        errors,
+        vec![ValidationError::AssignToImmutable(
+            "LIMIT".to_string(),
+            22..31,
+            limit..source.len() - 1
+        )]
+    );
+}
+
+fn parse_value(source: &str) -> Value {
+    let (expr, cont) = pexpr().parse(source.into()).unwrap();
+    assert_eq!(cont.remaining, "", "{}", source);
+    match expr.value {
+        Expr::Value(value) => value,
+        expr => panic!("expected a literal, got {:?}", expr),
+    }
+}
+
+#[test]
+fn test_integer_literals() {
+    assert_eq!(parse_value("1_000_000"), Value::Number(1_000_000));
+    assert_eq!(parse_value("0xff"), Value::Number(0xff));
+    assert_eq!(parse_value("0xDEAD_beef_u64"), Value::U64(0xdead_beef));
+    assert_eq!(parse_value("0b1010"), Value::Number(0b1010));
+    assert_eq!(parse_value("0o17"), Value::Number(0o17));
+    assert_eq!(parse_value("-0x10"), Value::Number(-16));
+    assert_eq!(parse_value("5i64"), Value::I64(5));
+    assert_eq!(parse_value("-9223372036854775808i64"), Value::I64(i64::MIN));
+    assert_eq!(parse_value("18446744073709551615u64"), Value::U64(u64::MAX));
+}
+
+#[test]
+fn test_integer_literal_errors() {
+    let error = pnumber().parse("-1u64".into()).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Expected unsigned 64-bit integer but got -1u64 at line: 1, column: 1"
+    );
+    let error = pnumber().parse("0x1_0000_0000".into()).unwrap_err();
+    assert_eq!(error.expected, Expected::Class("32-bit integer".into()));
+    let error = pnumber().parse("1.5i64".into()).unwrap_err();
+    assert_eq!(error.expected, Expected::Class("integer".into()));
+}
+
+#[test]
+fn test_float_literals() {
+    assert_eq!(parse_value("1.5"), Value::Float(1.5));
+    assert_eq!(parse_value("-0.25"), Value::Float(-0.25));
+    assert_eq!(parse_value("1e3"), Value::Float(1000.0));
+    assert_eq!(parse_value("2.5E-3"), Value::Float(0.0025));
+    assert_eq!(parse_value("1_000.000_1"), Value::Float(1000.0001));
+}
+
+#[test]
+fn test_float_does_not_swallow_range() {
+    assert_eq!(parse_grouped("0..10"), "0 .. 10");
+    assert_eq!(parse_grouped("0.5..1.5"), "0.5 .. 1.5");
+}
+
+#[test]
+fn test_char_literals() {
+    assert_eq!(parse_value("'a'"), Value::Char('a'));
+    assert_eq!(parse_value("'é'"), Value::Char('é'));
+    assert_eq!(parse_value("'\\n'"), Value::Char('\n'));
+    assert_eq!(parse_value("'\\''"), Value::Char('\''));
+    assert_eq!(parse_value("'\\u{1F600}'"), Value::Char('😀'));
+    assert!(pchar_literal().parse("''".into()).is_err());
+    assert!(pchar_literal().parse("'ab'".into()).is_err());
+}
+
+#[test]
+fn test_char_literal_is_not_a_label() {
+    let (statement, _) = pstatement().parse("break 'a';".into()).unwrap();
+    assert_eq!(
+        statement.value,
+        Statement::Break(None, Some(Token::new(Expr::Value(Value::Char('a')), 6, 3)))
+    );
+    let (statement, _) = pstatement().parse("break 'a;".into()).unwrap();
+    assert!(matches!(statement.value, Statement::Break(Some(_), None)));
+}
+
+#[test]
+fn test_string_escapes() {
+    assert_eq!(
+        parse_value(r#""say \"hi\"\n\t\\ \{x\} \u{e9}""#),
+        Value::String("say \"hi\"\n\t\\ {x} é".to_string())
+    );
+    let error = pquoted_string().parse(r#""bad \q""#.into()).unwrap_err();
+    assert_eq!((error.position, error.actual), (5, "\\q"));
+    let error = pquoted_string().parse(r#""a {b}""#.into()).unwrap_err();
+    assert_eq!((error.position, error.actual), (3, "{"));
+}
+
+#[test]
+fn test_raw_strings() {
+    assert_eq!(
+        parse_value(r#"r"C:\path\{x}""#),
+        Value::String(r"C:\path\{x}".to_string())
+    );
+    assert_eq!(
+        parse_value(r###"r#"say "hi""#"###),
+        Value::String(r#"say "hi""#.to_string())
+    );
+    assert!(praw_string().parse(r##"r#"unterminated""##.into()).is_err());
+    assert_eq!(parse_grouped("r"), "r");
+}
+
+#[test]
+fn test_string_interpolation() {
+    let (expr, _) = pexpr().parse(r#""x is {x + 1}!""#.into()).unwrap();
+    assert_eq!(
+        expr.value.to_string(),
+        r#""x is " + to_string(x + 1) + "!""#
+    );
+    assert_eq!((expr.start, expr.length), (1, 13));
+
+    let (expr, _) = pexpr().parse(r#""{a}{b}""#.into()).unwrap();
+    assert_eq!(expr.value.to_string(), "to_string(a) + to_string(b)");
+    let (expr, _) = pexpr().parse(r#""{"nested {x}"}""#.into()).unwrap();
+    assert_eq!(
+        expr.value.to_string(),
+        r#"to_string("nested " + to_string(x))"#
+    );
+    assert_eq!(parse_value(r#""""#), Value::String(String::new()));
+}
+
+#[test]
+fn test_print_literals() {
+    let source = r#"fun f() -> unit {
+    let values = call(1.5, -2.0, 1e-7, 7i64, 8u64, 'x', '\'', "a\"b\{\}\n");
+    let negated = -(1.5) + -(7i64);
+}"#;
+    let (fun, _) = pfun().parse(source.into()).unwrap();
+    assert_eq!(fun.value.to_string(), source);
+}
+
+#[test]
+fn test_split_items_skips_literals() {
+    let source = r#"fun a() -> unit {
+    call("}", '{', "\"fun", r"}", "{ "}" }");
+}
+fun b() -> unit { call('}'); }"#;
+    let items = parallel::split_items(source);
+    assert_eq!(items.len(), 2);
+    assert_eq!(&source[items[1].clone()], "fun b() -> unit { call('}'); }");
+}
+
+#[test]
+fn test_struct_and_enum_declarations() {
+    let source = "struct Point { x: int, y: int }
+enum Shape { Circle(Point, int), Rect(Point, Point), Empty }
+struct Unit {}";
+    let (program, cont) = pprogram().parse(source.into()).unwrap();
+    assert_eq!(cont.remaining, "");
+    let items: Vec<_> = program
+        .value
+        .items
+        .into_iter()
+        .map(|item| item.value)
+        .collect();
+    assert_eq!(
+        items[0],
+        Item::Struct(
+            Token::new("Point".to_string(), 7, 5),
+            vec![
+                Token::new(
+                    Field(
+                        Token::new("x".to_string(), 15, 1),
+                        Token::new(TypeExpr::Named("int".to_string()), 18, 3)
+                    ),
+                    15,
+                    6
+                ),
+                Token::new(
+                    Field(
+                        Token::new("y".to_string(), 23, 1),
+                        Token::new(TypeExpr::Named("int".to_string()), 26, 3)
+                    ),
+                    23,
+                    6
+                ),
+            ]
+        )
+    );
+    match &items[1] {
+        Item::Enum(name, variants) => {
+            assert_eq!(name.value, "Shape");
+            let variants: Vec<_> = variants
+                .iter()
+                .map(|variant| (variant.value.0.value.as_str(), variant.value.1.len()))
+                .collect();
+            assert_eq!(variants, vec![("Circle", 2), ("Rect", 2), ("Empty", 0)]);
+        }
+        item => panic!("expected an enum, got {:?}", item),
+    }
+    assert_eq!(
+        items[2],
+        Item::Struct(Token::new("Unit".to_string(), 100, 4), vec![])
+    );
+}
+
+#[test]
+fn test_struct_literal_and_field_access() {
+    assert_eq!(parse_grouped("p.x.y * 2"), "(((p.x).y) * 2)");
+    assert_eq!(parse_grouped("-p.x + 1"), "((-(p.x)) + 1)");
+    assert_eq!(parse_grouped("a.b .. c.d"), "(a.b) .. (c.d)");
+    let (expr, _) = pexpr()
+        .parse("Point { x: 1, y: origin.y + 2 }.x".into())
+        .unwrap();
+    match expr.value {
+        Expr::Field(value, name) => {
+            assert_eq!(name, Token::new("x".to_string(), 32, 1));
+            match value.value {
+                Expr::Struct(name, fields) => {
+                    assert_eq!(name.value, "Point");
+                    assert_eq!(fields.len(), 2);
+                    assert_eq!(fields[1].0, Token::new("y".to_string(), 14, 1));
+                    assert_eq!(fields[1].1.value.to_string(), "origin.y + 2");
+                }
+                expr => panic!("expected a struct literal, got {:?}", expr),
+            }
+        }
+        expr => panic!("expected a field access, got {:?}", expr),
+    }
+}
+
+#[test]
+fn test_condition_is_not_a_struct_literal() {
+    let (expr, cont) = pexpr().parse("if ready { go(1); }".into()).unwrap();
+    assert_eq!(cont.remaining, "");
+    assert!(matches!(expr.value, Expr::If(condition, _, _)
+        if condition.value == Expr::Ident("ready".to_string())));
+}
+
+#[test]
+fn test_variant_expressions() {
+    let (expr, _) = pexpr().parse("Shape::Circle(origin, 2)".into()).unwrap();
+    assert_eq!(
+        expr.value,
+        Expr::Variant(
+            Token::new("Shape".to_string(), 0, 5),
+            Token::new("Circle".to_string(), 7, 6),
+            vec![
+                Token::new(Expr::Ident("origin".to_string()), 14, 6),
+                Token::new(Expr::Value(Value::Number(2)), 22, 1),
+            ]
+        )
+    );
+    let (expr, _) = pexpr().parse("Shape::Empty".into()).unwrap();
+    assert!(matches!(expr.value, Expr::Variant(_, _, payload) if payload.is_empty()));
+}
+
+#[test]
+fn test_field_assignment() {
+    let (statement, _) = pstatement().parse("p.x += 1".into()).unwrap();
+    match statement.value {
+        Statement::Assign(place, operator, _) => {
+            assert_eq!(place.value.to_string(), "p.x");
+            assert_eq!(operator.map(|op| op.value), Some(BinaryOp::Add));
+        }
+        statement => panic!("expected an assignment, got {:?}", statement),
+    }
+}
+
+fn parse_pattern(source: &str) -> Pattern {
+    let (pattern, cont) = ppattern().parse(source.into()).unwrap();
+    assert_eq!(cont.remaining, "", "{}", source);
+    pattern.value
+}
+
+#[test]
+fn test_patterns() {
+    assert_eq!(parse_pattern("_"), Pattern::Wildcard);
+    assert_eq!(parse_pattern("x"), Pattern::Binding("x".to_string()));
+    assert_eq!(parse_pattern("-1"), Pattern::Value(Value::Number(-1)));
+    assert_eq!(parse_pattern("true"), Pattern::Value(Value::Bool(true)));
+    assert_eq!(
+        parse_pattern("\"hi\""),
+        Pattern::Value(Value::String("hi".to_string()))
+    );
+    assert_eq!(parse_pattern("(x)"), Pattern::Binding("x".to_string()));
+    assert_eq!(
+        parse_pattern("(x, _)"),
+        Pattern::Tuple(vec![
+            Token::new(Pattern::Binding("x".to_string()), 1, 1),
+            Token::new(Pattern::Wildcard, 4, 1),
+        ])
+    );
+    assert_eq!(
+        parse_pattern("Shape::Rect(a, Point { x: 0, y })"),
+        Pattern::Variant(
+            Token::new("Shape".to_string(), 0, 5),
+            Token::new("Rect".to_string(), 7, 4),
+            vec![
+                Token::new(Pattern::Binding("a".to_string()), 12, 1),
+                Token::new(
+                    Pattern::Struct(
+                        Token::new("Point".to_string(), 15, 5),
+                        vec![
+                            (
+                                Token::new("x".to_string(), 23, 1),
+                                Token::new(Pattern::Value(Value::Number(0)), 26, 1)
+                            ),
+                            (
+                                Token::new("y".to_string(), 29, 1),
+                                Token::new(Pattern::Binding("y".to_string()), 29, 1)
+                            ),
+                        ],
+                        false
+                    ),
+                    15,
+                    15
+                ),
+            ]
+        )
+    );
+    assert!(matches!(
+        parse_pattern("Point { x, .. }"),
+        Pattern::Struct(_, fields, true) if fields.len() == 1
+    ));
+    assert!(matches!(
+        parse_pattern("Point { .. }"),
+        Pattern::Struct(_, fields, true) if fields.is_empty()
+    ));
+}
+
+#[test]
+fn test_rest_pattern_must_come_last() {
+    assert!(pmatch_arm().parse("Point { x, .. } => 1".into()).is_ok());
+    assert!(pmatch_arm().parse("Point { .., x } => 1".into()).is_err());
+}
+
+#[test]
+fn test_match() {
+    let source = "match shape {
+    Shape::Circle(_, r) if r > 10 => big(r),
+    Shape::Circle(_, r) => {
+        let d = r * 2;
+        d;
+    },
+    _ => 0
+}";
+    let (expr, cont) = pexpr().parse(source.into()).unwrap();
+    assert_eq!(cont.remaining, "");
+    let Expr::Match(scrutinee, arms) = expr.value else {
+        panic!("expected a match");
+    };
+    assert_eq!(scrutinee.value, Expr::Ident("shape".to_string()));
+    assert_eq!(arms.len(), 3);
+    assert_eq!(
+        arms[0].value.guard.as_ref().unwrap().value.to_string(),
+        "r > 10"
+    );
+    assert_eq!(arms[0].value.body.len(), 1);
+    assert_eq!(arms[1].value.guard, None);
+    assert_eq!(arms[1].value.body.len(), 2);
+    assert_eq!(arms[2].value.pattern.value, Pattern::Wildcard);
+}
+
+#[test]
+fn test_print_match() {
+    let source = "fun area(shape: Shape) -> int {
+    match shape {
+        Shape::Rect(Point { x: 0, .. }, Point { x, y }) if x > 0 => x * y,
+        Shape::Circle(_, r) => {
+            let d = r * 2;
+            d * d;
+        },
+        Shape::Empty => 0
+    };
+}";
+    let (fun, cont) = pfun().parse(source.into()).unwrap();
+    assert_eq!(cont.remaining, "");
+    assert_eq!(fun.value.to_string(), source);
+}
+
+fn validate_program_source(source: &str) -> Vec<ValidationError> {
+    let (program, cont) = pprogram().parse(source.into()).unwrap();
+    assert_eq!(cont.remaining, "");
+    validate_program(&program.value)
+}
+
+fn missing_patterns_in(source: &str) -> Vec<Vec<String>> {
+    validate_program_source(source)
+        .into_iter()
+        .map(|error| match error {
+            ValidationError::NonExhaustiveMatch(missing, _) => missing,
+            error => panic!("expected a non-exhaustive match, got {:?}", error),
+        })
+        .collect()
+}
+
+const SHAPES: &str = "struct Point { x: int, y: int }
+enum Shape { Circle(Point, int), Rect(Point, Point), Empty }
+";
+
+#[test]
+fn test_exhaustive_matches() {
+    let source = format!(
+        "{}fun f(shape: Shape, flag: bool, n: int) -> int {{
+    match shape {{ Shape::Circle(_, r) => r, Shape::Rect(a, b) => 1, Shape::Empty => 0 }};
+    match shape {{ Shape::Empty => 0, s => 1 }};
+    match flag {{ true => 1, false => 0 }};
+    match pair {{ (true, _) => 1, (false, 0) => 2, (_, _) => 3 }};
+    match Point {{ x: 1, y: 2 }} {{ Point {{ x: 0, .. }} => 0, Point {{ x, y }} => x }};
+}}",
+        SHAPES
+    );
+    assert_eq!(validate_program_source(&source), vec![]);
+}
+
+#[test]
+fn test_non_exhaustive_matches() {
+    let source = format!(
+        "{}fun f(shape: Shape, flag: bool, n: int) -> int {{
+    match shape {{ Shape::Circle(_, r) => r }};
+    match flag {{ true => 1 }};
+    match n {{ 0 => 1, 1 => 2 }};
+    match pair {{ (true, _) => 1, (_, true) => 2 }};
+    match shape {{ Shape::Rect(Point {{ x: 0, .. }}, _) => 0, Shape::Circle(_, _) => 1, Shape::Empty => 2 }};
+    match shape {{ Shape::Empty if n > 0 => 0, Shape::Circle(_, _) => 1, Shape::Rect(_, _) => 2 }};
+}}",
+        SHAPES
+    );
+    assert_eq!(
+        missing_patterns_in(&source),
+        vec![
+            vec!["Shape::Rect(_, _)", "Shape::Empty"],
+            vec!["false"],
+            vec!["_"],
+            vec!["(false, false)"],
+            vec!["Shape::Rect(Point { .. }, _)"],
+            vec!["Shape::Empty"],
+        ]
+    );
+}
+
+#[test]
+fn test_non_exhaustive_match_span() {
+    let source = format!(
+        "{}fun f(flag: bool) -> int {{
+    match flag {{ true => 1 }};
+}}",
+        SHAPES
+    );
+    let flag = source.rfind("flag").unwrap();
+    assert_eq!(
+        validate_program_source(&source),
+        vec![ValidationError::NonExhaustiveMatch(
+            vec!["false".to_string()],
+            flag..flag + 4
+        )]
+    );
+}
+
+#[test]
+fn test_validate_pattern_bindings_are_immutable() {
+    let source = "fun f(shape: Shape) -> int {
+    let mut total = 0;
+    match shape {
+        Shape::Circle(_, r) => {
+            r = 1;
+            total += r;
+        },
+        _ => 0
+    };
+    r = 2;
+}";
+    let r = source.find("r)").unwrap();
+    let assignment = source.find("r = 1").unwrap();
+    assert_eq!(
+        validate_source(source),
+        vec![ValidationError::AssignToImmutable(
+            "r".to_string(),
+            assignment..assignment + 5,
+            r..r + 1
+        )]
+    );
+}
+
+#[test]
+fn test_arrays() {
+    let (expr, cont) = pexpr().parse("[1, x, 3]".into()).unwrap();
+    assert_eq!(cont.remaining, "");
+    assert_eq!(
+        expr,
+        Token::new(
+            Expr::Array(vec![
+                Token::new(Expr::Value(Value::Number(1)), 1, 1),
+                Token::new(Expr::Ident("x".to_string()), 4, 1),
+                Token::new(Expr::Value(Value::Number(3)), 7, 1),
+            ]),
+            0,
+            9
+        )
+    );
+    let (expr, _) = pexpr().parse("[]".into()).unwrap();
+    assert_eq!(expr.value, Expr::Array(vec![]));
+    let (expr, _) = pexpr().parse("[0; n + 1]".into()).unwrap();
+    assert_eq!(
+        expr.value,
+        Expr::Repeat(
+            Box::new(Token::new(Expr::Value(Value::Number(0)), 1, 1)),
+            Box::new(Token::new(
+                Expr::Binary(
+                    Box::new(Token::new(Expr::Ident("n".to_string()), 4, 1)),
+                    Token::new(BinaryOp::Add, 6, 1),
+                    Box::new(Token::new(Expr::Value(Value::Number(1)), 8, 1)),
+                ),
+                4,
+                5
+            )),
+        )
+    );
+}
+
+#[test]
+fn test_tuples() {
+    let (expr, _) = pexpr().parse("()".into()).unwrap();
+    assert_eq!(expr.value, Expr::Tuple(vec![]));
+    let (expr, _) = pexpr().parse("(1)".into()).unwrap();
+    assert_eq!(expr, Token::new(Expr::Value(Value::Number(1)), 0, 3));
+    let (expr, _) = pexpr().parse("(1, (x, y))".into()).unwrap();
+    assert_eq!(expr.value.to_string(), "(1, (x, y))");
+    assert!(matches!(expr.value, Expr::Tuple(values) if values.len() == 2));
+    assert_eq!(parse_grouped("pair.0.1 + 1"), "(((pair.0).1) + 1)");
+}
+
+#[test]
+fn test_indexing_and_slicing() {
+    assert_eq!(parse_grouped("a[i + 1] * 2"), "((a[(i + 1)]) * 2)");
+    assert_eq!(parse_grouped("-grid[i][j]"), "(-((grid[i])[j]))");
+    assert_eq!(parse_grouped("a[1 .. n]"), "(a[1 .. n])");
+    assert_eq!(parse_grouped("points[0].x"), "((points[0]).x)");
+    let (expr, _) = pexpr().parse("a[1 ..= 3]".into()).unwrap();
+    assert_eq!(expr.start..expr.start + expr.length, 0..10);
+    match expr.value {
+        Expr::Index(_, index) => assert!(matches!(index.value, Expr::Range(_, _, true, None))),
+        expr => panic!("expected an index, got {:?}", expr),
+    }
+}
+
+#[test]
+fn test_unclosed_index_is_an_error() {
+    let error = pexpr().parse("a[1 + 2".into()).unwrap_err();
+    assert!(error.to_string().starts_with("Expected ']'"), "{}", error);
+}
+
+#[test]
+fn test_for_in() {
+    let (statement, cont) = pstatement()
+        .parse("'rows: for row in grid { total += row[0]; }".into())
+        .unwrap();
+    assert_eq!(cont.remaining, "");
+    match statement.value {
+        Statement::ForIn(label, name, iterable, body) => {
+            assert_eq!(label, Some(Token::new("rows".to_string(), 1, 4)));
+            assert_eq!(name, Token::new("row".to_string(), 11, 3));
+            assert_eq!(iterable, Token::new(Expr::Ident("grid".to_string()), 18, 4));
+            assert_eq!(body.len(), 1);
+        }
+        statement => panic!("expected a for in loop, got {:?}", statement),
+    }
+}
+
+#[test]
+fn test_print_collections() {
+    let source = "fun f(grid: Grid) -> int {
+    let mut totals = [0; 3];
+    for row in grid {
+        totals[row.0] += row.1[1 .. 3][0];
+    };
+    let pair = ((1).0, [], [x, -a[0]], ());
+    totals;
+}";
+    let (fun, cont) = pfun().parse(source.into()).unwrap();
+    assert_eq!(cont.remaining, "");
+    assert_eq!(fun.value.to_string(), source);
+    assert_eq!(validate_fun(&fun.value), vec![]);
+}
+
+#[test]
+fn test_validate_element_assignment() {
+    let source = "fun f() -> int {
+    let a = [1, 2];
+    let mut b = [a, a];
+    b[0][1] = 3;
+    a[0] = 4;
+    b.0 = a;
+}";
+    let assignment = source.find("a[0] = 4").unwrap();
+    let binding = source.find("let a").unwrap();
+    assert_eq!(
+        validate_source(source),
+        vec![ValidationError::AssignToImmutable(
+            "a".to_string(),
+            assignment..assignment + 8,
+            binding..binding + 14
+        )]
+    );
+}
+
+#[test]
+fn test_closures() {
+    let (expr, _) = pexpr().parse("|x: int, y| x + y".into()).unwrap();
+    let Expr::Closure(closure) = expr.value else {
+        panic!("expected a closure");
+    };
+    assert_eq!(
+        closure.params,
+        vec![
+            Token::new(
+                ClosureParameter(
+                    Token::new("x".to_string(), 1, 1),
+                    Some(Token::new(TypeExpr::Named("int".to_string()), 4, 3))
+                ),
+                1,
+                6
+            ),
+            Token::new(
+                ClosureParameter(Token::new("y".to_string(), 9, 1), None),
+                9,
+                1
+            ),
+        ]
+    );
+    assert_eq!(closure.return_type, None);
+    assert!(matches!(
+        closure.body[..],
+        [Token {
+            value: ExprOrStatement::Expr(Expr::Binary(_, _, _)),
+            ..
+        }]
+    ));
+
+    let (expr, _) = pexpr().parse("|| 0".into()).unwrap();
+    assert!(matches!(expr.value, Expr::Closure(Closure { ref params, .. }) if params.is_empty()));
+
+    let (expr, _) = pexpr().parse("fun(n) -> int { n * 2; }".into()).unwrap();
+    let Expr::Closure(closure) = expr.value else {
+        panic!("expected a closure");
+    };
+    assert_eq!(closure.params.len(), 1);
+    assert_eq!(
+        closure.return_type.map(|type_| type_.value),
+        Some(TypeExpr::Named("int".to_string()))
+    );
+    assert_eq!(closure.body.len(), 1);
+}
+
+#[test]
+fn test_function_types() {
+    let (param, _) = pparam()
+        .parse("f: fun(int, fun() -> bool) -> int".into())
+        .unwrap();
+    let Parameter(_, type_) = param.value;
+    let TypeExpr::Function(params, return_type) = &type_.value else {
+        panic!("expected a function type");
+    };
+    assert_eq!(params.len(), 2);
+    assert!(matches!(params[1].value, TypeExpr::Function(ref params, _) if params.is_empty()));
+    assert_eq!(return_type.value, TypeExpr::Named("int".to_string()));
+    assert_eq!(type_.value.to_string(), "fun(int, fun() -> bool) -> int");
+}
+
+#[test]
+fn test_call_any_callee() {
+    let (expr, _) = pexpr().parse("make(1)(2)".into()).unwrap();
+    let Expr::Call(callee, args) = expr.value else {
+        panic!("expected a call");
+    };
+    assert!(matches!(callee.value, Expr::Call(_, _)));
+    assert_eq!(args, vec![Token::new(Expr::Value(Value::Number(2)), 8, 1)]);
+    assert_eq!((expr.start, expr.length), (0, 10));
+
+    let (expr, _) = pexpr().parse("handlers[0].run()".into()).unwrap();
+    assert!(
+        matches!(expr.value, Expr::Call(ref callee, _) if matches!(callee.value, Expr::Field(_, _)))
+    );
+
+    let (expr, _) = pexpr().parse("(|x| x)(3)".into()).unwrap();
+    assert!(
+        matches!(expr.value, Expr::Call(ref callee, _) if matches!(callee.value, Expr::Closure(_)))
+    );
+}
+
+#[test]
+fn test_print_closures() {
+    let source = "fun compose(f: fun(int) -> int, g: fun(int) -> int) -> fun(int) -> int {
+    let twice = |x| f(f(x));
+    let run = || {
+        let y = g(1);
+        twice(y);
+    };
+    (|x: int| x + 1)(run()) + (fun(a) -> int {
+        a;
+    })(2);
+}";
+    let (fun, cont) = pfun().parse(source.into()).unwrap();
+    assert_eq!(cont.remaining, "");
+    assert_eq!(fun.value.to_string(), source);
+}
+
+#[test]
+fn test_validate_break_inside_closure() {
+    let source = "fun f() -> unit {
+    loop {
+        let stop = || {
+            break;
+        };
+    };
+}";
+    let position = source.find("break").unwrap();
+    assert_eq!(
+        validate_source(source),
+        vec![ValidationError::BreakOutsideLoop(position..position + 5)]
+    );
+}
+
+#[test]
+fn test_closure_captures() {
+    let source = "const limit: int = 10;
+fun counter(start: int) -> fun() -> int {
+    let mut count = start;
+    let step = 1;
+    let next = || {
+        count += step;
+        let below = |x| x < limit + count;
+        count;
+    };
+    next;
+}";
+    let (program, cont) = pprogram().parse(source.into()).unwrap();
+    assert_eq!(cont.remaining, "");
+    let captures = closure_captures(&program.value);
+    let count = source.find("let mut count").unwrap();
+    let step = source.find("let step").unwrap();
+    let capture = |name: &str, binding: usize, length: usize, mutated: bool| Capture {
+        name: name.to_string(),
+        binding: binding..binding + length,
+        mutated,
+    };
+
+    assert_eq!(captures.len(), 2);
+    assert_eq!(captures[0].closure.start, source.find("||").unwrap());
+    assert_eq!(
+        captures[0].captured,
+        vec![
+            capture("count", count, 21, true),
+            capture("step", step, 12, false)
+        ]
+    );
+    let below = source.find("|x|").unwrap();
+    assert_eq!(captures[1].closure, below..below + 21);
+    assert_eq!(
+        captures[1].captured,
+        vec![capture("count", count, 21, false)]
+    );
+}
+
+fn resolve_source(source: &str) -> Resolution {
+    let (program, cont) = pprogram().parse(source.into()).unwrap();
+    assert_eq!(cont.remaining, "");
+    resolve_program(&program.value)
+}
+
+#[test]
+fn test_resolve_references() {
+    let source = "import std.io;
+const LIMIT: int = 10;
+fun count(n: int) -> int {
+    let total = n;
+    for i = 0 .. LIMIT {
+        io;
+        let total = i;
+        total;
+    };
+    match total { (a, b) => a + b };
+    count(\"{total}\".len);
+}";
+    let resolution = resolve_source(source);
+    assert_eq!(resolution.errors, vec![]);
+    //the symbol referred to by the name at the start of the nth occurrence of text
+    let referred = |name: &str, text: &str, nth: usize| {
+        let start = source.match_indices(text).nth(nth).unwrap().0;
+        resolution.symbol_at(&(start..start + name.len())).cloned()
+    };
+    let symbol = |name: &str, kind, declared: &str| {
+        let start = source.find(declared).unwrap();
+        Some(Symbol {
+            name: name.to_string(),
+            kind,
+            declared: start..start + declared.len(),
+        })
+    };
+    let outer_total = symbol("total", SymbolKind::Local, "let total = n");
+    let inner_total = symbol("total", SymbolKind::Local, "let total = i");
+    assert_eq!(
+        referred("n", "n;", 0),
+        symbol("n", SymbolKind::Param, "n: int")
+    );
+    assert_eq!(
+        referred("LIMIT", "LIMIT", 1),
+        symbol("LIMIT", SymbolKind::Const, "LIMIT")
+    );
+    assert_eq!(
+        referred("io", "io", 1),
+        symbol("io", SymbolKind::Import, "io")
+    );
+    let declared = |text: &str| source.find(text).map(|start| start..start + 1);
+    assert_eq!(
+        referred("i", "i;", 0).map(|symbol| symbol.declared),
+        declared("i = 0")
+    );
+    assert_eq!(referred("total", "total;", 0), inner_total);
+    assert_eq!(referred("total", "total {", 0), outer_total);
+    assert_eq!(
+        referred("a", "a + b", 0).map(|symbol| symbol.declared),
+        declared("a, b")
+    );
+    assert_eq!(
+        referred("count", "count(", 1),
+        symbol("count", SymbolKind::Fun, "count")
+    );
+    assert_eq!(referred("total", "total}", 0), outer_total);
+    //interpolation calls to_string, with an empty span at the opening brace
+    let interpolated = source.find("{total}").unwrap();
+    assert_eq!(
+        resolution
+            .symbol_at(&(interpolated..interpolated))
+            .map(|symbol| symbol.kind),
+        Some(SymbolKind::Builtin)
+    );
+    //each declaration is a distinct symbol
+    let totals = resolution
+        .symbols
+        .iter()
+        .filter(|symbol| symbol.name == "total")
+        .count();
+    assert_eq!(totals, 2);
+}
+
+#[test]
+fn test_resolve_undefined() {
+    let source = "fun name(param: int, param_x: int) -> unit {
+    let x = 1;
+    call(x, param, function(param_x, 4));
+    if true {
+        let y = x;
+    } else {
+        y;
+    };
+    |z| z + w;
+    z;
+}";
+    let position = |text: &str| source.find(text).unwrap();
+    let y = source.rfind("y;").unwrap();
+    let z = source.rfind('z').unwrap();
+    assert_eq!(
+        resolve_source(source).errors,
+        vec![
+            ResolutionError::Undefined("call".to_string(), position("call")..position("call") + 4),
+            ResolutionError::Undefined(
+                "function".to_string(),
+                position("function")..position("function") + 8
+            ),
+            ResolutionError::Undefined("y".to_string(), y..y + 1),
+            ResolutionError::Undefined("w".to_string(), position("w")..position("w") + 1),
+            ResolutionError::Undefined("z".to_string(), z..z + 1),
+        ]
+    );
+}
+
+#[test]
+fn test_resolve_shadowed_unused() {
+    let source = "fun f(n: int, _m: int) -> unit {
+    let n = 1;
+    let x = 2;
+    let x = x + 1;
+    let y = 3;
+    if true {
+        let y = 4;
+        y;
+    };
+    y;
+    let _m = 5;
+    |y| y;
+}";
+    let statement = |text: &str| {
+        let start = source.find(text).unwrap();
+        start..start + text.len()
+    };
+    let errors = resolve_source(source).errors;
+    assert_eq!(
+        errors,
+        vec![ResolutionError::UnusedShadowed(
+            "n".to_string(),
+            statement("n: int"),
+            statement("let n = 1")
+        )]
+    );
+    assert_eq!(
+        errors[0].to_string(),
+        format!(
+            "n bound at {:?} is shadowed at {:?} without being used",
+            statement("n: int"),
+            statement("let n = 1")
+        )
+    );
+}
+
+#[test]
+fn test_resolve_duplicate_parameters() {
+    let source = "fun f(a: int, b: int, a: bool) -> unit {
+    let g = |x, y, x| x;
+    a;
+}";
+    let parameter = |text: &str, nth: usize| {
+        let start = source.match_indices(text).nth(nth).unwrap().0;
+        start..start + text.len()
+    };
+    assert_eq!(
+        resolve_source(source).errors,
+        vec![
+            ResolutionError::DuplicateParameter(
+                "a".to_string(),
+                parameter("a: bool", 0),
+                parameter("a: int", 0)
+            ),
+            ResolutionError::DuplicateParameter(
+                "x".to_string(),
+                parameter("x", 1),
+                parameter("x", 0)
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_split_items_function_types() {
+    let source = "fun apply(f: fun(int) -> int) -> int { f(1); }
+fun twice() -> fun (int) -> int { fun(x) -> int { x * 2; }; }
+fun main() -> unit { apply(twice()); }";
+    let items = parallel::split_items(source);
+    assert_eq!(items.len(), 3);
+    let unit = parallel::parse_unit(source);
+    assert_eq!(unit.errors, vec![]);
+    assert_eq!(unit.items.len(), 3);
+}
+
+#[test]
+fn test_split_items_keywords_as_names() {
+    let source = "fun name(param: type, param_x: type_x) -> unit { call(1); }
+struct type { enum: [const; 2] }
+fun const<import: struct>(a: import) -> type { a; }
+const c: type = 1;
+type t = enum;";
+    let (program, _) = pprogram().parse(source.into()).unwrap();
+    let unit = parallel::parse_unit(source);
+    assert_eq!(unit.errors, vec![]);
+    assert_eq!(unit.items, program.value.items);
+    assert_eq!(unit.items.len(), 5);
+}
+
+#[test]
+fn test_type_expressions() {
+    let parse = |source: &str| {
+        let (type_, cont) = ptype().parse(source.into()).unwrap();
+        assert_eq!(cont.remaining, "");
+        type_
+    };
+    assert_eq!(
+        parse("List<int>"),
+        Token::new(
+            TypeExpr::Applied(
+                Token::new("List".to_string(), 0, 4),
+                vec![Token::new(TypeExpr::Named("int".to_string()), 5, 3)]
+            ),
+            0,
+            8
+        )
+    );
+    assert_eq!(
+        parse("(int, [bool])").value,
+        TypeExpr::Tuple(vec![
+            Token::new(TypeExpr::Named("int".to_string()), 1, 3),
+            Token::new(
+                TypeExpr::Array(
+                    Box::new(Token::new(TypeExpr::Named("bool".to_string()), 7, 4)),
+                    None
+                ),
+                6,
+                6
+            ),
+        ])
+    );
+    assert_eq!(
+        parse("[u8; 4]").value,
+        TypeExpr::Array(
+            Box::new(Token::new(TypeExpr::Named("u8".to_string()), 1, 2)),
+            Some(4)
+        )
+    );
+    assert_eq!(parse("(int)").value, TypeExpr::Named("int".to_string()));
+    assert_eq!(parse("()").value, TypeExpr::Tuple(vec![]));
+
+    for source in [
+        "Map<string, List<(int, bool)>>",
+        "fun(T, [T; 2]) -> Option<T>",
+        "[fun() -> ()]",
+    ] {
+        assert_eq!(parse(source).value.to_string(), source);
+    }
+    assert!(ptype()
+        .parse("[int; 99999999999999999999999]".into())
+        .is_err());
+}
+
+#[test]
+fn test_generic_fun() {
+    let source = "fun max<T: Ord + Into<int>, U>(items: List<T>, default: U) -> (T, U) {
+    (items[0], default);
+}";
+    let (fun, cont) = pfun().parse(source.into()).unwrap();
+    assert_eq!(cont.remaining, "");
+    let generics: Vec<_> = fun
+        .value
+        .generics
+        .iter()
+        .map(|generic| {
+            let GenericParameter(name, bounds) = &generic.value;
+            let bounds: Vec<_> = bounds.iter().map(|bound| bound.value.to_string()).collect();
+            (name.value.as_str(), bounds)
+        })
+        .collect();
+    assert_eq!(
+        generics,
+        vec![
+            ("T", vec!["Ord".to_string(), "Into<int>".to_string()]),
+            ("U", vec![])
+        ]
+    );
+    assert_eq!(
+        fun.value.params[0].value.1.value,
+        TypeExpr::Applied(
+            Token::new("List".to_string(), 38, 4),
+            vec![Token::new(TypeExpr::Named("T".to_string()), 43, 1)]
+        )
+    );
+    assert_eq!(fun.value.to_string(), source);
+
+    let (fun, _) = pfun().parse("fun id(x: T) -> T { x; }".into()).unwrap();
+    assert!(fun.value.generics.is_empty());
+}
+
+#[test]
+fn test_item_type_expressions() {
+    let source = "const ORIGIN: (int, int) = (0, 0);
+type Grid = [[char; 8]; 8];
+enum Tree { Leaf, Node(List<Tree>, fun(int) -> bool) }";
+    let (program, cont) = pprogram().parse(source.into()).unwrap();
+    assert_eq!(cont.remaining, "");
+    let types: Vec<_> = program
+        .value
+        .items
+        .iter()
+        .flat_map(|item| match &item.value {
+            Item::Const(_, type_, _) | Item::Type(_, type_) => vec![type_.value.to_string()],
+            Item::Enum(_, variants) => variants
+                .iter()
+                .flat_map(|variant| &variant.value.1)
+                .map(|type_| type_.value.to_string())
+                .collect(),
+            _ => vec![],
+        })
+        .collect();
+    assert_eq!(
+        types,
+        vec![
+            "(int, int)",
+            "[[char; 8]; 8]",
+            "List<Tree>",
+            "fun(int) -> bool"
+        ]
+    );
+}
+
+mod roundtrip_properties {
+    use super::*;
+    use proptest::{collection::vec, option, prelude::*};
+
+    const KEYWORDS: [&str; 15] = [
+        "fun", "let", "if", "else", "for", "in", "while", "loop", "break", "continue", "return",
+        "mut", "match", "true", "false",
+    ];
+
+    fn tok<T>(value: T) -> Token<T> {
+        Token::new(value, 0, 0)
+    }
+
+    fn ident() -> impl Strategy<Value = String> {
+        "[a-z_][a-z0-9_]{0,6}".prop_filter("keywords are not identifiers", |ident| {
+            !KEYWORDS.contains(&ident.as_str())
+        })
+    }
+
+    fn value() -> impl Strategy<Value = Value> {
+        prop_oneof![
+            any::<i32>().prop_map(Value::Number),
+            any::<i64>().prop_map(Value::I64),
+            any::<u64>().prop_map(Value::U64),
+            any::<f64>()
+                .prop_filter("only finite floats have literals", |f| f.is_finite())
+                .prop_map(Value::Float),
+            any::<bool>().prop_map(Value::Bool),
+            any::<char>().prop_map(Value::Char),
+            "[a-zA-Z0-9 {}\\\\\"'\n\t\u{0}-\u{1f}é]{0,8}".prop_map(Value::String),
+        ]
+    }
+
+    fn simple_expr() -> BoxedStrategy<Expr> {
+        prop_oneof![value().prop_map(Expr::Value), ident().prop_map(Expr::Ident)].boxed()
+    }
+
+    fn range(expr: BoxedStrategy<Expr>) -> BoxedStrategy<Expr> {
+        (expr.clone(), expr.clone(), any::<bool>(), option::of(expr))
+            .prop_map(|(start, end, inclusive, step)| {
+                let step = step.map(|step| Box::new(tok(step)));
+                Expr::Range(Box::new(tok(start)), Box::new(tok(end)), inclusive, step)
+            })
+            .boxed()
+    }
+
+    fn compound_operator() -> impl Strategy<Value = BinaryOp> {
+        prop::sample::select(vec![
+            BinaryOp::Add,
+            BinaryOp::Sub,
+            BinaryOp::Mul,
+            BinaryOp::Div,
+            BinaryOp::Rem,
+            BinaryOp::BitAnd,
+            BinaryOp::BitOr,
+            BinaryOp::BitXor,
+            BinaryOp::Shl,
+            BinaryOp::Shr,
+        ])
+    }
+
+    fn body(expr: BoxedStrategy<Expr>) -> BoxedStrategy<Vec<Token<ExprOrStatement>>> {
+        let range = range(expr.clone());
+        let for_body = vec(
+            expr.clone().prop_map(ExprOrStatement::Expr).prop_map(tok),
+            1..3,
+        );
+        let item = prop_oneof![
+            expr.clone().prop_map(ExprOrStatement::Expr),
+            (
+                ident(),
+                option::of(type_expr()),
+                expr.clone(),
+                any::<bool>()
+            )
+                .prop_map(|(name, type_, value, mutable)| {
+                    ExprOrStatement::Statement(Statement::Let(
+                        tok(name),
+                        type_.map(tok),
+                        tok(value),
+                        mutable,
+                    ))
+                }),
+            (ident(), option::of(compound_operator()), expr.clone()).prop_map(
+                |(place, operator, value)| {
+                    let place = tok(Expr::Ident(place));
+                    let operator = operator.map(tok);
+                    ExprOrStatement::Statement(Statement::Assign(place, operator, tok(value)))
+                }
+            ),
+            (option::of(ident()), ident(), range, for_body.clone()).prop_map(
+                |(label, name, range, body)| {
+                    let label = label.map(tok);
+                    ExprOrStatement::Statement(Statement::For(label, tok(name), tok(range), body))
+                }
+            ),
+            (option::of(ident()), ident(), expr.clone(), for_body.clone()).prop_map(
+                |(label, name, iterable, body)| {
+                    let label = label.map(tok);
+                    let statement = Statement::ForIn(label, tok(name), tok(iterable), body);
+                    ExprOrStatement::Statement(statement)
+                }
+            ),
+            (option::of(ident()), expr.clone(), for_body.clone()).prop_map(
+                |(label, condition, body)| {
+                    let label = label.map(tok);
+                    ExprOrStatement::Statement(Statement::While(label, tok(condition), body))
+                }
+            ),
+            (option::of(ident()), option::of(simple_expr())).prop_map(|(label, value)| {
+                ExprOrStatement::Statement(Statement::Break(label.map(tok), value.map(tok)))
+            }),
+            option::of(ident()).prop_map(|label| {
+                ExprOrStatement::Statement(Statement::Continue(label.map(tok)))
+            }),
+            option::of(expr.clone()).prop_map(|value| {
+                ExprOrStatement::Statement(Statement::Return(value.map(tok)))
+            }),
+        ];
+        vec(item.prop_map(tok), 1..4).boxed()
+    }
+
+    fn pattern() -> BoxedStrategy<Pattern> {
+        let leaf = prop_oneof![
+            Just(Pattern::Wildcard),
+            value().prop_map(Pattern::Value),
+            ident().prop_map(Pattern::Binding),
+        ];
+        leaf.prop_recursive(2, 8, 3, |inner| {
+            let tuple = vec(inner.clone(), 2..4)
+                .prop_map(|patterns| Pattern::Tuple(patterns.into_iter().map(tok).collect()));
+            let fields = vec((ident(), inner.clone()), 0..3);
+            let pstruct = (ident(), fields, any::<bool>()).prop_map(|(name, fields, rest)| {
+                let fields = fields
+                    .into_iter()
+                    .map(|(field, pattern)| (tok(field), tok(pattern)))
+                    .collect();
+                Pattern::Struct(tok(name), fields, rest)
+            });
+            let variant =
+                (ident(), ident(), vec(inner, 0..3)).prop_map(|(enum_name, variant, payload)| {
+                    let payload = payload.into_iter().map(tok).collect();
+                    Pattern::Variant(tok(enum_name), tok(variant), payload)
+                });
+            prop_oneof![tuple, pstruct, variant]
+        })
+        .boxed()
+    }
+
+    fn type_expr() -> BoxedStrategy<TypeExpr> {
+        ident()
+            .prop_map(TypeExpr::Named)
+            .prop_recursive(2, 6, 3, |inner| {
+                let applied = (ident(), vec(inner.clone(), 1..3)).prop_map(|(name, arguments)| {
+                    TypeExpr::Applied(tok(name), arguments.into_iter().map(tok).collect())
+                });
+                let function =
+                    (vec(inner.clone(), 0..3), inner.clone()).prop_map(|(params, return_type)| {
+                        let params = params.into_iter().map(tok).collect();
+                        TypeExpr::Function(params, Box::new(tok(return_type)))
+                    });
+                let tuple = prop_oneof![Just(vec![]), vec(inner.clone(), 2..4)]
+                    .prop_map(|types| TypeExpr::Tuple(types.into_iter().map(tok).collect()));
+                let array = (inner, option::of(any::<usize>()))
+                    .prop_map(|(element, length)| TypeExpr::Array(Box::new(tok(element)), length));
+                prop_oneof![applied, function, tuple, array]
+            })
+            .boxed()
+    }
+
+    fn generic_param() -> impl Strategy<Value = Token<GenericParameter>> {
+        (ident(), vec(type_expr(), 0..3)).prop_map(|(name, bounds)| {
+            tok(GenericParameter(
+                tok(name),
+                bounds.into_iter().map(tok).collect(),
+            ))
+        })
+    }
+
+    fn expr() -> BoxedStrategy<Expr> {
+        simple_expr()
+            .prop_recursive(3, 24, 3, |inner| {
+                let call = (inner.clone(), vec(inner.clone(), 0..3)).prop_map(|(callee, args)| {
+                    Expr::Call(Box::new(tok(callee)), args.into_iter().map(tok).collect())
+                });
+                let param = (ident(), option::of(type_expr()))
+                    .prop_map(|(name, type_)| tok(ClosureParameter(tok(name), type_.map(tok))));
+                let closure = (
+                    vec(param, 0..3),
+                    option::of(type_expr()),
+                    body(inner.clone()),
+                )
+                    .prop_map(|(params, return_type, body)| {
+                        Expr::Closure(Closure {
+                            params,
+                            return_type: return_type.map(tok),
+                            body,
+                        })
+                    });
+                let body = body(inner.clone());
+                let pif = (simple_expr(), body.clone(), option::of(body.clone())).prop_map(
+                    |(condition, body, else_body)| {
+                        Expr::If(Box::new(tok(condition)), body, else_body)
+                    },
+                );
+                let binary = (
+                    inner.clone(),
+                    prop::sample::select(BinaryOp::ALL.to_vec()),
+                    inner.clone(),
+                )
+                    .prop_map(|(lhs, op, rhs)| {
+                        Expr::Binary(Box::new(tok(lhs)), tok(op), Box::new(tok(rhs)))
+                    });
+                let unary = (
+                    prop_oneof![Just(UnaryOp::Neg), Just(UnaryOp::Not)],
+                    inner.clone(),
+                )
+                    .prop_map(|(op, operand)| Expr::Unary(tok(op), Box::new(tok(operand))));
+                let ploop = (option::of(ident()), body.clone())
+                    .prop_map(|(label, body)| Expr::Loop(label.map(tok), body));
+                let fields = vec((ident(), inner.clone()), 0..3);
+                let pstruct = (ident(), fields).prop_map(|(name, fields)| {
+                    let fields = fields
+                        .into_iter()
+                        .map(|(field, value)| (tok(field), tok(value)))
+                        .collect();
+                    Expr::Struct(tok(name), fields)
+                });
+                let field = (inner.clone(), ident())
+                    .prop_map(|(value, name)| Expr::Field(Box::new(tok(value)), tok(name)));
+                let variant = (ident(), ident(), vec(inner.clone(), 0..3)).prop_map(
+                    |(enum_name, variant, payload)| {
+                        let payload = payload.into_iter().map(tok).collect();
+                        Expr::Variant(tok(enum_name), tok(variant), payload)
+                    },
+                );
+                let arm = (pattern(), option::of(simple_expr()), body.clone()).prop_map(
+                    |(pattern, guard, body)| {
+                        tok(MatchArm {
+                            pattern: tok(pattern),
+                            guard: guard.map(tok),
+                            body,
+                        })
+                    },
+                );
+                let pmatch = (simple_expr(), vec(arm, 1..3))
+                    .prop_map(|(scrutinee, arms)| Expr::Match(Box::new(tok(scrutinee)), arms));
+                let array = vec(inner.clone(), 0..3)
+                    .prop_map(|values| Expr::Array(values.into_iter().map(tok).collect()));
+                let repeat = (inner.clone(), inner.clone()).prop_map(|(value, count)| {
+                    Expr::Repeat(Box::new(tok(value)), Box::new(tok(count)))
+                });
+                let tuple = prop_oneof![Just(vec![]), vec(inner.clone(), 2..4)]
+                    .prop_map(|values| Expr::Tuple(values.into_iter().map(tok).collect()));
+                let index = (inner.clone(), inner.clone()).prop_map(|(value, index)| {
+                    Expr::Index(Box::new(tok(value)), Box::new(tok(index)))
+                });
+                prop_oneof![
+                    array,
+                    repeat,
+                    tuple,
+                    index,
+                    call,
+                    closure,
+                    pif,
+                    binary,
+                    unary,
+                    range(inner),
+                    ploop,
+                    pstruct,
+                    field,
+                    variant,
+                    pmatch
+                ]
+            })
+            .boxed()
+    }
+
+    pub(super) fn fun() -> impl Strategy<Value = Fun> {
+        let param =
+            (ident(), type_expr()).prop_map(|(name, type_)| tok(Parameter(tok(name), tok(type_))));
+        let generics = vec(generic_param(), 0..3);
+        (
+            ident(),
+            generics,
+            vec(param, 1..3),
+            type_expr(),
+            body(expr()),
+        )
+            .prop_map(|(name, generics, params, return_type, body)| Fun {
+                name: tok(name),
+                generics,
+                params,
+                body,
+                return_type: tok(return_type),
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn printed_fun_parses_back(fun in fun()) {
+            let source = fun.to_string();
+            let (parsed, cont) = pfun().parse(source.as_str().into()).unwrap();
+            prop_assert_eq!(cont.remaining, "");
+            prop_assert_eq!(parsed.value.to_string(), source);
+        }
+
+        #[test]
+        fn split_units_parse_as_programs(funs in vec(fun(), 1..4)) {
+            let funs: Vec<String> = funs.iter().map(Fun::to_string).collect();
+            let source = funs.join("\n");
+            let (program, _) = pprogram().parse(source.as_str().into()).unwrap();
+            let unit = parallel::parse_unit(&source);
+            prop_assert_eq!(&unit.errors, &vec![]);
+            prop_assert_eq!(&unit.items, &program.value.items);
+            prop_assert_eq!(parallel::parse_unit_parallel(&source, 2), unit);
+        }
+    }
+}
+
+mod no_panic_properties {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn pfun_never_panics(input in "\\PC*") {
+            let _ = pfun().parse(input.as_str().into());
+        }
+
+        #[test]
+        fn pfun_never_panics_on_mutated_programs(
+            fun in roundtrip_properties::fun(),
+            position in any::<prop::sample::Index>(),
+            length in 0..8usize,
+            insert in "[\\PC\n]{0,4}",
+        ) {
+            let source = fun.to_string();
+            let mut start = position.index(source.len() + 1);
+            while !source.is_char_boundary(start) {
+                start -= 1;
+            }
+            let mut end = (start + length).min(source.len());
+            while !source.is_char_boundary(end) {
+                end -= 1;
+            }
+            let source = format!("{}{}{}", &source[..start], insert, &source[end..]);
+            let _ = pfun().parse(source.as_str().into());
+        }
+
+        #[test]
+        fn deep_nesting_is_an_error(
+            depth in 33..2000usize,
+            (open, close) in prop::sample::select(vec![
+                ("(", ")"),
+                ("[", "]"),
+                ("-", ""),
+                ("f(", ")"),
+                ("if c { ", "; }"),
+                ("loop { break ", "; }"),
+                ("|| ", ""),
+            ]),
+        ) {
+            let source = format!("{}1{}", open.repeat(depth), close.repeat(depth));
+            //keywords are not reserved, so an `if` can still be read as a name
+            let result = pexpr().parse(source.as_str().into());
+            prop_assert!(!matches!(result, Ok((_, cont)) if cont.remaining.is_empty()));
+        }
+
+        #[test]
+        fn expressions_never_panic(input in "\\PC*") {
+            let _ = pexpr().parse(input.as_str().into());
+            let _ = pnumber().parse(input.as_str().into());
+            let _ = pquoted_string().parse(input.as_str().into());
+            let _ = pidentifier().parse(input.as_str().into());
+            let _ = pstatement().parse(input.as_str().into());
+        }
+    }
+}
+
+const PROGRAM_SOURCE: &str = "
+import std.io;
+const LIMIT: int = 10;
+type Count = int;
+
+fun main() -> unit {
+    count(LIMIT);
+}
+
+fun count(to: Count) -> unit {
+    for i = 0 .. to {
+        print(i);
+    };
+}
+";
+
+#[test]
+fn test_program() {
+    let (program, cont) = pprogram().parse(PROGRAM_SOURCE.into()).unwrap();
+    assert_eq!(cont.remaining, "");
+    let items: Vec<_> = program
+        .value
+        .items
+        .into_iter()
+        .map(|item| item.value)
+        .collect();
+    assert_eq!(items.len(), 5);
+    assert_eq!(
+        items[0],
+        Item::Import(vec![
+            Token::new("std".to_string(), 8, 3),
+            Token::new("io".to_string(), 12, 2),
+        ])
+    );
+    assert_eq!(
+        items[1],
+        Item::Const(
+            Token::new("LIMIT".to_string(), 22, 5),
+            Token::new(TypeExpr::Named("int".to_string()), 29, 3),
+            Token::new(Expr::Value(Value::Number(10)), 35, 2),
+        )
+    );
+    assert_eq!(
+        items[2],
+        Item::Type(
+            Token::new("Count".to_string(), 44, 5),
+            Token::new(TypeExpr::Named("int".to_string()), 52, 3),
+        )
+    );
+    match &items[3] {
+        Item::Fun(fun) => {
+            assert_eq!(fun.name.value, "main");
+            assert!(fun.params.is_empty());
+        }
+        item => panic!("expected a function, got {:?}", item),
+    }
+}
+
+#[test]
+fn test_program_empty() {
+    let (program, _) = pprogram().parse("  \n ".into()).unwrap();
+    assert!(program.value.items.is_empty());
+}
+
+#[test]
+fn test_program_trailing_garbage() {
+    let source = "fun main() -> unit { call(1); }\n}";
+    let result = pprogram().parse(source.into());
+    let error = result.unwrap_err();
+    assert_eq!(
+        (error.position, error.line_number, error.actual),
+        (32, 1, "}")
+    );
+    assert_eq!(
+        error.to_string(),
+        "Expected end of input or 'fun' or 'const' or 'type' or 'import' or 'struct' or 'enum' but got } at line: 2, column: 1"
+    );
+}
+
+#[test]
+fn test_program_reports_error_inside_item() {
+    let source = "fun main() -> unit { call(1); }\nfun broken(x: int) -> unit { call(; }";
+    let error = pprogram().parse(source.into()).unwrap_err();
+    assert_eq!(error.line_number, 1);
+    assert!(error.position > 32);
+}
+
+//runs main, giving what it returns or the error it stops with, and what it printed
+fn run_source(source: &str) -> (Result<String, RuntimeError>, String) {
+    let (program, cont) = pprogram().parse(source.into()).unwrap();
+    assert_eq!(cont.remaining, "");
+    let mut interpreter = Interpreter::with_output(&program.value, Vec::new());
+    let result = interpreter.run().map(|value| value.to_string());
+    let output = String::from_utf8(interpreter.into_output()).unwrap();
+    (result, output)
+}
+
+#[test]
+fn test_run_calls_between_functions() {
+    let source = "fun fact(n: int) -> int {
+    if n <= 1 { 1; } else { n * fact(n - 1); };
+}
+fun fib(n: int) -> int {
+    if n < 2 {
+        return n;
+    };
+    fib(n - 1) + fib(n - 2);
+}
+fun main() -> unit {
+    println(fact(10));
+    print(fib(15));
+    print(' ');
+    print(\"{fact(3)}!\");
+}";
+    assert_eq!(
+        run_source(source),
+        (Ok("()".to_string()), "3628800\n610 6!".to_string())
+    );
+}
+
+#[test]
+fn test_run_loops() {
+    let source = "fun main() -> int {
+    let mut total = 0;
+    for i = 0 .. 10 step 3 {
+        total += i;
+    };
+    for i = 3 ..= 1 step -1 {
+        total = total * 10 + i;
+    };
+    let mut n = 0;
+    while n < 5 {
+        n += 1;
+        if n % 2 == 0 {
+            continue;
+        };
+        total += 1000;
+    };
+    let found = 'search: loop {
+        for i = 0 .. 10 {
+            for c in \"ab\" {
+                if i == 4 && c == 'b' {
+                    break 'search i;
+                };
+            };
+        };
+        break -1;
+    };
+    total * 10 + found;
+}";
+    assert_eq!(run_source(source).0, Ok("213214".to_string()));
+}
+
+#[test]
+fn test_run_closures_capture_bindings() {
+    let source = "fun apply(f: fun(int) -> int, x: int) -> int {
+    f(x);
+}
+fun main() -> int {
+    let mut count = 0;
+    let add = |n| {
+        count += n;
+        count;
+    };
+    add(1);
+    add(2);
+    let scale = 10;
+    let times = |x| x * scale;
+    count * 100 + apply(times, add(4));
+}";
+    assert_eq!(run_source(source).0, Ok("370".to_string()));
+}
+
+#[test]
+fn test_run_structs_enums_and_match() {
+    let source = "struct Point { x: int, y: int }
+enum Shape { Circle(Point, int), Square(int), Empty }
+fun area(shape: Shape) -> int {
+    match shape {
+        Shape::Circle(Point { x, .. }, radius) if x > 0 => 3 * radius * radius,
+        Shape::Circle(_, _) => 0,
+        Shape::Square(side) => side * side,
+        Shape::Empty => -1
+    };
+}
+fun main() -> string {
+    let mut p = Point { y: 2, x: 1 };
+    let q = p;
+    p.x = 5;
+    let shapes = [Shape::Circle(p, 2), Shape::Circle(Point { x: 0, y: 0 }, 2), Shape::Square(3)];
+    let mut areas = [0; 3];
+    for i = 0 .. 3 {
+        areas[i] = area(shapes[i]);
+    };
+    \"{q} {p.x} {areas} {Shape::Square(4)} {(1, true).1}\";
+}";
+    assert_eq!(
+        run_source(source).0,
+        Ok("Point { x: 1, y: 2 } 5 [12, 0, 9] Shape::Square(4) true".to_string())
+    );
+}
+
+#[test]
+fn test_run_builtins() {
+    let source = "fun main() -> unit {
+    let words = split(\" a,bc,def \", \",\");
+    println(words);
+    println(len(words) + len(\"héllo\"));
+    println(trim(words[0]) + to_upper(words[1]) + to_lower(\"XY\"));
+    println(contains(words[2], \"ef\") || contains(\"x\", \"y\"));
+    println(substring(\"hello\", 1, 3) + to_string(parse_int(\"-12\") * 2));
+    println(chars(\"hey\")[1 .. 3]);
+}";
+    assert_eq!(
+        run_source(source),
+        (
+            Ok("()".to_string()),
+            "[ a, bc, def ]\n8\naBCxy\ntrue\nel-24\n[e, y]\n".to_string()
+        )
+    );
+}
+
+#[test]
+fn test_run_errors_report_span_and_trace() {
+    let source = "fun get(values: [int], i: int) -> int {
+    values[i];
+}
+fun main() -> int {
+    let values = [1, 2, 3];
+    get(values, 1) + get(values, 3);
+}";
+    let error = run_source(source).0.unwrap_err();
+    let at = |text: &str| {
+        let start = source.find(text).unwrap();
+        start..start + text.len()
+    };
+    let index = source.find("[i]").unwrap() + 1;
+    assert_eq!(error.kind, RuntimeErrorKind::IndexOutOfBounds(3, 3));
+    assert_eq!(error.span, index..index + 1);
+    assert_eq!(
+        error.trace,
+        vec![
+            Frame {
+                function: "get".to_string(),
+                call: Some(at("get(values, 3)")),
+            },
+            Frame {
+                function: "main".to_string(),
+                call: None,
+            },
+        ]
+    );
+    assert_eq!(
+        error.to_string(),
+        format!(
+            "index 3 is out of bounds for length 3 at {:?}\n    in get called at {:?}\n    in main",
+            index..index + 1,
+            at("get(values, 3)")
+        )
+    );
+
+    let source = "fun main() -> int {
+    let zero = 0;
+    println(\"before\");
+    1 / zero;
+}";
+    let (result, output) = run_source(source);
+    let error = result.unwrap_err();
+    assert_eq!(
+        (error.kind, error.span),
+        (RuntimeErrorKind::DivisionByZero, at_in(source, "1 / zero"))
+    );
+    assert_eq!(output, "before\n");
+}
+
+fn at_in(source: &str, text: &str) -> std::ops::Range<usize> {
+    let start = source.find(text).unwrap();
+    start..start + text.len()
+}
+
+#[test]
+fn test_run_rejects_huge_repeats() {
+    let source = "fun main() -> int {
+    let a = [0; 2000000000];
+    a[0];
+}";
+    let error = run_source(source).0.unwrap_err();
+    assert_eq!(
+        (error.kind, error.span),
+        (
+            RuntimeErrorKind::InvalidArgument("cannot repeat a value 2000000000 times".to_string()),
+            at_in(source, "2000000000")
+        )
+    );
+    let source = "fun main() -> int {
+    let a = [7; 3];
+    a[2];
+}";
+    assert_eq!(run_source(source).0, Ok("7".to_string()));
+}
+
+#[test]
+fn test_run_stack_overflow() {
+    let source = "fun down(n: int) -> int { down(n + 1); }
+fun main() -> int { down(0); }";
+    let (program, _) = pprogram().parse(source.into()).unwrap();
+    let mut interpreter = Interpreter::with_output(&program.value, Vec::new());
+    interpreter.max_depth = 16;
+    let error = interpreter.run().unwrap_err();
+    assert_eq!(error.kind, RuntimeErrorKind::StackOverflow);
+    assert_eq!(error.trace.len(), 16);
+    assert_eq!(error.trace[0].function, "down");
+    assert_eq!(
+        interpreter.call("down", vec![RuntimeValue::Bool(true)]),
+        Err(RuntimeError {
+            kind: RuntimeErrorKind::Mismatched("bool", "int"),
+            span: at_in(source, "n + 1"),
+            trace: vec![Frame {
+                function: "down".to_string(),
+                call: None,
+            }],
+        })
+    );
+}
@@ -0,0 +1,422 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    ops::Range,
+};
+
+use crate::parser_combinator::Token;
+
+use super::{exhaustiveness::*, *};
+
+/// A construct that parses but is not allowed where it appears.
+/// Spans are byte ranges of the offending statement or label.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    BreakOutsideLoop(Range<usize>),
+    ContinueOutsideLoop(Range<usize>),
+    BreakValueOutsideLoop(Range<usize>), //only `loop` can produce a value
+    UndeclaredLabel(String, Range<usize>),
+    AssignToImmutable(String, Range<usize>, Range<usize>), //name, assignment, binding
+    NonExhaustiveMatch(Vec<String>, Range<usize>),         //missing patterns, scrutinee
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ValidationError::BreakOutsideLoop(span) => {
+                write!(f, "break outside of a loop at {:?}", span)
+            }
+            ValidationError::ContinueOutsideLoop(span) => {
+                write!(f, "continue outside of a loop at {:?}", span)
+            }
+            ValidationError::BreakValueOutsideLoop(span) => {
+                write!(f, "break with a value outside of `loop` at {:?}", span)
+            }
+            ValidationError::UndeclaredLabel(label, span) => {
+                write!(f, "undeclared label '{} at {:?}", label, span)
+            }
+            ValidationError::AssignToImmutable(name, span, binding) => write!(
+                f,
+                "cannot assign to immutable {} at {:?}, bound at {:?}",
+                name, span, binding
+            ),
+            ValidationError::NonExhaustiveMatch(missing, span) => write!(
+                f,
+                "match on {:?} does not cover {}",
+                span,
+                missing.join(", ")
+            ),
+        }
+    }
+}
+
+struct LoopScope<'t> {
+    label: Option<&'t str>,
+    yields_value: bool,
+}
+
+struct Binding<'t> {
+    name: &'t str,
+    mutable: bool,
+    span: Range<usize>,
+}
+
+/// An outer binding referred to inside a closure.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capture {
+    pub name: String,
+    pub binding: Range<usize>,
+    pub mutated: bool, //assigned to inside the closure
+}
+
+/// The outer bindings a closure captures, in the order they are first referred to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Captures {
+    pub closure: Range<usize>,
+    pub captured: Vec<Capture>,
+}
+
+struct ClosureScope {
+    bindings: usize, //bindings declared outside the closure
+    captures: usize, //index of its entry in the captures
+}
+
+#[derive(Default)]
+struct Validator<'t> {
+    loops: Vec<LoopScope<'t>>,
+    bindings: Vec<Binding<'t>>, //innermost last
+    globals: usize,             //constants, which are never captured
+    closures: Vec<ClosureScope>,
+    captures: Vec<Captures>,
+    declarations: Declarations<'t>,
+    errors: Vec<ValidationError>,
+}
+
+impl<'t> Validator<'t> {
+    fn bind(&mut self, name: &'t str, mutable: bool, span: Range<usize>) {
+        self.bindings.push(Binding {
+            name,
+            mutable,
+            span,
+        });
+    }
+
+    fn bind_pattern(&mut self, pattern: &'t Token<Pattern>) {
+        match &pattern.value {
+            Pattern::Wildcard | Pattern::Value(_) => {}
+            Pattern::Binding(name) => self.bind(name, false, pattern.span()),
+            Pattern::Tuple(patterns) | Pattern::Variant(_, _, patterns) => {
+                for pattern in patterns {
+                    self.bind_pattern(pattern);
+                }
+            }
+            Pattern::Struct(_, fields, _) => {
+                for (_, pattern) in fields {
+                    self.bind_pattern(pattern);
+                }
+            }
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<usize> {
+        self.bindings
+            .iter()
+            .rposition(|binding| binding.name == name)
+    }
+
+    //a binding from outside a closure is captured by it, and by any closures it is nested in
+    fn capture(&mut self, binding: usize, mutated: bool) {
+        if binding < self.globals {
+            return;
+        }
+        let Binding { name, span, .. } = &self.bindings[binding];
+        for scope in self
+            .closures
+            .iter()
+            .filter(|scope| scope.bindings > binding)
+        {
+            let captured = &mut self.captures[scope.captures].captured;
+            match captured.iter_mut().find(|capture| capture.binding == *span) {
+                Some(capture) => capture.mutated |= mutated,
+                None => captured.push(Capture {
+                    name: name.to_string(),
+                    binding: span.clone(),
+                    mutated,
+                }),
+            }
+        }
+    }
+
+    //break and continue cannot reach loops outside the closure
+    fn closure(&mut self, closure: &'t Closure, span: Range<usize>) {
+        let bindings = self.bindings.len();
+        self.closures.push(ClosureScope {
+            bindings,
+            captures: self.captures.len(),
+        });
+        self.captures.push(Captures {
+            closure: span,
+            captured: Vec::new(),
+        });
+        for param in &closure.params {
+            self.bind(&param.value.0.value, false, param.span());
+        }
+        let loops = std::mem::take(&mut self.loops);
+        self.body(&closure.body);
+        self.loops = loops;
+        self.closures.pop();
+        self.bindings.truncate(bindings);
+    }
+
+    fn arm(&mut self, arm: &'t MatchArm) {
+        let scope = self.bindings.len();
+        self.bind_pattern(&arm.pattern);
+        if let Some(guard) = &arm.guard {
+            self.expr(guard);
+        }
+        self.body(&arm.body);
+        self.bindings.truncate(scope);
+    }
+
+    fn body(&mut self, body: &'t [Token<ExprOrStatement>]) {
+        let scope = self.bindings.len();
+        for item in body {
+            match &item.value {
+                ExprOrStatement::Expr(expr) => self.expr_at(expr, item.span()),
+                ExprOrStatement::Statement(statement) => self.statement(statement, item.span()),
+            }
+        }
+        self.bindings.truncate(scope);
+    }
+
+    fn fun(&mut self, fun: &'t Fun) {
+        let scope = self.bindings.len();
+        for param in &fun.params {
+            self.bind(&param.value.0.value, false, param.span());
+        }
+        self.body(&fun.body);
+        self.bindings.truncate(scope);
+    }
+
+    //names that are not bound here are left to name resolution
+    //assigning to a field or element of a binding needs the binding to be mutable
+    fn assign(&mut self, place: &'t Expr, span: Range<usize>) {
+        let name = match place {
+            Expr::Ident(name) => name,
+            Expr::Field(value, _) | Expr::Index(value, _) => {
+                return self.assign(&value.value, span);
+            }
+            _ => return,
+        };
+        let Some(binding) = self.lookup(name) else {
+            return;
+        };
+        self.capture(binding, true);
+        let binding = &self.bindings[binding];
+        if !binding.mutable {
+            let error =
+                ValidationError::AssignToImmutable(name.clone(), span, binding.span.clone());
+            self.errors.push(error);
+        }
+    }
+
+    fn looped(
+        &mut self,
+        label: &'t Option<Token<String>>,
+        yields_value: bool,
+        body: &'t [Token<ExprOrStatement>],
+    ) {
+        let label = label.as_ref().map(|label| label.value.as_str());
+        self.loops.push(LoopScope {
+            label,
+            yields_value,
+        });
+        self.body(body);
+        self.loops.pop();
+    }
+
+    //the loop a break or continue refers to, reporting an unknown label
+    fn target(&mut self, label: &Option<Token<String>>) -> Option<&LoopScope<'t>> {
+        match label {
+            None => self.loops.last(),
+            Some(label) => {
+                let target = self
+                    .loops
+                    .iter()
+                    .rposition(|scope| scope.label == Some(label.value.as_str()));
+                if target.is_none() {
+                    let error = ValidationError::UndeclaredLabel(label.value.clone(), label.span());
+                    self.errors.push(error);
+                }
+                target.map(|target| &self.loops[target])
+            }
+        }
+    }
+
+    fn statement(&mut self, statement: &'t Statement, span: Range<usize>) {
+        match statement {
+            Statement::Let(name, _, value, mutable) => {
+                self.expr(value);
+                self.bind(&name.value, *mutable, span);
+            }
+            Statement::Assign(place, _, value) => {
+                self.expr(place);
+                self.expr(value);
+                self.assign(&place.value, span);
+            }
+            Statement::For(label, name, range, body)
+            | Statement::ForIn(label, name, range, body) => {
+                self.expr(range);
+                let scope = self.bindings.len();
+                self.bind(&name.value, false, name.span());
+                self.looped(label, false, body);
+                self.bindings.truncate(scope);
+            }
+            Statement::While(label, condition, body) => {
+                self.expr(condition);
+                self.looped(label, false, body);
+            }
+            Statement::Break(label, value) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+                if self.loops.is_empty() {
+                    self.errors.push(ValidationError::BreakOutsideLoop(span));
+                } else {
+                    let yields_value = self.target(label).map(|target| target.yields_value);
+                    if value.is_some() && yields_value == Some(false) {
+                        self.errors
+                            .push(ValidationError::BreakValueOutsideLoop(span));
+                    }
+                }
+            }
+            Statement::Continue(label) => {
+                if self.loops.is_empty() {
+                    self.errors.push(ValidationError::ContinueOutsideLoop(span));
+                } else {
+                    self.target(label);
+                }
+            }
+            Statement::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &'t Token<Expr>) {
+        self.expr_at(&expr.value, expr.span())
+    }
+
+    fn expr_at(&mut self, expr: &'t Expr, span: Range<usize>) {
+        match expr {
+            Expr::Value(_) => {}
+            Expr::Ident(name) => {
+                if let Some(binding) = self.lookup(name) {
+                    self.capture(binding, false);
+                }
+            }
+            Expr::Call(callee, args) => {
+                self.expr(callee);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Expr::Range(start, end, _, step) => {
+                self.expr(start);
+                self.expr(end);
+                if let Some(step) = step {
+                    self.expr(step);
+                }
+            }
+            Expr::If(condition, body, else_body) => {
+                self.expr(condition);
+                self.body(body);
+                if let Some(else_body) = else_body {
+                    self.body(else_body);
+                }
+            }
+            Expr::Binary(lhs, _, rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            Expr::Unary(_, operand) => self.expr(operand),
+            Expr::Loop(label, body) => self.looped(label, true, body),
+            Expr::Struct(_, fields) => {
+                for (_, value) in fields {
+                    self.expr(value);
+                }
+            }
+            Expr::Field(value, _) => self.expr(value),
+            Expr::Variant(_, _, values) | Expr::Array(values) | Expr::Tuple(values) => {
+                for value in values {
+                    self.expr(value);
+                }
+            }
+            Expr::Repeat(value, count) => {
+                self.expr(value);
+                self.expr(count);
+            }
+            Expr::Index(value, index) => {
+                self.expr(value);
+                self.expr(index);
+            }
+            Expr::Match(scrutinee, arms) => {
+                self.expr(scrutinee);
+                for arm in arms {
+                    self.arm(&arm.value);
+                }
+                let missing = missing_patterns(&self.declarations, arms);
+                if !missing.is_empty() {
+                    let missing = missing.iter().map(Pattern::to_string).collect();
+                    let error = ValidationError::NonExhaustiveMatch(missing, scrutinee.span());
+                    self.errors.push(error);
+                }
+            }
+            Expr::Closure(closure) => self.closure(closure, span),
+        }
+    }
+}
+
+/// Checks that every `break` and `continue` in a function is inside a loop declaring
+/// the label it names, that only `let mut` bindings are assigned to, and that every
+/// `match` covers all values. Without a program, enums are assumed to have only the
+/// variants matched.
+pub fn validate_fun(fun: &Fun) -> Vec<ValidationError> {
+    let mut validator = Validator::default();
+    validator.fun(fun);
+    validator.errors
+}
+
+fn validate<'t>(program: &'t Program) -> Validator<'t> {
+    let mut validator = Validator {
+        declarations: Declarations::new(program),
+        ..Validator::default()
+    };
+    //constants are visible throughout the program, wherever they are declared
+    for item in &program.items {
+        if let Item::Const(name, _, _) = &item.value {
+            validator.bind(&name.value, false, item.span());
+        }
+    }
+    validator.globals = validator.bindings.len();
+    for item in &program.items {
+        match &item.value {
+            Item::Fun(fun) => validator.fun(fun),
+            Item::Const(_, _, value) => validator.expr(value),
+            Item::Type(_, _) | Item::Import(_) | Item::Struct(_, _) | Item::Enum(_, _) => {}
+        }
+    }
+    validator
+}
+
+/// Validates every item of a program, returning the errors in source order.
+pub fn validate_program(program: &Program) -> Vec<ValidationError> {
+    validate(program).errors
+}
+
+/// The outer bindings each closure in a program captures, in source order of the closures.
+/// Parameters and locals of enclosing functions and closures are captured; constants are not.
+pub fn closure_captures(program: &Program) -> Vec<Captures> {
+    validate(program).captures
+}
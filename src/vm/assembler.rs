@@ -0,0 +1,355 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    ops::Range,
+    rc::Rc,
+};
+
+use crate::{
+    parser_combinator::*,
+    untyped_language::{pchar_literal, pidentifier, pquoted_string, Value as Literal},
+};
+
+use super::*;
+
+const CONST: &str = "const";
+const FUN: &str = "fun";
+
+/// Why text could not be assembled into a module.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssembleError<'a> {
+    Parse(Error<'a>),
+    Misnumbered(usize, usize, Range<usize>), //expected, found
+}
+
+impl<'a> Display for AssembleError<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            AssembleError::Parse(error) => write!(f, "{}", error),
+            AssembleError::Misnumbered(expected, found, span) => {
+                write!(
+                    f,
+                    "expected {} but numbered {} at {:?}",
+                    expected, found, span
+                )
+            }
+        }
+    }
+}
+
+impl<'a> From<Error<'a>> for AssembleError<'a> {
+    fn from(error: Error<'a>) -> Self {
+        AssembleError::Parse(error)
+    }
+}
+
+fn assemble_error<'a>(expected: &str, actual: &'a str, input: ContinuationState<'a>) -> Error<'a> {
+    Error::new(
+        Expected::Class(expected.into()),
+        actual,
+        input.position,
+        input.line_number,
+        input.line_position,
+    )
+}
+
+//whitespace, and comments from a `;` to the end of the line
+fn pskip<'a>() -> impl Parser<'a, ()> {
+    parser_from_fn(move |input: ContinuationState<'a>| {
+        let mut rest = input.remaining;
+        loop {
+            let trimmed = rest.trim_start();
+            rest = match trimmed.strip_prefix(';') {
+                Some(comment) => comment.find('\n').map_or("", |end| &comment[end..]),
+                None => break,
+            };
+        }
+        let rest = rest.trim_start();
+        let length = input.remaining.len() - rest.len();
+        Ok((Token::new((), input.position, length), input.skip(length)))
+    })
+}
+
+fn plexeme<'a, T: Clone + 'a>(parser: impl Parser<'a, T> + 'a) -> impl Parser<'a, T> {
+    parser.then(pskip()).left()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    Register,
+    Constant,
+    Slot,
+    Target,
+    Count,
+    Bool,
+}
+
+impl Operand {
+    const ALL: [Operand; 6] = [
+        Operand::Register,
+        Operand::Constant,
+        Operand::Slot,
+        Operand::Target,
+        Operand::Count,
+        Operand::Bool,
+    ];
+
+    //the pattern, the name used in errors and the largest value
+    fn syntax(self) -> (&'static str, &'static str, u32) {
+        match self {
+            Operand::Register => ("r[0-9]+", "register", Register::MAX as u32),
+            Operand::Constant => ("k[0-9]+", "constant", u32::MAX),
+            Operand::Slot => ("s[0-9]+", "slot", u32::MAX),
+            Operand::Target => ("@[0-9]+", "jump target", u32::MAX),
+            Operand::Count => ("[0-9]+", "count", u8::MAX as u32),
+            Operand::Bool => ("true|false", "bool", 1),
+        }
+    }
+}
+
+//the digits after any prefix, if they are at most `max`
+fn integer(text: &str, max: u32) -> Option<u32> {
+    let digits = text.trim_start_matches(|c: char| !c.is_ascii_digit());
+    digits.parse().ok().filter(|value| *value <= max)
+}
+
+fn pinteger<'a>(pattern: &'a str, name: &'static str, max: u32) -> impl Parser<'a, u32> {
+    let text = pregex(pattern);
+    parser_from_fn(move |input: ContinuationState<'a>| {
+        let (token, cont) = text.parse(input)?;
+        match integer(token.value, max) {
+            Some(value) => Ok((Token::new(value, token.start, token.length), cont)),
+            None => Err(assemble_error(name, token.value, input)),
+        }
+    })
+}
+
+fn poperand<'a>(operand: Operand) -> impl Parser<'a, u32> {
+    let (pattern, name, max) = operand.syntax();
+    let text = pregex(pattern);
+    parser_from_fn(move |input: ContinuationState<'a>| {
+        let (token, cont) = text.parse(input)?;
+        let value = match operand {
+            Operand::Bool => Some((token.value == "true") as u32),
+            _ => integer(token.value, max),
+        };
+        match value {
+            Some(value) => Ok((Token::new(value, token.start, token.length), cont)),
+            None => Err(assemble_error(name, token.value, input)),
+        }
+    })
+}
+
+//the operands written after a mnemonic
+fn operands(mnemonic: &str) -> Option<&'static [Operand]> {
+    use Operand::*;
+    Some(match mnemonic {
+        "loadk" => &[Register, Constant],
+        "loadb" => &[Register, Bool],
+        "loadu" | "ret" | "chkstep" => &[Register],
+        "move" | "neg" | "not" => &[Register, Register],
+        "add" | "sub" | "mul" | "div" | "rem" | "eq" | "ne" | "lt" | "le" => {
+            &[Register, Register, Register]
+        }
+        "jmp" => &[Target],
+        "jmpif" | "jmpifnot" => &[Register, Target],
+        "call" => &[Register, Register, Register, Count],
+        "spill" => &[Slot, Register],
+        "reload" => &[Register, Slot],
+        _ => return None,
+    })
+}
+
+//builds an instruction from operands parsed by the shape given by `operands`
+fn instruction(mnemonic: &str, values: &[u32]) -> Instruction {
+    let r = |index: usize| values[index] as Register;
+    match mnemonic {
+        "loadk" => Instruction::LoadConst(r(0), values[1]),
+        "loadb" => Instruction::LoadBool(r(0), values[1] == 1),
+        "loadu" => Instruction::LoadUnit(r(0)),
+        "ret" => Instruction::Return(r(0)),
+        "move" => Instruction::Move(r(0), r(1)),
+        "neg" => Instruction::Neg(r(0), r(1)),
+        "not" => Instruction::Not(r(0), r(1)),
+        "add" => Instruction::Add(r(0), r(1), r(2)),
+        "sub" => Instruction::Sub(r(0), r(1), r(2)),
+        "mul" => Instruction::Mul(r(0), r(1), r(2)),
+        "div" => Instruction::Div(r(0), r(1), r(2)),
+        "rem" => Instruction::Rem(r(0), r(1), r(2)),
+        "eq" => Instruction::Eq(r(0), r(1), r(2)),
+        "ne" => Instruction::Ne(r(0), r(1), r(2)),
+        "lt" => Instruction::Lt(r(0), r(1), r(2)),
+        "le" => Instruction::Le(r(0), r(1), r(2)),
+        "jmp" => Instruction::Jump(values[0]),
+        "jmpif" => Instruction::JumpIf(r(0), values[1]),
+        "jmpifnot" => Instruction::JumpIfNot(r(0), values[1]),
+        "call" => Instruction::Call(r(0), r(1), r(2), values[3] as u8),
+        "spill" => Instruction::Spill(values[0], r(1)),
+        "chkstep" => Instruction::CheckStep(r(0)),
+        _ => Instruction::Reload(r(0), values[1]),
+    }
+}
+
+/// An instruction as written by its [`Display`] implementation, such as `call r3, r1, r4, 1`.
+pub fn pinstruction<'a>() -> impl Parser<'a, Instruction> {
+    let mnemonic = plexeme(pregex("[a-z]+"));
+    let operand = Operand::ALL.map(|operand| plexeme(poperand(operand)));
+    let comma = plexeme(pchar(','));
+    parser_from_fn(move |input: ContinuationState<'a>| {
+        let (name, mut cont) = mnemonic.parse(input)?;
+        let Some(shape) = operands(name.value) else {
+            return Err(assemble_error("instruction", name.value, input));
+        };
+        let mut values = Vec::new();
+        let mut end = name.start + name.length;
+        for (index, kind) in shape.iter().enumerate() {
+            if index > 0 {
+                cont = comma.parse(cont)?.1;
+            }
+            let (value, next) = operand[*kind as usize].parse(cont)?;
+            end = value.start + value.length;
+            values.push(value.value);
+            cont = next;
+        }
+        let instruction = instruction(name.value, &values);
+        Ok((
+            Token::new(instruction, input.position, end - input.position),
+            cont,
+        ))
+    })
+}
+
+//integers, and floats as written by their debug formatting
+fn pnumber_constant<'a>() -> impl Parser<'a, Value> {
+    let text = pregex("-?([0-9]+(\\.[0-9]+)?(e-?[0-9]+)?|inf|NaN)");
+    parser_from_fn(move |input: ContinuationState<'a>| {
+        let (token, cont) = text.parse(input)?;
+        let float = token.value.contains(['.', 'e', 'i', 'N']);
+        let value = match float {
+            true => token.value.parse().ok().map(Value::Float),
+            false => token.value.parse().ok().map(Value::Int),
+        };
+        match value {
+            Some(value) => Ok((Token::new(value, token.start, token.length), cont)),
+            None => Err(assemble_error("64-bit integer", token.value, input)),
+        }
+    })
+}
+
+/// A constant as written by [`disassemble`]: `()`, `true`, `42`, `1.5`, `'c'`, `"text"` or a
+/// function such as `<fun 0>`.
+pub fn pconstant<'a>() -> impl Parser<'a, Value> {
+    let unit = pstring("()").map(|_| Value::Unit);
+    let ptrue = pkeyword("true").map(|_| Value::Bool(true));
+    let pfalse = pkeyword("false").map(|_| Value::Bool(false));
+    let function = pstring("<fun ")
+        .then(pinteger("[0-9]+", "function", u32::MAX))
+        .right()
+        .then(pchar('>'))
+        .left()
+        .map(Value::Function);
+    let char = pchar_literal().map(|literal| match literal {
+        Literal::Char(c) => Value::Char(c),
+        _ => unreachable!("a char literal"),
+    });
+    let string = pquoted_string().map(|literal| match literal {
+        Literal::String(value) => Value::String(Rc::from(value)),
+        _ => unreachable!("a string literal"),
+    });
+    unit.or(ptrue)
+        .or(pfalse)
+        .or(function)
+        .or(char)
+        .or(string)
+        .or(pnumber_constant())
+}
+
+//a constant declaration, optionally numbered as `const k0 = ...`
+fn pconstant_declaration<'a>() -> impl Parser<'a, (Token<Option<u32>>, Token<Value>)> {
+    let number = plexeme(poperand(Operand::Constant))
+        .then(plexeme(pchar('=')))
+        .left();
+    plexeme(pkeyword(CONST))
+        .then(poptional(number))
+        .right()
+        .then(plexeme(pconstant()))
+}
+
+//a function without its code, as `fun name arity 1 registers 4 slots 0`
+fn pfunction_header<'a>() -> impl Parser<'a, Function> {
+    let field = |name: &'static str, max: usize| {
+        let value = pinteger("[0-9]+", name, max as u32);
+        plexeme(pkeyword(name)).then(plexeme(value)).right()
+    };
+    plexeme(pkeyword(FUN))
+        .then(plexeme(pidentifier()))
+        .right()
+        .then(field("arity", u8::MAX as usize))
+        .then(field("registers", MAX_REGISTERS))
+        .then(field("slots", u32::MAX as usize))
+        .map(|(fields, slots)| {
+            let (fields, registers) = fields.value;
+            let (name, arity) = fields.value;
+            Function {
+                name: name.value,
+                arity: arity.value as u8,
+                registers: registers.value as usize,
+                slots: slots.value as usize,
+                code: Vec::new(),
+                spans: Vec::new(),
+            }
+        })
+}
+
+//checks the number written before a constant or instruction, if there is one
+fn numbered<'a>(number: Token<Option<u32>>, expected: usize) -> Result<(), AssembleError<'a>> {
+    match number.value {
+        Some(found) if found as usize != expected => Err(AssembleError::Misnumbered(
+            expected,
+            found as usize,
+            number.span(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Reads a module written by [`disassemble`], so that bytecode can be written by hand:
+/// ```text
+/// const k0 = 1
+///
+/// fun dec arity 1 registers 2 slots 0
+///     loadk r1, k0
+///     sub r0, r0, r1  ; numbering instructions is optional
+///     ret r0
+/// ```
+/// Constants and instructions may be numbered, as they are by [`disassemble`], in which case
+/// the numbers must count up from zero. Spans are not read back, so the module has none. The
+/// module is not verified.
+pub fn assemble(text: &str) -> Result<Module, AssembleError<'_>> {
+    let constant = pconstant_declaration();
+    let header = pfunction_header();
+    let instruction =
+        poptional(plexeme(pinteger("[0-9]+", "instruction number", u32::MAX))).then(pinstruction());
+    let mut module = Module::default();
+    let (_, mut cont) = pskip().parse(text.into())?;
+    while !cont.remaining.is_empty() {
+        if pkeyword(CONST).parse(cont).is_ok() {
+            let (declaration, next) = constant.parse(cont)?;
+            let (number, value) = declaration.value;
+            numbered(number, module.constants.len())?;
+            module.constants.push(value.value);
+            cont = next;
+        } else if pkeyword(FUN).parse(cont).is_ok() || module.functions.is_empty() {
+            let (function, next) = header.parse(cont)?;
+            module.functions.push(function.value);
+            cont = next;
+        } else {
+            let (numbered_instruction, next) = instruction.parse(cont)?;
+            let (number, instruction) = numbered_instruction.value;
+            let function = module.functions.last_mut().expect("a function");
+            numbered(number, function.code.len())?;
+            function.code.push(instruction.value);
+            cont = next;
+        }
+    }
+    Ok(module)
+}
@@ -0,0 +1,145 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    ops::Range,
+};
+
+use super::*;
+
+/// A register in the window of the running function.
+pub type Register = u8;
+
+/// The most registers a function can use.
+pub const MAX_REGISTERS: usize = Register::MAX as usize + 1;
+
+/// One instruction. Operands name registers in the running function's window, except where
+/// noted. Jump targets are indices into the function's code. The compiler first emits
+/// instructions over virtual registers, hence the register type parameter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction<R = Register> {
+    LoadConst(R, u32), //dst, index into the constant pool
+    LoadBool(R, bool), //dst, value
+    LoadUnit(R),       //dst
+    Move(R, R),        //dst, src
+    Neg(R, R),         //dst, src
+    Not(R, R),         //dst, src
+    Add(R, R, R),      //dst, lhs, rhs
+    Sub(R, R, R),      //dst, lhs, rhs
+    Mul(R, R, R),      //dst, lhs, rhs
+    Div(R, R, R),      //dst, lhs, rhs
+    Rem(R, R, R),      //dst, lhs, rhs
+    Eq(R, R, R),       //dst, lhs, rhs
+    Ne(R, R, R),       //dst, lhs, rhs
+    Lt(R, R, R),       //dst, lhs, rhs
+    Le(R, R, R),       //dst, lhs, rhs
+    Jump(u32),         //target
+    JumpIf(R, u32),    //condition, target
+    JumpIfNot(R, u32), //condition, target
+    Call(R, R, R, u8), //dst, callee, first argument, argument count
+    Return(R),         //src
+    Spill(u32, R),     //slot in the frame, src
+    Reload(R, u32),    //dst, slot in the frame
+    CheckStep(R),      //src, failing if it is a zero range step
+}
+
+impl<R> Instruction<R> {
+    /// The name the instruction is written with.
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Instruction::LoadConst(_, _) => "loadk",
+            Instruction::LoadBool(_, _) => "loadb",
+            Instruction::LoadUnit(_) => "loadu",
+            Instruction::Move(_, _) => "move",
+            Instruction::Neg(_, _) => "neg",
+            Instruction::Not(_, _) => "not",
+            Instruction::Add(_, _, _) => "add",
+            Instruction::Sub(_, _, _) => "sub",
+            Instruction::Mul(_, _, _) => "mul",
+            Instruction::Div(_, _, _) => "div",
+            Instruction::Rem(_, _, _) => "rem",
+            Instruction::Eq(_, _, _) => "eq",
+            Instruction::Ne(_, _, _) => "ne",
+            Instruction::Lt(_, _, _) => "lt",
+            Instruction::Le(_, _, _) => "le",
+            Instruction::Jump(_) => "jmp",
+            Instruction::JumpIf(_, _) => "jmpif",
+            Instruction::JumpIfNot(_, _) => "jmpifnot",
+            Instruction::Call(_, _, _, _) => "call",
+            Instruction::Return(_) => "ret",
+            Instruction::Spill(_, _) => "spill",
+            Instruction::Reload(_, _) => "reload",
+            Instruction::CheckStep(_) => "chkstep",
+        }
+    }
+}
+
+/// Writes the instruction as its mnemonic followed by its operands, such as `add r0, r1, r2`.
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.mnemonic())?;
+        match *self {
+            Instruction::LoadConst(dst, index) => write!(f, " r{}, k{}", dst, index),
+            Instruction::LoadBool(dst, value) => write!(f, " r{}, {}", dst, value),
+            Instruction::LoadUnit(dst) | Instruction::Return(dst) | Instruction::CheckStep(dst) => {
+                write!(f, " r{}", dst)
+            }
+            Instruction::Move(dst, src)
+            | Instruction::Neg(dst, src)
+            | Instruction::Not(dst, src) => {
+                write!(f, " r{}, r{}", dst, src)
+            }
+            Instruction::Add(dst, lhs, rhs)
+            | Instruction::Sub(dst, lhs, rhs)
+            | Instruction::Mul(dst, lhs, rhs)
+            | Instruction::Div(dst, lhs, rhs)
+            | Instruction::Rem(dst, lhs, rhs)
+            | Instruction::Eq(dst, lhs, rhs)
+            | Instruction::Ne(dst, lhs, rhs)
+            | Instruction::Lt(dst, lhs, rhs)
+            | Instruction::Le(dst, lhs, rhs) => write!(f, " r{}, r{}, r{}", dst, lhs, rhs),
+            Instruction::Jump(target) => write!(f, " @{}", target),
+            Instruction::JumpIf(condition, target) | Instruction::JumpIfNot(condition, target) => {
+                write!(f, " r{}, @{}", condition, target)
+            }
+            Instruction::Call(dst, callee, args, count) => {
+                write!(f, " r{}, r{}, r{}, {}", dst, callee, args, count)
+            }
+            Instruction::Spill(slot, src) => write!(f, " s{}, r{}", slot, src),
+            Instruction::Reload(dst, slot) => write!(f, " r{}, s{}", dst, slot),
+        }
+    }
+}
+
+/// The code of one function. Arguments arrive in the first `arity` registers, and running
+/// past the end of the code returns unit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub arity: u8,
+    pub registers: usize, //the size of the function's register window
+    pub slots: usize,     //the number of spill slots
+    pub code: Vec<Instruction>,
+    pub spans: Vec<Range<usize>>, //the source each instruction was compiled from, if any
+}
+
+/// The functions of a program along with the constants they load. Functions refer to each
+/// other through [`Value::Function`] constants.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Module {
+    pub constants: Vec<Value>,
+    pub functions: Vec<Function>,
+}
+
+impl Module {
+    /// The index of the function with the given name.
+    pub fn function(&self, name: &str) -> Option<usize> {
+        self.functions
+            .iter()
+            .position(|function| function.name == name)
+    }
+
+    /// The source an instruction was compiled from.
+    pub fn span(&self, location: Location) -> Option<&Range<usize>> {
+        let function = self.functions.get(location.function)?;
+        function.spans.get(location.pc)
+    }
+}
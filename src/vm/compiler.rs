@@ -0,0 +1,956 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt::{self, Display, Formatter},
+    ops::Range,
+    rc::Rc,
+};
+
+use crate::{
+    parser_combinator::Token,
+    untyped_language::{
+        lowering::{globals, literal_sign, InlineConsts},
+        BinaryOp, Expr, ExprOrStatement, Fun, Program, Statement, UnaryOp, Value as Literal,
+    },
+};
+
+use super::*;
+
+/// The number of registers compiled functions keep values in by default. Values that do
+/// not fit are spilled to slots in the frame.
+pub const DEFAULT_REGISTERS: usize = 64;
+
+//two registers above those values are kept in, for reloading spilled operands
+const SCRATCH: usize = 2;
+
+/// The most arguments a call can pass, so that they fit above the scratch registers.
+pub const MAX_ARGUMENTS: usize = MAX_REGISTERS - SCRATCH;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompileError {
+    Undefined(String, Range<usize>),
+    Unsupported(&'static str, Range<usize>), //what, where
+    TooManyArguments(usize, Range<usize>),
+    RecursiveConst(String, Range<usize>),
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            CompileError::Undefined(name, span) => {
+                write!(f, "{} is not defined at {:?}", name, span)
+            }
+            CompileError::Unsupported(what, span) => {
+                write!(f, "{} cannot be compiled yet at {:?}", what, span)
+            }
+            CompileError::TooManyArguments(count, span) => write!(
+                f,
+                "{} arguments is more than the {} allowed at {:?}",
+                count, MAX_ARGUMENTS, span
+            ),
+            CompileError::RecursiveConst(name, span) => {
+                write!(f, "constant {} refers to itself at {:?}", name, span)
+            }
+        }
+    }
+}
+
+/// Lowers functions to bytecode for [`Vm`], keeping values in at most `registers`
+/// registers of each frame.
+#[derive(Debug, Clone, Copy)]
+pub struct Compiler {
+    pub registers: usize,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self {
+            registers: DEFAULT_REGISTERS,
+        }
+    }
+}
+
+type VReg = u32;
+
+//converts the registers of an instruction, telling `f` whether each is written to
+fn map<R: Copy, S>(instruction: Instruction<R>, mut f: impl FnMut(R, bool) -> S) -> Instruction<S> {
+    use Instruction::*;
+    match instruction {
+        LoadConst(dst, index) => LoadConst(f(dst, true), index),
+        LoadBool(dst, value) => LoadBool(f(dst, true), value),
+        LoadUnit(dst) => LoadUnit(f(dst, true)),
+        Move(dst, src) => Move(f(dst, true), f(src, false)),
+        Neg(dst, src) => Neg(f(dst, true), f(src, false)),
+        Not(dst, src) => Not(f(dst, true), f(src, false)),
+        Add(dst, lhs, rhs) => Add(f(dst, true), f(lhs, false), f(rhs, false)),
+        Sub(dst, lhs, rhs) => Sub(f(dst, true), f(lhs, false), f(rhs, false)),
+        Mul(dst, lhs, rhs) => Mul(f(dst, true), f(lhs, false), f(rhs, false)),
+        Div(dst, lhs, rhs) => Div(f(dst, true), f(lhs, false), f(rhs, false)),
+        Rem(dst, lhs, rhs) => Rem(f(dst, true), f(lhs, false), f(rhs, false)),
+        Eq(dst, lhs, rhs) => Eq(f(dst, true), f(lhs, false), f(rhs, false)),
+        Ne(dst, lhs, rhs) => Ne(f(dst, true), f(lhs, false), f(rhs, false)),
+        Lt(dst, lhs, rhs) => Lt(f(dst, true), f(lhs, false), f(rhs, false)),
+        Le(dst, lhs, rhs) => Le(f(dst, true), f(lhs, false), f(rhs, false)),
+        Jump(target) => Jump(target),
+        JumpIf(condition, target) => JumpIf(f(condition, false), target),
+        JumpIfNot(condition, target) => JumpIfNot(f(condition, false), target),
+        Call(dst, callee, args, count) => {
+            //the arguments are read, but only the first is named
+            let (callee, args) = (f(callee, false), f(args, false));
+            Call(f(dst, true), callee, args, count)
+        }
+        Return(src) => Return(f(src, false)),
+        Spill(slot, src) => Spill(slot, f(src, false)),
+        Reload(dst, slot) => Reload(f(dst, true), slot),
+        CheckStep(src) => CheckStep(f(src, false)),
+    }
+}
+
+//the registers an instruction reads, and the one it writes
+fn operands(instruction: &Instruction<VReg>) -> (Vec<VReg>, Option<VReg>) {
+    let mut uses = Vec::new();
+    let mut def = None;
+    map(*instruction, |register, written| match written {
+        true => def = Some(register),
+        false => uses.push(register),
+    });
+    if let Instruction::Call(_, _, first, count) = instruction {
+        uses.extend(*first + 1..*first + *count as VReg);
+    }
+    (uses, def)
+}
+
+fn target(instruction: &Instruction<VReg>) -> Option<u32> {
+    match instruction {
+        Instruction::Jump(target)
+        | Instruction::JumpIf(_, target)
+        | Instruction::JumpIfNot(_, target) => Some(*target),
+        _ => None,
+    }
+}
+
+//whether evaluating an expression can run statements, which may assign to locals
+fn has_block(expr: &Expr) -> bool {
+    match expr {
+        Expr::If(_, _, _) | Expr::Loop(_, _) => true,
+        Expr::Call(callee, args) => {
+            has_block(&callee.value) || args.iter().any(|arg| has_block(&arg.value))
+        }
+        Expr::Binary(lhs, _, rhs) => has_block(&lhs.value) || has_block(&rhs.value),
+        Expr::Unary(_, value) => has_block(&value.value),
+        _ => false,
+    }
+}
+
+struct Loop<'p> {
+    label: Option<&'p str>,
+    exit: u32,
+    next: u32,            //where continue goes
+    result: Option<VReg>, //for loops that break with a value
+}
+
+//a function over virtual registers, where jumps target labels
+struct Lowering<'p, 'c> {
+    funs: &'c HashMap<&'p str, u32>,
+    consts: &'c HashMap<&'p str, &'p Token<Expr>>,
+    constants: &'c mut Vec<Value>,
+    code: Vec<(Instruction<VReg>, Range<usize>)>,
+    labels: Vec<usize>,           //by label, the instruction it is placed before
+    arguments: HashMap<VReg, u8>, //registers that pass an argument, by position
+    vregs: VReg,
+    bindings: Vec<(&'p str, VReg)>, //innermost last
+    loops: Vec<Loop<'p>>,
+    evaluating: Vec<&'p str>, //constants being inlined, to catch cycles
+}
+
+impl<'p, 'c> InlineConsts<'p> for Lowering<'p, 'c> {
+    type Binding = VReg;
+    type Output = VReg;
+    type Error = CompileError;
+
+    fn definition(&self, name: &str) -> Option<(&'p str, &'p Token<Expr>)> {
+        self.consts
+            .get_key_value(name)
+            .map(|(&name, &value)| (name, value))
+    }
+
+    fn evaluating(&mut self) -> &mut Vec<&'p str> {
+        &mut self.evaluating
+    }
+
+    fn bindings(&mut self) -> &mut Vec<(&'p str, VReg)> {
+        &mut self.bindings
+    }
+
+    fn lower(&mut self, expr: &'p Token<Expr>) -> Result<VReg, CompileError> {
+        self.expr(expr)
+    }
+
+    fn recursive(name: &str, span: &Range<usize>) -> CompileError {
+        CompileError::RecursiveConst(name.to_string(), span.clone())
+    }
+}
+
+impl<'p, 'c> Lowering<'p, 'c> {
+    fn fresh(&mut self) -> VReg {
+        self.vregs += 1;
+        self.vregs - 1
+    }
+
+    fn label(&mut self) -> u32 {
+        self.labels.push(usize::MAX);
+        self.labels.len() as u32 - 1
+    }
+
+    fn place(&mut self, label: u32) {
+        self.labels[label as usize] = self.code.len();
+    }
+
+    fn emit(&mut self, instruction: Instruction<VReg>, span: &Range<usize>) {
+        self.code.push((instruction, span.clone()));
+    }
+
+    fn constant(&mut self, value: Value) -> u32 {
+        let index = self
+            .constants
+            .iter()
+            .position(|constant| match (constant, &value) {
+                //0.0 and -0.0 compare equal but divide differently
+                (Value::Float(lhs), Value::Float(rhs)) => lhs.to_bits() == rhs.to_bits(),
+                _ => *constant == value,
+            });
+        index.unwrap_or_else(|| {
+            self.constants.push(value);
+            self.constants.len() - 1
+        }) as u32
+    }
+
+    fn load(&mut self, value: Value, span: &Range<usize>) -> VReg {
+        let dst = self.fresh();
+        let instruction = match value {
+            Value::Unit => Instruction::LoadUnit(dst),
+            Value::Bool(value) => Instruction::LoadBool(dst, value),
+            value => Instruction::LoadConst(dst, self.constant(value)),
+        };
+        self.emit(instruction, span);
+        dst
+    }
+
+    fn copy(&mut self, src: VReg, span: &Range<usize>) -> VReg {
+        let dst = self.fresh();
+        self.emit(Instruction::Move(dst, src), span);
+        dst
+    }
+
+    fn body(
+        &mut self,
+        body: &'p [Token<ExprOrStatement>],
+        span: &Range<usize>,
+    ) -> Result<VReg, CompileError> {
+        let scope = self.bindings.len();
+        let mut value = None;
+        for item in body {
+            value = match &item.value {
+                ExprOrStatement::Expr(expr) => Some(self.expr_at(expr, &item.span())?),
+                ExprOrStatement::Statement(statement) => {
+                    self.statement(statement, &item.span())?;
+                    None
+                }
+            };
+        }
+        self.bindings.truncate(scope);
+        match value {
+            Some(value) => Ok(value),
+            None => Ok(self.load(Value::Unit, span)),
+        }
+    }
+
+    fn local(&self, name: &str) -> Option<VReg> {
+        let binding = self.bindings.iter().rev().find(|(bound, _)| *bound == name);
+        binding.map(|(_, register)| *register)
+    }
+
+    //a value that later operands cannot change by assigning to the local holding it
+    fn operand(
+        &mut self,
+        expr: &'p Token<Expr>,
+        rest: &[&'p Token<Expr>],
+    ) -> Result<VReg, CompileError> {
+        let value = self.expr(expr)?;
+        let bound = self.bindings.iter().any(|(_, register)| *register == value);
+        match bound && rest.iter().any(|expr| has_block(&expr.value)) {
+            true => Ok(self.copy(value, &expr.span())),
+            false => Ok(value),
+        }
+    }
+
+    fn ident(&mut self, name: &'p str, span: &Range<usize>) -> Result<VReg, CompileError> {
+        if let Some(register) = self.local(name) {
+            return Ok(register);
+        }
+        if let Some(result) = self.inline_const(name, span) {
+            return result;
+        }
+        match self.funs.get(name) {
+            Some(&index) => Ok(self.load(Value::Function(index), span)),
+            None => Err(CompileError::Undefined(name.to_string(), span.clone())),
+        }
+    }
+
+    fn literal(&mut self, literal: &Literal, span: &Range<usize>) -> Result<VReg, CompileError> {
+        let value = match literal {
+            Literal::Number(value) => Value::Int(*value as i64),
+            Literal::I64(value) => Value::Int(*value),
+            Literal::U64(value) => match i64::try_from(*value) {
+                Ok(value) => Value::Int(value),
+                Err(_) => {
+                    return Err(CompileError::Unsupported(
+                        "u64 values above i64",
+                        span.clone(),
+                    ))
+                }
+            },
+            Literal::Float(value) => Value::Float(*value),
+            Literal::Bool(value) => Value::Bool(*value),
+            Literal::Char(value) => Value::Char(*value),
+            Literal::String(value) => Value::String(Rc::from(value.as_str())),
+        };
+        Ok(self.load(value, span))
+    }
+
+    fn call(
+        &mut self,
+        callee: &'p Token<Expr>,
+        args: &'p [Token<Expr>],
+        span: &Range<usize>,
+    ) -> Result<VReg, CompileError> {
+        if args.len() > MAX_ARGUMENTS {
+            return Err(CompileError::TooManyArguments(args.len(), span.clone()));
+        }
+        let rest: Vec<_> = args.iter().collect();
+        let callee = self.operand(callee, &rest)?;
+        let mut values = Vec::new();
+        for (i, arg) in args.iter().enumerate() {
+            values.push(self.operand(arg, &rest[i + 1..])?);
+        }
+        //arguments are moved into place once every one is evaluated, so that no call
+        //between overwrites them
+        let first = self.vregs;
+        for position in 0..args.len().max(1) {
+            let register = self.fresh();
+            self.arguments.insert(register, position as u8);
+        }
+        for (i, value) in values.into_iter().enumerate() {
+            self.emit(Instruction::Move(first + i as VReg, value), span);
+        }
+        let dst = self.fresh();
+        self.emit(
+            Instruction::Call(dst, callee, first, args.len() as u8),
+            span,
+        );
+        Ok(dst)
+    }
+
+    fn binary(
+        &mut self,
+        lhs: &'p Token<Expr>,
+        op: &Token<BinaryOp>,
+        rhs: &'p Token<Expr>,
+        span: &Range<usize>,
+    ) -> Result<VReg, CompileError> {
+        if let BinaryOp::And | BinaryOp::Or = op.value {
+            let result = self.fresh();
+            let end = self.label();
+            let value = self.expr(lhs)?;
+            self.emit(Instruction::Move(result, value), span);
+            let skip = match op.value {
+                BinaryOp::And => Instruction::JumpIfNot(result, end),
+                _ => Instruction::JumpIf(result, end),
+            };
+            self.emit(skip, span);
+            let value = self.expr(rhs)?;
+            self.emit(Instruction::Move(result, value), span);
+            self.place(end);
+            return Ok(result);
+        }
+        let lhs = self.operand(lhs, &[rhs])?;
+        let rhs = self.expr(rhs)?;
+        let dst = self.fresh();
+        let instruction = self.arithmetic(op, dst, lhs, rhs)?;
+        self.emit(instruction, span);
+        Ok(dst)
+    }
+
+    fn arithmetic(
+        &self,
+        op: &Token<BinaryOp>,
+        dst: VReg,
+        lhs: VReg,
+        rhs: VReg,
+    ) -> Result<Instruction<VReg>, CompileError> {
+        Ok(match op.value {
+            BinaryOp::Add => Instruction::Add(dst, lhs, rhs),
+            BinaryOp::Sub => Instruction::Sub(dst, lhs, rhs),
+            BinaryOp::Mul => Instruction::Mul(dst, lhs, rhs),
+            BinaryOp::Div => Instruction::Div(dst, lhs, rhs),
+            BinaryOp::Rem => Instruction::Rem(dst, lhs, rhs),
+            BinaryOp::Eq => Instruction::Eq(dst, lhs, rhs),
+            BinaryOp::Ne => Instruction::Ne(dst, lhs, rhs),
+            BinaryOp::Lt => Instruction::Lt(dst, lhs, rhs),
+            BinaryOp::Le => Instruction::Le(dst, lhs, rhs),
+            BinaryOp::Gt => Instruction::Lt(dst, rhs, lhs),
+            BinaryOp::Ge => Instruction::Le(dst, rhs, lhs),
+            _ => return Err(CompileError::Unsupported("bitwise operators", op.span())),
+        })
+    }
+
+    fn if_else(
+        &mut self,
+        condition: &'p Token<Expr>,
+        then: &'p [Token<ExprOrStatement>],
+        otherwise: &'p Option<Vec<Token<ExprOrStatement>>>,
+        span: &Range<usize>,
+    ) -> Result<VReg, CompileError> {
+        let condition = self.expr(condition)?;
+        let result = self.fresh();
+        let (other, end) = (self.label(), self.label());
+        self.emit(Instruction::JumpIfNot(condition, other), span);
+        let value = self.body(then, span)?;
+        self.emit(Instruction::Move(result, value), span);
+        self.emit(Instruction::Jump(end), span);
+        self.place(other);
+        match otherwise {
+            Some(otherwise) => {
+                let value = self.body(otherwise, span)?;
+                self.emit(Instruction::Move(result, value), span);
+            }
+            None => self.emit(Instruction::LoadUnit(result), span),
+        }
+        self.place(end);
+        Ok(result)
+    }
+
+    //runs the body with break and continue going to the given labels
+    fn looping(
+        &mut self,
+        label: &'p Option<Token<String>>,
+        exit: u32,
+        next: u32,
+        result: Option<VReg>,
+        body: &'p [Token<ExprOrStatement>],
+        span: &Range<usize>,
+    ) -> Result<(), CompileError> {
+        self.loops.push(Loop {
+            label: label.as_ref().map(|label| label.value.as_str()),
+            exit,
+            next,
+            result,
+        });
+        let result = self.body(body, span);
+        self.loops.pop();
+        result.map(|_| ())
+    }
+
+    fn target(
+        &self,
+        label: &Option<Token<String>>,
+        span: &Range<usize>,
+    ) -> Result<&Loop<'p>, CompileError> {
+        let found = match label {
+            Some(label) => self
+                .loops
+                .iter()
+                .rev()
+                .find(|target| target.label == Some(label.value.as_str())),
+            None => self.loops.last(),
+        };
+        match (found, label) {
+            (Some(found), _) => Ok(found),
+            (None, Some(label)) => Err(CompileError::Undefined(
+                format!("'{}", label.value),
+                label.span(),
+            )),
+            (None, None) => Err(CompileError::Unsupported(
+                "break outside a loop",
+                span.clone(),
+            )),
+        }
+    }
+
+    fn for_range(
+        &mut self,
+        label: &'p Option<Token<String>>,
+        name: &'p Token<String>,
+        range: &'p Token<Expr>,
+        body: &'p [Token<ExprOrStatement>],
+        span: &Range<usize>,
+    ) -> Result<(), CompileError> {
+        let Expr::Range(start, end, inclusive, step) = &range.value else {
+            return Err(CompileError::Unsupported(
+                "ranges held in values",
+                range.span(),
+            ));
+        };
+        let range_span = range.span();
+        //the bounds are evaluated once, before the body can change what they refer to
+        let value = self.expr(start)?;
+        let index = self.copy(value, &range_span);
+        let value = self.expr(end)?;
+        let end = self.copy(value, &range_span);
+        let (step, sign) = match step {
+            Some(step) => {
+                let value = self.expr(step)?;
+                let sign = literal_sign(&step.value);
+                //a step that may be zero fails before the first iteration, as in the interpreter
+                if sign.is_none() || sign == Some(Ordering::Equal) {
+                    self.emit(Instruction::CheckStep(value), &step.span());
+                }
+                (self.copy(value, &range_span), sign)
+            }
+            None => (
+                self.load(Value::Int(1), &range_span),
+                Some(Ordering::Greater),
+            ),
+        };
+        let (top, next, exit) = (self.label(), self.label(), self.label());
+        self.place(top);
+        let condition = self.fresh();
+        let compare = |lhs, rhs| match inclusive {
+            true => Instruction::Le(condition, lhs, rhs),
+            false => Instruction::Lt(condition, lhs, rhs),
+        };
+        match sign {
+            Some(Ordering::Greater) => self.emit(compare(index, end), &range_span),
+            Some(Ordering::Less) => self.emit(compare(end, index), &range_span),
+            Some(Ordering::Equal) => {
+                self.emit(Instruction::LoadBool(condition, false), &range_span)
+            }
+            None => {
+                //counts up or down by the sign of the step, which is known not to be zero
+                let zero = self.load(Value::Int(0), &range_span);
+                let (down, test) = (self.label(), self.label());
+                let direction = self.fresh();
+                self.emit(Instruction::Lt(direction, zero, step), &range_span);
+                self.emit(Instruction::JumpIfNot(direction, down), &range_span);
+                self.emit(compare(index, end), &range_span);
+                self.emit(Instruction::Jump(test), &range_span);
+                self.place(down);
+                self.emit(compare(end, index), &range_span);
+                self.place(test);
+            }
+        }
+        self.emit(Instruction::JumpIfNot(condition, exit), &range_span);
+        //the body gets its own copy, so assigning to it does not change the iteration
+        let scope = self.bindings.len();
+        let value = self.copy(index, &name.span());
+        self.bindings.push((&name.value, value));
+        let result = self.looping(label, exit, next, None, body, span);
+        self.bindings.truncate(scope);
+        result?;
+        self.place(next);
+        self.emit(Instruction::Add(index, index, step), &range_span);
+        self.emit(Instruction::Jump(top), &range_span);
+        self.place(exit);
+        Ok(())
+    }
+
+    fn statement(
+        &mut self,
+        statement: &'p Statement,
+        span: &Range<usize>,
+    ) -> Result<(), CompileError> {
+        match statement {
+            Statement::Let(name, _, value, _) => {
+                let value = self.expr(value)?;
+                let local = self.copy(value, span);
+                self.bindings.push((&name.value, local));
+            }
+            Statement::Assign(place, op, value) => {
+                let Expr::Ident(name) = &place.value else {
+                    return Err(CompileError::Unsupported(
+                        "assigning to anything but a local",
+                        place.span(),
+                    ));
+                };
+                let Some(local) = self.local(name) else {
+                    return Err(CompileError::Undefined(name.clone(), place.span()));
+                };
+                let value = self.expr(value)?;
+                let instruction = match op {
+                    Some(op) => self.arithmetic(op, local, local, value)?,
+                    None => Instruction::Move(local, value),
+                };
+                self.emit(instruction, span);
+            }
+            Statement::For(label, name, range, body) => {
+                self.for_range(label, name, range, body, span)?
+            }
+            Statement::ForIn(_, _, _, _) => {
+                return Err(CompileError::Unsupported("for in loops", span.clone()))
+            }
+            Statement::While(label, condition, body) => {
+                let (top, exit) = (self.label(), self.label());
+                self.place(top);
+                let value = self.expr(condition)?;
+                self.emit(Instruction::JumpIfNot(value, exit), &condition.span());
+                self.looping(label, exit, top, None, body, span)?;
+                self.emit(Instruction::Jump(top), span);
+                self.place(exit);
+            }
+            Statement::Break(label, value) => {
+                let target = self.target(label, span)?;
+                let (exit, result) = (target.exit, target.result);
+                if let Some(result) = result {
+                    let value = match value {
+                        Some(value) => self.expr(value)?,
+                        None => self.load(Value::Unit, span),
+                    };
+                    self.emit(Instruction::Move(result, value), span);
+                }
+                self.emit(Instruction::Jump(exit), span);
+            }
+            Statement::Continue(label) => {
+                let next = self.target(label, span)?.next;
+                self.emit(Instruction::Jump(next), span);
+            }
+            Statement::Return(value) => {
+                let value = match value {
+                    Some(value) => self.expr(value)?,
+                    None => self.load(Value::Unit, span),
+                };
+                self.emit(Instruction::Return(value), span);
+            }
+        }
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &'p Token<Expr>) -> Result<VReg, CompileError> {
+        self.expr_at(&expr.value, &expr.span())
+    }
+
+    fn expr_at(&mut self, expr: &'p Expr, span: &Range<usize>) -> Result<VReg, CompileError> {
+        let unsupported = |what| Err(CompileError::Unsupported(what, span.clone()));
+        match expr {
+            Expr::Value(literal) => self.literal(literal, span),
+            Expr::Ident(name) => self.ident(name, span),
+            Expr::Call(callee, args) => self.call(callee, args, span),
+            Expr::Binary(lhs, op, rhs) => self.binary(lhs, op, rhs, span),
+            Expr::Unary(op, value) => {
+                let value = self.expr(value)?;
+                let dst = self.fresh();
+                let instruction = match op.value {
+                    UnaryOp::Neg => Instruction::Neg(dst, value),
+                    UnaryOp::Not => Instruction::Not(dst, value),
+                };
+                self.emit(instruction, span);
+                Ok(dst)
+            }
+            Expr::If(condition, then, otherwise) => self.if_else(condition, then, otherwise, span),
+            Expr::Loop(label, body) => {
+                let result = self.fresh();
+                let (top, exit) = (self.label(), self.label());
+                self.place(top);
+                self.looping(label, exit, top, Some(result), body, span)?;
+                self.emit(Instruction::Jump(top), span);
+                self.place(exit);
+                Ok(result)
+            }
+            Expr::Range(_, _, _, _) => unsupported("ranges outside for loops"),
+            Expr::Struct(_, _) | Expr::Field(_, _) => unsupported("structs"),
+            Expr::Variant(_, _, _) | Expr::Match(_, _) => unsupported("enums"),
+            Expr::Array(_) | Expr::Repeat(_, _) | Expr::Index(_, _) => unsupported("arrays"),
+            Expr::Tuple(_) => unsupported("tuples"),
+            Expr::Closure(_) => unsupported("closures"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Home {
+    Register(Register),
+    Slot(u32),
+}
+
+//the first and last instruction each virtual register is live at, by liveness analysis
+fn intervals(lowering: &Lowering, params: usize) -> Vec<(usize, usize, VReg)> {
+    let code = &lowering.code;
+    let successors = |i: usize| -> Vec<usize> {
+        let jump = target(&code[i].0).map(|label| lowering.labels[label as usize]);
+        match code[i].0 {
+            Instruction::Jump(_) => jump.into_iter().collect(),
+            Instruction::Return(_) => Vec::new(),
+            _ => std::iter::once(i + 1)
+                .chain(jump)
+                .filter(|&next| next < code.len())
+                .collect(),
+        }
+    };
+    let operands: Vec<_> = code
+        .iter()
+        .map(|(instruction, _)| operands(instruction))
+        .collect();
+    let mut live_in = vec![HashSet::new(); code.len()];
+    let mut live_out = vec![HashSet::new(); code.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for i in (0..code.len()).rev() {
+            let out: HashSet<VReg> = successors(i)
+                .into_iter()
+                .flat_map(|next| live_in[next].iter().copied())
+                .collect();
+            let (uses, def) = &operands[i];
+            let mut live: HashSet<VReg> = out
+                .iter()
+                .copied()
+                .filter(|register| Some(*register) != *def)
+                .collect();
+            live.extend(uses);
+            if live != live_in[i] || out != live_out[i] {
+                changed = true;
+                live_in[i] = live;
+                live_out[i] = out;
+            }
+        }
+    }
+    let mut ranges: HashMap<VReg, (usize, usize)> = HashMap::new();
+    let mut extend = |register: VReg, at: usize| {
+        let range = ranges.entry(register).or_insert((at, at));
+        range.0 = range.0.min(at);
+        range.1 = range.1.max(at);
+    };
+    //every parameter is live from the start, so that each is allocated where it arrives
+    for param in 0..params as VReg {
+        extend(param, 0);
+    }
+    for (i, (uses, def)) in operands.iter().enumerate() {
+        for &register in uses
+            .iter()
+            .chain(def)
+            .chain(&live_in[i])
+            .chain(&live_out[i])
+        {
+            extend(register, i);
+        }
+    }
+    let mut intervals: Vec<_> = ranges
+        .into_iter()
+        .filter(|(register, _)| !lowering.arguments.contains_key(register))
+        .map(|(register, (start, end))| (start, end, register))
+        .collect();
+    intervals.sort_by_key(|&(start, _, register)| (start, register));
+    intervals
+}
+
+//linear scan: each interval takes the lowest free register, and when none is free the one
+//that is live longest is spilled
+fn allocate(intervals: &[(usize, usize, VReg)], registers: usize) -> (HashMap<VReg, Home>, u32) {
+    let mut homes = HashMap::new();
+    let mut free: BTreeSet<Register> = (0..registers)
+        .map(|register| register as Register)
+        .collect();
+    let mut active: Vec<(usize, VReg, Register)> = Vec::new(); //end, virtual, physical
+    let mut slots = 0;
+    let mut spill = |homes: &mut HashMap<VReg, Home>, register| {
+        homes.insert(register, Home::Slot(slots));
+        slots += 1;
+    };
+    for &(start, end, register) in intervals {
+        active.retain(|&(active_end, _, physical)| {
+            let expired = active_end < start;
+            if expired {
+                free.insert(physical);
+            }
+            !expired
+        });
+        if let Some(physical) = free.pop_first() {
+            homes.insert(register, Home::Register(physical));
+            active.push((end, register, physical));
+            continue;
+        }
+        let furthest = active
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, (end, _, _))| *end);
+        match furthest {
+            Some((i, &(furthest_end, spilled, physical))) if furthest_end > end => {
+                spill(&mut homes, spilled);
+                homes.insert(register, Home::Register(physical));
+                active[i] = (end, register, physical);
+            }
+            _ => spill(&mut homes, register),
+        }
+    }
+    (homes, slots)
+}
+
+impl Compiler {
+    /// Compiles a function on its own, so it can call only itself.
+    pub fn compile_fun(&self, fun: &Fun) -> Result<Module, CompileError> {
+        let funs = HashMap::from([(fun.name.value.as_str(), 0)]);
+        let mut module = Module::default();
+        let function = self.function(fun, &funs, &HashMap::new(), &mut module.constants)?;
+        module.functions.push(function);
+        Ok(module)
+    }
+
+    /// Compiles every function of a program, in order. Constants are compiled where used.
+    pub fn compile_program(&self, program: &Program) -> Result<Module, CompileError> {
+        let (in_order, consts) = globals(program);
+        let funs = in_order
+            .iter()
+            .enumerate()
+            .map(|(index, fun)| (fun.name.value.as_str(), index as u32))
+            .collect();
+        let mut module = Module::default();
+        for fun in in_order {
+            let function = self.function(fun, &funs, &consts, &mut module.constants)?;
+            module.functions.push(function);
+        }
+        Ok(module)
+    }
+
+    fn function<'p>(
+        &self,
+        fun: &'p Fun,
+        funs: &HashMap<&'p str, u32>,
+        consts: &HashMap<&'p str, &'p Token<Expr>>,
+        constants: &mut Vec<Value>,
+    ) -> Result<Function, CompileError> {
+        let arity = fun.params.len();
+        if arity > MAX_ARGUMENTS {
+            return Err(CompileError::TooManyArguments(arity, fun.name.span()));
+        }
+        let mut lowering = Lowering {
+            funs,
+            consts,
+            constants,
+            code: Vec::new(),
+            labels: Vec::new(),
+            arguments: HashMap::new(),
+            vregs: 0,
+            bindings: Vec::new(),
+            loops: Vec::new(),
+            evaluating: Vec::new(),
+        };
+        for param in &fun.params {
+            let register = lowering.fresh();
+            lowering.bindings.push((&param.value.0.value, register));
+        }
+        let name = fun.name.span();
+        let value = lowering.body(&fun.body, &name)?;
+        lowering.emit(Instruction::Return(value), &name);
+
+        let intervals = intervals(&lowering, arity);
+        let most_arguments =
+            lowering
+                .code
+                .iter()
+                .filter_map(|(instruction, _)| match instruction {
+                    Instruction::Call(_, _, _, count) => Some(*count as usize),
+                    _ => None,
+                });
+        let most_arguments = most_arguments.max().unwrap_or(0);
+        let registers = self.registers.min(MAX_REGISTERS - SCRATCH - most_arguments);
+        let (homes, slots) = allocate(&intervals, registers);
+        let used = homes.values().filter_map(|home| match home {
+            Home::Register(register) => Some(*register as usize + 1),
+            Home::Slot(_) => None,
+        });
+        let scratch = used.max().unwrap_or(0);
+        let outgoing = scratch + SCRATCH;
+        let home = |register: VReg| match lowering.arguments.get(&register) {
+            Some(position) => Home::Register((outgoing + *position as usize) as Register),
+            None => homes[&register],
+        };
+
+        let mut code = Vec::new();
+        let mut spans = Vec::new();
+        //parameters are moved from where they arrive to where they were allocated, spilling
+        //first so that no move overwrites a parameter still to be spilled
+        for (i, param) in fun.params.iter().enumerate() {
+            if let Home::Slot(slot) = home(i as VReg) {
+                code.push(Instruction::Spill(slot, i as Register));
+                spans.push(param.span());
+            }
+        }
+        for (i, param) in fun.params.iter().enumerate() {
+            match home(i as VReg) {
+                Home::Register(register) if register as usize != i => {
+                    code.push(Instruction::Move(register, i as Register));
+                    spans.push(param.span());
+                }
+                _ => {}
+            }
+        }
+        let mut positions = Vec::new();
+        for (instruction, span) in &lowering.code {
+            positions.push(code.len());
+            let (uses, def) = operands(instruction);
+            let mut sources = HashMap::new();
+            let mut reloads = 0;
+            for register in uses {
+                if sources.contains_key(&register) {
+                    continue;
+                }
+                let physical = match home(register) {
+                    Home::Register(physical) => physical,
+                    Home::Slot(slot) => {
+                        let physical = (scratch + reloads) as Register;
+                        reloads += 1;
+                        code.push(Instruction::Reload(physical, slot));
+                        spans.push(span.clone());
+                        physical
+                    }
+                };
+                sources.insert(register, physical);
+            }
+            let (dst, spilled) = match def.map(home) {
+                Some(Home::Slot(slot)) => (scratch as Register, Some(slot)),
+                Some(Home::Register(physical)) => (physical, None),
+                None => (0, None),
+            };
+            let instruction = map(*instruction, |register, written| match written {
+                true => dst,
+                false => sources[&register],
+            });
+            if !matches!(instruction, Instruction::Move(dst, src) if dst == src) {
+                code.push(instruction);
+                spans.push(span.clone());
+            }
+            if let Some(slot) = spilled {
+                code.push(Instruction::Spill(slot, dst));
+                spans.push(span.clone());
+            }
+        }
+        positions.push(code.len());
+        let resolve = |label: u32| positions[lowering.labels[label as usize]] as u32;
+        for instruction in &mut code {
+            *instruction = match *instruction {
+                Instruction::Jump(label) => Instruction::Jump(resolve(label)),
+                Instruction::JumpIf(condition, label) => {
+                    Instruction::JumpIf(condition, resolve(label))
+                }
+                Instruction::JumpIfNot(condition, label) => {
+                    Instruction::JumpIfNot(condition, resolve(label))
+                }
+                instruction => instruction,
+            };
+        }
+
+        let mut window = arity;
+        for instruction in &code {
+            map(*instruction, |register, _| {
+                window = window.max(register as usize + 1)
+            });
+        }
+        Ok(Function {
+            name: fun.name.value.clone(),
+            arity: arity as u8,
+            registers: window,
+            slots: slots as usize,
+            code,
+            spans,
+        })
+    }
+}
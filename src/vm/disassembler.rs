@@ -0,0 +1,87 @@
+use std::fmt::Write;
+
+use crate::untyped_language::Value as Literal;
+
+use super::*;
+
+//a constant written so that it reads back as the same value
+fn constant(value: &Value) -> String {
+    match value {
+        Value::Char(c) => Literal::Char(*c).to_string(),
+        Value::String(value) => Literal::String(value.to_string()).to_string(),
+        value => value.to_string(),
+    }
+}
+
+//a constant as shown in a comment, where functions are given by name
+fn describe(module: &Module, value: &Value) -> String {
+    match value {
+        Value::Function(index) => match module.functions.get(*index as usize) {
+            Some(function) => function.name.clone(),
+            None => constant(value),
+        },
+        value => constant(value),
+    }
+}
+
+/// Writes a module in the textual form read by [`assemble`]: the constant pool, then each
+/// function as a header followed by its numbered instructions. Constants loaded by an
+/// instruction are shown after it, and when the source the module was compiled from is given,
+/// each source line is shown above the first instruction compiled from it. Everything after a
+/// `;` is a comment.
+/// ```text
+/// const k0 = 2
+///
+/// fun half arity 1 registers 3 slots 0
+///     ; 1 | fun half(n: int) -> int { n / 2; }
+///     0  loadk r1, k0  ; 2
+///     1  div r2, r0, r1
+///     2  ret r2
+/// ```
+pub fn disassemble(module: &Module, source: Option<&str>) -> String {
+    let mut out = String::new();
+    for (index, value) in module.constants.iter().enumerate() {
+        write!(out, "const k{} = {}", index, constant(value)).expect("writing to a string");
+        if let Value::Function(_) = value {
+            write!(out, "  ; {}", describe(module, value)).expect("writing to a string");
+        }
+        out.push('\n');
+    }
+    for function in &module.functions {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        writeln!(
+            out,
+            "fun {} arity {} registers {} slots {}",
+            function.name, function.arity, function.registers, function.slots
+        )
+        .expect("writing to a string");
+        let width = function.code.len().saturating_sub(1).to_string().len();
+        let mut line = None;
+        for (pc, instruction) in function.code.iter().enumerate() {
+            //a span from some other source may not fall on a character boundary
+            let before = match (source, function.spans.get(pc)) {
+                (Some(source), Some(span)) => source.get(..span.start.min(source.len())),
+                _ => None,
+            };
+            if let (Some(source), Some(before)) = (source, before) {
+                let number = before.matches('\n').count() + 1;
+                if line != Some(number) {
+                    let text = source.lines().nth(number - 1).unwrap_or_default();
+                    writeln!(out, "    ; {} | {}", number, text.trim())
+                        .expect("writing to a string");
+                    line = Some(number);
+                }
+            }
+            write!(out, "    {:>width$}  {}", pc, instruction).expect("writing to a string");
+            if let Instruction::LoadConst(_, index) = instruction {
+                if let Some(value) = module.constants.get(*index as usize) {
+                    write!(out, "  ; {}", describe(module, value)).expect("writing to a string");
+                }
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
@@ -0,0 +1,451 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    ops::Range,
+    rc::Rc,
+};
+
+use super::*;
+
+/// The first bytes of every module file.
+pub const MAGIC: [u8; 4] = *b"NGLB";
+
+/// The version of the format written, and the only one read.
+pub const VERSION: u16 = 1;
+
+/// Why bytes could not be read as a module. Offsets are of the byte where reading failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    UnexpectedEnd(usize),
+    InvalidTag(&'static str, u8, usize), //what was being read, tag, offset
+    InvalidUtf8(usize),
+    InvalidChar(u32, usize),
+    TrailingBytes(usize),
+    LineTableTooLong(usize), //offset of the run that passed the end of the code
+}
+
+impl Display for FormatError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            FormatError::BadMagic => f.write_str("not a module file"),
+            FormatError::UnsupportedVersion(version) => {
+                write!(
+                    f,
+                    "version {} is not supported, expected {}",
+                    version, VERSION
+                )
+            }
+            FormatError::UnexpectedEnd(offset) => write!(f, "unexpected end at byte {}", offset),
+            FormatError::InvalidTag(what, tag, offset) => {
+                write!(f, "invalid {} tag {} at byte {}", what, tag, offset)
+            }
+            FormatError::InvalidUtf8(offset) => write!(f, "invalid utf-8 at byte {}", offset),
+            FormatError::InvalidChar(value, offset) => {
+                write!(f, "invalid char {:#x} at byte {}", value, offset)
+            }
+            FormatError::TrailingBytes(offset) => write!(f, "unexpected bytes from {}", offset),
+            FormatError::LineTableTooLong(offset) => {
+                write!(f, "line table longer than the code at byte {}", offset)
+            }
+        }
+    }
+}
+
+//constant tags
+const UNIT: u8 = 0;
+const BOOL: u8 = 1;
+const INT: u8 = 2;
+const FLOAT: u8 = 3;
+const CHAR: u8 = 4;
+const STRING: u8 = 5;
+const FUNCTION: u8 = 6;
+
+fn opcode(instruction: &Instruction) -> u8 {
+    match instruction {
+        Instruction::LoadConst(_, _) => 0,
+        Instruction::LoadBool(_, _) => 1,
+        Instruction::LoadUnit(_) => 2,
+        Instruction::Move(_, _) => 3,
+        Instruction::Neg(_, _) => 4,
+        Instruction::Not(_, _) => 5,
+        Instruction::Add(_, _, _) => 6,
+        Instruction::Sub(_, _, _) => 7,
+        Instruction::Mul(_, _, _) => 8,
+        Instruction::Div(_, _, _) => 9,
+        Instruction::Rem(_, _, _) => 10,
+        Instruction::Eq(_, _, _) => 11,
+        Instruction::Ne(_, _, _) => 12,
+        Instruction::Lt(_, _, _) => 13,
+        Instruction::Le(_, _, _) => 14,
+        Instruction::Jump(_) => 15,
+        Instruction::JumpIf(_, _) => 16,
+        Instruction::JumpIfNot(_, _) => 17,
+        Instruction::Call(_, _, _, _) => 18,
+        Instruction::Return(_) => 19,
+        Instruction::Spill(_, _) => 20,
+        Instruction::Reload(_, _) => 21,
+        Instruction::CheckStep(_) => 22,
+    }
+}
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    fn len(&mut self, value: usize) {
+        self.u32(value as u32);
+    }
+
+    fn str(&mut self, value: &str) {
+        self.len(value.len());
+        self.bytes.extend(value.as_bytes());
+    }
+
+    fn constant(&mut self, value: &Value) {
+        match value {
+            Value::Unit => self.u8(UNIT),
+            Value::Bool(value) => {
+                self.u8(BOOL);
+                self.u8(*value as u8);
+            }
+            Value::Int(value) => {
+                self.u8(INT);
+                self.bytes.extend(value.to_le_bytes());
+            }
+            Value::Float(value) => {
+                self.u8(FLOAT);
+                self.bytes.extend(value.to_bits().to_le_bytes());
+            }
+            Value::Char(value) => {
+                self.u8(CHAR);
+                self.u32(*value as u32);
+            }
+            Value::String(value) => {
+                self.u8(STRING);
+                self.str(value);
+            }
+            Value::Function(index) => {
+                self.u8(FUNCTION);
+                self.u32(*index);
+            }
+            Value::Object(object) => panic!("{} is only meaningful to the vm that made it", object),
+        }
+    }
+
+    fn instruction(&mut self, instruction: &Instruction) {
+        self.u8(opcode(instruction));
+        match *instruction {
+            Instruction::LoadConst(dst, index) => {
+                self.u8(dst);
+                self.u32(index);
+            }
+            Instruction::LoadBool(dst, value) => {
+                self.u8(dst);
+                self.u8(value as u8);
+            }
+            Instruction::LoadUnit(register)
+            | Instruction::Return(register)
+            | Instruction::CheckStep(register) => self.u8(register),
+            Instruction::Move(dst, src)
+            | Instruction::Neg(dst, src)
+            | Instruction::Not(dst, src) => {
+                self.u8(dst);
+                self.u8(src);
+            }
+            Instruction::Add(dst, lhs, rhs)
+            | Instruction::Sub(dst, lhs, rhs)
+            | Instruction::Mul(dst, lhs, rhs)
+            | Instruction::Div(dst, lhs, rhs)
+            | Instruction::Rem(dst, lhs, rhs)
+            | Instruction::Eq(dst, lhs, rhs)
+            | Instruction::Ne(dst, lhs, rhs)
+            | Instruction::Lt(dst, lhs, rhs)
+            | Instruction::Le(dst, lhs, rhs) => {
+                self.u8(dst);
+                self.u8(lhs);
+                self.u8(rhs);
+            }
+            Instruction::Jump(target) => self.u32(target),
+            Instruction::JumpIf(register, target) | Instruction::JumpIfNot(register, target) => {
+                self.u8(register);
+                self.u32(target);
+            }
+            Instruction::Call(dst, callee, args, count) => {
+                self.u8(dst);
+                self.u8(callee);
+                self.u8(args);
+                self.u8(count);
+            }
+            Instruction::Spill(slot, register) | Instruction::Reload(register, slot) => {
+                self.u32(slot);
+                self.u8(register);
+            }
+        }
+    }
+}
+
+//runs of instructions compiled from the same source, as (count, start, end)
+fn line_table(spans: &[Range<usize>]) -> Vec<(usize, &Range<usize>)> {
+    let mut runs: Vec<(usize, &Range<usize>)> = Vec::new();
+    for span in spans {
+        match runs.last_mut() {
+            Some((count, last)) if *last == span => *count += 1,
+            _ => runs.push((1, span)),
+        }
+    }
+    runs
+}
+
+/// Writes a module as a header of [`MAGIC`] and [`VERSION`], then the constant pool, a table
+/// of functions, the code of every function and a table of the source each instruction was
+/// compiled from. Numbers are little endian, and lengths are `u32`s. Panics if a constant
+/// refers to a heap object.
+pub fn write_module(module: &Module) -> Vec<u8> {
+    let mut writer = Writer::default();
+    writer.bytes.extend(MAGIC);
+    writer.u16(VERSION);
+    writer.len(module.constants.len());
+    for constant in &module.constants {
+        writer.constant(constant);
+    }
+    let tables: Vec<_> = module
+        .functions
+        .iter()
+        .map(|function| line_table(&function.spans))
+        .collect();
+    writer.len(module.functions.len());
+    for (function, table) in module.functions.iter().zip(&tables) {
+        writer.str(&function.name);
+        writer.u8(function.arity);
+        writer.u16(function.registers as u16);
+        writer.len(function.slots);
+        writer.len(function.code.len());
+        writer.len(table.len());
+    }
+    for function in &module.functions {
+        for instruction in &function.code {
+            writer.instruction(instruction);
+        }
+    }
+    for table in &tables {
+        for (count, span) in table {
+            writer.len(*count);
+            writer.len(span.start);
+            writer.len(span.end);
+        }
+    }
+    writer.bytes
+}
+
+struct Reader<'b> {
+    bytes: &'b [u8],
+    offset: usize,
+}
+
+impl<'b> Reader<'b> {
+    fn take(&mut self, count: usize) -> Result<&'b [u8], FormatError> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset + count)
+            .ok_or(FormatError::UnexpectedEnd(self.bytes.len()))?;
+        self.offset += count;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], FormatError> {
+        Ok(self.take(N)?.try_into().expect("N bytes"))
+    }
+
+    fn u8(&mut self) -> Result<u8, FormatError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, FormatError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(FormatError::InvalidTag("bool", tag, self.offset - 1)),
+        }
+    }
+
+    fn u16(&mut self) -> Result<u16, FormatError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, FormatError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn len(&mut self) -> Result<usize, FormatError> {
+        Ok(self.u32()? as usize)
+    }
+
+    fn string(&mut self) -> Result<String, FormatError> {
+        let len = self.len()?;
+        let offset = self.offset;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| FormatError::InvalidUtf8(offset))
+    }
+
+    fn constant(&mut self) -> Result<Value, FormatError> {
+        let offset = self.offset;
+        Ok(match self.u8()? {
+            UNIT => Value::Unit,
+            BOOL => Value::Bool(self.bool()?),
+            INT => Value::Int(i64::from_le_bytes(self.array()?)),
+            FLOAT => Value::Float(f64::from_bits(u64::from_le_bytes(self.array()?))),
+            CHAR => {
+                let value = self.u32()?;
+                let value =
+                    char::from_u32(value).ok_or(FormatError::InvalidChar(value, offset + 1))?;
+                Value::Char(value)
+            }
+            STRING => Value::String(Rc::from(self.string()?)),
+            FUNCTION => Value::Function(self.u32()?),
+            tag => return Err(FormatError::InvalidTag("constant", tag, offset)),
+        })
+    }
+
+    fn instruction(&mut self) -> Result<Instruction, FormatError> {
+        let offset = self.offset;
+        let opcode = self.u8()?;
+        let binary = |reader: &mut Self| -> Result<_, FormatError> {
+            Ok((reader.u8()?, reader.u8()?, reader.u8()?))
+        };
+        Ok(match opcode {
+            0 => Instruction::LoadConst(self.u8()?, self.u32()?),
+            1 => Instruction::LoadBool(self.u8()?, self.bool()?),
+            2 => Instruction::LoadUnit(self.u8()?),
+            3 => Instruction::Move(self.u8()?, self.u8()?),
+            4 => Instruction::Neg(self.u8()?, self.u8()?),
+            5 => Instruction::Not(self.u8()?, self.u8()?),
+            6..=14 => {
+                let (dst, lhs, rhs) = binary(self)?;
+                match opcode {
+                    6 => Instruction::Add(dst, lhs, rhs),
+                    7 => Instruction::Sub(dst, lhs, rhs),
+                    8 => Instruction::Mul(dst, lhs, rhs),
+                    9 => Instruction::Div(dst, lhs, rhs),
+                    10 => Instruction::Rem(dst, lhs, rhs),
+                    11 => Instruction::Eq(dst, lhs, rhs),
+                    12 => Instruction::Ne(dst, lhs, rhs),
+                    13 => Instruction::Lt(dst, lhs, rhs),
+                    _ => Instruction::Le(dst, lhs, rhs),
+                }
+            }
+            15 => Instruction::Jump(self.u32()?),
+            16 => Instruction::JumpIf(self.u8()?, self.u32()?),
+            17 => Instruction::JumpIfNot(self.u8()?, self.u32()?),
+            18 => Instruction::Call(self.u8()?, self.u8()?, self.u8()?, self.u8()?),
+            19 => Instruction::Return(self.u8()?),
+            20 => Instruction::Spill(self.u32()?, self.u8()?),
+            21 => {
+                let slot = self.u32()?;
+                Instruction::Reload(self.u8()?, slot)
+            }
+            22 => Instruction::CheckStep(self.u8()?),
+            opcode => return Err(FormatError::InvalidTag("opcode", opcode, offset)),
+        })
+    }
+}
+
+/// Reads a module written by [`write_module`]. The module is not verified.
+pub fn read_module(bytes: &[u8]) -> Result<Module, FormatError> {
+    let mut reader = Reader { bytes, offset: 0 };
+    if reader.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+        return Err(FormatError::BadMagic);
+    }
+    let version = reader.u16()?;
+    if version != VERSION {
+        return Err(FormatError::UnsupportedVersion(version));
+    }
+    let mut module = Module::default();
+    for _ in 0..reader.len()? {
+        module.constants.push(reader.constant()?);
+    }
+    let mut lengths = Vec::new(); //of code and line table, by function
+    for _ in 0..reader.len()? {
+        module.functions.push(Function {
+            name: reader.string()?,
+            arity: reader.u8()?,
+            registers: reader.u16()? as usize,
+            slots: reader.len()?,
+            code: Vec::new(),
+            spans: Vec::new(),
+        });
+        lengths.push((reader.len()?, reader.len()?));
+    }
+    for (function, (code, _)) in module.functions.iter_mut().zip(&lengths) {
+        for _ in 0..*code {
+            function.code.push(reader.instruction()?);
+        }
+    }
+    for (function, (code, table)) in module.functions.iter_mut().zip(&lengths) {
+        let mut covered = 0;
+        for _ in 0..*table {
+            let offset = reader.offset;
+            let count = reader.len()?;
+            let span = reader.len()?..reader.len()?;
+            //checked before expanding, as the counts are not to be trusted
+            covered += count;
+            if covered > *code {
+                return Err(FormatError::LineTableTooLong(offset));
+            }
+            function.spans.extend(std::iter::repeat_n(span, count));
+        }
+    }
+    match reader.offset == bytes.len() {
+        true => Ok(module),
+        false => Err(FormatError::TrailingBytes(reader.offset)),
+    }
+}
+
+/// Why bytes could not be loaded as a module.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadError {
+    Format(FormatError),
+    Verify(VerifyError),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            LoadError::Format(error) => write!(f, "{}", error),
+            LoadError::Verify(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl From<FormatError> for LoadError {
+    fn from(error: FormatError) -> Self {
+        LoadError::Format(error)
+    }
+}
+
+impl From<VerifyError> for LoadError {
+    fn from(error: VerifyError) -> Self {
+        LoadError::Verify(error)
+    }
+}
+
+/// Reads a module and verifies it, so it is safe to run.
+pub fn load_module(bytes: &[u8]) -> Result<Module, LoadError> {
+    let module = read_module(bytes)?;
+    verify_module(&module)?;
+    Ok(module)
+}
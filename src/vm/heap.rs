@@ -0,0 +1,255 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    mem::size_of,
+};
+
+use super::*;
+
+/// The most bytes the heap holds by default.
+pub const DEFAULT_HEAP_SIZE: usize = 64 << 20;
+
+/// The bytes allocated by default before the first collection.
+pub const DEFAULT_GC_THRESHOLD: usize = 1 << 20;
+
+/// A reference to an object on a [`Heap`], valid until a collection that cannot reach it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjectRef(u32);
+
+impl ObjectRef {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl Display for ObjectRef {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "<object {}>", self.0)
+    }
+}
+
+/// A value that lives on the heap. Only strings made at run time are allocated for now, but
+/// objects that hold other values are traced through [`Object::values`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Object {
+    String(String),
+}
+
+impl Object {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::String(_) => "string",
+        }
+    }
+
+    /// The bytes the object is counted as taking up.
+    pub fn size(&self) -> usize {
+        let payload = match self {
+            Object::String(value) => value.len(),
+        };
+        size_of::<Object>() + payload
+    }
+
+    /// The values the object holds.
+    pub fn values(&self) -> Vec<&Value> {
+        match self {
+            Object::String(_) => Vec::new(),
+        }
+    }
+}
+
+fn references<'v>(values: impl IntoIterator<Item = &'v Value>) -> Vec<ObjectRef> {
+    let references = values.into_iter().filter_map(|value| match value {
+        Value::Object(object) => Some(*object),
+        _ => None,
+    });
+    references.collect()
+}
+
+/// How big the heap may grow and when it is collected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapConfig {
+    pub max_bytes: usize, //allocating past this fails, after a collection
+    pub threshold: usize, //bytes allocated before the first collection
+    pub stress: bool,     //collect before every allocation, to find missing roots
+}
+
+impl Default for HeapConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes: DEFAULT_HEAP_SIZE,
+            threshold: DEFAULT_GC_THRESHOLD,
+            stress: false,
+        }
+    }
+}
+
+/// Counts kept by the collector since the heap was made.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GcStats {
+    pub collections: usize,
+    pub allocations: usize, //objects allocated
+    pub freed: usize,       //objects collected
+    pub live_objects: usize,
+    pub live_bytes: usize,
+    pub peak_bytes: usize, //the most bytes live at once
+}
+
+impl Display for GcStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} collections, {} allocated, {} freed, {} live in {} bytes, peak {} bytes",
+            self.collections,
+            self.allocations,
+            self.freed,
+            self.live_objects,
+            self.live_bytes,
+            self.peak_bytes
+        )
+    }
+}
+
+/// Objects allocated by a running program, reclaimed by a mark-sweep collector. The heap does
+/// not know what refers to its objects, so the caller gives the roots of every allocation:
+/// any object they do not lead to may be collected before the new one is made. Freed places
+/// are reused, so a reference kept anywhere but the roots can come to name another object.
+pub struct Heap {
+    objects: Vec<Option<Object>>,
+    marked: Vec<bool>,
+    free: Vec<u32>, //places of collected objects
+    threshold: usize,
+    config: HeapConfig,
+    stats: GcStats,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new(HeapConfig::default())
+    }
+}
+
+impl Heap {
+    pub fn new(config: HeapConfig) -> Self {
+        Self {
+            objects: Vec::new(),
+            marked: Vec::new(),
+            free: Vec::new(),
+            threshold: config.threshold,
+            config,
+            stats: GcStats::default(),
+        }
+    }
+
+    pub fn config(&self) -> HeapConfig {
+        self.config
+    }
+
+    pub fn stats(&self) -> GcStats {
+        self.stats
+    }
+
+    /// The object a reference names. Panics if it has been collected.
+    pub fn get(&self, object: ObjectRef) -> &Object {
+        let found = self.objects.get(object.index());
+        found.and_then(Option::as_ref).expect("a live object")
+    }
+
+    /// The object a reference names, to change in place. Panics if it has been collected.
+    pub fn get_mut(&mut self, object: ObjectRef) -> &mut Object {
+        let found = self.objects.get_mut(object.index());
+        found.and_then(Option::as_mut).expect("a live object")
+    }
+
+    /// Puts an object on the heap, first collecting everything the roots and the object
+    /// itself do not lead to if the heap would grow past its threshold or its size.
+    pub fn allocate<'v>(
+        &mut self,
+        object: Object,
+        roots: impl IntoIterator<Item = &'v Value>,
+    ) -> Result<ObjectRef, VmErrorKind> {
+        let size = object.size();
+        //always collecting before running out
+        let limit = self.threshold.min(self.config.max_bytes);
+        if self.config.stress || self.stats.live_bytes + size > limit {
+            let mut pending = references(roots);
+            pending.extend(references(object.values()));
+            self.sweep(pending);
+        }
+        if self.stats.live_bytes + size > self.config.max_bytes {
+            return Err(VmErrorKind::OutOfMemory(size));
+        }
+        self.stats.allocations += 1;
+        self.stats.live_objects += 1;
+        self.stats.live_bytes += size;
+        self.stats.peak_bytes = self.stats.peak_bytes.max(self.stats.live_bytes);
+        match self.free.pop() {
+            Some(index) => {
+                self.objects[index as usize] = Some(object);
+                Ok(ObjectRef(index))
+            }
+            None => {
+                self.objects.push(Some(object));
+                self.marked.push(false);
+                Ok(ObjectRef(self.objects.len() as u32 - 1))
+            }
+        }
+    }
+
+    /// Frees every object the roots do not lead to, returning how many there were.
+    pub fn collect<'v>(&mut self, roots: impl IntoIterator<Item = &'v Value>) -> usize {
+        self.sweep(references(roots))
+    }
+
+    //marks everything the pending objects lead to, then frees the rest
+    fn sweep(&mut self, mut pending: Vec<ObjectRef>) -> usize {
+        while let Some(object) = pending.pop() {
+            if std::mem::replace(&mut self.marked[object.index()], true) {
+                continue;
+            }
+            pending.extend(references(self.get(object).values()));
+        }
+        let mut freed = 0;
+        for (index, object) in self.objects.iter_mut().enumerate() {
+            let marked = std::mem::take(&mut self.marked[index]);
+            if let (Some(found), false) = (object.as_ref(), marked) {
+                self.stats.live_bytes -= found.size();
+                *object = None;
+                self.free.push(index as u32);
+                freed += 1;
+            }
+        }
+        self.stats.collections += 1;
+        self.stats.freed += freed;
+        self.stats.live_objects -= freed;
+        //collect again once the live objects have doubled
+        self.threshold = self.config.threshold.max(self.stats.live_bytes * 2);
+        freed
+    }
+
+    /// The type of a value, looking through references.
+    pub fn type_name(&self, value: &Value) -> &'static str {
+        match value {
+            Value::Object(object) => self.get(*object).type_name(),
+            value => value.type_name(),
+        }
+    }
+
+    /// The text of a string, whether it is on the heap or not.
+    pub fn string<'a>(&'a self, value: &'a Value) -> Option<&'a str> {
+        match value {
+            Value::String(value) => Some(value),
+            Value::Object(object) => match self.get(*object) {
+                Object::String(value) => Some(value),
+            },
+            _ => None,
+        }
+    }
+
+    /// Whether two values are equal, comparing strings by their text wherever they live.
+    pub fn equal(&self, lhs: &Value, rhs: &Value) -> bool {
+        match (self.string(lhs), self.string(rhs)) {
+            (Some(lhs), Some(rhs)) => lhs == rhs,
+            _ => lhs == rhs,
+        }
+    }
+}
@@ -0,0 +1,413 @@
+use std::{
+    cmp::Ordering,
+    fmt::{self, Display, Formatter},
+    rc::Rc,
+};
+
+use crate::untyped_language::BinaryOp;
+
+use super::*;
+
+/// The number of nested calls allowed by default.
+pub const MAX_FRAMES: usize = 256;
+
+/// What went wrong while running bytecode.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VmErrorKind {
+    InvalidOperand(&'static str, &'static str), //operator, operand type
+    Mismatched(&'static str, &'static str),     //lhs type, rhs type
+    DivisionByZero,
+    Overflow(&'static str), //operator
+    NotCallable(&'static str),
+    ArityMismatch(usize, usize), //expected, found
+    StackOverflow,
+    UnknownFunction(usize),
+    OutOfMemory(usize), //bytes asked for
+    ZeroStep,
+}
+
+impl Display for VmErrorKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            VmErrorKind::InvalidOperand(op, type_) => write!(f, "cannot apply {} to {}", op, type_),
+            VmErrorKind::Mismatched(lhs, rhs) => write!(f, "cannot combine {} with {}", lhs, rhs),
+            VmErrorKind::DivisionByZero => f.write_str("division by zero"),
+            VmErrorKind::Overflow(op) => write!(f, "{} overflowed", op),
+            VmErrorKind::NotCallable(type_) => write!(f, "cannot call a {}", type_),
+            VmErrorKind::ArityMismatch(expected, found) => {
+                write!(f, "expected {} arguments but got {}", expected, found)
+            }
+            VmErrorKind::StackOverflow => f.write_str("too many nested calls"),
+            VmErrorKind::UnknownFunction(index) => write!(f, "no function {}", index),
+            VmErrorKind::OutOfMemory(size) => write!(f, "out of memory allocating {} bytes", size),
+            VmErrorKind::ZeroStep => f.write_str("range step cannot be zero"),
+        }
+    }
+}
+
+/// An instruction in a module, as a function index and an index into its code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub function: usize,
+    pub pc: usize,
+}
+
+/// An error along with the instruction that raised it, followed by the call instruction of
+/// each frame it happened in, innermost first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VmError {
+    pub kind: VmErrorKind,
+    pub trace: Vec<Location>,
+}
+
+impl Display for VmError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        for location in &self.trace {
+            write!(f, "\n    at {}:{}", location.function, location.pc)?;
+        }
+        Ok(())
+    }
+}
+
+//a running function, whose registers start at `base` in the register file
+struct Frame {
+    function: usize,
+    pc: usize,
+    base: usize,
+    slots: usize, //where the function's spill slots start
+    dst: usize,   //where the caller wants the result, in the register file
+}
+
+/// Runs the functions of a module. Every frame is a window onto one register file: a call
+/// starts the callee's window at its first argument, so arguments are passed without being
+/// copied, and a call may overwrite any register from its first argument up. Spill slots
+/// are private to each frame. Strings made while running live on a [`Heap`], whose roots are
+/// the register windows of the running frames and their spill slots.
+pub struct Vm<'m> {
+    module: &'m Module,
+    registers: Vec<Value>,
+    slots: Vec<Value>,
+    frames: Vec<Frame>,
+    heap: Heap,
+    pub max_frames: usize,
+}
+
+fn arithmetic(op: BinaryOp, lhs: &Value, rhs: &Value, heap: &Heap) -> Result<Value, VmErrorKind> {
+    match (lhs, rhs) {
+        (Value::Int(lhs), Value::Int(rhs)) => {
+            if matches!(op, BinaryOp::Div | BinaryOp::Rem) && *rhs == 0 {
+                return Err(VmErrorKind::DivisionByZero);
+            }
+            let result = match op {
+                BinaryOp::Add => lhs.checked_add(*rhs),
+                BinaryOp::Sub => lhs.checked_sub(*rhs),
+                BinaryOp::Mul => lhs.checked_mul(*rhs),
+                BinaryOp::Div => lhs.checked_div(*rhs),
+                _ => lhs.checked_rem(*rhs),
+            };
+            result
+                .map(Value::Int)
+                .ok_or(VmErrorKind::Overflow(op.symbol()))
+        }
+        (Value::Float(lhs), Value::Float(rhs)) => Ok(Value::Float(match op {
+            BinaryOp::Add => lhs + rhs,
+            BinaryOp::Sub => lhs - rhs,
+            BinaryOp::Mul => lhs * rhs,
+            BinaryOp::Div => lhs / rhs,
+            _ => lhs % rhs,
+        })),
+        (lhs, rhs) => Err(invalid(op, lhs, rhs, heap)),
+    }
+}
+
+fn compare(op: BinaryOp, lhs: &Value, rhs: &Value, heap: &Heap) -> Result<Value, VmErrorKind> {
+    let ordering = match (lhs, rhs) {
+        (Value::Int(lhs), Value::Int(rhs)) => Some(lhs.cmp(rhs)),
+        (Value::Float(lhs), Value::Float(rhs)) => lhs.partial_cmp(rhs),
+        (Value::Char(lhs), Value::Char(rhs)) => Some(lhs.cmp(rhs)),
+        (lhs, rhs) => match (heap.string(lhs), heap.string(rhs)) {
+            (Some(lhs), Some(rhs)) => Some(lhs.cmp(rhs)),
+            _ => return Err(invalid(op, lhs, rhs, heap)),
+        },
+    };
+    //comparisons with NaN are false
+    Ok(Value::Bool(match op {
+        BinaryOp::Lt => ordering == Some(Ordering::Less),
+        _ => ordering.is_some_and(Ordering::is_le),
+    }))
+}
+
+//operands of different types are reported as a mismatch, otherwise as the wrong type
+fn invalid(op: BinaryOp, lhs: &Value, rhs: &Value, heap: &Heap) -> VmErrorKind {
+    let (lhs, rhs) = (heap.type_name(lhs), heap.type_name(rhs));
+    match lhs == rhs {
+        true => VmErrorKind::InvalidOperand(op.symbol(), lhs),
+        false => VmErrorKind::Mismatched(lhs, rhs),
+    }
+}
+
+impl<'m> Vm<'m> {
+    pub fn new(module: &'m Module) -> Self {
+        Self::with_heap(module, HeapConfig::default())
+    }
+
+    pub fn with_heap(module: &'m Module, config: HeapConfig) -> Self {
+        Self {
+            module,
+            registers: Vec::new(),
+            slots: Vec::new(),
+            frames: Vec::new(),
+            heap: Heap::new(config),
+            max_frames: MAX_FRAMES,
+        }
+    }
+
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+
+    /// Calls the function at `function` in the module and runs until it returns. A string
+    /// made while running is returned as a [`Value::String`], and any other object as a
+    /// reference into the heap that stays valid until the next call or collection.
+    pub fn call(&mut self, function: usize, args: Vec<Value>) -> Result<Value, VmError> {
+        self.registers.clear();
+        self.slots.clear();
+        self.frames.clear();
+        let count = args.len();
+        self.registers.extend(args);
+        self.enter(function, 0, count, 0).map_err(|kind| VmError {
+            kind,
+            trace: Vec::new(),
+        })?;
+        let value = self.run()?;
+        match value {
+            Value::Object(object) => match self.heap.get(object) {
+                Object::String(value) => Ok(Value::String(Rc::from(value.as_str()))),
+            },
+            value => Ok(value),
+        }
+    }
+
+    //only the windows of running frames can be read before being written again, so the
+    //registers above them are dropped rather than scanned as roots
+    fn drop_dead_registers(&mut self) {
+        let functions = &self.module.functions;
+        let windows = self
+            .frames
+            .iter()
+            .map(|frame| frame.base + functions[frame.function].registers);
+        self.registers.truncate(windows.max().unwrap_or(0));
+    }
+
+    fn allocate(&mut self, object: Object) -> Result<Value, VmErrorKind> {
+        self.drop_dead_registers();
+        let roots = self.registers.iter().chain(&self.slots);
+        Ok(Value::Object(self.heap.allocate(object, roots)?))
+    }
+
+    /// Frees every object the running frames cannot reach, returning how many there were.
+    pub fn collect(&mut self) -> usize {
+        self.drop_dead_registers();
+        self.heap.collect(self.registers.iter().chain(&self.slots))
+    }
+
+    fn enter(
+        &mut self,
+        function: usize,
+        base: usize,
+        count: usize,
+        dst: usize,
+    ) -> Result<(), VmErrorKind> {
+        let callee = self
+            .module
+            .functions
+            .get(function)
+            .ok_or(VmErrorKind::UnknownFunction(function))?;
+        if callee.arity as usize != count {
+            return Err(VmErrorKind::ArityMismatch(callee.arity as usize, count));
+        }
+        if self.frames.len() >= self.max_frames {
+            return Err(VmErrorKind::StackOverflow);
+        }
+        let top = base + callee.registers;
+        if self.registers.len() < top {
+            self.registers.resize(top, Value::Unit);
+        }
+        let slots = self.slots.len();
+        self.slots.resize(slots + callee.slots, Value::Unit);
+        self.frames.push(Frame {
+            function,
+            pc: 0,
+            base,
+            slots,
+            dst,
+        });
+        Ok(())
+    }
+
+    //every frame has moved past the instruction it is running
+    fn error(&self, kind: VmErrorKind) -> VmError {
+        let trace = self.frames.iter().rev().map(|frame| Location {
+            function: frame.function,
+            pc: frame.pc - 1,
+        });
+        VmError {
+            kind,
+            trace: trace.collect(),
+        }
+    }
+
+    fn run(&mut self) -> Result<Value, VmError> {
+        loop {
+            let frame = self.frames.last_mut().expect("a running function");
+            let code = &self.module.functions[frame.function].code;
+            let instruction = code.get(frame.pc).copied();
+            frame.pc += 1;
+            let (base, slots) = (frame.base, frame.slots);
+            let result = match instruction {
+                Some(instruction) => self.step(instruction, base, slots),
+                //running past the end of a function returns unit
+                None => Ok(self.leave(Value::Unit)),
+            };
+            match result {
+                Ok(Some(value)) => return Ok(value),
+                Ok(None) => {}
+                Err(kind) => return Err(self.error(kind)),
+            }
+        }
+    }
+
+    //pops the running frame, giving the value if it was the outermost
+    fn leave(&mut self, value: Value) -> Option<Value> {
+        let frame = self.frames.pop().expect("a running function");
+        self.slots.truncate(frame.slots);
+        match self.frames.is_empty() {
+            true => Some(value),
+            false => {
+                self.registers[frame.dst] = value;
+                None
+            }
+        }
+    }
+
+    fn jump(&mut self, target: u32) {
+        self.frames.last_mut().expect("a running function").pc = target as usize;
+    }
+
+    fn step(
+        &mut self,
+        instruction: Instruction,
+        base: usize,
+        slots: usize,
+    ) -> Result<Option<Value>, VmErrorKind> {
+        let at = |register: Register| base + register as usize;
+        let registers = &mut self.registers;
+        match instruction {
+            Instruction::LoadConst(dst, index) => {
+                registers[at(dst)] = self.module.constants[index as usize].clone();
+            }
+            Instruction::LoadBool(dst, value) => registers[at(dst)] = Value::Bool(value),
+            Instruction::LoadUnit(dst) => registers[at(dst)] = Value::Unit,
+            Instruction::Move(dst, src) => registers[at(dst)] = registers[at(src)].clone(),
+            Instruction::Neg(dst, src) => {
+                registers[at(dst)] = match &registers[at(src)] {
+                    Value::Int(value) => value
+                        .checked_neg()
+                        .map(Value::Int)
+                        .ok_or(VmErrorKind::Overflow("-"))?,
+                    Value::Float(value) => Value::Float(-value),
+                    value => {
+                        let type_ = self.heap.type_name(value);
+                        return Err(VmErrorKind::InvalidOperand("-", type_));
+                    }
+                }
+            }
+            Instruction::Not(dst, src) => {
+                registers[at(dst)] = match &registers[at(src)] {
+                    Value::Bool(value) => Value::Bool(!value),
+                    Value::Int(value) => Value::Int(!value),
+                    value => {
+                        let type_ = self.heap.type_name(value);
+                        return Err(VmErrorKind::InvalidOperand("!", type_));
+                    }
+                }
+            }
+            Instruction::Add(dst, lhs, rhs)
+            | Instruction::Sub(dst, lhs, rhs)
+            | Instruction::Mul(dst, lhs, rhs)
+            | Instruction::Div(dst, lhs, rhs)
+            | Instruction::Rem(dst, lhs, rhs) => {
+                let op = match instruction {
+                    Instruction::Add(_, _, _) => BinaryOp::Add,
+                    Instruction::Sub(_, _, _) => BinaryOp::Sub,
+                    Instruction::Mul(_, _, _) => BinaryOp::Mul,
+                    Instruction::Div(_, _, _) => BinaryOp::Div,
+                    _ => BinaryOp::Rem,
+                };
+                let (lhs, rhs) = (&registers[at(lhs)], &registers[at(rhs)]);
+                let value = match (op, self.heap.string(lhs), self.heap.string(rhs)) {
+                    (BinaryOp::Add, Some(lhs), Some(rhs)) => {
+                        let joined = format!("{}{}", lhs, rhs);
+                        self.allocate(Object::String(joined))?
+                    }
+                    _ => arithmetic(op, lhs, rhs, &self.heap)?,
+                };
+                self.registers[at(dst)] = value;
+            }
+            Instruction::Eq(dst, lhs, rhs) => {
+                let equal = self.heap.equal(&registers[at(lhs)], &registers[at(rhs)]);
+                registers[at(dst)] = Value::Bool(equal);
+            }
+            Instruction::Ne(dst, lhs, rhs) => {
+                let equal = self.heap.equal(&registers[at(lhs)], &registers[at(rhs)]);
+                registers[at(dst)] = Value::Bool(!equal);
+            }
+            Instruction::Lt(dst, lhs, rhs) => {
+                let (lhs, rhs) = (&registers[at(lhs)], &registers[at(rhs)]);
+                registers[at(dst)] = compare(BinaryOp::Lt, lhs, rhs, &self.heap)?;
+            }
+            Instruction::Le(dst, lhs, rhs) => {
+                let (lhs, rhs) = (&registers[at(lhs)], &registers[at(rhs)]);
+                registers[at(dst)] = compare(BinaryOp::Le, lhs, rhs, &self.heap)?;
+            }
+            Instruction::Jump(target) => self.jump(target),
+            Instruction::JumpIf(condition, target) | Instruction::JumpIfNot(condition, target) => {
+                let expected = matches!(instruction, Instruction::JumpIf(_, _));
+                match registers[at(condition)] {
+                    Value::Bool(value) if value == expected => self.jump(target),
+                    Value::Bool(_) => {}
+                    ref value => {
+                        let op = instruction.mnemonic();
+                        return Err(VmErrorKind::InvalidOperand(op, self.heap.type_name(value)));
+                    }
+                }
+            }
+            Instruction::Call(dst, callee, args, count) => match registers[at(callee)] {
+                Value::Function(function) => {
+                    self.enter(function as usize, at(args), count as usize, at(dst))?;
+                }
+                ref value => return Err(VmErrorKind::NotCallable(self.heap.type_name(value))),
+            },
+            Instruction::Return(src) => {
+                let value = registers[at(src)].clone();
+                return Ok(self.leave(value));
+            }
+            Instruction::Spill(slot, src) => {
+                self.slots[slots + slot as usize] = registers[at(src)].clone();
+            }
+            Instruction::Reload(dst, slot) => {
+                registers[at(dst)] = self.slots[slots + slot as usize].clone();
+            }
+            Instruction::CheckStep(src) => match registers[at(src)] {
+                Value::Int(0) => return Err(VmErrorKind::ZeroStep),
+                Value::Int(_) => {}
+                ref value => {
+                    let op = instruction.mnemonic();
+                    return Err(VmErrorKind::InvalidOperand(op, self.heap.type_name(value)));
+                }
+            },
+        }
+        Ok(None)
+    }
+}
@@ -0,0 +1,22 @@
+pub mod assembler;
+pub mod bytecode;
+pub mod compiler;
+pub mod disassembler;
+pub mod format;
+pub mod heap;
+pub mod machine;
+pub mod value;
+pub mod verifier;
+
+pub use assembler::*;
+pub use bytecode::*;
+pub use compiler::*;
+pub use disassembler::*;
+pub use format::*;
+pub use heap::*;
+pub use machine::*;
+pub use value::*;
+pub use verifier::*;
+
+#[cfg(test)]
+pub mod tests;
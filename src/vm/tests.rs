@@ -0,0 +1,1010 @@
+use std::rc::Rc;
+
+use crate::{
+    parser_combinator::*,
+    untyped_language::{pprogram, Interpreter, RuntimeValue},
+};
+
+use super::*;
+
+use Instruction::*;
+
+fn function(name: &str, arity: u8, registers: usize, code: Vec<Instruction>) -> Function {
+    Function {
+        name: name.to_string(),
+        arity,
+        registers,
+        slots: 0,
+        code,
+        spans: Vec::new(),
+    }
+}
+
+fn run(module: &Module, name: &str, args: Vec<Value>) -> Result<Value, VmError> {
+    let function = module.function(name).unwrap();
+    Vm::new(module).call(function, args)
+}
+
+//fib(n) = if n < 2 { n } else { fib(n - 1) + fib(n - 2) }, keeping the first result below
+//the argument register so that the second call does not overwrite it
+fn fib_module() -> Module {
+    Module {
+        constants: vec![Value::Int(2), Value::Int(1), Value::Function(0)],
+        functions: vec![function(
+            "fib",
+            1,
+            5,
+            vec![
+                LoadConst(1, 0),
+                Lt(2, 0, 1),
+                JumpIfNot(2, 4),
+                Return(0),
+                LoadConst(1, 2),
+                LoadConst(2, 1),
+                Sub(4, 0, 2),
+                Call(3, 1, 4, 1),
+                Sub(4, 0, 2),
+                Sub(4, 4, 2),
+                Call(4, 1, 4, 1),
+                Add(3, 3, 4),
+                Return(3),
+            ],
+        )],
+    }
+}
+
+#[test]
+fn test_arithmetic() {
+    let module = Module {
+        constants: vec![Value::Int(6), Value::Int(7)],
+        functions: vec![function(
+            "main",
+            0,
+            4,
+            vec![
+                LoadConst(0, 0),
+                LoadConst(1, 1),
+                Mul(2, 0, 1),
+                Sub(2, 2, 1),
+                Rem(3, 2, 0),
+                Neg(3, 3),
+                Add(2, 2, 3),
+                Return(2),
+            ],
+        )],
+    };
+    assert_eq!(run(&module, "main", vec![]), Ok(Value::Int(30)));
+
+    let module = Module {
+        constants: vec![Value::Float(1.5), Value::Float(0.5)],
+        functions: vec![function(
+            "main",
+            0,
+            2,
+            vec![LoadConst(0, 0), LoadConst(1, 1), Div(0, 0, 1), Return(0)],
+        )],
+    };
+    assert_eq!(run(&module, "main", vec![]), Ok(Value::Float(3.0)));
+}
+
+#[test]
+fn test_loop() {
+    //sum(n) = 1 + 2 + ... + n
+    let module = Module {
+        constants: vec![Value::Int(0), Value::Int(1)],
+        functions: vec![function(
+            "sum",
+            1,
+            5,
+            vec![
+                LoadConst(1, 0),
+                LoadConst(2, 1),
+                Move(3, 2),
+                Le(4, 3, 0),
+                JumpIfNot(4, 8),
+                Add(1, 1, 3),
+                Add(3, 3, 2),
+                Jump(3),
+                Return(1),
+            ],
+        )],
+    };
+    assert_eq!(
+        run(&module, "sum", vec![Value::Int(100)]),
+        Ok(Value::Int(5050))
+    );
+    assert_eq!(run(&module, "sum", vec![Value::Int(-1)]), Ok(Value::Int(0)));
+}
+
+#[test]
+fn test_recursive_calls() {
+    let module = fib_module();
+    assert_eq!(
+        run(&module, "fib", vec![Value::Int(20)]),
+        Ok(Value::Int(6765))
+    );
+}
+
+#[test]
+fn test_call_passes_arguments_in_order() {
+    //main() = sub(10, 3), returning unit by running off the end of check()
+    let module = Module {
+        constants: vec![
+            Value::Int(10),
+            Value::Int(3),
+            Value::Function(1),
+            Value::Function(2),
+        ],
+        functions: vec![
+            function(
+                "main",
+                0,
+                5,
+                vec![
+                    LoadConst(0, 2),
+                    LoadConst(3, 0),
+                    LoadConst(4, 1),
+                    Call(1, 0, 3, 2),
+                    LoadConst(0, 3),
+                    Call(2, 0, 3, 0),
+                    Eq(2, 2, 2),
+                    JumpIf(2, 9),
+                    LoadBool(1, false),
+                    Return(1),
+                ],
+            ),
+            function("sub", 2, 2, vec![Sub(0, 0, 1), Return(0)]),
+            function("check", 0, 1, vec![LoadUnit(0)]),
+        ],
+    };
+    assert_eq!(run(&module, "main", vec![]), Ok(Value::Int(7)));
+}
+
+#[test]
+fn test_strings_and_comparisons() {
+    let module = Module {
+        constants: vec![
+            Value::String(Rc::from("ab")),
+            Value::String(Rc::from("c")),
+            Value::Char('x'),
+            Value::Float(f64::NAN),
+        ],
+        functions: vec![function(
+            "main",
+            0,
+            4,
+            vec![
+                LoadConst(0, 0),
+                LoadConst(1, 1),
+                Add(0, 0, 1),
+                Lt(1, 1, 0),
+                Not(1, 1),
+                LoadConst(2, 3),
+                Le(2, 2, 2),
+                Ne(1, 1, 2),
+                JumpIfNot(1, 11),
+                LoadConst(3, 2),
+                Ne(0, 0, 3),
+                Return(0),
+            ],
+        )],
+    };
+    assert_eq!(run(&module, "main", vec![]), Ok(Value::Bool(true)));
+}
+
+#[test]
+fn test_errors_report_location_and_trace() {
+    //main() = divide(1, 0)
+    let module = Module {
+        constants: vec![Value::Int(1), Value::Int(0), Value::Function(1)],
+        functions: vec![
+            function(
+                "main",
+                0,
+                4,
+                vec![
+                    LoadConst(0, 2),
+                    LoadConst(2, 0),
+                    LoadConst(3, 1),
+                    Call(1, 0, 2, 2),
+                    Return(1),
+                ],
+            ),
+            function("divide", 2, 2, vec![Div(0, 0, 1), Return(0)]),
+        ],
+    };
+    let error = run(&module, "main", vec![]).unwrap_err();
+    assert_eq!(
+        error,
+        VmError {
+            kind: VmErrorKind::DivisionByZero,
+            trace: vec![
+                Location { function: 1, pc: 0 },
+                Location { function: 0, pc: 3 },
+            ],
+        }
+    );
+    assert_eq!(
+        error.to_string(),
+        "division by zero\n    at 1:0\n    at 0:3"
+    );
+
+    let module = Module {
+        constants: vec![Value::Int(1), Value::Function(0)],
+        functions: vec![function(
+            "main",
+            0,
+            3,
+            vec![
+                LoadConst(0, 0),
+                LoadBool(1, true),
+                JumpIf(1, 5),
+                Call(2, 0, 1, 1),
+                Return(2),
+                Add(0, 0, 1),
+            ],
+        )],
+    };
+    let kind = |module: &Module| run(module, "main", vec![]).unwrap_err().kind;
+    assert_eq!(kind(&module), VmErrorKind::Mismatched("int", "bool"));
+    let mut module = module;
+    module.functions[0].code[2] = JumpIf(0, 5);
+    assert_eq!(kind(&module), VmErrorKind::InvalidOperand("jmpif", "int"));
+    module.functions[0].code[2] = JumpIfNot(1, 5);
+    assert_eq!(kind(&module), VmErrorKind::NotCallable("int"));
+    module.functions[0].code[0] = LoadConst(0, 1);
+    assert_eq!(kind(&module), VmErrorKind::ArityMismatch(0, 1));
+    assert_eq!(
+        run(&fib_module(), "fib", vec![]),
+        Err(VmError {
+            kind: VmErrorKind::ArityMismatch(1, 0),
+            trace: vec![],
+        })
+    );
+}
+
+#[test]
+fn test_stack_overflow() {
+    //down() = down()
+    let module = Module {
+        constants: vec![Value::Function(0)],
+        functions: vec![function(
+            "down",
+            0,
+            2,
+            vec![LoadConst(0, 0), Call(1, 0, 1, 0), Return(1)],
+        )],
+    };
+    let mut vm = Vm::new(&module);
+    vm.max_frames = 20;
+    let error = vm.call(0, vec![]).unwrap_err();
+    assert_eq!(error.kind, VmErrorKind::StackOverflow);
+    assert_eq!(error.trace.len(), 20);
+    assert!(error.trace.iter().all(|location| location.pc == 1));
+}
+
+#[test]
+fn test_display_instructions() {
+    let code: Vec<_> = fib_module().functions[0]
+        .code
+        .iter()
+        .map(Instruction::to_string)
+        .collect();
+    assert_eq!(
+        code[..8],
+        [
+            "loadk r1, k0",
+            "lt r2, r0, r1",
+            "jmpifnot r2, @4",
+            "ret r0",
+            "loadk r1, k2",
+            "loadk r2, k1",
+            "sub r4, r0, r2",
+            "call r3, r1, r4, 1",
+        ]
+    );
+}
+
+const PROGRAM: &str = "const LIMIT: int = 4;
+fun fact(n: int) -> int {
+    if n <= 1 { 1; } else { n * fact(n - 1); };
+}
+fun fib(n: int) -> int {
+    if n < 2 {
+        return n;
+    };
+    fib(n - 1) + fib(n - 2);
+}
+fun loops(n: int, step: int) -> int {
+    let mut total = 0;
+    for i = 0 .. n step 3 {
+        total += i;
+    };
+    for i = LIMIT ..= 1 step -1 {
+        total = total * 10 + i;
+    };
+    for i = n .. 0 step step {
+        total -= i;
+        i = 100;
+    };
+    let mut i = 0;
+    while i < n {
+        i += 1;
+        if i % 2 == 0 {
+            continue;
+        };
+        total += 1000;
+    };
+    let found = 'search: loop {
+        for i = 0 .. n {
+            for j = 0 .. 2 {
+                if i == 4 && j > 0 || false {
+                    break 'search i;
+                };
+            };
+        };
+        break -1;
+    };
+    total * 10 + found;
+}
+fun sum(a: int, b: int, c: int, d: int, e: int, f: int) -> int {
+    let g = a + b;
+    let h = c * d - e;
+    let k = if f > 0 { g - h; } else { h - g; };
+    a + b + c + d + e + f + g + h + k + fact(LIMIT) + g * h;
+}
+fun zeroes(x: int) -> float {
+    let a = 0.0;
+    1.0 / -0.0;
+}
+fun main(x: int) -> string {
+    let a = \"n=\";
+    if !(x > 0) && x != -5 { \"none\"; } else { a; };
+}";
+
+fn compile(registers: usize) -> Module {
+    let (program, cont) = pprogram().parse(PROGRAM.into()).unwrap();
+    assert_eq!(cont.remaining, "");
+    Compiler { registers }
+        .compile_program(&program.value)
+        .unwrap()
+}
+
+#[test]
+fn test_compiled_functions_match_the_interpreter() {
+    let (program, _) = pprogram().parse(PROGRAM.into()).unwrap();
+    let mut interpreter = Interpreter::with_output(&program.value, Vec::new());
+    let calls: [(&str, &[i32]); 11] = [
+        ("fact", &[10]),
+        ("fib", &[15]),
+        ("loops", &[10, -2]),
+        ("loops", &[-3, 2]),
+        ("loops", &[5, 0]),
+        ("sum", &[1, 2, 3, 4, 5, 6]),
+        ("sum", &[-1, 2, -3, 4, -5, -6]),
+        ("zeroes", &[0]),
+        ("main", &[3]),
+        ("main", &[-5]),
+        ("main", &[-4]),
+    ];
+    //with enough registers, with a few, and with every value spilled
+    for registers in [DEFAULT_REGISTERS, 3, 0] {
+        let module = compile(registers);
+        for (name, args) in calls {
+            let expected = interpreter.call(
+                name,
+                args.iter().map(|&arg| RuntimeValue::Int(arg)).collect(),
+            );
+            let values = args.iter().map(|&arg| Value::Int(arg as i64)).collect();
+            //strings are shown quoted by the vm, and errors are compared by message and span
+            let found = match run(&module, name, values) {
+                Ok(Value::String(value)) => Ok(value.to_string()),
+                Ok(value) => Ok(value.to_string()),
+                Err(error) => Err((error.kind.to_string(), module.span(error.trace[0]).cloned())),
+            };
+            let expected = match expected {
+                Ok(value) => Ok(value.to_string()),
+                Err(error) => Err((error.kind.to_string(), Some(error.span))),
+            };
+            assert_eq!(found, expected, "{}{:?} with {}", name, args, registers);
+        }
+    }
+}
+
+#[test]
+fn test_register_allocation_spills_past_the_limit() {
+    let sum = |module: &Module| module.functions[module.function("sum").unwrap()].clone();
+    let roomy = sum(&compile(DEFAULT_REGISTERS));
+    assert_eq!(roomy.slots, 0);
+    assert!(!roomy
+        .code
+        .iter()
+        .any(|instruction| matches!(instruction, Spill(_, _) | Reload(_, _))));
+    let cramped = sum(&compile(3));
+    assert!(cramped.slots > 0);
+    assert!(cramped
+        .code
+        .iter()
+        .any(|instruction| matches!(instruction, Spill(_, _))));
+    assert!(cramped
+        .code
+        .iter()
+        .any(|instruction| matches!(instruction, Reload(_, _))));
+    //three registers for values, two for reloading and the rest for passing arguments
+    assert!(cramped.registers <= 3 + 2 + 1);
+    assert!(cramped.registers < roomy.registers);
+    assert_eq!(cramped.spans.len(), cramped.code.len());
+}
+
+#[test]
+fn test_runtime_errors_map_to_source() {
+    let source = "fun divide(a: int, b: int) -> int {
+    a / b;
+}
+fun main() -> int {
+    let zero = 0;
+    divide(1, zero) + 1;
+}";
+    let (program, _) = pprogram().parse(source.into()).unwrap();
+    let at = |text: &str| {
+        let start = source.find(text).unwrap();
+        start..start + text.len()
+    };
+    for registers in [DEFAULT_REGISTERS, 0] {
+        let module = Compiler { registers }
+            .compile_program(&program.value)
+            .unwrap();
+        let error = run(&module, "main", vec![]).unwrap_err();
+        assert_eq!(error.kind, VmErrorKind::DivisionByZero);
+        let spans: Vec<_> = error
+            .trace
+            .iter()
+            .map(|location| module.span(*location).cloned())
+            .collect();
+        assert_eq!(spans, vec![Some(at("a / b")), Some(at("divide(1, zero)"))]);
+    }
+}
+
+#[test]
+fn test_compile_errors() {
+    let compile = |source: &str| {
+        let (program, _) = pprogram().parse(source.into()).unwrap();
+        Compiler::default().compile_program(&program.value)
+    };
+    let at = |source: &str, text: &str| {
+        let start = source.find(text).unwrap();
+        start..start + text.len()
+    };
+    let source = "fun f() -> int { g(1); }";
+    assert_eq!(
+        compile(source),
+        Err(CompileError::Undefined("g".to_string(), at(source, "g")))
+    );
+    let source = "fun f() -> int { let x = [1, 2]; }";
+    assert_eq!(
+        compile(source),
+        Err(CompileError::Unsupported("arrays", at(source, "[1, 2]")))
+    );
+    let source = "const A: int = B + 1;\nconst B: int = A;\nfun f() -> int { A; }";
+    let a = source.find("A;").unwrap();
+    assert_eq!(
+        compile(source),
+        Err(CompileError::RecursiveConst("A".to_string(), a..a + 1))
+    );
+    let source = "fun f(x: int) -> int { x << 1; }";
+    assert_eq!(
+        compile(source),
+        Err(CompileError::Unsupported(
+            "bitwise operators",
+            at(source, "<<")
+        ))
+    );
+}
+
+#[test]
+fn test_modules_round_trip() {
+    let mut constants = fib_module();
+    constants.constants.extend([
+        Value::Unit,
+        Value::Bool(true),
+        Value::Float(-1.5),
+        Value::Char('λ'),
+        Value::String(Rc::from("ab\n")),
+    ]);
+    let modules = [
+        fib_module(),
+        constants,
+        compile(DEFAULT_REGISTERS),
+        compile(0),
+    ];
+    for module in modules {
+        let bytes = write_module(&module);
+        assert_eq!(&bytes[..4], &MAGIC);
+        assert_eq!(read_module(&bytes), Ok(module.clone()));
+        assert_eq!(load_module(&bytes), Ok(module));
+    }
+    let module = load_module(&write_module(&compile(3))).unwrap();
+    assert_eq!(
+        run(&module, "fib", vec![Value::Int(15)]),
+        Ok(Value::Int(610))
+    );
+    //instructions compiled from the same source share an entry in the line table
+    let sum = &module.functions[module.function("sum").unwrap()];
+    assert_eq!(sum.spans.len(), sum.code.len());
+}
+
+#[test]
+fn test_reading_rejects_malformed_bytes() {
+    let module = Module {
+        constants: vec![Value::Int(1)],
+        functions: vec![function("f", 0, 1, vec![LoadConst(0, 0), Return(0)])],
+    };
+    let bytes = write_module(&module);
+    let with = |offset: usize, byte: u8| {
+        let mut bytes = bytes.clone();
+        bytes[offset] = byte;
+        read_module(&bytes)
+    };
+    assert_eq!(with(0, b'X'), Err(FormatError::BadMagic));
+    assert_eq!(with(4, 2), Err(FormatError::UnsupportedVersion(2)));
+    //the constant tag follows the header and the number of constants
+    assert_eq!(with(10, 9), Err(FormatError::InvalidTag("constant", 9, 10)));
+    let opcode = bytes.len() - 2;
+    assert_eq!(
+        with(opcode, 99),
+        Err(FormatError::InvalidTag("opcode", 99, opcode))
+    );
+    for end in 4..bytes.len() {
+        assert_eq!(
+            read_module(&bytes[..end]),
+            Err(FormatError::UnexpectedEnd(end)),
+            "{}",
+            end
+        );
+    }
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert_eq!(
+        read_module(&trailing),
+        Err(FormatError::TrailingBytes(bytes.len()))
+    );
+    assert_eq!(read_module(b"NG"), Err(FormatError::BadMagic));
+    //one function with no code, and a line table claiming u32::MAX instructions
+    let mut huge = MAGIC.to_vec();
+    huge.extend(VERSION.to_le_bytes());
+    huge.extend([
+        0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0,
+    ]);
+    huge.extend([0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(huge.len(), 45);
+    assert_eq!(read_module(&huge), Err(FormatError::LineTableTooLong(33)));
+}
+
+#[test]
+fn test_verifier_rejects_malformed_modules() {
+    let verify = |registers: usize, code: Vec<Instruction>| {
+        let mut constants = fib_module().constants;
+        constants.extend([Value::Function(9), Value::String(Rc::from("s"))]);
+        let module = Module {
+            constants,
+            functions: vec![function("f", 1, registers, code)],
+        };
+        verify_module(&module).map_err(|error| (error.kind, error.pc))
+    };
+    let at = |kind: VerifyErrorKind, pc: usize| Err((kind, Some(pc)));
+    let type_ = |kind: VmErrorKind, pc: usize| at(VerifyErrorKind::Type(kind), pc);
+    assert_eq!(verify(2, vec![Move(1, 0), Jump(3), Return(1)]), Ok(()));
+    assert_eq!(
+        verify(2, vec![Move(1, 0), Jump(4), Return(1)]),
+        at(VerifyErrorKind::JumpOutOfRange(4), 1)
+    );
+    assert_eq!(
+        verify(2, vec![Move(1, 0), Add(1, 1, 2)]),
+        at(VerifyErrorKind::RegisterOutOfRange(2), 1)
+    );
+    assert_eq!(
+        verify(2, vec![Call(0, 1, 1, 2)]),
+        at(VerifyErrorKind::RegisterOutOfRange(2), 0)
+    );
+    assert_eq!(
+        verify(2, vec![Spill(0, 0)]),
+        at(VerifyErrorKind::SlotOutOfRange(0), 0)
+    );
+    assert_eq!(
+        verify(2, vec![LoadConst(0, 5)]),
+        at(VerifyErrorKind::ConstantOutOfRange(5), 0)
+    );
+    let mut heap = Heap::default();
+    let object = heap.allocate(Object::String("s".to_string()), []).unwrap();
+    let module = Module {
+        constants: vec![Value::Object(object)],
+        functions: vec![function("f", 0, 1, vec![LoadConst(0, 0), Return(0)])],
+    };
+    assert_eq!(
+        verify_module(&module).map_err(|error| (error.kind, error.pc)),
+        at(VerifyErrorKind::ObjectConstant(0), 0)
+    );
+    assert_eq!(
+        verify(2, vec![LoadConst(0, 3)]),
+        type_(VmErrorKind::UnknownFunction(9), 0)
+    );
+    //constants are tracked through moves until control flow joins
+    assert_eq!(
+        verify(3, vec![LoadConst(1, 4), Move(2, 1), Call(0, 2, 1, 0)]),
+        type_(VmErrorKind::NotCallable("string"), 2)
+    );
+    assert_eq!(
+        verify(2, vec![LoadConst(1, 2), Call(0, 1, 1, 0)]),
+        type_(VmErrorKind::ArityMismatch(1, 0), 1)
+    );
+    assert_eq!(
+        verify(2, vec![LoadConst(1, 4), JumpIf(1, 0)]),
+        type_(VmErrorKind::InvalidOperand("jmpif", "string"), 1)
+    );
+    assert_eq!(
+        verify(2, vec![LoadConst(1, 4), CheckStep(1)]),
+        type_(VmErrorKind::InvalidOperand("chkstep", "string"), 1)
+    );
+    assert_eq!(
+        verify(3, vec![LoadConst(1, 0), LoadConst(2, 4), Sub(0, 1, 2)]),
+        type_(VmErrorKind::Mismatched("int", "string"), 2)
+    );
+    assert_eq!(
+        verify(2, vec![LoadUnit(1), Neg(1, 1)]),
+        type_(VmErrorKind::InvalidOperand("-", "unit"), 1)
+    );
+    assert_eq!(
+        verify(
+            3,
+            vec![LoadConst(1, 4), Jump(2), Move(0, 0), Call(0, 1, 1, 1)]
+        ),
+        Ok(())
+    );
+    let invalid = |function: Function| {
+        verify_module(&Module {
+            constants: Vec::new(),
+            functions: vec![function],
+        })
+        .map_err(|error| (error.kind, error.pc))
+    };
+    assert_eq!(
+        invalid(function("f", 0, 300, vec![])),
+        Err((VerifyErrorKind::TooManyRegisters(300), None))
+    );
+    assert_eq!(
+        invalid(function("f", 2, 1, vec![])),
+        Err((VerifyErrorKind::ArityExceedsRegisters(2, 1), None))
+    );
+    let mut spans = function("f", 0, 1, vec![LoadUnit(0), Return(0)]);
+    spans.spans.push(0..1);
+    assert_eq!(
+        invalid(spans),
+        Err((VerifyErrorKind::SpansMismatch(1, 2), None))
+    );
+    let mut module = fib_module();
+    module.functions[0].code[4] = LoadConst(1, 7);
+    assert_eq!(
+        load_module(&write_module(&module)),
+        Err(LoadError::Verify(VerifyError {
+            kind: VerifyErrorKind::ConstantOutOfRange(7),
+            function: 0,
+            pc: Some(4),
+        }))
+    );
+}
+
+#[test]
+fn test_disassemble() {
+    let expected = "const k0 = 2
+const k1 = 1
+const k2 = <fun 0>  ; fib
+
+fun fib arity 1 registers 5 slots 0
+     0  loadk r1, k0  ; 2
+     1  lt r2, r0, r1
+     2  jmpifnot r2, @4
+     3  ret r0
+     4  loadk r1, k2  ; fib
+     5  loadk r2, k1  ; 1
+     6  sub r4, r0, r2
+     7  call r3, r1, r4, 1
+     8  sub r4, r0, r2
+     9  sub r4, r4, r2
+    10  call r4, r1, r4, 1
+    11  add r3, r3, r4
+    12  ret r3
+";
+    assert_eq!(disassemble(&fib_module(), None), expected);
+    let source = "fun half(n: int) -> int {
+    let two = 2;
+    n / two;
+}";
+    let (program, _) = pprogram().parse(source.into()).unwrap();
+    let module = Compiler::default().compile_program(&program.value).unwrap();
+    let expected = "const k0 = 2
+
+fun half arity 1 registers 3 slots 0
+    ; 2 | let two = 2;
+    0  loadk r1, k0  ; 2
+    1  move r2, r1
+    ; 3 | n / two;
+    2  div r1, r0, r2
+    ; 1 | fun half(n: int) -> int {
+    3  ret r1
+";
+    assert_eq!(disassemble(&module, Some(source)), expected);
+    //spans falling inside a character of the wrong source are left unannotated
+    for other in [
+        "é".repeat(source.len()),
+        "a".to_string() + &"é".repeat(source.len()),
+    ] {
+        let plain: String = disassemble(&module, Some(&other))
+            .lines()
+            .filter(|line| !line.starts_with("    ; "))
+            .map(|line| line.to_string() + "\n")
+            .collect();
+        assert_eq!(plain, disassemble(&module, None));
+    }
+}
+
+#[test]
+fn test_assemble_reads_disassembly() {
+    let mut constants = fib_module();
+    constants.constants.extend([
+        Value::Unit,
+        Value::Bool(false),
+        Value::Int(-7),
+        Value::Float(-1.5),
+        Value::Float(1e100),
+        Value::Float(f64::INFINITY),
+        Value::Char('\''),
+        Value::String(Rc::from("say \"{hi}\"\n")),
+    ]);
+    let modules = [
+        fib_module(),
+        constants,
+        compile(DEFAULT_REGISTERS),
+        compile(0),
+    ];
+    for mut module in modules {
+        let text = disassemble(&module, Some(PROGRAM));
+        for function in &mut module.functions {
+            function.spans.clear();
+        }
+        assert_eq!(assemble(&text), Ok(module), "{}", text);
+    }
+}
+
+#[test]
+fn test_assembled_snippets_run() {
+    //sum(n) = 1 + 2 + ... + n, with a spilled total
+    let module = assemble(
+        "const k0 = 0
+        const 1
+
+        fun sum arity 1 registers 3 slots 1  ; sum(n)
+            loadk r1, k0
+            spill s0, r1
+            loadk r2, k1
+        ; loop while n > 0
+         3  lt r1, r1, r0
+         4  jmpifnot r1, @11
+            reload r1, s0
+            add r1, r1, r0
+            spill s0, r1
+            sub r0, r0, r2
+            loadk r1, k0
+            jmp @3
+        11  reload r1, s0
+            ret r1",
+    )
+    .unwrap();
+    assert_eq!(verify_module(&module), Ok(()));
+    assert_eq!(
+        run(&module, "sum", vec![Value::Int(10)]),
+        Ok(Value::Int(55))
+    );
+}
+
+#[test]
+fn test_assemble_errors() {
+    let error = |text: &'static str| match assemble(text) {
+        Err(AssembleError::Parse(error)) => (error.actual, error.line_number, error.line_position),
+        result => panic!("{:?}", result),
+    };
+    assert_eq!(
+        error("fun f arity 0 registers 1 slots 0\n  jump @0"),
+        ("jump", 1, 2)
+    );
+    assert_eq!(
+        error("fun f arity 0 registers 1 slots 0\n  ret r256"),
+        ("r256", 1, 6)
+    );
+    assert_eq!(
+        error("fun f arity 0 registers 1 slots 0\n  move r0 r0"),
+        ("r", 1, 10)
+    );
+    assert_eq!(error("fun f arity 0 registers 257 slots 0"), ("257", 0, 24));
+    assert_eq!(error("loadu r0"), ("l", 0, 0));
+    //jump targets are written with an `@`
+    assert_eq!(
+        error("fun f arity 0 registers 1 slots 0\n  jmp 0"),
+        ("0", 1, 6)
+    );
+    assert_eq!(
+        error("const k0 = 99999999999999999999"),
+        ("99999999999999999999", 0, 11)
+    );
+    assert_eq!(
+        assemble("const 1\nconst k2 = 2"),
+        Err(AssembleError::Misnumbered(1, 2, 14..16))
+    );
+    assert_eq!(
+        assemble("fun f arity 0 registers 1 slots 0\n 0 loadu r0\n 0 ret r0"),
+        Err(AssembleError::Misnumbered(1, 0, 47..48))
+    );
+}
+
+fn string(text: &str) -> Object {
+    Object::String(text.to_string())
+}
+
+#[test]
+fn test_heap_collects_unreachable_objects() {
+    let mut heap = Heap::default();
+    let a = Value::Object(heap.allocate(string("a"), []).unwrap());
+    let b = heap.allocate(string("b"), []).unwrap();
+    let c = Value::Object(heap.allocate(string("c"), []).unwrap());
+    assert_eq!(heap.stats().live_objects, 3);
+    assert_eq!(heap.type_name(&c), "string");
+
+    //only a and c are roots, so b goes
+    assert_eq!(heap.collect([&a, &c, &Value::Int(1)]), 1);
+    let stats = heap.stats();
+    assert_eq!(
+        (stats.collections, stats.allocations, stats.freed),
+        (1, 3, 1)
+    );
+    assert_eq!(stats.live_objects, 2);
+    assert!(stats.live_bytes < stats.peak_bytes);
+    assert_eq!(heap.string(&a), Some("a"));
+    assert_eq!(heap.string(&c), Some("c"));
+    //the places of freed objects are reused
+    let d = heap.allocate(string("d"), []).unwrap();
+    assert_eq!(d, b);
+
+    //strings are compared by their text, wherever they live
+    let copy = Value::Object(heap.allocate(string("a"), [&a, &c]).unwrap());
+    assert!(heap.equal(&a, &copy));
+    assert!(heap.equal(&Value::String(Rc::from("a")), &copy));
+    assert!(!heap.equal(&a, &c));
+    assert!(!heap.equal(&a, &Value::Int(1)));
+}
+
+#[test]
+fn test_heap_stress_collects_at_every_allocation() {
+    let mut heap = Heap::new(HeapConfig {
+        stress: true,
+        ..HeapConfig::default()
+    });
+    //a string kept and a string dropped every step
+    let mut kept = Vec::new();
+    for i in 0..100 {
+        heap.allocate(string("garbage"), &kept).unwrap();
+        let text = heap.allocate(string(&i.to_string()), &kept).unwrap();
+        kept.push(Value::Object(text));
+    }
+    let stats = heap.stats();
+    assert_eq!(stats.collections, stats.allocations);
+    assert_eq!(stats.live_objects, 100);
+    assert_eq!(stats.freed, 100);
+    let found: Vec<_> = kept
+        .iter()
+        .map(|value| heap.string(value).unwrap())
+        .collect();
+    let expected: Vec<String> = (0..100).map(|i| i.to_string()).collect();
+    assert_eq!(found, expected);
+    assert_eq!(heap.collect([]), 100);
+    assert_eq!(heap.stats().live_bytes, 0);
+}
+
+#[test]
+fn test_heap_size_is_limited() {
+    let size = string("0123456789").size();
+    let mut heap = Heap::new(HeapConfig {
+        max_bytes: size * 3,
+        threshold: size,
+        stress: false,
+    });
+    let mut roots = Vec::new();
+    for _ in 0..3 {
+        let object = heap.allocate(string("0123456789"), &roots).unwrap();
+        roots.push(Value::Object(object));
+    }
+    assert_eq!(
+        heap.allocate(string("0123456789"), &roots),
+        Err(VmErrorKind::OutOfMemory(size))
+    );
+    //once something is let go, the collection before the allocation makes room
+    roots.pop();
+    assert!(heap.allocate(string("0123456789"), &roots).is_ok());
+    //collections only happen past the threshold, which grows with what is live
+    assert_eq!(heap.stats().collections, 4);
+    assert_eq!(heap.stats().freed, 1);
+}
+
+const STRINGS: &str = "fun repeat(s: string, n: int) -> string {
+    if n == 0 { \"\"; } else { s + repeat(s, n - 1); };
+}
+fun build(n: int) -> string {
+    let out = \"\";
+    for i = 0 .. n {
+        let piece = \"<\" + repeat(\"x\", i) + \">\";
+        out = out + piece;
+    };
+    if out == \"<><x>\" { \"two\"; } else { out + repeat(\"!\", 2); };
+}";
+
+#[test]
+fn test_vm_strings_survive_stress_collection() {
+    let (program, _) = pprogram().parse(STRINGS.into()).unwrap();
+    //with enough registers, with a few, and with every value spilled
+    for registers in [DEFAULT_REGISTERS, 3, 0] {
+        let module = Compiler { registers }
+            .compile_program(&program.value)
+            .unwrap();
+        let build = module.function("build").unwrap();
+        for stress in [false, true] {
+            let config = HeapConfig {
+                stress,
+                ..HeapConfig::default()
+            };
+            let mut vm = Vm::with_heap(&module, config);
+            assert_eq!(
+                vm.call(build, vec![Value::Int(2)]),
+                Ok(Value::String(Rc::from("two")))
+            );
+            assert_eq!(
+                vm.call(build, vec![Value::Int(5)]),
+                Ok(Value::String(Rc::from("<><x><xx><xxx><xxxx>!!")))
+            );
+            let stats = vm.heap().stats();
+            assert!(stats.allocations > 30);
+            match stress {
+                true => assert_eq!(stats.collections, stats.allocations),
+                false => assert_eq!(stats.collections, 0),
+            }
+            //nothing is running, so nothing is left once the result has been taken
+            vm.collect();
+            assert_eq!(vm.heap().stats().live_objects, 0);
+        }
+    }
+}
+
+#[test]
+fn test_vm_runs_out_of_memory() {
+    let (program, _) = pprogram().parse(STRINGS.into()).unwrap();
+    let module = Compiler::default().compile_program(&program.value).unwrap();
+    let config = HeapConfig {
+        max_bytes: string("").size() * 8,
+        ..HeapConfig::default()
+    };
+    let mut vm = Vm::with_heap(&module, config);
+    let repeat = module.function("repeat").unwrap();
+    assert!(vm
+        .call(repeat, vec![Value::String(Rc::from("a")), Value::Int(4)])
+        .is_ok());
+    let error = vm
+        .call(
+            repeat,
+            vec![Value::String(Rc::from("abcdefgh")), Value::Int(50)],
+        )
+        .unwrap_err();
+    assert!(matches!(error.kind, VmErrorKind::OutOfMemory(_)));
+    //collecting only helps while the result fits, so it fails on the way out of the recursion
+    assert!(error.trace.len() > 1);
+    assert!(error
+        .trace
+        .iter()
+        .all(|location| location.function == repeat));
+}
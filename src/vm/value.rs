@@ -0,0 +1,50 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    rc::Rc,
+};
+
+use super::*;
+
+/// A value held in a register or the constant pool.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Unit,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Char(char),
+    String(Rc<str>),
+    Function(u32),     //index into the module's functions
+    Object(ObjectRef), //on the heap of the vm running it
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Unit => "unit",
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::Char(_) => "char",
+            Value::String(_) => "string",
+            Value::Function(_) => "function",
+            //the heap knows which type of object it is
+            Value::Object(_) => "object",
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Value::Unit => f.write_str("()"),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{:?}", value),
+            Value::Char(value) => write!(f, "{:?}", value),
+            Value::String(value) => write!(f, "{:?}", value),
+            Value::Function(index) => write!(f, "<fun {}>", index),
+            Value::Object(object) => write!(f, "{}", object),
+        }
+    }
+}
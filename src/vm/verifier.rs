@@ -0,0 +1,320 @@
+use std::{
+    collections::HashSet,
+    fmt::{self, Display, Formatter},
+};
+
+use super::*;
+
+/// Why a module would not run safely.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerifyErrorKind {
+    TooManyRegisters(usize),
+    ArityExceedsRegisters(u8, usize), //arity, registers
+    SpansMismatch(usize, usize),      //spans, instructions
+    JumpOutOfRange(u32),
+    RegisterOutOfRange(Register),
+    SlotOutOfRange(u32),
+    ConstantOutOfRange(u32),
+    ObjectConstant(u32), //index of a constant referring to a heap object
+    //an instruction that is certain to fail on the constant it is given
+    Type(VmErrorKind),
+}
+
+impl Display for VerifyErrorKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            VerifyErrorKind::TooManyRegisters(registers) => {
+                write!(f, "{} registers is more than {}", registers, MAX_REGISTERS)
+            }
+            VerifyErrorKind::ArityExceedsRegisters(arity, registers) => {
+                write!(
+                    f,
+                    "{} arguments do not fit in {} registers",
+                    arity, registers
+                )
+            }
+            VerifyErrorKind::SpansMismatch(spans, instructions) => {
+                write!(f, "{} spans for {} instructions", spans, instructions)
+            }
+            VerifyErrorKind::JumpOutOfRange(target) => write!(f, "no instruction @{}", target),
+            VerifyErrorKind::RegisterOutOfRange(register) => write!(f, "no register r{}", register),
+            VerifyErrorKind::SlotOutOfRange(slot) => write!(f, "no slot s{}", slot),
+            VerifyErrorKind::ConstantOutOfRange(index) => write!(f, "no constant k{}", index),
+            VerifyErrorKind::ObjectConstant(index) => {
+                write!(f, "constant k{} refers to a heap object", index)
+            }
+            VerifyErrorKind::Type(kind) => write!(f, "{}", kind),
+        }
+    }
+}
+
+/// A problem with a function, and the instruction it was found at if it is in the code.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifyError {
+    pub kind: VerifyErrorKind,
+    pub function: usize,
+    pub pc: Option<usize>,
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.pc {
+            Some(pc) => write!(f, "{}\n    at {}:{}", self.kind, self.function, pc),
+            None => write!(f, "{}\n    in {}", self.kind, self.function),
+        }
+    }
+}
+
+//what is known about a register from the constants loaded into it
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Known {
+    Type(&'static str),
+    Function(usize),
+}
+
+impl Known {
+    fn type_name(self) -> &'static str {
+        match self {
+            Known::Type(type_) => type_,
+            Known::Function(_) => "function",
+        }
+    }
+}
+
+struct Verifier<'m> {
+    module: &'m Module,
+    function: &'m Function,
+    known: Vec<Option<Known>>,
+}
+
+impl<'m> Verifier<'m> {
+    fn register(&self, register: Register) -> Result<(), VerifyErrorKind> {
+        match (register as usize) < self.function.registers {
+            true => Ok(()),
+            false => Err(VerifyErrorKind::RegisterOutOfRange(register)),
+        }
+    }
+
+    fn known(&self, register: Register) -> Option<Known> {
+        self.known[register as usize]
+    }
+
+    fn set(&mut self, register: Register, known: Option<Known>) {
+        self.known[register as usize] = known;
+    }
+
+    fn binary(
+        &mut self,
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+        result: Option<Known>,
+    ) -> Result<(), VerifyErrorKind> {
+        for register in [dst, lhs, rhs] {
+            self.register(register)?;
+        }
+        if let (Some(lhs), Some(rhs)) = (self.known(lhs), self.known(rhs)) {
+            if lhs.type_name() != rhs.type_name() {
+                let kind = VmErrorKind::Mismatched(lhs.type_name(), rhs.type_name());
+                return Err(VerifyErrorKind::Type(kind));
+            }
+        }
+        self.set(dst, result);
+        Ok(())
+    }
+
+    fn instruction(&mut self, instruction: Instruction) -> Result<(), VerifyErrorKind> {
+        let (code, slots) = (self.function.code.len(), self.function.slots);
+        match instruction {
+            Instruction::LoadConst(dst, index) => {
+                self.register(dst)?;
+                let constant = self
+                    .module
+                    .constants
+                    .get(index as usize)
+                    .ok_or(VerifyErrorKind::ConstantOutOfRange(index))?;
+                let known = match *constant {
+                    Value::Function(function)
+                        if function as usize >= self.module.functions.len() =>
+                    {
+                        let kind = VmErrorKind::UnknownFunction(function as usize);
+                        return Err(VerifyErrorKind::Type(kind));
+                    }
+                    Value::Function(function) => Known::Function(function as usize),
+                    //no heap has objects before the module runs
+                    Value::Object(_) => return Err(VerifyErrorKind::ObjectConstant(index)),
+                    ref value => Known::Type(value.type_name()),
+                };
+                self.set(dst, Some(known));
+            }
+            Instruction::LoadBool(dst, _) => {
+                self.register(dst)?;
+                self.set(dst, Some(Known::Type("bool")));
+            }
+            Instruction::LoadUnit(dst) => {
+                self.register(dst)?;
+                self.set(dst, Some(Known::Type("unit")));
+            }
+            Instruction::Move(dst, src) => {
+                self.register(dst)?;
+                self.register(src)?;
+                self.set(dst, self.known(src));
+            }
+            Instruction::Neg(dst, src) | Instruction::Not(dst, src) => {
+                self.register(dst)?;
+                self.register(src)?;
+                let (op, allowed) = match instruction {
+                    Instruction::Neg(_, _) => ("-", ["int", "float"]),
+                    _ => ("!", ["bool", "int"]),
+                };
+                let known = self.known(src);
+                if let Some(type_) = known.map(Known::type_name) {
+                    if !allowed.contains(&type_) {
+                        let kind = VmErrorKind::InvalidOperand(op, type_);
+                        return Err(VerifyErrorKind::Type(kind));
+                    }
+                }
+                self.set(dst, known);
+            }
+            Instruction::Add(dst, lhs, rhs)
+            | Instruction::Sub(dst, lhs, rhs)
+            | Instruction::Mul(dst, lhs, rhs)
+            | Instruction::Div(dst, lhs, rhs)
+            | Instruction::Rem(dst, lhs, rhs) => self.binary(dst, lhs, rhs, None)?,
+            Instruction::Lt(dst, lhs, rhs) | Instruction::Le(dst, lhs, rhs) => {
+                self.binary(dst, lhs, rhs, Some(Known::Type("bool")))?
+            }
+            Instruction::Eq(dst, lhs, rhs) | Instruction::Ne(dst, lhs, rhs) => {
+                for register in [dst, lhs, rhs] {
+                    self.register(register)?;
+                }
+                self.set(dst, Some(Known::Type("bool")));
+            }
+            Instruction::Jump(target) if target as usize > code => {
+                return Err(VerifyErrorKind::JumpOutOfRange(target));
+            }
+            Instruction::Jump(_) => {}
+            Instruction::JumpIf(condition, target) | Instruction::JumpIfNot(condition, target) => {
+                self.register(condition)?;
+                if target as usize > code {
+                    return Err(VerifyErrorKind::JumpOutOfRange(target));
+                }
+                match self.known(condition).map(Known::type_name) {
+                    Some(type_) if type_ != "bool" => {
+                        let kind = VmErrorKind::InvalidOperand(instruction.mnemonic(), type_);
+                        return Err(VerifyErrorKind::Type(kind));
+                    }
+                    _ => self.set(condition, Some(Known::Type("bool"))),
+                }
+            }
+            Instruction::Call(dst, callee, args, count) => {
+                self.register(dst)?;
+                self.register(callee)?;
+                //the callee's window starts at its first argument, which need not exist
+                if args as usize + count as usize > self.function.registers {
+                    let last = (args as usize + count as usize - 1).max(args as usize);
+                    return Err(VerifyErrorKind::RegisterOutOfRange(last as Register));
+                }
+                match self.known(callee) {
+                    Some(Known::Function(function)) => {
+                        let arity = self.module.functions[function].arity;
+                        if arity != count {
+                            let kind = VmErrorKind::ArityMismatch(arity as usize, count as usize);
+                            return Err(VerifyErrorKind::Type(kind));
+                        }
+                    }
+                    Some(Known::Type(type_)) => {
+                        return Err(VerifyErrorKind::Type(VmErrorKind::NotCallable(type_)));
+                    }
+                    None => {}
+                }
+                //the callee may overwrite any register from its first argument up
+                for known in &mut self.known[args as usize..] {
+                    *known = None;
+                }
+                self.set(dst, None);
+            }
+            Instruction::Return(src) => self.register(src)?,
+            Instruction::CheckStep(src) => {
+                self.register(src)?;
+                match self.known(src).map(Known::type_name) {
+                    Some(type_) if type_ != "int" => {
+                        let kind = VmErrorKind::InvalidOperand(instruction.mnemonic(), type_);
+                        return Err(VerifyErrorKind::Type(kind));
+                    }
+                    _ => self.set(src, Some(Known::Type("int"))),
+                }
+            }
+            Instruction::Spill(slot, _) | Instruction::Reload(_, slot)
+                if slot as usize >= slots =>
+            {
+                return Err(VerifyErrorKind::SlotOutOfRange(slot));
+            }
+            Instruction::Spill(_, src) => self.register(src)?,
+            Instruction::Reload(dst, _) => {
+                self.register(dst)?;
+                self.set(dst, None);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn verify_function(
+    module: &Module,
+    function: &Function,
+) -> Result<(), (VerifyErrorKind, Option<usize>)> {
+    if function.registers > MAX_REGISTERS {
+        return Err((VerifyErrorKind::TooManyRegisters(function.registers), None));
+    }
+    if function.arity as usize > function.registers {
+        let kind = VerifyErrorKind::ArityExceedsRegisters(function.arity, function.registers);
+        return Err((kind, None));
+    }
+    if !function.spans.is_empty() && function.spans.len() != function.code.len() {
+        let kind = VerifyErrorKind::SpansMismatch(function.spans.len(), function.code.len());
+        return Err((kind, None));
+    }
+    let targets: HashSet<usize> = function
+        .code
+        .iter()
+        .filter_map(|instruction| match instruction {
+            Instruction::Jump(target)
+            | Instruction::JumpIf(_, target)
+            | Instruction::JumpIfNot(_, target) => Some(*target as usize),
+            _ => None,
+        })
+        .collect();
+    let mut verifier = Verifier {
+        module,
+        function,
+        known: vec![None; MAX_REGISTERS],
+    };
+    let mut reachable = true; //whether the previous instruction can fall through
+    for (pc, instruction) in function.code.iter().enumerate() {
+        //nothing is known where control flow joins
+        if targets.contains(&pc) || !reachable {
+            verifier.known.fill(None);
+        }
+        verifier
+            .instruction(*instruction)
+            .map_err(|kind| (kind, Some(pc)))?;
+        reachable = !matches!(instruction, Instruction::Jump(_) | Instruction::Return(_));
+    }
+    Ok(())
+}
+
+/// Checks that a module can be run without indexing outside its constants, registers, slots or
+/// code, and without an instruction that is certain to fail on the constants it is given. The
+/// [`Vm`] assumes the modules it runs are well formed, so modules that were not compiled in
+/// this process should be verified first.
+pub fn verify_module(module: &Module) -> Result<(), VerifyError> {
+    for (index, function) in module.functions.iter().enumerate() {
+        verify_function(module, function).map_err(|(kind, pc)| VerifyError {
+            kind,
+            function: index,
+            pc,
+        })?;
+    }
+    Ok(())
+}
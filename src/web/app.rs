@@ -1,4 +1,4 @@
-use crate::{parser_combinator::Parser, untyped_language::pfun, web::templates::*};
+use crate::{parser_combinator::Parser, untyped_language::pprogram, web::templates::*};
 use axum::{
     response::IntoResponse,
     routing::{get, post},
@@ -37,7 +37,7 @@ struct Code {
 }
 
 async fn code(Form(code): Form<Code>) -> impl IntoResponse {
-    let parser = pfun();
+    let parser = pprogram();
     let start = std::time::Instant::now();
     let result = parser.parse(code.code.as_str().into());
     let end = std::time::Instant::now();